@@ -0,0 +1,25 @@
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS title_generated BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN IF NOT EXISTS retention_days INTEGER,
+    ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS pinned_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS language TEXT,
+    ADD COLUMN IF NOT EXISTS detected_language TEXT,
+    ADD COLUMN IF NOT EXISTS locked BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS locked_by_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS max_credits BIGINT,
+    ADD COLUMN IF NOT EXISTS credits_spent BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS media_bytes BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS system_prompt TEXT,
+    ADD COLUMN IF NOT EXISTS style_preset TEXT,
+    ADD COLUMN IF NOT EXISTS voice_profile TEXT,
+    ADD COLUMN IF NOT EXISTS generation_settings JSONB;
+
+CREATE INDEX IF NOT EXISTS conversations_user_id_updated_at_idx
+    ON conversations (user_id, updated_at DESC);
+CREATE INDEX IF NOT EXISTS conversations_expires_at_idx
+    ON conversations (expires_at) WHERE expires_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS conversations_deleted_at_idx
+    ON conversations (deleted_at) WHERE deleted_at IS NOT NULL;
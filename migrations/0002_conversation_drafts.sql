@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS conversation_drafts (
+    conversation_id UUID PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    content TEXT NOT NULL,
+    attachment_ids UUID[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS attachments (
+    id UUID PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    filename TEXT NOT NULL,
+    size BIGINT NOT NULL DEFAULT 0,
+    total_size BIGINT,
+    waveform JSONB,
+    completed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS attachments_user_id_idx ON attachments (user_id);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS credit_usage (
+    id UUID PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    model TEXT NOT NULL,
+    credits BIGINT NOT NULL,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS credit_usage_user_id_created_at_idx
+    ON credit_usage (user_id, created_at);
+CREATE INDEX IF NOT EXISTS credit_usage_created_at_idx ON credit_usage (created_at);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS user_instructions (
+    user_id BIGINT PRIMARY KEY,
+    about_me TEXT,
+    response_style TEXT,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS generated_images (
+    id UUID PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    prompt TEXT NOT NULL,
+    model TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS generated_images_user_id_created_at_idx
+    ON generated_images (user_id, created_at DESC);
@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS integration_bots (
+    id UUID PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    api_key_hash TEXT NOT NULL UNIQUE,
+    webhook_url TEXT,
+    model TEXT,
+    credits_remaining BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS integration_bots_user_id_idx ON integration_bots (user_id);
+
+CREATE TABLE IF NOT EXISTS integration_bot_threads (
+    bot_id UUID NOT NULL REFERENCES integration_bots (id) ON DELETE CASCADE,
+    external_thread_id TEXT NOT NULL,
+    conversation_id UUID NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (bot_id, external_thread_id)
+);
@@ -0,0 +1,41 @@
+CREATE EXTENSION IF NOT EXISTS vector;
+
+CREATE TABLE IF NOT EXISTS collections (
+    id UUID PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    organization_id TEXT,
+    scope VARCHAR(16) NOT NULL,
+    name TEXT NOT NULL,
+    embedding_model TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS collections_user_id_idx ON collections (user_id);
+CREATE INDEX IF NOT EXISTS collections_organization_id_idx
+    ON collections (organization_id) WHERE organization_id IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS documents (
+    id UUID PRIMARY KEY,
+    collection_id UUID NOT NULL REFERENCES collections (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    chunk_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS documents_collection_id_idx ON documents (collection_id);
+
+-- Embeddings keep the dimension of whichever model the collection uses.
+CREATE TABLE IF NOT EXISTS document_chunks (
+    id UUID PRIMARY KEY,
+    document_id UUID NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    collection_id UUID NOT NULL REFERENCES collections (id) ON DELETE CASCADE,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding vector NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS document_chunks_document_id_idx ON document_chunks (document_id);
+CREATE INDEX IF NOT EXISTS document_chunks_collection_id_idx ON document_chunks (collection_id);
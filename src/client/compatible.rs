@@ -0,0 +1,211 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use rs_openai::chat::Role;
+
+use crate::{
+    client::provider::{
+        ChatParams, ImageOptions, InferenceProvider, ModerationResult, ToolTurn, TranscriptSegment,
+    },
+    config::{
+        providers::{ProviderEntry, ProviderKind},
+        proxy::ProxyConfig,
+    },
+    utils::{openai::chat_completion_body, proxy::with_proxy, retry::Resilience},
+};
+
+// Chat-only provider for Azure OpenAI deployments and OpenAI-compatible servers such as vLLM or Ollama.
+pub struct CompatibleClient {
+    name: String,
+    kind: ProviderKind,
+    base_url: String,
+    api_key: Option<String>,
+    api_version: Option<String>,
+    http: Client,
+    request_timeout: Duration,
+    resilience: Arc<Resilience>,
+}
+
+impl CompatibleClient {
+    pub fn build(
+        entry: &ProviderEntry,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+        proxy: &ProxyConfig,
+        resilience: Arc<Resilience>,
+    ) -> Result<Self, String> {
+        let builder = Client::builder()
+            .connect_timeout(connect_timeout)
+            .pool_idle_timeout(Duration::from_secs(90));
+        let http = with_proxy(builder, proxy, entry.proxy.as_deref())?
+            .build()
+            .map_err(|e| format!("Error in building {} http client: {}", entry.name, e))?;
+        Ok(CompatibleClient {
+            name: entry.name.clone(),
+            kind: entry.kind,
+            base_url: entry.base_url.clone(),
+            api_key: entry.api_key.clone(),
+            api_version: entry.api_version.clone(),
+            http,
+            request_timeout,
+            resilience,
+        })
+    }
+
+    async fn send_chat_request(
+        &self,
+        model_name: &str,
+        request_body: &serde_json::Value,
+    ) -> Result<Response, String> {
+        let request = self.authorize(match self.kind {
+            ProviderKind::Azure => self.http.post(format!(
+                "{}/openai/deployments/{}/chat/completions",
+                self.base_url, model_name
+            )),
+            _ => self
+                .http
+                .post(format!("{}/chat/completions", self.base_url)),
+        });
+        let response =
+            tokio::time::timeout(self.request_timeout, request.json(request_body).send())
+                .await
+                .map_err(|_| {
+                    format!(
+                        "{} request timed out after {} seconds",
+                        self.name,
+                        self.request_timeout.as_secs()
+                    )
+                })?
+                .map_err(|e| format!("{} response failed: {}", self.name, e))?;
+        match response.status() {
+            status if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => Err(
+                format!("{} rejected the chat completion: {}", self.name, status),
+            ),
+            _ => Ok(response),
+        }
+    }
+
+    // Azure takes the key in its own header and needs the API version on every call.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.kind {
+            ProviderKind::Azure => {
+                let request = request.query(&[(
+                    "api-version",
+                    self.api_version.as_deref().unwrap_or_default(),
+                )]);
+                match &self.api_key {
+                    Some(api_key) => request.header("api-key", api_key),
+                    None => request,
+                }
+            }
+            _ => match &self.api_key {
+                Some(api_key) => request.bearer_auth(api_key),
+                None => request,
+            },
+        }
+    }
+
+    fn unsupported<T>(&self, operation: &str) -> Result<T, String> {
+        Err(format!(
+            "The provider '{}' does not support {}",
+            self.name, operation
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl InferenceProvider for CompatibleClient {
+    async fn send_chat_completion(
+        &self,
+        model_name: String,
+        conversations: Vec<(String, Role, Vec<String>, Option<ToolTurn>)>,
+        params: &ChatParams,
+    ) -> Result<Response, String> {
+        let request_body = chat_completion_body(&model_name, &conversations, params);
+        self.resilience
+            .call(&self.name, || {
+                self.send_chat_request(&model_name, &request_body)
+            })
+            .await
+    }
+
+    async fn speech_to_text(
+        &self,
+        _audio_data: Vec<u8>,
+        _filename: String,
+        _prompt: Option<String>,
+        _language: Option<String>,
+    ) -> Result<String, String> {
+        self.unsupported("speech to text")
+    }
+
+    async fn speech_to_segments(
+        &self,
+        _audio_data: Vec<u8>,
+        _filename: String,
+        _prompt: Option<String>,
+        _language: Option<String>,
+    ) -> Result<Vec<TranscriptSegment>, String> {
+        self.unsupported("speech to text")
+    }
+
+    async fn text_to_image(
+        &self,
+        _prompt: &str,
+        _options: &ImageOptions,
+    ) -> Result<Vec<String>, String> {
+        self.unsupported("image generation")
+    }
+
+    async fn enhance_image_prompt(
+        &self,
+        _model_name: &str,
+        _prompt: &str,
+    ) -> Result<String, String> {
+        self.unsupported("image prompt enhancement")
+    }
+
+    async fn embed(
+        &self,
+        _model_name: &str,
+        _inputs: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>, String> {
+        self.unsupported("embeddings")
+    }
+
+    async fn structured_completion(
+        &self,
+        _model_name: &str,
+        _messages: Vec<serde_json::Value>,
+        _schema_name: &str,
+        _schema: &serde_json::Value,
+    ) -> Result<String, String> {
+        self.unsupported("structured completions")
+    }
+
+    async fn moderate(
+        &self,
+        _model_name: &str,
+        _text: &str,
+        _images: Vec<String>,
+    ) -> Result<ModerationResult, String> {
+        self.unsupported("moderation")
+    }
+
+    async fn probe(&self) -> Result<(), String> {
+        let url = match self.kind {
+            ProviderKind::Azure => format!("{}/openai/models", self.base_url),
+            _ => format!("{}/models", self.base_url),
+        };
+        tokio::time::timeout(
+            self.request_timeout,
+            self.authorize(self.http.get(url)).send(),
+        )
+        .await
+        .map_err(|_| format!("{} probe timed out", self.name))?
+        .map_err(|e| format!("{} probe failed: {}", self.name, e))?
+        .error_for_status()
+        .map_err(|e| format!("{} rejected the probe: {}", self.name, e))?;
+        Ok(())
+    }
+}
@@ -1,9 +1,56 @@
 use std::time::Duration;
 
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement,
+    TransactionTrait,
+};
+use tracing::info;
 
 use crate::config::ServiceConfig;
 
+// Applied in order, each once. Add new files at the end and never edit an applied one.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "0001_conversation_columns",
+        include_str!("../../migrations/0001_conversation_columns.sql"),
+    ),
+    (
+        "0002_conversation_drafts",
+        include_str!("../../migrations/0002_conversation_drafts.sql"),
+    ),
+    (
+        "0003_attachments",
+        include_str!("../../migrations/0003_attachments.sql"),
+    ),
+    (
+        "0004_credit_usage",
+        include_str!("../../migrations/0004_credit_usage.sql"),
+    ),
+    (
+        "0005_user_instructions",
+        include_str!("../../migrations/0005_user_instructions.sql"),
+    ),
+    (
+        "0006_generated_images",
+        include_str!("../../migrations/0006_generated_images.sql"),
+    ),
+    (
+        "0007_outbox_events",
+        include_str!("../../migrations/0007_outbox_events.sql"),
+    ),
+    (
+        "0008_integration_bots",
+        include_str!("../../migrations/0008_integration_bots.sql"),
+    ),
+    (
+        "0009_collections",
+        include_str!("../../migrations/0009_collections.sql"),
+    ),
+];
+
+// Any constant works, as long as every instance of the service takes the same lock.
+const MIGRATION_LOCK_ID: i64 = 0x7765_6167_6900_0001;
+
 pub type DatabaseClient = DatabaseConnection;
 
 pub trait DatabaseClientExt: Sized {
@@ -27,3 +74,59 @@ impl DatabaseClientExt for DatabaseClient {
         Ok(db)
     }
 }
+
+/// Brings the schema up to date, recording applied migrations in `schema_migrations`.
+pub async fn run_migrations(db: &DatabaseConnection) -> Result<(), String> {
+    let transaction = db
+        .begin()
+        .await
+        .map_err(|e| format!("Starting the migration transaction failed: {}", e))?;
+    // Instances starting together would otherwise apply the same migration twice.
+    transaction
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_advisory_xact_lock($1)",
+            [MIGRATION_LOCK_ID.into()],
+        ))
+        .await
+        .map_err(|e| format!("Taking the migration lock failed: {}", e))?;
+    transaction
+        .execute_unprepared(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (\
+             name TEXT PRIMARY KEY, \
+             applied_at TIMESTAMPTZ NOT NULL DEFAULT now())",
+        )
+        .await
+        .map_err(|e| format!("Creating the migrations table failed: {}", e))?;
+    for (name, sql) in MIGRATIONS {
+        let applied = transaction
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "SELECT 1 FROM schema_migrations WHERE name = $1",
+                [(*name).into()],
+            ))
+            .await
+            .map_err(|e| format!("Reading the applied migrations failed: {}", e))?
+            .is_some();
+        if applied {
+            continue;
+        }
+        transaction
+            .execute_unprepared(sql)
+            .await
+            .map_err(|e| format!("Applying migration '{}' failed: {}", name, e))?;
+        transaction
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "INSERT INTO schema_migrations (name) VALUES ($1)",
+                [(*name).into()],
+            ))
+            .await
+            .map_err(|e| format!("Recording migration '{}' failed: {}", name, e))?;
+        info!("Applied database migration '{}'.", name);
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Committing the migrations failed: {}", e))
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::config::{events::BusKind, ServiceConfig};
+
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+// JetStream drops a message whose id it has already seen, so outbox retries stay idempotent.
+const NATS_MSG_ID_HEADER: &str = "Nats-Msg-Id";
+
+// Publishes domain events for analytics consumers.
+pub enum EventBus {
+    Nats(async_nats::Client),
+    Kafka {
+        http: reqwest::Client,
+        rest_url: String,
+    },
+}
+
+impl EventBus {
+    /// Returns `None` when no bus is configured. The NATS connection is made in
+    /// the background, so an unreachable server does not prevent startup.
+    pub async fn build_from_config(config: &ServiceConfig) -> Result<Option<Self>, String> {
+        let Some(kind) = config.events.kind else {
+            return Ok(None);
+        };
+        let bus = match kind {
+            BusKind::Nats => EventBus::Nats(
+                async_nats::ConnectOptions::new()
+                    .retry_on_initial_connect()
+                    .connect(config.events.url.as_str())
+                    .await
+                    .map_err(|e| format!("Error in connecting to NATS: {}", e))?,
+            ),
+            BusKind::Kafka => EventBus::Kafka {
+                http: reqwest::Client::builder()
+                    .timeout(PUBLISH_TIMEOUT)
+                    .build()
+                    .map_err(|e| format!("Error in building the Kafka REST client: {}", e))?,
+                rest_url: config.events.url.clone(),
+            },
+        };
+        info!("Publishing domain events to {:?}.", kind);
+        Ok(Some(bus))
+    }
+
+    pub async fn publish(
+        &self,
+        subject: &str,
+        event_id: &str,
+        payload: &Value,
+    ) -> Result<(), String> {
+        match self {
+            EventBus::Nats(client) => {
+                let mut headers = async_nats::HeaderMap::new();
+                headers.insert(NATS_MSG_ID_HEADER, event_id);
+                client
+                    .publish_with_headers(
+                        subject.to_string(),
+                        headers,
+                        Bytes::from(payload.to_string()),
+                    )
+                    .await
+                    .map_err(|e| format!("Publishing to NATS failed: {}", e))?;
+                tokio::time::timeout(PUBLISH_TIMEOUT, client.flush())
+                    .await
+                    .map_err(|_| "Flushing the NATS connection timed out".to_string())?
+                    .map_err(|e| format!("Flushing the NATS connection failed: {}", e))
+            }
+            EventBus::Kafka { http, rest_url } => {
+                http.post(format!("{}/topics/{}", rest_url, subject))
+                    .header("Content-Type", "application/vnd.kafka.json.v2+json")
+                    .json(&json!({
+                        "records": [{ "key": event_id, "value": payload }]
+                    }))
+                    .send()
+                    .await
+                    .map_err(|e| format!("Sending the Kafka REST request failed: {}", e))?
+                    .error_for_status()
+                    .map_err(|e| format!("Kafka REST proxy rejected the event: {}", e))?;
+                Ok(())
+            }
+        }
+    }
+}
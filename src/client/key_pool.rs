@@ -0,0 +1,129 @@
+use crate::config::openai::KeyStrategy;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+struct PooledKey {
+    key: String,
+    errors: AtomicU64,
+    quarantined_until: Mutex<Option<Instant>>,
+}
+
+impl PooledKey {
+    fn is_available(&self, now: Instant) -> bool {
+        self.quarantined_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .map_or(true, |until| until <= now)
+    }
+}
+
+pub struct KeyPool {
+    keys: Vec<PooledKey>,
+    next: AtomicUsize,
+    strategy: KeyStrategy,
+    quarantine: Duration,
+    cooldown: Duration,
+}
+
+impl KeyPool {
+    pub fn new(
+        keys: Vec<String>,
+        strategy: KeyStrategy,
+        quarantine: Duration,
+        cooldown: Duration,
+    ) -> Self {
+        KeyPool {
+            keys: keys
+                .into_iter()
+                .map(|key| PooledKey {
+                    key,
+                    errors: AtomicU64::new(0),
+                    quarantined_until: Mutex::new(None),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            strategy,
+            quarantine,
+            cooldown,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Every key, quarantined or not, for probing.
+    pub fn all(&self) -> Vec<(usize, String)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(index, pooled)| (index, pooled.key.clone()))
+            .collect()
+    }
+
+    pub fn acquire(&self) -> Option<(usize, String)> {
+        let now = Instant::now();
+        let available = (0..self.keys.len()).filter(|&index| self.keys[index].is_available(now));
+        let index = match self.strategy {
+            KeyStrategy::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                let count = self.keys.len();
+                (0..count)
+                    .map(|offset| (start + offset) % count)
+                    .find(|&index| self.keys[index].is_available(now))
+            }
+            KeyStrategy::LeastErrors => {
+                available.min_by_key(|&index| self.keys[index].errors.load(Ordering::Relaxed))
+            }
+        }?;
+        Some((index, self.keys[index].key.clone()))
+    }
+
+    pub fn report_success(&self, index: usize) {
+        let errors = &self.keys[index].errors;
+        let _ = errors.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |e| {
+            Some(e.saturating_sub(1))
+        });
+    }
+
+    pub fn report_error(&self, index: usize) {
+        self.keys[index].errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Takes a key out of rotation after OpenAI rejects it: for long on 401, briefly on 429.
+    pub fn quarantine(&self, index: usize, status: u16) {
+        self.report_error(index);
+        let duration = if status == 401 {
+            self.quarantine
+        } else {
+            self.cooldown
+        };
+        warn!(
+            "Quarantining OpenAI key #{} for {} seconds after status {}.",
+            index,
+            duration.as_secs(),
+            status
+        );
+        *self.keys[index]
+            .quarantined_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Instant::now() + duration);
+    }
+}
+
+// Finds the HTTP status in an OpenAI error that should take the key out of rotation.
+pub fn rejected_status(error: &str) -> Option<u16> {
+    if error.contains("401 Unauthorized") {
+        Some(401)
+    } else if error.contains("429 Too Many Requests") {
+        Some(429)
+    } else {
+        None
+    }
+}
@@ -1 +1,7 @@
+pub mod compatible;
 pub mod db;
+pub mod event_bus;
+pub mod key_pool;
+pub mod openai;
+pub mod provider;
+pub mod registry;
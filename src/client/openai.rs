@@ -0,0 +1,315 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Client, Response, StatusCode,
+};
+use rs_openai::{chat::Role, OpenAI};
+use tracing::warn;
+
+use crate::{
+    client::{
+        key_pool::{rejected_status, KeyPool},
+        provider::{
+            ChatParams, ImageOptions, InferenceProvider, ModerationResult, ToolTurn,
+            TranscriptSegment,
+        },
+    },
+    config::ServiceConfig,
+    utils::{openai, proxy::with_proxy, retry::Resilience},
+};
+
+const UPSTREAM: &str = "OpenAI";
+
+pub struct OpenAIClient {
+    keys: KeyPool,
+    http: Client,
+    // One transcription client per key of the pool.
+    transcribers: HashMap<String, OpenAI>,
+    request_timeout: Duration,
+    resilience: Arc<Resilience>,
+}
+
+impl OpenAIClient {
+    pub fn build_from_config(
+        config: &ServiceConfig,
+        resilience: Arc<Resilience>,
+    ) -> Result<Self, String> {
+        let mut headers = HeaderMap::new();
+        if let Some(org_id) = &config.openai.org_id {
+            headers.insert(
+                "OpenAI-Organization",
+                HeaderValue::from_str(org_id)
+                    .map_err(|e| format!("Invalid OpenAI organization id: {}", e))?,
+            );
+        }
+        let builder = Client::builder()
+            .default_headers(headers)
+            .connect_timeout(Duration::from_secs(config.openai.connect_timeout_secs))
+            .pool_idle_timeout(Duration::from_secs(90));
+        let http = with_proxy(builder, &config.proxy, config.openai.proxy_url.as_deref())?
+            .build()
+            .map_err(|e| format!("Error in building OpenAI http client: {}", e))?;
+        let transcribers = config
+            .openai
+            .openai_keys
+            .iter()
+            .map(|key| {
+                let client = OpenAI::new(&OpenAI {
+                    api_key: key.clone(),
+                    org_id: config.openai.org_id.clone(),
+                });
+                (key.clone(), client)
+            })
+            .collect();
+        Ok(OpenAIClient {
+            transcribers,
+            keys: KeyPool::new(
+                config.openai.openai_keys.clone(),
+                config.openai.key_strategy,
+                Duration::from_secs(config.openai.key_quarantine_secs),
+                Duration::from_secs(config.openai.key_cooldown_secs),
+            ),
+            http,
+            request_timeout: Duration::from_secs(config.openai.request_timeout_secs),
+            resilience,
+        })
+    }
+
+    async fn with_timeout<T>(
+        &self,
+        operation: impl Future<Output = Result<T, String>>,
+    ) -> Result<T, String> {
+        tokio::time::timeout(self.request_timeout, operation)
+            .await
+            .map_err(|_| {
+                format!(
+                    "OpenAI request timed out after {} seconds",
+                    self.request_timeout.as_secs()
+                )
+            })?
+    }
+
+    // Retries transient failures across the whole key rotation, behind the OpenAI circuit breaker.
+    async fn with_key<T, F, Fut>(&self, operation: F) -> Result<T, String>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        self.resilience
+            .call(UPSTREAM, || self.rotate_keys(&operation))
+            .await
+    }
+
+    // Runs the request with a key from the pool, moving on to the next key when one is rejected.
+    async fn rotate_keys<T, F, Fut>(&self, operation: &F) -> Result<T, String>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let mut last_error = String::from("Every OpenAI key is quarantined");
+        for _ in 0..self.keys.len() {
+            let Some((index, key)) = self.keys.acquire() else {
+                break;
+            };
+            match self.with_timeout(operation(key)).await {
+                Ok(value) => {
+                    self.keys.report_success(index);
+                    return Ok(value);
+                }
+                Err(e) => match rejected_status(&e) {
+                    Some(status) => {
+                        self.keys.quarantine(index, status);
+                        last_error = e;
+                    }
+                    None => {
+                        self.keys.report_error(index);
+                        return Err(e);
+                    }
+                },
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[async_trait::async_trait]
+impl InferenceProvider for OpenAIClient {
+    async fn send_chat_completion(
+        &self,
+        model_name: String,
+        conversations: Vec<(String, Role, Vec<String>, Option<ToolTurn>)>,
+        params: &ChatParams,
+    ) -> Result<Response, String> {
+        self.with_key(|key| {
+            let model_name = model_name.clone();
+            let conversations = conversations.clone();
+            async move {
+                let response = openai::send_chat_completion(
+                    &self.http,
+                    &key,
+                    model_name,
+                    conversations,
+                    params,
+                )
+                .await?;
+                match response.status() {
+                    status @ (StatusCode::UNAUTHORIZED | StatusCode::TOO_MANY_REQUESTS) => {
+                        Err(format!("OpenAI rejected the chat completion: {}", status))
+                    }
+                    status if status.is_server_error() => {
+                        Err(format!("OpenAI chat completion failed: {}", status))
+                    }
+                    _ => Ok(response),
+                }
+            }
+        })
+        .await
+    }
+
+    async fn speech_to_text(
+        &self,
+        audio_data: Vec<u8>,
+        filename: String,
+        prompt: Option<String>,
+        language: Option<String>,
+    ) -> Result<String, String> {
+        self.with_key(|key| {
+            let audio_data = audio_data.clone();
+            let filename = filename.clone();
+            let prompt = prompt.clone();
+            let language = language.clone();
+            async move {
+                let client = self
+                    .transcribers
+                    .get(&key)
+                    .ok_or_else(|| "No transcription client for the OpenAI key".to_string())?;
+                openai::speech_to_text(client, audio_data, filename, prompt, language).await
+            }
+        })
+        .await
+    }
+
+    async fn speech_to_segments(
+        &self,
+        audio_data: Vec<u8>,
+        filename: String,
+        prompt: Option<String>,
+        language: Option<String>,
+    ) -> Result<Vec<TranscriptSegment>, String> {
+        self.with_key(|key| {
+            let audio_data = audio_data.clone();
+            let filename = filename.clone();
+            let prompt = prompt.clone();
+            let language = language.clone();
+            async move {
+                openai::speech_to_segments(&self.http, &key, audio_data, filename, prompt, language)
+                    .await
+            }
+        })
+        .await
+    }
+
+    async fn text_to_image(
+        &self,
+        prompt: &str,
+        options: &ImageOptions,
+    ) -> Result<Vec<String>, String> {
+        // The images API takes no negative prompt. Naming what to leave out is the
+        // closest it gets, and the model may still draw it, so this is no guarantee.
+        let prompt = match options.negative_prompt.as_deref().map(str::trim) {
+            Some(negative) if !negative.is_empty() => {
+                format!("{}\n\nDo not include: {}", prompt, negative)
+            }
+            _ => prompt.to_string(),
+        };
+        self.with_key(|key| {
+            let prompt = prompt.clone();
+            async move { openai::text_to_image(&self.http, &key, &prompt, options).await }
+        })
+        .await
+    }
+
+    async fn enhance_image_prompt(&self, model_name: &str, prompt: &str) -> Result<String, String> {
+        self.with_key(|key| async move {
+            openai::enhance_image_prompt(&self.http, &key, model_name, prompt).await
+        })
+        .await
+    }
+
+    async fn embed(&self, model_name: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, String> {
+        self.with_key(|key| {
+            let inputs = inputs.clone();
+            async move { openai::create_embeddings(&self.http, &key, model_name, inputs).await }
+        })
+        .await
+    }
+
+    async fn structured_completion(
+        &self,
+        model_name: &str,
+        messages: Vec<serde_json::Value>,
+        schema_name: &str,
+        schema: &serde_json::Value,
+    ) -> Result<String, String> {
+        self.with_key(|key| {
+            let messages = messages.clone();
+            async move {
+                openai::structured_completion(
+                    &self.http,
+                    &key,
+                    model_name,
+                    messages,
+                    schema_name,
+                    schema,
+                )
+                .await
+            }
+        })
+        .await
+    }
+
+    async fn moderate(
+        &self,
+        model_name: &str,
+        text: &str,
+        images: Vec<String>,
+    ) -> Result<ModerationResult, String> {
+        self.with_key(|key| {
+            let images = images.clone();
+            async move { openai::moderate(&self.http, &key, model_name, text, images).await }
+        })
+        .await
+    }
+
+    // Tries every key, so a revoked one is quarantined before a user request hits it.
+    async fn probe(&self) -> Result<(), String> {
+        let keys = self.keys.all();
+        if keys.is_empty() {
+            return Err("No OpenAI keys are configured".to_string());
+        }
+        let mut failures = vec![];
+        for (index, key) in &keys {
+            match self
+                .with_timeout(openai::list_models(&self.http, key))
+                .await
+            {
+                Ok(()) => self.keys.report_success(*index),
+                Err(e) => {
+                    match rejected_status(&e) {
+                        Some(status) => self.keys.quarantine(*index, status),
+                        None => self.keys.report_error(*index),
+                    }
+                    failures.push(format!("key #{}: {}", index, e));
+                }
+            }
+        }
+        if failures.len() == keys.len() {
+            return Err(failures.join("; "));
+        }
+        if !failures.is_empty() {
+            warn!("Some OpenAI keys failed the probe: {}", failures.join("; "));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use crate::entity::conversation::ToolCall;
+use reqwest::Response;
+use rs_openai::chat::Role;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AspectRatio {
+    #[default]
+    Square,
+    Portrait,
+    Landscape,
+}
+
+impl AspectRatio {
+    /// The DALL·E 3 image size for this aspect ratio.
+    pub fn size(&self) -> &'static str {
+        match self {
+            AspectRatio::Square => "1024x1024",
+            AspectRatio::Portrait => "1024x1792",
+            AspectRatio::Landscape => "1792x1024",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub enum ImageModel {
+    #[serde(rename = "dall-e-2")]
+    DallE2,
+    #[default]
+    #[serde(rename = "dall-e-3")]
+    DallE3,
+}
+
+impl ImageModel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageModel::DallE2 => "dall-e-2",
+            ImageModel::DallE3 => "dall-e-3",
+        }
+    }
+
+    pub fn sizes(&self) -> &'static [&'static str] {
+        match self {
+            ImageModel::DallE2 => &["256x256", "512x512", "1024x1024"],
+            ImageModel::DallE3 => &["1024x1024", "1024x1792", "1792x1024"],
+        }
+    }
+
+    pub fn max_images(&self) -> u8 {
+        match self {
+            ImageModel::DallE2 => 10,
+            ImageModel::DallE3 => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageQuality {
+    #[default]
+    Standard,
+    Hd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageStyle {
+    Vivid,
+    Natural,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    pub model: ImageModel,
+    pub aspect_ratio: AspectRatio,
+    /// An explicit `WxH` size, which wins over the aspect ratio.
+    pub size: Option<String>,
+    pub quality: ImageQuality,
+    pub style: Option<ImageStyle>,
+    pub count: u8,
+    /// Only a hint: OpenAI image models have no negative prompt, so it is
+    /// appended to the prompt and may be ignored, or even pull the subject in.
+    pub negative_prompt: Option<String>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            model: ImageModel::default(),
+            aspect_ratio: AspectRatio::default(),
+            size: None,
+            quality: ImageQuality::default(),
+            style: None,
+            count: 1,
+            negative_prompt: None,
+        }
+    }
+}
+
+impl ImageOptions {
+    pub fn size(&self) -> &str {
+        match (&self.size, self.model) {
+            (Some(size), _) => size,
+            (None, ImageModel::DallE2) => "1024x1024",
+            (None, ImageModel::DallE3) => self.aspect_ratio.size(),
+        }
+    }
+
+    /// Rejects combinations the model does not offer.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.model.sizes().contains(&self.size()) {
+            return Err(format!(
+                "{} supports the sizes {}",
+                self.model.name(),
+                self.model.sizes().join(", ")
+            ));
+        }
+        if self.count == 0 || self.count > self.model.max_images() {
+            return Err(format!(
+                "{} generates between 1 and {} images per request",
+                self.model.name(),
+                self.model.max_images()
+            ));
+        }
+        if self.model == ImageModel::DallE2
+            && (self.quality != ImageQuality::Standard || self.style.is_some())
+        {
+            return Err("dall-e-2 supports neither quality nor style".to_string());
+        }
+        Ok(())
+    }
+
+    /// The list price of one image with these options.
+    pub fn usd_per_image(&self) -> f64 {
+        match (self.model, self.size(), self.quality) {
+            (ImageModel::DallE2, "256x256", _) => 0.016,
+            (ImageModel::DallE2, "512x512", _) => 0.018,
+            (ImageModel::DallE2, _, _) => 0.02,
+            (ImageModel::DallE3, "1024x1024", ImageQuality::Standard) => 0.04,
+            (ImageModel::DallE3, "1024x1024", ImageQuality::Hd) => 0.08,
+            (ImageModel::DallE3, _, ImageQuality::Standard) => 0.08,
+            (ImageModel::DallE3, _, ImageQuality::Hd) => 0.12,
+        }
+    }
+}
+
+/// Sampling parameters sent with a chat completion. Unset fields are left
+/// out of the request, so the provider's defaults apply.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub tools: Option<serde_json::Value>,
+}
+
+/// A stretch of a transcript; `start` and `end` are seconds into the audio.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TranscriptSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// The verdict of the moderation endpoint on one message.
+#[derive(Debug, Clone, Default)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    /// Names of the categories the message was flagged for.
+    pub categories: Vec<String>,
+    pub category_scores: BTreeMap<String, f64>,
+}
+
+/// The tool calling side of a prompt message, if it has one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolTurn {
+    /// An assistant message that asked for these calls.
+    Calls(Vec<ToolCall>),
+    /// The result of the call with this id, sent with the `tool` role.
+    Result(String),
+}
+
+#[async_trait::async_trait]
+pub trait InferenceProvider: Send + Sync {
+    async fn send_chat_completion(
+        &self,
+        model_name: String,
+        conversations: Vec<(String, Role, Vec<String>, Option<ToolTurn>)>,
+        params: &ChatParams,
+    ) -> Result<Response, String>;
+
+    async fn speech_to_text(
+        &self,
+        audio_data: Vec<u8>,
+        filename: String,
+        prompt: Option<String>,
+        language: Option<String>,
+    ) -> Result<String, String>;
+
+    /// Like `speech_to_text`, but keeps the timing of every segment.
+    async fn speech_to_segments(
+        &self,
+        audio_data: Vec<u8>,
+        filename: String,
+        prompt: Option<String>,
+        language: Option<String>,
+    ) -> Result<Vec<TranscriptSegment>, String>;
+
+    /// Returns the URLs of the generated images.
+    async fn text_to_image(
+        &self,
+        prompt: &str,
+        options: &ImageOptions,
+    ) -> Result<Vec<String>, String>;
+
+    async fn enhance_image_prompt(&self, model_name: &str, prompt: &str) -> Result<String, String>;
+
+    async fn embed(&self, model_name: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, String>;
+
+    async fn structured_completion(
+        &self,
+        model_name: &str,
+        messages: Vec<serde_json::Value>,
+        schema_name: &str,
+        schema: &serde_json::Value,
+    ) -> Result<String, String>;
+
+    /// Screens text and images, given as data URLs, with the moderation endpoint.
+    async fn moderate(
+        &self,
+        model_name: &str,
+        text: &str,
+        images: Vec<String>,
+    ) -> Result<ModerationResult, String>;
+
+    /// Makes a minimal authenticated request, which checks the credentials and
+    /// warms the connection pool.
+    async fn probe(&self) -> Result<(), String>;
+}
@@ -0,0 +1,228 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::http::StatusCode;
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::{
+    client::{compatible::CompatibleClient, provider::InferenceProvider},
+    config::{
+        constant::{MODEL_TO_CREDITS_PER_1K_TOKENS, MODEL_TO_PRICE, OPENAI_MODEL_CAPABILITIES},
+        providers::ModelCapabilities,
+        ServiceConfig,
+    },
+    utils::{
+        error::{AppError, AppResult, ErrorCode},
+        openai::TokenUsage,
+        retry::Resilience,
+    },
+};
+
+/// Name the default OpenAI client is reported under.
+pub const DEFAULT_PROVIDER: &str = "openai";
+
+struct ModelRoute {
+    provider: Arc<dyn InferenceProvider>,
+    credits: i64,
+    capabilities: ModelCapabilities,
+}
+
+// Maps chat model names to the provider serving them. Models in MODEL_TO_PRICE go to the default OpenAI client.
+// Providers assigned to a data region only serve users of that region and may repeat global models.
+pub struct ProviderRegistry {
+    default: Arc<dyn InferenceProvider>,
+    routes: HashMap<String, ModelRoute>,
+    regional_routes: HashMap<String, HashMap<String, ModelRoute>>,
+    strict_residency: bool,
+    providers: Vec<(String, Arc<dyn InferenceProvider>)>,
+}
+
+impl ProviderRegistry {
+    pub fn build_from_config(
+        config: &ServiceConfig,
+        default: Arc<dyn InferenceProvider>,
+        resilience: Arc<Resilience>,
+    ) -> Result<Self, String> {
+        let mut routes = HashMap::new();
+        let mut regional_routes: HashMap<String, HashMap<String, ModelRoute>> = HashMap::new();
+        let mut providers = vec![(DEFAULT_PROVIDER.to_string(), default.clone())];
+        for (region, settings) in &config.residency.regions {
+            for name in &settings.providers {
+                if !config.providers.entries.iter().any(|e| &e.name == name) {
+                    return Err(format!(
+                        "The region '{}' refers to the unknown provider '{}'",
+                        region, name
+                    ));
+                }
+            }
+        }
+        for entry in &config.providers.entries {
+            let provider: Arc<dyn InferenceProvider> = Arc::new(CompatibleClient::build(
+                entry,
+                Duration::from_secs(config.openai.connect_timeout_secs),
+                Duration::from_secs(config.openai.request_timeout_secs),
+                &config.proxy,
+                resilience.clone(),
+            )?);
+            providers.push((entry.name.clone(), provider.clone()));
+            if let Some(region) = config.residency.region_of_provider(&entry.name) {
+                let region_routes = regional_routes.entry(region.to_string()).or_default();
+                for model in &entry.models {
+                    if region_routes.contains_key(model) {
+                        return Err(format!(
+                            "The model '{}' of provider '{}' is already registered in the region '{}'",
+                            model, entry.name, region
+                        ));
+                    }
+                    region_routes.insert(
+                        model.clone(),
+                        ModelRoute {
+                            provider: provider.clone(),
+                            credits: entry.credits,
+                            capabilities: entry.capabilities,
+                        },
+                    );
+                }
+                info!(
+                    "Registered the provider '{}' for the models {:?} in the region '{}'.",
+                    entry.name, entry.models, region
+                );
+                continue;
+            }
+            for model in &entry.models {
+                if MODEL_TO_PRICE.contains_key(model.as_str()) || routes.contains_key(model) {
+                    return Err(format!(
+                        "The model '{}' of provider '{}' is already registered",
+                        model, entry.name
+                    ));
+                }
+                routes.insert(
+                    model.clone(),
+                    ModelRoute {
+                        provider: provider.clone(),
+                        credits: entry.credits,
+                        capabilities: entry.capabilities,
+                    },
+                );
+            }
+            info!(
+                "Registered the provider '{}' for the models {:?}.",
+                entry.name, entry.models
+            );
+        }
+        Ok(ProviderRegistry {
+            default,
+            routes,
+            regional_routes,
+            strict_residency: config.residency.strict,
+            providers,
+        })
+    }
+
+    /// Every configured provider by name, the default OpenAI client first.
+    pub fn providers(&self) -> &[(String, Arc<dyn InferenceProvider>)] {
+        &self.providers
+    }
+
+    pub fn available_models(&self) -> Vec<String> {
+        let mut models: Vec<String> = MODEL_TO_PRICE
+            .keys()
+            .map(|model| model.to_string())
+            .chain(self.routes.keys().cloned())
+            .chain(
+                self.regional_routes
+                    .values()
+                    .flat_map(|routes| routes.keys().cloned()),
+            )
+            .collect();
+        models.sort();
+        models.dedup();
+        models
+    }
+
+    pub fn price(&self, model: &str) -> Option<i64> {
+        MODEL_TO_PRICE
+            .get(model)
+            .copied()
+            .or_else(|| self.routes.get(model).map(|route| route.credits))
+            .or_else(|| {
+                self.regional_routes
+                    .values()
+                    .find_map(|routes| routes.get(model))
+                    .map(|route| route.credits)
+            })
+    }
+
+    /// Capabilities of a model, taken from its global route before any regional one.
+    pub fn capabilities(&self, model: &str) -> Option<ModelCapabilities> {
+        if MODEL_TO_PRICE.contains_key(model) {
+            return Some(OPENAI_MODEL_CAPABILITIES);
+        }
+        self.routes
+            .get(model)
+            .or_else(|| {
+                self.regional_routes
+                    .values()
+                    .find_map(|routes| routes.get(model))
+            })
+            .map(|route| route.capabilities)
+    }
+
+    // Credits for a finished reply: per-token rates where the model has them, the flat price otherwise.
+    pub fn credits_for_usage(&self, model: &str, usage: &TokenUsage) -> i64 {
+        match MODEL_TO_CREDITS_PER_1K_TOKENS.get(model) {
+            Some((prompt_rate, completion_rate)) => ((usage.prompt_tokens as f64 * prompt_rate
+                + usage.completion_tokens as f64 * completion_rate)
+                / 1000.0)
+                .ceil() as i64,
+            None => self.price(model).unwrap_or_default(),
+        }
+    }
+
+    /// Picks the provider for a model. Users in a data region get that region's
+    /// endpoint when it serves the model; otherwise the global one, unless
+    /// residency is strict.
+    pub fn chat_provider(
+        &self,
+        model: &str,
+        region: Option<&str>,
+    ) -> AppResult<Arc<dyn InferenceProvider>> {
+        if let Some(region) = region {
+            if let Some(route) = self
+                .regional_routes
+                .get(region)
+                .and_then(|routes| routes.get(model))
+            {
+                return Ok(route.provider.clone());
+            }
+            if self.strict_residency {
+                error!(
+                    "Error occurred: The model {} has no endpoint in the region {}",
+                    model, region
+                );
+                return Err(AppError::new(
+                    StatusCode::FORBIDDEN,
+                    ErrorCode::RegionUnavailable,
+                    format!("The model {} is not available in your data region", model),
+                ));
+            }
+        }
+        if MODEL_TO_PRICE.contains_key(model) {
+            return Ok(self.default.clone());
+        }
+        match self.routes.get(model) {
+            Some(route) => Ok(route.provider.clone()),
+            None => Err(self.unknown_model(model)),
+        }
+    }
+
+    pub fn unknown_model(&self, model: &str) -> AppError {
+        error!("Error occurred: Invalid model name: {}", model);
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::UnknownModel,
+            format!("Invalid model name: {}", model),
+        )
+        .with_details(json!({ "available_models": self.available_models() }))
+    }
+}
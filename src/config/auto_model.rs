@@ -0,0 +1,152 @@
+use std::{env, str::FromStr};
+
+/// Model name that lets the service pick the model for each message.
+pub const AUTO_MODEL: &str = "auto";
+
+/// What the service knows about a message when it picks a model for it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestTraits {
+    pub images: bool,
+    pub voice: bool,
+    /// Unknown for a voice message until it is transcribed.
+    pub message_chars: Option<usize>,
+    /// Estimated tokens of the history, system prompt and message together.
+    pub context_tokens: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Images,
+    Voice,
+    MessageCharsBelow(usize),
+    MessageCharsAbove(usize),
+    ContextTokensBelow(u64),
+    ContextTokensAbove(u64),
+    Always,
+}
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "images" => return Ok(Condition::Images),
+            "voice" => return Ok(Condition::Voice),
+            "*" => return Ok(Condition::Always),
+            _ => {}
+        }
+        let invalid = || format!("Unknown condition: {}", s);
+        let (name, below, limit) = match (s.split_once('<'), s.split_once('>')) {
+            (Some((name, limit)), None) => (name.trim(), true, limit.trim()),
+            (None, Some((name, limit))) => (name.trim(), false, limit.trim()),
+            _ => return Err(invalid()),
+        };
+        let limit = limit
+            .parse::<u64>()
+            .map_err(|_| format!("'{}' is not a valid limit in: {}", limit, s))?;
+        match (name, below) {
+            ("message_chars", true) => Ok(Condition::MessageCharsBelow(limit as usize)),
+            ("message_chars", false) => Ok(Condition::MessageCharsAbove(limit as usize)),
+            ("context_tokens", true) => Ok(Condition::ContextTokensBelow(limit)),
+            ("context_tokens", false) => Ok(Condition::ContextTokensAbove(limit)),
+            _ => Err(invalid()),
+        }
+    }
+}
+impl Condition {
+    pub fn matches(&self, traits: &RequestTraits) -> bool {
+        match *self {
+            Condition::Images => traits.images,
+            Condition::Voice => traits.voice,
+            Condition::MessageCharsBelow(limit) => traits.message_chars.is_some_and(|n| n < limit),
+            Condition::MessageCharsAbove(limit) => traits.message_chars.is_some_and(|n| n > limit),
+            Condition::ContextTokensBelow(limit) => traits.context_tokens < limit,
+            Condition::ContextTokensAbove(limit) => traits.context_tokens > limit,
+            Condition::Always => true,
+        }
+    }
+}
+
+/// Picks `model` when every condition holds.
+#[derive(Clone, Debug)]
+pub struct AutoModelRule {
+    pub conditions: Vec<Condition>,
+    pub model: String,
+}
+impl FromStr for AutoModelRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (conditions, model) = s
+            .split_once("=>")
+            .ok_or_else(|| format!("'{}' is not conditions=>model", s.trim()))?;
+        let model = model.trim().to_string();
+        if model.is_empty() || model == AUTO_MODEL {
+            return Err(format!("'{}' does not name a model", s.trim()));
+        }
+        let conditions = conditions
+            .split('&')
+            .map(str::parse)
+            .collect::<Result<Vec<Condition>, String>>()?;
+        Ok(AutoModelRule { conditions, model })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AutoModelConfig {
+    /// Checked in order; the first rule whose conditions all hold wins.
+    pub rules: Vec<AutoModelRule>,
+    pub default_model: String,
+}
+impl Default for AutoModelConfig {
+    fn default() -> Self {
+        AutoModelConfig {
+            rules: vec![
+                AutoModelRule {
+                    conditions: vec![Condition::Images],
+                    model: "gpt-4o".to_string(),
+                },
+                AutoModelRule {
+                    conditions: vec![Condition::ContextTokensAbove(16000)],
+                    model: "gpt-4o".to_string(),
+                },
+                AutoModelRule {
+                    conditions: vec![Condition::MessageCharsBelow(280)],
+                    model: "gpt-4o-mini".to_string(),
+                },
+            ],
+            default_model: "gpt-4o".to_string(),
+        }
+    }
+}
+impl AutoModelConfig {
+    // Reads AUTO_MODEL_RULES=images=>gpt-4o;message_chars<280&context_tokens<4000=>gpt-4o-mini;...
+    // and AUTO_MODEL_DEFAULT, used when no rule matches.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("AUTO_MODEL_RULES") {
+            self.rules = value
+                .split(';')
+                .filter(|rule| !rule.trim().is_empty())
+                .map(str::parse)
+                .collect::<Result<_, String>>()
+                .map_err(|e| format!("AUTO_MODEL_RULES is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("AUTO_MODEL_DEFAULT") {
+            let value = value.trim();
+            if value.is_empty() || value == AUTO_MODEL {
+                return Err("AUTO_MODEL_DEFAULT must name a model".to_string());
+            }
+            self.default_model = value.to_string();
+        }
+
+        Ok(())
+    }
+
+    pub fn select(&self, traits: &RequestTraits) -> &str {
+        self.rules
+            .iter()
+            .find(|rule| rule.conditions.iter().all(|c| c.matches(traits)))
+            .map_or(self.default_model.as_str(), |rule| rule.model.as_str())
+    }
+}
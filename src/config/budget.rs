@@ -0,0 +1,72 @@
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BudgetAction {
+    #[default]
+    Degrade,
+    Disable,
+}
+impl FromStr for BudgetAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "degrade" => Ok(BudgetAction::Degrade),
+            "disable" => Ok(BudgetAction::Disable),
+            other => Err(format!("Unknown budget action: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BudgetConfig {
+    pub daily_usd: Option<f64>,
+    pub action: BudgetAction,
+    pub fallback_model: String,
+    pub alert_webhook: Option<String>,
+}
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            daily_usd: None,
+            action: BudgetAction::default(),
+            fallback_model: String::from("gpt-4o-mini"),
+            alert_webhook: None,
+        }
+    }
+}
+impl BudgetConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("SPEND_DAILY_BUDGET_USD") {
+            if !value.trim().is_empty() {
+                let budget = value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| "SPEND_DAILY_BUDGET_USD is not a valid f64".to_string())?;
+                if budget <= 0.0 {
+                    return Err("SPEND_DAILY_BUDGET_USD must be positive".to_string());
+                }
+                self.daily_usd = Some(budget);
+            }
+        }
+
+        if let Ok(value) = env::var("SPEND_BUDGET_ACTION") {
+            self.action = value
+                .parse()
+                .map_err(|e| format!("SPEND_BUDGET_ACTION is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("SPEND_FALLBACK_MODEL") {
+            if !value.trim().is_empty() {
+                self.fallback_model = value.trim().to_string();
+            }
+        }
+
+        self.alert_webhook = env::var("ALERT_WEBHOOK_URL")
+            .ok()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MarkdownMode {
+    #[default]
+    Keep,
+    Strip,
+    Normalize,
+}
+impl FromStr for MarkdownMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" => Ok(MarkdownMode::Keep),
+            "strip" => Ok(MarkdownMode::Strip),
+            "normalize" => Ok(MarkdownMode::Normalize),
+            other => Err(format!("Unknown markdown mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InjectionPolicy {
+    Allow,
+    Flag,
+    #[default]
+    Block,
+}
+impl FromStr for InjectionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "allow" => Ok(InjectionPolicy::Allow),
+            "flag" => Ok(InjectionPolicy::Flag),
+            "block" => Ok(InjectionPolicy::Block),
+            other => Err(format!("Unknown injection policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChatConfig {
+    pub markdown_mode: MarkdownMode,
+    pub injection_policy: InjectionPolicy,
+    pub system_preamble: Option<String>,
+    pub title_max_words: usize,
+    pub title_max_chars: usize,
+    /// Model that names a conversation after its first exchange; `None` keeps the word heuristic.
+    pub title_model: Option<String>,
+}
+impl Default for ChatConfig {
+    fn default() -> Self {
+        ChatConfig {
+            markdown_mode: MarkdownMode::default(),
+            injection_policy: InjectionPolicy::default(),
+            system_preamble: None,
+            title_max_words: 3,
+            title_max_chars: 30,
+            title_model: Some(String::from("gpt-4o-mini")),
+        }
+    }
+}
+impl ChatConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("CHAT_MARKDOWN_MODE") {
+            self.markdown_mode = value
+                .parse()
+                .map_err(|e| format!("CHAT_MARKDOWN_MODE is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("CHAT_INJECTION_POLICY") {
+            self.injection_policy = value
+                .parse()
+                .map_err(|e| format!("CHAT_INJECTION_POLICY is not valid: {}", e))?;
+        }
+
+        if let Ok(path) = env::var("CHAT_SYSTEM_PREAMBLE_FILE") {
+            if !path.trim().is_empty() {
+                let preamble = std::fs::read_to_string(path.trim())
+                    .map_err(|e| format!("CHAT_SYSTEM_PREAMBLE_FILE could not be read: {}", e))?;
+                self.system_preamble = Some(preamble);
+            }
+        } else if let Ok(preamble) = env::var("CHAT_SYSTEM_PREAMBLE") {
+            self.system_preamble = Some(preamble.replace("\\n", "\n"));
+        }
+        if let Ok(value) = env::var("CHAT_TITLE_MAX_WORDS") {
+            self.title_max_words = value
+                .parse::<usize>()
+                .map_err(|_| "CHAT_TITLE_MAX_WORDS is not a valid usize".to_string())?;
+            if self.title_max_words == 0 {
+                return Err("CHAT_TITLE_MAX_WORDS must be positive".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("CHAT_TITLE_MAX_CHARS") {
+            self.title_max_chars = value
+                .parse::<usize>()
+                .map_err(|_| "CHAT_TITLE_MAX_CHARS is not a valid usize".to_string())?;
+            if self.title_max_chars == 0 {
+                return Err("CHAT_TITLE_MAX_CHARS must be positive".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("CHAT_TITLE_MODEL") {
+            self.title_model = Some(value.trim().to_string()).filter(|model| !model.is_empty());
+        }
+
+        self.system_preamble = self
+            .system_preamble
+            .take()
+            .map(|preamble| preamble.trim().to_string())
+            .filter(|preamble| !preamble.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::providers::ModelCapabilities;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 
@@ -10,4 +11,44 @@ lazy_static! {
         m.insert("gpt-4o-mini", 1);
         m
     };
+    // Credits charged per 1K prompt and completion tokens.
+    pub static ref MODEL_TO_CREDITS_PER_1K_TOKENS: HashMap<&'static str, (f64, f64)> = {
+        let mut m = HashMap::new();
+        m.insert("gpt-4o", (5.0, 15.0));
+        m.insert("gpt-4o-2024-05-13", (5.0, 15.0));
+        m.insert("gpt-4o-2024-08-06", (5.0, 15.0));
+        m.insert("gpt-4o-mini", (0.15, 0.6));
+        m
+    };
+    // Blended input/output list prices, used to estimate upstream spend.
+    pub static ref MODEL_TO_USD_PER_1K_TOKENS: HashMap<&'static str, f64> = {
+        let mut m = HashMap::new();
+        m.insert("gpt-4o", 0.01);
+        m.insert("gpt-4o-2024-05-13", 0.01);
+        m.insert("gpt-4o-2024-08-06", 0.01);
+        m.insert("gpt-4o-mini", 0.0004);
+        m
+    };
 }
+
+// Every model in MODEL_TO_PRICE belongs to the gpt-4o family.
+pub const OPENAI_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: true,
+    audio_input: false,
+    tools: true,
+    json_mode: true,
+    streaming: true,
+    max_context: Some(128_000),
+};
+
+pub const IMAGE_USD: f64 = 0.04;
+pub const IMAGE_GENERATION_MODEL: &str = "dall-e-3";
+pub const IMAGE_GENERATION_CREDITS: i64 = 20;
+pub const SPEECH_USD_PER_1K_CHARS: f64 = 0.015;
+pub const TRANSCRIPTION_USD_PER_MINUTE: f64 = 0.006;
+
+pub const EMBEDDING_MODELS: [&str; 3] = [
+    "text-embedding-3-small",
+    "text-embedding-3-large",
+    "text-embedding-ada-002",
+];
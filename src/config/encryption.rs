@@ -0,0 +1,47 @@
+use base64::prelude::*;
+use std::env;
+
+pub const MESSAGE_KEY_LEN: usize = 32;
+
+#[derive(Clone, Debug, Default)]
+pub struct EncryptionConfig {
+    /// Base64 AES-256 key for message content and transcriptions. Messages
+    /// are stored in plain text when it is unset.
+    pub message_key: Option<String>,
+}
+impl EncryptionConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        self.message_key = env::var("MESSAGE_ENCRYPTION_KEY")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        // A file lets the key be mounted by a KMS or secret manager instead of living in the env.
+        if let Ok(path) = env::var("MESSAGE_ENCRYPTION_KEY_FILE") {
+            if !path.trim().is_empty() {
+                if self.message_key.is_some() {
+                    return Err(
+                        "Set only one of MESSAGE_ENCRYPTION_KEY and MESSAGE_ENCRYPTION_KEY_FILE"
+                            .to_string(),
+                    );
+                }
+                let key = std::fs::read_to_string(path.trim())
+                    .map_err(|e| format!("MESSAGE_ENCRYPTION_KEY_FILE could not be read: {}", e))?;
+                self.message_key = Some(key.trim().to_string());
+            }
+        }
+
+        if let Some(key) = self.message_key.as_deref() {
+            let decoded = BASE64_STANDARD
+                .decode(key.trim())
+                .map_err(|_| "The message encryption key is not valid base64".to_string())?;
+            if decoded.len() != MESSAGE_KEY_LEN {
+                return Err(format!(
+                    "The message encryption key must be {} bytes long",
+                    MESSAGE_KEY_LEN
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
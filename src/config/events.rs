@@ -0,0 +1,70 @@
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusKind {
+    Nats,
+    /// Published through a Kafka REST proxy, so no native client is needed.
+    Kafka,
+}
+impl FromStr for BusKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "nats" => Ok(BusKind::Nats),
+            "kafka" => Ok(BusKind::Kafka),
+            other => Err(format!("Unknown event bus kind: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EventBusConfig {
+    pub kind: Option<BusKind>,
+    pub url: String,
+    pub subject_prefix: String,
+}
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        EventBusConfig {
+            kind: None,
+            url: String::new(),
+            subject_prefix: String::from("weagi"),
+        }
+    }
+}
+impl EventBusConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        let Some(kind) = env::var("EVENT_BUS_KIND")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        else {
+            return Ok(());
+        };
+        self.kind = Some(
+            kind.parse()
+                .map_err(|e| format!("EVENT_BUS_KIND is not valid: {}", e))?,
+        );
+
+        self.url = env::var("EVENT_BUS_URL")
+            .ok()
+            .map(|v| v.trim().trim_end_matches('/').to_string())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| "EVENT_BUS_URL not set in environment".to_string())?;
+
+        if let Ok(value) = env::var("EVENT_BUS_SUBJECT_PREFIX") {
+            self.subject_prefix = value.trim().trim_end_matches('.').to_string();
+        }
+
+        Ok(())
+    }
+
+    /// The NATS subject or Kafka topic of a domain event.
+    pub fn subject(&self, event: &str) -> String {
+        if self.subject_prefix.is_empty() {
+            event.to_string()
+        } else {
+            format!("{}.{}", self.subject_prefix, event)
+        }
+    }
+}
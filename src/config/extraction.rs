@@ -0,0 +1,42 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct ExtractionConfig {
+    pub model: String,
+    pub max_attempts: u32,
+    pub action_items_webhook: Option<String>,
+}
+impl Default for ExtractionConfig {
+    fn default() -> Self {
+        ExtractionConfig {
+            model: String::from("gpt-4o-mini"),
+            max_attempts: 3,
+            action_items_webhook: None,
+        }
+    }
+}
+impl ExtractionConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("EXTRACTION_MODEL") {
+            if value.trim().is_empty() {
+                return Err("EXTRACTION_MODEL must not be empty".to_string());
+            }
+            self.model = value.trim().to_string();
+        }
+
+        if let Ok(value) = env::var("EXTRACTION_MAX_ATTEMPTS") {
+            self.max_attempts = value
+                .parse::<u32>()
+                .map_err(|_| "EXTRACTION_MAX_ATTEMPTS is not a valid u32".to_string())?;
+            if self.max_attempts == 0 {
+                return Err("EXTRACTION_MAX_ATTEMPTS must be at least 1".to_string());
+            }
+        }
+
+        self.action_items_webhook = env::var("ACTION_ITEMS_WEBHOOK_URL")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        Ok(())
+    }
+}
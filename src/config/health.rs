@@ -0,0 +1,36 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct HealthConfig {
+    /// Seconds between provider probes after the startup one; 0 probes only at startup.
+    pub probe_interval_secs: u64,
+    pub probe_timeout_secs: u64,
+}
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            probe_interval_secs: 60,
+            probe_timeout_secs: 10,
+        }
+    }
+}
+impl HealthConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("HEALTH_PROBE_INTERVAL_SECS") {
+            self.probe_interval_secs = value
+                .parse::<u64>()
+                .map_err(|_| "HEALTH_PROBE_INTERVAL_SECS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("HEALTH_PROBE_TIMEOUT_SECS") {
+            self.probe_timeout_secs = value
+                .parse::<u64>()
+                .map_err(|_| "HEALTH_PROBE_TIMEOUT_SECS is not a valid u64".to_string())?;
+            if self.probe_timeout_secs == 0 {
+                return Err("HEALTH_PROBE_TIMEOUT_SECS must be at least 1".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
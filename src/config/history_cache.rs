@@ -0,0 +1,23 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct HistoryCacheConfig {
+    /// Conversations kept in memory at once; 0 turns the cache off.
+    pub capacity: usize,
+}
+impl Default for HistoryCacheConfig {
+    fn default() -> Self {
+        HistoryCacheConfig { capacity: 1_000 }
+    }
+}
+impl HistoryCacheConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("HISTORY_CACHE_CAPACITY") {
+            self.capacity = value
+                .parse::<usize>()
+                .map_err(|_| "HISTORY_CACHE_CAPACITY is not a valid usize".to_string())?;
+        }
+
+        Ok(())
+    }
+}
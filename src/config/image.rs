@@ -0,0 +1,54 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct ImageConfig {
+    pub prompt_enhancer_model: String,
+    pub cache_dir: String,
+    /// How long a generated image is reused for the same prompt; 0 disables the cache.
+    pub cache_ttl_secs: u64,
+    pub cache_hit_credits: i64,
+}
+impl Default for ImageConfig {
+    fn default() -> Self {
+        ImageConfig {
+            prompt_enhancer_model: String::from("gpt-4o-mini"),
+            cache_dir: String::from("./cache/images"),
+            cache_ttl_secs: 7 * 24 * 60 * 60,
+            cache_hit_credits: 0,
+        }
+    }
+}
+impl ImageConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("IMAGE_PROMPT_ENHANCER_MODEL") {
+            if value.trim().is_empty() {
+                return Err("IMAGE_PROMPT_ENHANCER_MODEL must not be empty".to_string());
+            }
+            self.prompt_enhancer_model = value.trim().to_string();
+        }
+
+        if let Ok(value) = env::var("IMAGE_CACHE_DIR") {
+            if value.trim().is_empty() {
+                return Err("IMAGE_CACHE_DIR must not be empty".to_string());
+            }
+            self.cache_dir = value.trim().trim_end_matches('/').to_string();
+        }
+
+        if let Ok(value) = env::var("IMAGE_CACHE_TTL_SECS") {
+            self.cache_ttl_secs = value
+                .parse::<u64>()
+                .map_err(|_| "IMAGE_CACHE_TTL_SECS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("IMAGE_CACHE_HIT_CREDITS") {
+            self.cache_hit_credits = value
+                .parse::<i64>()
+                .map_err(|_| "IMAGE_CACHE_HIT_CREDITS is not a valid i64".to_string())?;
+            if self.cache_hit_credits < 0 {
+                return Err("IMAGE_CACHE_HIT_CREDITS must not be negative".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -5,6 +5,9 @@ pub struct JWTConfig {
     pub access_token_expired_date: u64,
     pub refresh_token_secret: String,
     pub access_token_secret: String,
+    pub support_role: String,
+    /// Lifetime of the conversation-scoped tokens handed to embedded widgets.
+    pub widget_token_ttl_secs: u64,
 }
 impl JWTConfig {
     pub fn init_from_env(&mut self) -> Result<(), String> {
@@ -24,6 +27,22 @@ impl JWTConfig {
         self.access_token_secret = env::var("JWT_ACCESS_TOKEN_SECRET")
             .map_err(|_| "JWT_ACCESS_TOKEN_SECRET not set in environment".to_string())?;
 
+        self.support_role = env::var("JWT_SUPPORT_ROLE")
+            .ok()
+            .map(|role| role.trim().to_string())
+            .filter(|role| !role.is_empty())
+            .unwrap_or_else(|| String::from("support"));
+
+        self.widget_token_ttl_secs = match env::var("JWT_WIDGET_TOKEN_TTL_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| "JWT_WIDGET_TOKEN_TTL_SECS is not a valid u64".to_string())?,
+            Err(_) => 900,
+        };
+        if self.widget_token_ttl_secs == 0 {
+            return Err("JWT_WIDGET_TOKEN_TTL_SECS must be at least 1".to_string());
+        }
+
         Ok(())
     }
 }
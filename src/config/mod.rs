@@ -1,30 +1,117 @@
+pub mod auto_model;
+pub mod budget;
+pub mod chat;
 pub mod constant;
 pub mod db;
 pub mod deepgram;
+pub mod encryption;
+pub mod events;
+pub mod extraction;
+pub mod health;
+pub mod history_cache;
+pub mod image;
 pub mod jwt;
+pub mod moderation;
 pub mod openai;
+pub mod outbox;
+pub mod postprocess;
+pub mod providers;
+pub mod proxy;
+pub mod queue;
+pub mod rag;
+pub mod rate_limit;
+pub mod redaction;
+pub mod residency;
+pub mod retention;
+pub mod retry;
 pub mod server;
+pub mod session;
+pub mod slo;
+pub mod style;
+pub mod tools;
+pub mod trace_sampling;
 pub mod tracing;
+pub mod transcription;
+pub mod tts;
+pub mod upload;
 
 use dotenv::dotenv;
 
 #[derive(Clone, Default, Debug)]
 pub struct ServiceConfig {
+    pub chat: chat::ChatConfig,
     pub db: db::DatabaseConfig,
     pub server: server::ServerConfig,
     pub jwt: jwt::JWTConfig,
     pub openai: openai::OpenAIConfig,
     pub deepgram: deepgram::DeepgramConfig,
+    pub tts: tts::TtsConfig,
+    pub retention: retention::RetentionConfig,
+    pub image: image::ImageConfig,
+    pub rag: rag::RagConfig,
+    pub tools: tools::ToolsConfig,
+    pub extraction: extraction::ExtractionConfig,
+    pub upload: upload::UploadConfig,
+    pub rate_limit: rate_limit::RateLimitConfig,
+    pub budget: budget::BudgetConfig,
+    pub providers: providers::ProvidersConfig,
+    pub proxy: proxy::ProxyConfig,
+    pub queue: queue::QueueConfig,
+    pub slo: slo::SloConfig,
+    pub outbox: outbox::OutboxConfig,
+    pub style: style::StyleConfig,
+    pub encryption: encryption::EncryptionConfig,
+    pub residency: residency::ResidencyConfig,
+    pub events: events::EventBusConfig,
+    pub session: session::SessionConfig,
+    pub redaction: redaction::RedactionConfig,
+    pub postprocess: postprocess::PostProcessConfig,
+    pub retry: retry::RetryConfig,
+    pub health: health::HealthConfig,
+    pub transcription: transcription::TranscriptionConfig,
+    pub auto_model: auto_model::AutoModelConfig,
+    pub moderation: moderation::ModerationConfig,
+    pub trace_sampling: trace_sampling::TraceSamplingConfig,
+    pub history_cache: history_cache::HistoryCacheConfig,
 }
 
 impl ServiceConfig {
     pub fn init_from_env(&mut self) -> Result<(), String> {
         dotenv().ok();
+        self.chat.init_from_env()?;
         self.db.init_from_env()?;
         self.server.init_from_env()?;
         self.jwt.init_from_env()?;
         self.openai.init_from_env()?;
         self.deepgram.init_from_env()?;
+        self.tts.init_from_env()?;
+        self.retention.init_from_env()?;
+        self.image.init_from_env()?;
+        self.rag.init_from_env()?;
+        self.tools.init_from_env()?;
+        self.extraction.init_from_env()?;
+        self.upload.init_from_env()?;
+        self.rate_limit.init_from_env()?;
+        self.budget.init_from_env()?;
+        self.providers.init_from_env()?;
+        self.proxy.init_from_env()?;
+        self.queue.init_from_env()?;
+        self.slo.init_from_env()?;
+        self.outbox.init_from_env()?;
+        self.style.init_from_env()?;
+        self.encryption.init_from_env()?;
+        self.residency.init_from_env()?;
+        self.events.init_from_env()?;
+        self.session.init_from_env()?;
+        self.redaction.init_from_env()?;
+        self.postprocess.init_from_env()?;
+        self.retry.init_from_env()?;
+        self.health.init_from_env()?;
+        self.transcription.init_from_env()?;
+        self.auto_model.init_from_env()?;
+        self.moderation.init_from_env()?;
+        self.trace_sampling.init_from_env()?;
+        self.history_cache.init_from_env()?;
         Ok(())
     }
 }
@@ -0,0 +1,54 @@
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ModerationMode {
+    #[default]
+    Off,
+    /// Flagged messages are answered, and their flags stored with them.
+    Log,
+    /// Flagged messages are refused before they reach the model.
+    Block,
+}
+impl FromStr for ModerationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(ModerationMode::Off),
+            "log" => Ok(ModerationMode::Log),
+            "block" => Ok(ModerationMode::Block),
+            other => Err(format!("Unknown moderation mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ModerationConfig {
+    pub mode: ModerationMode,
+    pub model: String,
+}
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        ModerationConfig {
+            mode: ModerationMode::Off,
+            model: "omni-moderation-latest".to_string(),
+        }
+    }
+}
+impl ModerationConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("MODERATION_MODE") {
+            self.mode = value
+                .parse()
+                .map_err(|e| format!("MODERATION_MODE is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("MODERATION_MODEL") {
+            if !value.trim().is_empty() {
+                self.model = value.trim().to_string();
+            }
+        }
+
+        Ok(())
+    }
+}
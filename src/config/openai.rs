@@ -1,12 +1,89 @@
-use std::env;
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyStrategy {
+    #[default]
+    RoundRobin,
+    LeastErrors,
+}
+impl FromStr for KeyStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "round-robin" | "round_robin" => Ok(KeyStrategy::RoundRobin),
+            "least-errors" | "least_errors" => Ok(KeyStrategy::LeastErrors),
+            other => Err(format!("Unknown key strategy: {}", other)),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct OpenAIConfig {
-    pub openai_key: String,
+    pub openai_keys: Vec<String>,
+    pub key_strategy: KeyStrategy,
+    pub key_quarantine_secs: u64,
+    pub key_cooldown_secs: u64,
+    pub org_id: Option<String>,
+    pub connect_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+    pub proxy_url: Option<String>,
 }
 impl OpenAIConfig {
     pub fn init_from_env(&mut self) -> Result<(), String> {
-        self.openai_key =
-            env::var("OPENAI_KEY").map_err(|_| "OPENAI_KEY not set in environment".to_string())?;
+        self.openai_keys = match env::var("OPENAI_KEYS") {
+            Ok(value) if !value.trim().is_empty() => value
+                .split(',')
+                .map(|key| key.trim().to_string())
+                .filter(|key| !key.is_empty())
+                .collect(),
+            _ => vec![env::var("OPENAI_KEY")
+                .map_err(|_| "OPENAI_KEY not set in environment".to_string())?],
+        };
+        if self.openai_keys.is_empty() {
+            return Err("OPENAI_KEYS does not contain any key".to_string());
+        }
+
+        if let Ok(value) = env::var("OPENAI_KEY_STRATEGY") {
+            self.key_strategy = value
+                .parse()
+                .map_err(|e| format!("OPENAI_KEY_STRATEGY is not valid: {}", e))?;
+        }
+
+        self.key_quarantine_secs = match env::var("OPENAI_KEY_QUARANTINE_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| "OPENAI_KEY_QUARANTINE_SECS is not a valid u64".to_string())?,
+            Err(_) => 3600,
+        };
+
+        self.key_cooldown_secs = match env::var("OPENAI_KEY_COOLDOWN_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| "OPENAI_KEY_COOLDOWN_SECS is not a valid u64".to_string())?,
+            Err(_) => 60,
+        };
+
+        self.org_id = env::var("OPENAI_ORG_ID").ok().filter(|v| !v.is_empty());
+
+        self.connect_timeout_secs = match env::var("OPENAI_CONNECT_TIMEOUT_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| "OPENAI_CONNECT_TIMEOUT_SECS is not a valid u64".to_string())?,
+            Err(_) => 10,
+        };
+
+        self.request_timeout_secs = match env::var("OPENAI_REQUEST_TIMEOUT_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| "OPENAI_REQUEST_TIMEOUT_SECS is not a valid u64".to_string())?,
+            Err(_) => 120,
+        };
+
+        self.proxy_url = env::var("OPENAI_PROXY_URL")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
 
         Ok(())
     }
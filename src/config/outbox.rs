@@ -0,0 +1,67 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct OutboxConfig {
+    pub message_events_webhook: Option<String>,
+    /// Signs webhook deliveries. Kept apart from the auth service secret, as
+    /// webhook receivers are third parties.
+    pub webhook_secret_key: Option<String>,
+    pub dispatch_interval_secs: u64,
+    pub batch_size: u64,
+    pub max_attempts: i32,
+    pub retry_base_secs: u64,
+}
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        OutboxConfig {
+            message_events_webhook: None,
+            webhook_secret_key: None,
+            dispatch_interval_secs: 10,
+            batch_size: 50,
+            max_attempts: 10,
+            retry_base_secs: 10,
+        }
+    }
+}
+impl OutboxConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        self.message_events_webhook = env::var("MESSAGE_EVENTS_WEBHOOK_URL")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+        self.webhook_secret_key = env::var("WEBHOOK_SECRET_KEY")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        if let Ok(value) = env::var("OUTBOX_DISPATCH_INTERVAL_SECS") {
+            self.dispatch_interval_secs = value
+                .parse::<u64>()
+                .map_err(|_| "OUTBOX_DISPATCH_INTERVAL_SECS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("OUTBOX_BATCH_SIZE") {
+            self.batch_size = value
+                .parse::<u64>()
+                .map_err(|_| "OUTBOX_BATCH_SIZE is not a valid u64".to_string())?;
+            if self.batch_size == 0 {
+                return Err("OUTBOX_BATCH_SIZE must be at least 1".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("OUTBOX_MAX_ATTEMPTS") {
+            self.max_attempts = value
+                .parse::<i32>()
+                .map_err(|_| "OUTBOX_MAX_ATTEMPTS is not a valid i32".to_string())?;
+            if self.max_attempts <= 0 {
+                return Err("OUTBOX_MAX_ATTEMPTS must be at least 1".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("OUTBOX_RETRY_BASE_SECS") {
+            self.retry_base_secs = value
+                .parse::<u64>()
+                .map_err(|_| "OUTBOX_RETRY_BASE_SECS is not a valid u64".to_string())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::{env, str::FromStr};
+
+/// One transformation applied to streamed reply text, in the configured order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostProcessStage {
+    /// Applies the request's markdown mode.
+    Markdown,
+    Profanity,
+    /// Sends bare links through `link_prefix`.
+    Links,
+    /// Appends `watermark` once the reply is complete.
+    Watermark,
+}
+impl FromStr for PostProcessStage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "markdown" => Ok(PostProcessStage::Markdown),
+            "profanity" => Ok(PostProcessStage::Profanity),
+            "links" => Ok(PostProcessStage::Links),
+            "watermark" => Ok(PostProcessStage::Watermark),
+            other => Err(format!("Unknown post-processing stage: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PostProcessConfig {
+    pub stages: Vec<PostProcessStage>,
+    pub profanity_words: Vec<String>,
+    /// Prepended to each percent-encoded link, e.g. `https://go.example.com/?url=`.
+    pub link_prefix: Option<String>,
+    pub watermark: Option<String>,
+}
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        PostProcessConfig {
+            stages: vec![PostProcessStage::Markdown],
+            profanity_words: Vec::new(),
+            link_prefix: None,
+            watermark: None,
+        }
+    }
+}
+impl PostProcessConfig {
+    // Reads CHAT_POST_PROCESSORS=markdown,profanity,links,watermark; a stage whose
+    // setting is missing is rejected rather than silently doing nothing.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("CHAT_POST_PROCESSORS") {
+            let mut stages = Vec::new();
+            for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                let stage = name
+                    .parse::<PostProcessStage>()
+                    .map_err(|e| format!("CHAT_POST_PROCESSORS is not valid: {}", e))?;
+                if stages.contains(&stage) {
+                    return Err(format!("CHAT_POST_PROCESSORS lists '{}' twice", name));
+                }
+                stages.push(stage);
+            }
+            self.stages = stages;
+        }
+
+        if let Ok(value) = env::var("POSTPROCESS_PROFANITY_WORDS") {
+            self.profanity_words = value
+                .split(',')
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect();
+        }
+
+        if let Ok(value) = env::var("POSTPROCESS_LINK_PREFIX") {
+            self.link_prefix = Some(value.trim().to_string()).filter(|prefix| !prefix.is_empty());
+        }
+
+        if let Ok(value) = env::var("POSTPROCESS_WATERMARK") {
+            self.watermark = Some(value.replace("\\n", "\n")).filter(|w| !w.trim().is_empty());
+        }
+
+        for stage in &self.stages {
+            let missing = match stage {
+                PostProcessStage::Profanity if self.profanity_words.is_empty() => {
+                    Some("POSTPROCESS_PROFANITY_WORDS")
+                }
+                PostProcessStage::Links if self.link_prefix.is_none() => {
+                    Some("POSTPROCESS_LINK_PREFIX")
+                }
+                PostProcessStage::Watermark if self.watermark.is_none() => {
+                    Some("POSTPROCESS_WATERMARK")
+                }
+                _ => None,
+            };
+            if let Some(name) = missing {
+                return Err(format!("{} must be set for the {:?} stage", name, stage));
+            }
+        }
+
+        Ok(())
+    }
+}
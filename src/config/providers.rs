@@ -0,0 +1,152 @@
+use serde::Serialize;
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProviderKind {
+    OpenAI,
+    Azure,
+    Compatible,
+}
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" => Ok(ProviderKind::OpenAI),
+            "azure" => Ok(ProviderKind::Azure),
+            "compatible" | "vllm" | "ollama" => Ok(ProviderKind::Compatible),
+            other => Err(format!("Unknown provider kind: {}", other)),
+        }
+    }
+}
+
+/// What a chat model accepts, so clients can enable features per model.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct ModelCapabilities {
+    pub vision: bool,
+    pub audio_input: bool,
+    pub tools: bool,
+    pub json_mode: bool,
+    pub streaming: bool,
+    /// Context window in tokens, when known.
+    pub max_context: Option<u32>,
+}
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        ModelCapabilities {
+            vision: false,
+            audio_input: false,
+            tools: false,
+            json_mode: false,
+            streaming: true,
+            max_context: None,
+        }
+    }
+}
+impl ModelCapabilities {
+    // Parses a comma-separated list of the flags a model has, e.g. "vision,tools".
+    fn from_flags(flags: &str) -> Result<Self, String> {
+        let mut capabilities = ModelCapabilities {
+            streaming: false,
+            ..Default::default()
+        };
+        for flag in flags.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            match flag.to_lowercase().as_str() {
+                "vision" => capabilities.vision = true,
+                "audio_input" | "audio-in" => capabilities.audio_input = true,
+                "tools" => capabilities.tools = true,
+                "json_mode" | "json" => capabilities.json_mode = true,
+                "streaming" => capabilities.streaming = true,
+                other => return Err(format!("Unknown model capability: {}", other)),
+            }
+        }
+        Ok(capabilities)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProviderEntry {
+    pub name: String,
+    pub kind: ProviderKind,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub api_version: Option<String>,
+    pub models: Vec<String>,
+    pub credits: i64,
+    pub proxy: Option<String>,
+    pub capabilities: ModelCapabilities,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ProvidersConfig {
+    pub entries: Vec<ProviderEntry>,
+}
+impl ProvidersConfig {
+    // Reads LLM_PROVIDERS=name,... and, for each name,
+    // LLM_PROVIDER_<NAME>_{KIND,BASE_URL,API_KEY,API_VERSION,MODELS,CREDITS,PROXY,CAPABILITIES,MAX_CONTEXT}.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        let Ok(names) = env::var("LLM_PROVIDERS") else {
+            return Ok(());
+        };
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let prefix = format!("LLM_PROVIDER_{}", name.to_uppercase());
+            let var = |suffix: &str| {
+                env::var(format!("{}_{}", prefix, suffix))
+                    .ok()
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+            };
+
+            let kind: ProviderKind = var("KIND")
+                .ok_or_else(|| format!("{}_KIND not set in environment", prefix))?
+                .parse()
+                .map_err(|e| format!("{}_KIND is not valid: {}", prefix, e))?;
+            let base_url = match (var("BASE_URL"), kind) {
+                (Some(url), _) => url.trim_end_matches('/').to_string(),
+                (None, ProviderKind::OpenAI) => String::from("https://api.openai.com/v1"),
+                (None, _) => return Err(format!("{}_BASE_URL not set in environment", prefix)),
+            };
+            let api_version = var("API_VERSION");
+            if kind == ProviderKind::Azure && api_version.is_none() {
+                return Err(format!("{}_API_VERSION not set in environment", prefix));
+            }
+            let models: Vec<String> = var("MODELS")
+                .ok_or_else(|| format!("{}_MODELS not set in environment", prefix))?
+                .split(',')
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .collect();
+            let credits = match var("CREDITS") {
+                Some(value) => value
+                    .parse::<i64>()
+                    .map_err(|_| format!("{}_CREDITS is not a valid i64", prefix))?,
+                None => 1,
+            };
+            let mut capabilities = match var("CAPABILITIES") {
+                Some(flags) => ModelCapabilities::from_flags(&flags)
+                    .map_err(|e| format!("{}_CAPABILITIES is not valid: {}", prefix, e))?,
+                None => ModelCapabilities::default(),
+            };
+            if let Some(value) = var("MAX_CONTEXT") {
+                capabilities.max_context = Some(
+                    value
+                        .parse::<u32>()
+                        .map_err(|_| format!("{}_MAX_CONTEXT is not a valid u32", prefix))?,
+                );
+            }
+
+            self.entries.push(ProviderEntry {
+                name: name.to_string(),
+                kind,
+                base_url,
+                api_key: var("API_KEY"),
+                api_version,
+                models,
+                credits,
+                proxy: var("PROXY"),
+                capabilities,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use std::env;
+
+#[derive(Clone, Debug, Default)]
+pub struct ProxyConfig {
+    pub url: Option<String>,
+    pub no_proxy: Option<String>,
+}
+impl ProxyConfig {
+    // OUTBOUND_PROXY_URL accepts http://, https:// and socks5:// URLs, optionally with user:password@.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        self.url = env::var("OUTBOUND_PROXY_URL")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        self.no_proxy = env::var("OUTBOUND_NO_PROXY")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        Ok(())
+    }
+}
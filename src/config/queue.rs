@@ -0,0 +1,36 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct QueueConfig {
+    pub max_concurrent: usize,
+    pub max_waiting: usize,
+}
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            max_concurrent: 0,
+            max_waiting: 100,
+        }
+    }
+}
+impl QueueConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("CHAT_MAX_CONCURRENT_REQUESTS") {
+            self.max_concurrent = value
+                .parse::<usize>()
+                .map_err(|_| "CHAT_MAX_CONCURRENT_REQUESTS is not a valid usize".to_string())?;
+        }
+
+        if let Ok(value) = env::var("CHAT_QUEUE_MAX_WAITING") {
+            self.max_waiting = value
+                .parse::<usize>()
+                .map_err(|_| "CHAT_QUEUE_MAX_WAITING is not a valid usize".to_string())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_concurrent > 0
+    }
+}
@@ -0,0 +1,118 @@
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FusionStrategy {
+    #[default]
+    Vector,
+    ReciprocalRank,
+    Weighted,
+}
+impl FromStr for FusionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "vector" => Ok(FusionStrategy::Vector),
+            "rrf" => Ok(FusionStrategy::ReciprocalRank),
+            "weighted" => Ok(FusionStrategy::Weighted),
+            other => Err(format!("Unknown fusion strategy: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RagConfig {
+    pub default_embedding_model: String,
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+    pub top_k: u64,
+    pub fusion: FusionStrategy,
+    pub rrf_k: f32,
+    pub keyword_weight: f32,
+    pub candidate_multiplier: u64,
+    pub text_search_config: String,
+}
+impl Default for RagConfig {
+    fn default() -> Self {
+        RagConfig {
+            default_embedding_model: String::from("text-embedding-3-small"),
+            chunk_size: 1200,
+            chunk_overlap: 200,
+            top_k: 5,
+            fusion: FusionStrategy::Vector,
+            rrf_k: 60.0,
+            keyword_weight: 0.3,
+            candidate_multiplier: 4,
+            text_search_config: String::from("english"),
+        }
+    }
+}
+impl RagConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("RAG_EMBEDDING_MODEL") {
+            if !value.trim().is_empty() {
+                self.default_embedding_model = value.trim().to_string();
+            }
+        }
+
+        if let Ok(value) = env::var("RAG_CHUNK_SIZE") {
+            self.chunk_size = value
+                .parse::<usize>()
+                .map_err(|_| "RAG_CHUNK_SIZE is not a valid usize".to_string())?;
+        }
+
+        if let Ok(value) = env::var("RAG_CHUNK_OVERLAP") {
+            self.chunk_overlap = value
+                .parse::<usize>()
+                .map_err(|_| "RAG_CHUNK_OVERLAP is not a valid usize".to_string())?;
+        }
+        if self.chunk_overlap >= self.chunk_size {
+            return Err("RAG_CHUNK_OVERLAP must be less than RAG_CHUNK_SIZE".to_string());
+        }
+
+        if let Ok(value) = env::var("RAG_TOP_K") {
+            self.top_k = value
+                .parse::<u64>()
+                .map_err(|_| "RAG_TOP_K is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("RAG_FUSION") {
+            self.fusion = value
+                .parse()
+                .map_err(|e| format!("RAG_FUSION is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("RAG_RRF_K") {
+            self.rrf_k = value
+                .parse::<f32>()
+                .map_err(|_| "RAG_RRF_K is not a valid f32".to_string())?;
+            if self.rrf_k <= 0.0 {
+                return Err("RAG_RRF_K must be positive".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("RAG_KEYWORD_WEIGHT") {
+            self.keyword_weight = value
+                .parse::<f32>()
+                .map_err(|_| "RAG_KEYWORD_WEIGHT is not a valid f32".to_string())?;
+            if !(0.0..=1.0).contains(&self.keyword_weight) {
+                return Err("RAG_KEYWORD_WEIGHT must be between 0 and 1".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("RAG_CANDIDATE_MULTIPLIER") {
+            self.candidate_multiplier = value
+                .parse::<u64>()
+                .map_err(|_| "RAG_CANDIDATE_MULTIPLIER is not a valid u64".to_string())?
+                .max(1);
+        }
+
+        if let Ok(value) = env::var("RAG_TEXT_SEARCH_CONFIG") {
+            if !value.trim().is_empty() {
+                self.text_search_config = value.trim().to_string();
+            }
+        }
+
+        Ok(())
+    }
+}
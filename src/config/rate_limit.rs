@@ -0,0 +1,40 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// Requests a user may make per window; 0, the default, turns limiting off.
+    pub requests: u32,
+    pub window_secs: u64,
+}
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            requests: 0,
+            window_secs: 60,
+        }
+    }
+}
+impl RateLimitConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("RATE_LIMIT_REQUESTS") {
+            self.requests = value
+                .parse::<u32>()
+                .map_err(|_| "RATE_LIMIT_REQUESTS is not a valid u32".to_string())?;
+        }
+
+        if let Ok(value) = env::var("RATE_LIMIT_WINDOW_SECS") {
+            self.window_secs = value
+                .parse::<u64>()
+                .map_err(|_| "RATE_LIMIT_WINDOW_SECS is not a valid u64".to_string())?;
+            if self.window_secs == 0 {
+                return Err("RATE_LIMIT_WINDOW_SECS must be positive".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.requests > 0
+    }
+}
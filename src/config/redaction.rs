@@ -0,0 +1,97 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    str::FromStr,
+};
+
+/// Kinds of personal data that can be masked before a prompt leaves the service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PiiKind {
+    Email,
+    Phone,
+    Card,
+    /// Person names, found by the external NER service.
+    Name,
+}
+impl FromStr for PiiKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "email" => Ok(PiiKind::Email),
+            "phone" => Ok(PiiKind::Phone),
+            "card" => Ok(PiiKind::Card),
+            "name" => Ok(PiiKind::Name),
+            other => Err(format!("Unknown personal data kind: {}", other)),
+        }
+    }
+}
+impl PiiKind {
+    pub fn placeholder(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "[EMAIL]",
+            PiiKind::Phone => "[PHONE]",
+            PiiKind::Card => "[CARD]",
+            PiiKind::Name => "[NAME]",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RedactionConfig {
+    pub default_kinds: BTreeSet<PiiKind>,
+    pub organization_kinds: HashMap<String, BTreeSet<PiiKind>>,
+    pub ner_url: Option<String>,
+}
+impl RedactionConfig {
+    // Reads REDACTION_KINDS=email,phone,... and ORGANIZATION_REDACTION=org=email|card,...
+    // where `none` turns redaction off for an organization, plus REDACTION_NER_URL.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("REDACTION_KINDS") {
+            self.default_kinds = parse_kinds(value.split(','))
+                .map_err(|e| format!("REDACTION_KINDS is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("ORGANIZATION_REDACTION") {
+            for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (organization, kinds) = pair.split_once('=').ok_or_else(|| {
+                    format!("ORGANIZATION_REDACTION entry '{}' is not org=kinds", pair)
+                })?;
+                let kinds = parse_kinds(kinds.split('|'))
+                    .map_err(|e| format!("ORGANIZATION_REDACTION is not valid: {}", e))?;
+                self.organization_kinds
+                    .insert(organization.trim().to_string(), kinds);
+            }
+        }
+
+        if let Ok(value) = env::var("REDACTION_NER_URL") {
+            self.ner_url =
+                Some(value.trim().trim_end_matches('/').to_string()).filter(|url| !url.is_empty());
+        }
+        let needs_ner = self.default_kinds.contains(&PiiKind::Name)
+            || self
+                .organization_kinds
+                .values()
+                .any(|kinds| kinds.contains(&PiiKind::Name));
+        if needs_ner && self.ner_url.is_none() {
+            return Err("REDACTION_NER_URL must be set to redact names".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The kinds to mask for a user; an organization's own policy replaces the default.
+    pub fn kinds_for(&self, organization_id: Option<&str>) -> &BTreeSet<PiiKind> {
+        organization_id
+            .and_then(|id| self.organization_kinds.get(id))
+            .unwrap_or(&self.default_kinds)
+    }
+}
+
+fn parse_kinds<'a>(values: impl Iterator<Item = &'a str>) -> Result<BTreeSet<PiiKind>, String> {
+    values
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty() && !kind.eq_ignore_ascii_case("none"))
+        .map(str::parse)
+        .collect()
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+};
+
+/// Where the data of users tagged with a region is processed and stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    /// Names of `LLM_PROVIDERS` entries that only serve this region.
+    pub providers: Vec<String>,
+    /// Subdirectory of each media directory holding this region's files.
+    pub media_dir: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ResidencyConfig {
+    pub regions: BTreeMap<String, Region>,
+    pub organization_regions: HashMap<String, String>,
+    /// Refuse models with no endpoint in the user's region instead of
+    /// falling back to the global providers.
+    pub strict: bool,
+}
+impl ResidencyConfig {
+    // Reads DATA_REGIONS=name,... and, for each name, REGION_<NAME>_{PROVIDERS,MEDIA_DIR},
+    // plus ORGANIZATION_REGIONS=org=region,... and DATA_RESIDENCY_STRICT.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(names) = env::var("DATA_REGIONS") {
+            for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                let name = name.to_lowercase();
+                let prefix = format!("REGION_{}", name.to_uppercase());
+                let var = |suffix: &str| {
+                    env::var(format!("{}_{}", prefix, suffix))
+                        .ok()
+                        .map(|v| v.trim().to_string())
+                        .filter(|v| !v.is_empty())
+                };
+
+                let providers: Vec<String> = var("PROVIDERS")
+                    .ok_or_else(|| format!("{}_PROVIDERS not set in environment", prefix))?
+                    .split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect();
+                let media_dir = var("MEDIA_DIR").unwrap_or_else(|| name.clone());
+                if media_dir.contains("..") || media_dir.starts_with('/') {
+                    return Err(format!("{}_MEDIA_DIR must be a relative path", prefix));
+                }
+                self.regions.insert(
+                    name,
+                    Region {
+                        providers,
+                        media_dir: media_dir.trim_end_matches('/').to_string(),
+                    },
+                );
+            }
+        }
+
+        if let Ok(value) = env::var("ORGANIZATION_REGIONS") {
+            for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (organization, region) = pair.split_once('=').ok_or_else(|| {
+                    format!("ORGANIZATION_REGIONS entry '{}' is not org=region", pair)
+                })?;
+                let region = region.trim().to_lowercase();
+                if !self.regions.contains_key(&region) {
+                    return Err(format!(
+                        "ORGANIZATION_REGIONS refers to the unknown region '{}'",
+                        region
+                    ));
+                }
+                self.organization_regions
+                    .insert(organization.trim().to_string(), region);
+            }
+        }
+
+        if let Ok(value) = env::var("DATA_RESIDENCY_STRICT") {
+            self.strict = value
+                .parse::<bool>()
+                .map_err(|_| "DATA_RESIDENCY_STRICT is not a valid bool".to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the region of a user: their own tag wins over their organization's.
+    /// Unknown regions are ignored so a stale tag cannot block the user.
+    pub fn region_for(
+        &self,
+        user_region: Option<&str>,
+        organization_id: Option<&str>,
+    ) -> Option<String> {
+        user_region
+            .map(str::to_lowercase)
+            .filter(|region| self.regions.contains_key(region))
+            .or_else(|| {
+                organization_id
+                    .and_then(|id| self.organization_regions.get(id))
+                    .cloned()
+            })
+    }
+
+    /// Directory for new media of the given kind, e.g. `images/eu`.
+    pub fn media_dir(&self, kind: &str, region: Option<&str>) -> String {
+        match region.and_then(|region| self.regions.get(region)) {
+            Some(region) => format!("{}/{}", kind, region.media_dir),
+            None => kind.to_string(),
+        }
+    }
+
+    pub fn region_of_provider(&self, provider: &str) -> Option<&str> {
+        self.regions
+            .iter()
+            .find(|(_, region)| region.providers.iter().any(|p| p == provider))
+            .map(|(name, _)| name.as_str())
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashMap, env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RetentionAction {
+    #[default]
+    Archive,
+    Delete,
+}
+impl FromStr for RetentionAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "archive" => Ok(RetentionAction::Archive),
+            "delete" => Ok(RetentionAction::Delete),
+            other => Err(format!("Unknown retention action: {}", other)),
+        }
+    }
+}
+
+/// How long the uploaded audio of a voice message is kept. The transcript
+/// stays with the conversation either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VoiceRetention {
+    #[default]
+    Keep,
+    /// The audio is never written to disk.
+    TranscriptOnly,
+    Days(u32),
+}
+impl FromStr for VoiceRetention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" => Ok(VoiceRetention::Keep),
+            "transcript" | "transcript-only" | "none" => Ok(VoiceRetention::TranscriptOnly),
+            other => match other.trim_end_matches('d').parse::<u32>() {
+                Ok(days) if days > 0 => Ok(VoiceRetention::Days(days)),
+                _ => Err(format!("Unknown voice retention: {}", other)),
+            },
+        }
+    }
+}
+impl VoiceRetention {
+    /// Subdirectory of the voice media directory that holds files kept for
+    /// a limited time; the retention sweeper removes them once they expire.
+    pub fn media_subdir(&self) -> Option<String> {
+        match self {
+            VoiceRetention::Days(days) => Some(format!("{}{}d", VOICE_TTL_DIR_PREFIX, days)),
+            _ => None,
+        }
+    }
+}
+
+pub const VOICE_TTL_DIR_PREFIX: &str = "ttl-";
+
+#[derive(Clone, Debug)]
+pub struct RetentionConfig {
+    pub default_days: Option<i32>,
+    pub action: RetentionAction,
+    pub sweep_interval_secs: u64,
+    pub sweep_batch_size: u64,
+    /// Days a deleted conversation is kept before it is purged for good.
+    pub purge_after_days: u32,
+    pub voice_retention: VoiceRetention,
+    pub organization_voice_retention: HashMap<String, VoiceRetention>,
+}
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            default_days: None,
+            action: RetentionAction::Archive,
+            sweep_interval_secs: 3600,
+            sweep_batch_size: 500,
+            purge_after_days: 30,
+            voice_retention: VoiceRetention::Keep,
+            organization_voice_retention: HashMap::new(),
+        }
+    }
+}
+impl RetentionConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("RETENTION_DEFAULT_DAYS") {
+            let days = value
+                .parse::<i32>()
+                .map_err(|_| "RETENTION_DEFAULT_DAYS is not a valid i32".to_string())?;
+            if days <= 0 {
+                return Err("RETENTION_DEFAULT_DAYS must be positive".to_string());
+            }
+            self.default_days = Some(days);
+        }
+
+        if let Ok(value) = env::var("RETENTION_ACTION") {
+            self.action = value
+                .parse()
+                .map_err(|e| format!("RETENTION_ACTION is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("RETENTION_SWEEP_INTERVAL_SECS") {
+            self.sweep_interval_secs = value
+                .parse::<u64>()
+                .map_err(|_| "RETENTION_SWEEP_INTERVAL_SECS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("RETENTION_SWEEP_BATCH_SIZE") {
+            self.sweep_batch_size = value
+                .parse::<u64>()
+                .map_err(|_| "RETENTION_SWEEP_BATCH_SIZE is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("RETENTION_PURGE_AFTER_DAYS") {
+            self.purge_after_days = value
+                .parse::<u32>()
+                .map_err(|_| "RETENTION_PURGE_AFTER_DAYS is not a valid u32".to_string())?;
+        }
+
+        // VOICE_RETENTION=keep|transcript|<days>, ORGANIZATION_VOICE_RETENTION=org=policy,...
+        if let Ok(value) = env::var("VOICE_RETENTION") {
+            if !value.trim().is_empty() {
+                self.voice_retention = value
+                    .parse()
+                    .map_err(|e| format!("VOICE_RETENTION is not valid: {}", e))?;
+            }
+        }
+
+        if let Ok(value) = env::var("ORGANIZATION_VOICE_RETENTION") {
+            for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (organization, policy) = pair.split_once('=').ok_or_else(|| {
+                    format!(
+                        "ORGANIZATION_VOICE_RETENTION entry '{}' is not org=policy",
+                        pair
+                    )
+                })?;
+                let policy = policy
+                    .parse()
+                    .map_err(|e| format!("ORGANIZATION_VOICE_RETENTION is not valid: {}", e))?;
+                self.organization_voice_retention
+                    .insert(organization.trim().to_string(), policy);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The voice retention for a user; an organization's own policy replaces the default.
+    pub fn voice_retention_for(&self, organization_id: Option<&str>) -> VoiceRetention {
+        organization_id
+            .and_then(|id| self.organization_voice_retention.get(id))
+            .copied()
+            .unwrap_or(self.voice_retention)
+    }
+}
@@ -0,0 +1,70 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct RetryConfig {
+    /// Attempts per upstream call, including the first one.
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Consecutive transient failures that open an upstream's circuit.
+    pub breaker_failures: u32,
+    pub breaker_open_secs: u64,
+}
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay_ms: 250,
+            max_delay_ms: 4000,
+            breaker_failures: 5,
+            breaker_open_secs: 30,
+        }
+    }
+}
+impl RetryConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("UPSTREAM_RETRY_MAX_ATTEMPTS") {
+            self.max_attempts = value
+                .parse::<u32>()
+                .map_err(|_| "UPSTREAM_RETRY_MAX_ATTEMPTS is not a valid u32".to_string())?;
+            if self.max_attempts == 0 {
+                return Err("UPSTREAM_RETRY_MAX_ATTEMPTS must be at least 1".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("UPSTREAM_RETRY_BASE_DELAY_MS") {
+            self.base_delay_ms = value
+                .parse::<u64>()
+                .map_err(|_| "UPSTREAM_RETRY_BASE_DELAY_MS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("UPSTREAM_RETRY_MAX_DELAY_MS") {
+            self.max_delay_ms = value
+                .parse::<u64>()
+                .map_err(|_| "UPSTREAM_RETRY_MAX_DELAY_MS is not a valid u64".to_string())?;
+        }
+        if self.max_delay_ms < self.base_delay_ms {
+            return Err(
+                "UPSTREAM_RETRY_MAX_DELAY_MS must not be below UPSTREAM_RETRY_BASE_DELAY_MS"
+                    .to_string(),
+            );
+        }
+
+        if let Ok(value) = env::var("UPSTREAM_BREAKER_FAILURES") {
+            self.breaker_failures = value
+                .parse::<u32>()
+                .map_err(|_| "UPSTREAM_BREAKER_FAILURES is not a valid u32".to_string())?;
+            if self.breaker_failures == 0 {
+                return Err("UPSTREAM_BREAKER_FAILURES must be at least 1".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("UPSTREAM_BREAKER_OPEN_SECS") {
+            self.breaker_open_secs = value
+                .parse::<u64>()
+                .map_err(|_| "UPSTREAM_BREAKER_OPEN_SECS is not a valid u64".to_string())?;
+        }
+
+        Ok(())
+    }
+}
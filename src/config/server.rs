@@ -7,6 +7,13 @@ pub struct ServerConfig {
     pub port: u16,
     pub auth_service: String,
     pub auth_secret_key: String,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub unix_socket_path: Option<String>,
+    pub metrics_token: Option<String>,
+    /// Requires internal requests to carry signed timestamp and nonce headers
+    /// and rejects replays. Only enable once the auth service sends them.
+    pub signed_timestamps: bool,
 }
 
 impl ServerConfig {
@@ -18,6 +25,13 @@ impl ServerConfig {
         format!("http://{}:{}", self.addr, self.port)
     }
 
+    pub fn tls_paths(&self) -> Option<(&str, &str)> {
+        Some((
+            self.tls_cert_path.as_deref()?,
+            self.tls_key_path.as_deref()?,
+        ))
+    }
+
     pub fn get_socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
         self.get_addr().parse()
     }
@@ -37,6 +51,31 @@ impl ServerConfig {
             .parse::<u16>()
             .map_err(|_| "SERVER_PORT is not a valid u16".to_string())?;
 
+        self.tls_cert_path = env::var("SERVER_TLS_CERT_PATH")
+            .ok()
+            .filter(|v| !v.is_empty());
+        self.tls_key_path = env::var("SERVER_TLS_KEY_PATH")
+            .ok()
+            .filter(|v| !v.is_empty());
+        self.unix_socket_path = env::var("SERVER_UNIX_SOCKET_PATH")
+            .ok()
+            .filter(|v| !v.is_empty());
+        // When set, scrapers must send it as a bearer token to read /metrics.
+        self.metrics_token = env::var("METRICS_TOKEN").ok().filter(|v| !v.is_empty());
+        if let Some(value) = env::var("INTERNAL_SIGNED_TIMESTAMPS")
+            .ok()
+            .filter(|v| !v.is_empty())
+        {
+            self.signed_timestamps = value
+                .parse::<bool>()
+                .map_err(|_| "INTERNAL_SIGNED_TIMESTAMPS is not a valid bool".to_string())?;
+        }
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            return Err(
+                "SERVER_TLS_CERT_PATH and SERVER_TLS_KEY_PATH must be set together".to_string(),
+            );
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,36 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct SessionConfig {
+    pub cache_ttl_secs: u64,
+    pub cache_capacity: usize,
+}
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            cache_ttl_secs: 30,
+            cache_capacity: 10_000,
+        }
+    }
+}
+impl SessionConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("SESSION_CACHE_TTL_SECS") {
+            self.cache_ttl_secs = value
+                .parse::<u64>()
+                .map_err(|_| "SESSION_CACHE_TTL_SECS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("SESSION_CACHE_CAPACITY") {
+            self.cache_capacity = value
+                .parse::<usize>()
+                .map_err(|_| "SESSION_CACHE_CAPACITY is not a valid usize".to_string())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_cache_enabled(&self) -> bool {
+        self.cache_ttl_secs > 0 && self.cache_capacity > 0
+    }
+}
@@ -0,0 +1,56 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct SloConfig {
+    pub first_token_p95_ms: Option<u64>,
+    pub window_secs: u64,
+    pub min_samples: usize,
+    pub alert_cooldown_secs: u64,
+}
+impl Default for SloConfig {
+    fn default() -> Self {
+        SloConfig {
+            first_token_p95_ms: None,
+            window_secs: 300,
+            min_samples: 20,
+            alert_cooldown_secs: 900,
+        }
+    }
+}
+impl SloConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("SLO_FIRST_TOKEN_P95_MS") {
+            if !value.trim().is_empty() {
+                self.first_token_p95_ms = Some(
+                    value
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_| "SLO_FIRST_TOKEN_P95_MS is not a valid u64".to_string())?,
+                );
+            }
+        }
+
+        if let Ok(value) = env::var("SLO_WINDOW_SECS") {
+            self.window_secs = value
+                .parse::<u64>()
+                .map_err(|_| "SLO_WINDOW_SECS is not a valid u64".to_string())?;
+            if self.window_secs == 0 {
+                return Err("SLO_WINDOW_SECS must be positive".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("SLO_MIN_SAMPLES") {
+            self.min_samples = value
+                .parse::<usize>()
+                .map_err(|_| "SLO_MIN_SAMPLES is not a valid usize".to_string())?;
+        }
+
+        if let Ok(value) = env::var("SLO_ALERT_COOLDOWN_SECS") {
+            self.alert_cooldown_secs = value
+                .parse::<u64>()
+                .map_err(|_| "SLO_ALERT_COOLDOWN_SECS is not a valid u64".to_string())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::BTreeMap, env};
+
+#[derive(Clone, Debug)]
+pub struct StyleConfig {
+    pub presets: BTreeMap<String, String>,
+}
+impl Default for StyleConfig {
+    fn default() -> Self {
+        let presets = [
+            (
+                "concise",
+                "Answer as briefly as possible. Skip introductions, caveats and summaries unless they are essential.",
+            ),
+            (
+                "detailed",
+                "Give thorough, well-structured answers. Explain your reasoning, cover relevant edge cases and include examples where they help.",
+            ),
+            (
+                "bullet-points",
+                "Format the answer as a short list of bullet points. Keep each point to a single idea.",
+            ),
+            (
+                "eli5",
+                "Explain things as you would to a curious five-year-old: plain words, short sentences and everyday analogies, with no jargon.",
+            ),
+        ];
+        StyleConfig {
+            presets: presets
+                .into_iter()
+                .map(|(name, prompt)| (name.to_string(), prompt.to_string()))
+                .collect(),
+        }
+    }
+}
+impl StyleConfig {
+    // STYLE_PRESETS_FILE points to a JSON object of preset name to prompt; an empty prompt removes a built-in preset.
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        let Ok(path) = env::var("STYLE_PRESETS_FILE") else {
+            return Ok(());
+        };
+        if path.trim().is_empty() {
+            return Ok(());
+        }
+        let content = std::fs::read_to_string(path.trim())
+            .map_err(|e| format!("STYLE_PRESETS_FILE could not be read: {}", e))?;
+        let presets: BTreeMap<String, String> = serde_json::from_str(&content)
+            .map_err(|e| format!("STYLE_PRESETS_FILE is not valid: {}", e))?;
+        for (name, prompt) in presets {
+            let name = name.trim().to_lowercase();
+            let prompt = prompt.trim().to_string();
+            if prompt.is_empty() {
+                self.presets.remove(&name);
+            } else {
+                self.presets.insert(name, prompt);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn prompt_for(&self, name: &str) -> Option<&str> {
+        self.presets
+            .get(&name.trim().to_lowercase())
+            .map(String::as_str)
+    }
+}
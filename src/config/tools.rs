@@ -0,0 +1,77 @@
+use std::{collections::HashMap, env};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ToolLimits {
+    pub timeout_secs: u64,
+    pub max_calls_per_turn: u32,
+    pub max_output_chars: usize,
+}
+impl Default for ToolLimits {
+    fn default() -> Self {
+        ToolLimits {
+            timeout_secs: 15,
+            max_calls_per_turn: 5,
+            max_output_chars: 8000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ToolsConfig {
+    pub default_limits: ToolLimits,
+    pub limits: HashMap<String, ToolLimits>,
+}
+impl ToolsConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("TOOL_TIMEOUT_SECS") {
+            self.default_limits.timeout_secs = value
+                .parse::<u64>()
+                .map_err(|_| "TOOL_TIMEOUT_SECS is not a valid u64".to_string())?;
+        }
+
+        if let Ok(value) = env::var("TOOL_MAX_CALLS_PER_TURN") {
+            self.default_limits.max_calls_per_turn = value
+                .parse::<u32>()
+                .map_err(|_| "TOOL_MAX_CALLS_PER_TURN is not a valid u32".to_string())?;
+        }
+
+        if let Ok(value) = env::var("TOOL_MAX_OUTPUT_CHARS") {
+            self.default_limits.max_output_chars = value
+                .parse::<usize>()
+                .map_err(|_| "TOOL_MAX_OUTPUT_CHARS is not a valid usize".to_string())?;
+        }
+
+        if let Ok(value) = env::var("TOOL_LIMITS") {
+            for entry in value.split(',').filter(|s| !s.trim().is_empty()) {
+                let parsed = entry.split_once('=').and_then(|(name, limits)| {
+                    let mut parts = limits.split(':').map(str::trim);
+                    let limits = ToolLimits {
+                        timeout_secs: parts.next()?.parse().ok()?,
+                        max_calls_per_turn: parts.next()?.parse().ok()?,
+                        max_output_chars: parts.next()?.parse().ok()?,
+                    };
+                    parts
+                        .next()
+                        .is_none()
+                        .then(|| (name.trim().to_string(), limits))
+                });
+                let Some((name, limits)) = parsed else {
+                    return Err(format!(
+                        "TOOL_LIMITS entry is not name=timeout_secs:max_calls:max_output_chars: {}",
+                        entry
+                    ));
+                };
+                self.limits.insert(name, limits);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn limits_for(&self, tool_name: &str) -> ToolLimits {
+        self.limits
+            .get(tool_name)
+            .copied()
+            .unwrap_or(self.default_limits)
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+use uuid::Uuid;
+
+/// Which requests get a full trace: their headers and debug events are
+/// logged. Requests that log an error, server errors among them, get theirs
+/// logged too, the events before the error included.
+#[derive(Clone, Debug, Default)]
+pub struct TraceSamplingConfig {
+    /// Share of requests, from 0 to 1, traced in full.
+    pub rate: f64,
+    /// Rates for routes starting with the given path; the longest match wins.
+    pub routes: Vec<(String, f64)>,
+}
+impl TraceSamplingConfig {
+    // Reads TRACE_SAMPLE_RATE=0.01 and
+    // TRACE_SAMPLE_ROUTES=/api/chat/conversation/:conversation_id/message=0.2;/api/health=0
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("TRACE_SAMPLE_RATE") {
+            self.rate =
+                parse_rate(&value).map_err(|e| format!("TRACE_SAMPLE_RATE is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("TRACE_SAMPLE_ROUTES") {
+            self.routes = value
+                .split(';')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(|entry| {
+                    let (route, rate) = entry
+                        .rsplit_once('=')
+                        .ok_or_else(|| format!("'{}' is not route=rate", entry.trim()))?;
+                    Ok((route.trim().to_string(), parse_rate(rate)?))
+                })
+                .collect::<Result<_, String>>()
+                .map_err(|e| format!("TRACE_SAMPLE_ROUTES is not valid: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn rate_for(&self, route: &str) -> f64 {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| route.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.rate, |(_, rate)| *rate)
+    }
+
+    pub fn sample(&self, route: &str) -> bool {
+        let rate = self.rate_for(route);
+        if rate <= 0.0 {
+            return false;
+        }
+        let roll = (Uuid::new_v4().as_u128() % 1_000_000) as f64 / 1_000_000.0;
+        roll < rate
+    }
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate = value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("'{}' is not a number", value.trim()))?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("{} is not between 0 and 1", rate));
+    }
+    Ok(rate)
+}
@@ -1,17 +1,182 @@
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use crate::utils::{api_key::API_KEY_HEADER, signature::SIGNATURE_HEADER};
+use axum::http::HeaderMap;
+use std::fmt::{self, Write};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    filter::{dynamic_filter_fn, filter_fn, FilterExt, LevelFilter},
+    fmt,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+// Field `create_router` puts on every request span, true when it was picked for a full trace.
+const SAMPLED_FIELD: &str = "sampled";
+
+// Target of the held back events logged once their request has failed.
+const REPLAY_TARGET: &str = "trace_replay";
+
+// At most this many debug events are held back per request.
+const MAX_HELD_EVENTS: usize = 256;
+
+const REDACTED_HEADERS: [&str; 6] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    SIGNATURE_HEADER,
+    API_KEY_HEADER,
+];
+
+/// Logs headers with the values of credentials and signatures replaced.
+pub struct RedactedHeaders<'a>(pub &'a HeaderMap);
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.0 {
+            if REDACTED_HEADERS
+                .iter()
+                .any(|redacted| name.as_str().eq_ignore_ascii_case(redacted))
+            {
+                map.entry(&name, &"[redacted]");
+            } else {
+                map.entry(&name, &value);
+            }
+        }
+        map.finish()
+    }
+}
+
+// Set on a request span that is traced in full.
+struct Sampled;
+
+// Debug events of a request that was not sampled, logged if it ends up failing.
+struct Held(Vec<String>);
+
+fn in_sampled_span<S>(ctx: &Context<'_, S>) -> bool
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    ctx.lookup_current().is_some_and(|span| {
+        span.scope()
+            .any(|span| span.extensions().get::<Sampled>().is_some())
+    })
+}
+
+struct SampledVisitor(Option<bool>);
+
+impl Visit for SampledVisitor {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == SAMPLED_FIELD {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+struct EventLine(String);
+
+impl Visit for EventLine {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        let _ = match field.name() {
+            "message" => write!(self.0, "{:?}", value),
+            name => write!(self.0, "{}={:?}", name, value),
+        };
+    }
+}
+
+/// Marks the spans of sampled requests and holds back the debug events of the
+/// others. A request that logs an error has them logged after all and is
+/// traced in full from then on.
+struct SampledSpans;
+
+impl<S> Layer<S> for SampledSpans
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = SampledVisitor(None);
+        attrs.record(&mut visitor);
+        let Some(sampled) = visitor.0 else {
+            return;
+        };
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if sampled {
+            span.extensions_mut().insert(Sampled);
+        } else {
+            span.extensions_mut().insert(Held(vec![]));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if metadata.target() == REPLAY_TARGET {
+            return;
+        }
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        for span in scope {
+            let mut extensions = span.extensions_mut();
+            if extensions.get::<Sampled>().is_some() {
+                return;
+            }
+            let Some(held) = extensions.get_mut::<Held>() else {
+                continue;
+            };
+            if *metadata.level() == Level::ERROR {
+                let lines = std::mem::take(&mut held.0);
+                extensions.remove::<Held>();
+                extensions.insert(Sampled);
+                drop(extensions);
+                for line in lines {
+                    tracing::info!(target: REPLAY_TARGET, "{}", line);
+                }
+            } else if *metadata.level() > Level::INFO && held.0.len() < MAX_HELD_EVENTS {
+                let mut line = EventLine(String::new());
+                event.record(&mut line);
+                held.0.push(format!(
+                    "{} {}: {}",
+                    metadata.level(),
+                    metadata.target(),
+                    line.0
+                ));
+            }
+            return;
+        }
+    }
+}
 
 pub fn subscribe_tracing() {
+    let env_filter = EnvFilter::from_default_env()
+        .add_directive(tracing::Level::INFO.into())
+        .add_directive("sqlx=off".parse().unwrap());
+    // Inside a sampled request everything but sqlx gets through.
+    let sampled_filter = dynamic_filter_fn(|metadata, ctx| {
+        !metadata.target().starts_with("sqlx") && in_sampled_span(ctx)
+    });
+    let held_filter =
+        LevelFilter::DEBUG.and(filter_fn(|metadata| !metadata.target().starts_with("sqlx")));
+
     tracing_subscriber::registry()
+        .with(SampledSpans.with_filter(held_filter))
         .with(
             fmt::layer()
                 .with_file(true)
                 .with_line_number(true)
-                .with_thread_ids(true),
-        )
-        .with(
-            EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into())
-                .add_directive("sqlx=off".parse().unwrap()),
+                .with_thread_ids(true)
+                .with_filter(env_filter.or(sampled_filter)),
         )
         .init();
 }
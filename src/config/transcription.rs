@@ -0,0 +1,64 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct TranscriptionConfig {
+    /// Length of the pieces a split WAV recording is transcribed in.
+    pub chunk_secs: u32,
+    /// Recordings above this size are split before they are sent upstream.
+    pub max_upload_bytes: usize,
+    /// Audio shared by neighbouring pieces of an oversized recording, so no
+    /// word is lost at a cut.
+    pub overlap_secs: u32,
+    /// Pieces of one oversized recording transcribed at the same time.
+    pub max_concurrency: usize,
+}
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        TranscriptionConfig {
+            chunk_secs: 60,
+            max_upload_bytes: 25 * 1024 * 1024,
+            overlap_secs: 2,
+            max_concurrency: 4,
+        }
+    }
+}
+impl TranscriptionConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("TRANSCRIPTION_CHUNK_SECS") {
+            self.chunk_secs = value
+                .parse::<u32>()
+                .map_err(|_| "TRANSCRIPTION_CHUNK_SECS is not a valid u32".to_string())?;
+            if self.chunk_secs == 0 {
+                return Err("TRANSCRIPTION_CHUNK_SECS must be at least 1".to_string());
+            }
+        }
+
+        if let Ok(value) = env::var("TRANSCRIPTION_MAX_UPLOAD_BYTES") {
+            self.max_upload_bytes = value
+                .parse::<usize>()
+                .map_err(|_| "TRANSCRIPTION_MAX_UPLOAD_BYTES is not a valid usize".to_string())?;
+        }
+
+        if let Ok(value) = env::var("TRANSCRIPTION_OVERLAP_SECS") {
+            self.overlap_secs = value
+                .parse::<u32>()
+                .map_err(|_| "TRANSCRIPTION_OVERLAP_SECS is not a valid u32".to_string())?;
+        }
+        if self.overlap_secs >= self.chunk_secs {
+            return Err(
+                "TRANSCRIPTION_OVERLAP_SECS must be below TRANSCRIPTION_CHUNK_SECS".to_string(),
+            );
+        }
+
+        if let Ok(value) = env::var("TRANSCRIPTION_MAX_CONCURRENCY") {
+            self.max_concurrency = value
+                .parse::<usize>()
+                .map_err(|_| "TRANSCRIPTION_MAX_CONCURRENCY is not a valid usize".to_string())?;
+            if self.max_concurrency == 0 {
+                return Err("TRANSCRIPTION_MAX_CONCURRENCY must be at least 1".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
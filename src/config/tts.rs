@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArtifactMode {
+    Keep,
+    Skip,
+    Summarize,
+}
+impl FromStr for ArtifactMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" => Ok(ArtifactMode::Keep),
+            "skip" => Ok(ArtifactMode::Skip),
+            "summarize" => Ok(ArtifactMode::Summarize),
+            other => Err(format!("Unknown artifact mode: {}", other)),
+        }
+    }
+}
+
+/// Linear16 sample rates Deepgram can synthesize.
+pub const DEEPGRAM_SAMPLE_RATES: [u32; 5] = [8000, 16000, 24000, 32000, 48000];
+pub const DEFAULT_SAMPLE_RATE: u32 = 16000;
+
+const AURA_VOICES: [&str; 12] = [
+    "asteria", "luna", "stella", "athena", "hera", "orion", "arcas", "perseus", "angus", "orpheus",
+    "helios", "zeus",
+];
+
+/// A named Deepgram voice a client can pick instead of the language default.
+/// Audio is always synthesized as Linear16 so it can be leveled, measured and
+/// transcoded; only the model and sample rate vary.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoiceProfile {
+    pub model: String,
+    pub sample_rate: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TtsConfig {
+    pub segment_min_chars: usize,
+    pub segment_max_chars: usize,
+    pub segment_language: String,
+    pub abbreviations: Vec<String>,
+    pub code_blocks: ArtifactMode,
+    pub tables: ArtifactMode,
+    pub urls: ArtifactMode,
+    pub voice: String,
+    pub voices: HashMap<String, String>,
+    pub voice_profiles: BTreeMap<String, VoiceProfile>,
+    pub elevenlabs_key: Option<String>,
+    pub elevenlabs_model: String,
+    pub custom_voices: Vec<String>,
+    pub target_lufs: Option<f32>,
+    pub max_gain_db: f32,
+}
+impl Default for TtsConfig {
+    fn default() -> Self {
+        TtsConfig {
+            segment_min_chars: 40,
+            segment_max_chars: 300,
+            segment_language: String::from("en"),
+            abbreviations: vec![],
+            code_blocks: ArtifactMode::Summarize,
+            tables: ArtifactMode::Summarize,
+            urls: ArtifactMode::Summarize,
+            voice: String::from("aura-asteria-en"),
+            voices: HashMap::new(),
+            voice_profiles: AURA_VOICES
+                .iter()
+                .map(|name| {
+                    (
+                        name.to_string(),
+                        VoiceProfile {
+                            model: format!("aura-{}-en", name),
+                            sample_rate: DEFAULT_SAMPLE_RATE,
+                        },
+                    )
+                })
+                .collect(),
+            elevenlabs_key: None,
+            elevenlabs_model: String::from("eleven_turbo_v2_5"),
+            custom_voices: vec![],
+            target_lufs: None,
+            max_gain_db: 12.0,
+        }
+    }
+}
+impl TtsConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("TTS_SEGMENT_MIN_CHARS") {
+            self.segment_min_chars = value
+                .parse::<usize>()
+                .map_err(|_| "TTS_SEGMENT_MIN_CHARS is not a valid usize".to_string())?;
+        }
+
+        if let Ok(value) = env::var("TTS_SEGMENT_MAX_CHARS") {
+            self.segment_max_chars = value
+                .parse::<usize>()
+                .map_err(|_| "TTS_SEGMENT_MAX_CHARS is not a valid usize".to_string())?;
+        }
+        if self.segment_max_chars < self.segment_min_chars {
+            return Err("TTS_SEGMENT_MAX_CHARS must not be less than TTS_SEGMENT_MIN_CHARS".into());
+        }
+
+        if let Ok(value) = env::var("TTS_SEGMENT_LANGUAGE") {
+            self.segment_language = value.trim().to_lowercase();
+        }
+
+        if let Ok(value) = env::var("TTS_SEGMENT_ABBREVIATIONS") {
+            self.abbreviations = value
+                .split(',')
+                .map(|s| s.trim().trim_end_matches('.').to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+
+        if let Ok(value) = env::var("TTS_CODE_BLOCKS") {
+            self.code_blocks = value
+                .parse()
+                .map_err(|e| format!("TTS_CODE_BLOCKS is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("TTS_TABLES") {
+            self.tables = value
+                .parse()
+                .map_err(|e| format!("TTS_TABLES is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("TTS_URLS") {
+            self.urls = value
+                .parse()
+                .map_err(|e| format!("TTS_URLS is not valid: {}", e))?;
+        }
+
+        if let Ok(value) = env::var("TTS_VOICE") {
+            if !value.trim().is_empty() {
+                self.voice = value.trim().to_string();
+            }
+        }
+
+        if let Ok(value) = env::var("TTS_VOICES") {
+            for entry in value.split(',').filter(|s| !s.trim().is_empty()) {
+                let Some((language, voice)) = entry.split_once('=') else {
+                    return Err(format!("TTS_VOICES entry is not language=voice: {}", entry));
+                };
+                self.voices
+                    .insert(language.trim().to_lowercase(), voice.trim().to_string());
+            }
+        }
+
+        // TTS_VOICE_PROFILES=name=model[@sample_rate],... adds profiles or replaces built-in ones.
+        if let Ok(value) = env::var("TTS_VOICE_PROFILES") {
+            for entry in value.split(',').filter(|s| !s.trim().is_empty()) {
+                let Some((name, profile)) = entry.split_once('=') else {
+                    return Err(format!(
+                        "TTS_VOICE_PROFILES entry is not name=model[@sample_rate]: {}",
+                        entry
+                    ));
+                };
+                let (model, sample_rate) = match profile.split_once('@') {
+                    Some((model, rate)) => (
+                        model,
+                        rate.trim().parse::<u32>().map_err(|_| {
+                            format!("TTS_VOICE_PROFILES sample rate is not valid: {}", entry)
+                        })?,
+                    ),
+                    None => (profile, DEFAULT_SAMPLE_RATE),
+                };
+                if !DEEPGRAM_SAMPLE_RATES.contains(&sample_rate) {
+                    return Err(format!(
+                        "TTS_VOICE_PROFILES sample rate must be one of {:?}: {}",
+                        DEEPGRAM_SAMPLE_RATES, entry
+                    ));
+                }
+                self.voice_profiles.insert(
+                    name.trim().to_lowercase(),
+                    VoiceProfile {
+                        model: model.trim().to_string(),
+                        sample_rate,
+                    },
+                );
+            }
+        }
+
+        self.elevenlabs_key = env::var("ELEVENLABS_KEY")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        if let Ok(value) = env::var("ELEVENLABS_MODEL") {
+            if !value.trim().is_empty() {
+                self.elevenlabs_model = value.trim().to_string();
+            }
+        }
+
+        if let Ok(value) = env::var("TTS_CUSTOM_VOICES") {
+            self.custom_voices = value
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+
+        if let Ok(value) = env::var("TTS_TARGET_LUFS") {
+            let target = value
+                .parse::<f32>()
+                .map_err(|_| "TTS_TARGET_LUFS is not a valid f32".to_string())?;
+            if target >= 0.0 {
+                return Err("TTS_TARGET_LUFS must be negative".to_string());
+            }
+            self.target_lufs = Some(target);
+        }
+
+        if let Ok(value) = env::var("TTS_MAX_GAIN_DB") {
+            self.max_gain_db = value
+                .parse::<f32>()
+                .map_err(|_| "TTS_MAX_GAIN_DB is not a valid f32".to_string())?
+                .abs();
+        }
+
+        Ok(())
+    }
+
+    pub fn allows_custom_voice(&self, voice_id: &str) -> bool {
+        self.elevenlabs_key.is_some() && self.custom_voices.iter().any(|v| v == voice_id)
+    }
+
+    pub fn voice_profile(&self, name: &str) -> Option<&VoiceProfile> {
+        self.voice_profiles.get(&name.trim().to_lowercase())
+    }
+
+    pub fn voice_for(&self, language: Option<&str>) -> &str {
+        language
+            .and_then(|language| self.voices.get(language))
+            .unwrap_or(&self.voice)
+    }
+}
@@ -0,0 +1,39 @@
+use std::env;
+
+#[derive(Clone, Debug)]
+pub struct UploadConfig {
+    pub dir: String,
+    pub max_bytes: i64,
+}
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            dir: String::from("./uploads"),
+            max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+impl UploadConfig {
+    pub fn init_from_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("UPLOAD_DIR") {
+            if !value.trim().is_empty() {
+                self.dir = value.trim().trim_end_matches('/').to_string();
+            }
+        }
+
+        if let Ok(value) = env::var("UPLOAD_MAX_BYTES") {
+            self.max_bytes = value
+                .parse::<i64>()
+                .map_err(|_| "UPLOAD_MAX_BYTES is not a valid i64".to_string())?;
+            if self.max_bytes <= 0 {
+                return Err("UPLOAD_MAX_BYTES must be positive".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn path_for(&self, attachment_id: uuid::Uuid) -> String {
+        format!("{}/{}", self.dir, attachment_id)
+    }
+}
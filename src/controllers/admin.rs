@@ -0,0 +1,429 @@
+use crate::{
+    controllers::chat::{handle_transaction, MAX_CONVERSATION_PAGE_SIZE},
+    dto::{
+        request::{
+            RepairConversationRequest, ServiceConversationRequest, ServiceConversationsRequest,
+            ServiceUsageRequest, SupportAccessQuery,
+        },
+        response::{
+            AdminStatusResponse, ConversationSummary, ForceDeleteConversationResponse,
+            GetConversationResponse, ModelUsage, RepairConversationResponse,
+            RetrieveAllConversationResponse, UsageAggregateResponse,
+        },
+    },
+    entity::conversation::reply_threads,
+    repositories::{
+        conversation::{self, ConversationFilter, ConversationPage},
+        draft, usage,
+    },
+    service::quarantine,
+    utils::{
+        error::{format_error, AppError, AppResult, ErrorCode},
+        file::delete_files,
+        jwt::UserClaims,
+        signature::SignedJson,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+// Checks the support role and the mandatory reason, leaving an audit trail either way.
+fn authorize_support(
+    state: &ServiceState,
+    admin: &UserClaims,
+    user_id: i64,
+    conversation_id: Uuid,
+    reason: Option<String>,
+    action: &str,
+) -> AppResult<()> {
+    if !admin.has_role(&state.config.jwt.support_role) {
+        warn!(
+            target: "audit",
+            "User '{}' was denied support access to conversation '{}' of user '{}'.",
+            admin.uid, conversation_id, user_id
+        );
+        return Err(format_error(
+            "Support access requires the support role",
+            admin.uid,
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    let reason = reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty())
+        .ok_or_else(|| {
+            format_error(
+                "A reason is required for support access",
+                "reason must not be empty",
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+    warn!(
+        target: "audit",
+        "Support user '{}' (session '{}') is {} conversation '{}' of user '{}'. Reason: {}",
+        admin.uid, admin.sid, action, conversation_id, user_id, reason
+    );
+    Ok(())
+}
+
+pub async fn get_user_conversation(
+    Path((user_id, conversation_id)): Path<(i64, Uuid)>,
+    Query(query): Query<SupportAccessQuery>,
+    State(state): State<Arc<ServiceState>>,
+    admin: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    authorize_support(
+        &state,
+        &admin,
+        user_id,
+        conversation_id,
+        query.reason,
+        "reading",
+    )?;
+    read_user_conversation(state, user_id, conversation_id).await
+}
+
+async fn read_user_conversation(
+    state: Arc<ServiceState>,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> AppResult<Response> {
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user_id,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching conversation details from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            let Some(model) = model else {
+                let error_message = "Requested conversation could not be found".to_string();
+                error!("Failed to retrieve: {}", error_message);
+                return Err(AppError::new(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::ConversationNotFound,
+                    error_message,
+                ));
+            };
+            let (messages, corrupted) =
+                quarantine::load_messages(conversation_id, model.conversation);
+            let generation_settings = model.generation_settings();
+            let replies = reply_threads(&messages);
+            Ok(Json(GetConversationResponse {
+                messages,
+                replies,
+                corrupted,
+                locked: model.locked,
+                max_credits: model.max_credits,
+                credits_spent: model.credits_spent,
+                system_prompt: model.system_prompt,
+                style_preset: model.style_preset,
+                voice_profile: model.voice_profile,
+                generation_settings,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn repair_user_conversation(
+    Path((user_id, conversation_id)): Path<(i64, Uuid)>,
+    State(state): State<Arc<ServiceState>>,
+    admin: UserClaims,
+    Json(req): Json<RepairConversationRequest>,
+) -> AppResult<impl IntoResponse> {
+    authorize_support(
+        &state,
+        &admin,
+        user_id,
+        conversation_id,
+        req.reason,
+        "repairing",
+    )?;
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user_id,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching conversation details from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            let Some(model) = model else {
+                let error_message = "Requested conversation could not be found".to_string();
+                error!("Failed to repair: {}", error_message);
+                return Err(AppError::new(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::ConversationNotFound,
+                    error_message,
+                ));
+            };
+            let outcome = quarantine::repair(
+                conversation_id,
+                model.conversation.clone(),
+                req.replacements,
+                req.drop_corrupted,
+            )
+            .map_err(|e| {
+                format_error(
+                    "The conversation could not be repaired",
+                    e,
+                    StatusCode::BAD_REQUEST,
+                )
+            })?;
+            conversation::replace_messages(transaction, model, outcome.conversation)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to save the repaired conversation due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(RepairConversationResponse {
+                repaired: outcome.repaired,
+                dropped_turns: outcome.dropped_turns,
+                corrupted: outcome.corrupted,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn get_status(
+    State(state): State<Arc<ServiceState>>,
+    admin: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    if !admin.has_role(&state.config.jwt.support_role) {
+        return Err(format_error(
+            "The service status requires the support role",
+            admin.uid,
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    let database = state.db.ping().await.is_ok();
+    let warmed_up = state.health.is_warmed_up();
+    Ok(Json(AdminStatusResponse {
+        ready: database && warmed_up,
+        database,
+        warmed_up,
+        providers: state.health.snapshot(),
+    }))
+}
+
+// Service-key requests carry no user identity, so the reason is all the audit trail has.
+fn audit_service_access(
+    user_id: i64,
+    conversation_id: Uuid,
+    reason: Option<String>,
+    action: &str,
+) -> AppResult<()> {
+    let reason = reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty())
+        .ok_or_else(|| {
+            format_error(
+                "A reason is required for support access",
+                "reason must not be empty",
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+    warn!(
+        target: "audit",
+        "The auth service is {} conversation '{}' of user '{}'. Reason: {}",
+        action, conversation_id, user_id, reason
+    );
+    Ok(())
+}
+
+pub async fn service_list_conversations(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<ServiceConversationsRequest>,
+) -> AppResult<impl IntoResponse> {
+    if let Some(limit) = req.limit {
+        if limit == 0 || limit > MAX_CONVERSATION_PAGE_SIZE {
+            return Err(format_error(
+                "Invalid page size",
+                format!("limit must be between 1 and {}", MAX_CONVERSATION_PAGE_SIZE),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    let filter = ConversationFilter {
+        include_archived: req.include_archived,
+        ..Default::default()
+    };
+    let page = ConversationPage {
+        limit: Some(req.limit.unwrap_or(MAX_CONVERSATION_PAGE_SIZE)),
+        offset: req.offset.unwrap_or_default(),
+        ..Default::default()
+    };
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let (conversations, total) =
+                conversation::find_page_by_user_id(transaction, req.user_id, &filter, &page)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to fetch user's conversations due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+            let conversation_list: Vec<ConversationSummary> = conversations
+                .into_iter()
+                .map(ConversationSummary::from)
+                .collect();
+            Ok(Json(RetrieveAllConversationResponse {
+                conversation_list,
+                total,
+                offset: page.offset,
+                limit: page.limit,
+            }))
+        })
+    })
+    .await
+}
+
+pub async fn service_get_conversation(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<ServiceConversationRequest>,
+) -> AppResult<impl IntoResponse> {
+    audit_service_access(req.user_id, req.conversation_id, req.reason, "reading")?;
+    read_user_conversation(state, req.user_id, req.conversation_id).await
+}
+
+/// Removes a conversation and its media right away, including one the user
+/// already deleted and that is still waiting to be purged.
+pub async fn service_delete_conversation(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<ServiceConversationRequest>,
+) -> AppResult<impl IntoResponse> {
+    audit_service_access(
+        req.user_id,
+        req.conversation_id,
+        req.reason,
+        "force-deleting",
+    )?;
+
+    let model = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_id(transaction, req.conversation_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error fetching the conversation from the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+                .filter(|model| model.user_id == req.user_id)
+                .ok_or_else(|| {
+                    format_error(
+                        "Requested conversation could not be found",
+                        req.conversation_id,
+                        StatusCode::NOT_FOUND,
+                    )
+                    .with_code(ErrorCode::ConversationNotFound)
+                })?;
+            draft::delete_by_conversation_ids(transaction, vec![model.id])
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the conversation's drafts due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            conversation::delete_by_ids(transaction, vec![model.id])
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the conversation due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(model)
+        })
+    })
+    .await?;
+
+    let deleted_files = delete_files(&model.media_files());
+    Ok(Json(ForceDeleteConversationResponse {
+        conversation_id: model.id,
+        deleted_files,
+    }))
+}
+
+pub async fn service_usage(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<ServiceUsageRequest>,
+) -> AppResult<impl IntoResponse> {
+    if let (Some(from), Some(to)) = (req.from, req.to) {
+        if from > to {
+            return Err(format_error(
+                "Invalid date range",
+                "from must not be later than to",
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let rows = usage::aggregate_by_model(transaction, req.user_id, req.from, req.to)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to aggregate credit usage due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            let models: Vec<ModelUsage> = rows
+                .into_iter()
+                .map(
+                    |(model, requests, credits, prompt_tokens, completion_tokens)| ModelUsage {
+                        model,
+                        requests,
+                        credits,
+                        prompt_tokens,
+                        completion_tokens,
+                    },
+                )
+                .collect();
+            Ok(Json(UsageAggregateResponse {
+                user_id: req.user_id,
+                from: req.from,
+                to: req.to,
+                requests: models.iter().map(|m| m.requests).sum(),
+                credits: models.iter().map(|m| m.credits).sum(),
+                models,
+            }))
+        })
+    })
+    .await
+}
@@ -0,0 +1,328 @@
+use crate::{
+    config::auto_model::AUTO_MODEL,
+    controllers::chat::handle_transaction,
+    dto::{
+        request::{
+            BotMessageRequest, CreateBotRequest, WidgetMessageRequest, WidgetSessionRequest,
+        },
+        response::{CreateBotResponse, DeleteBotResponse, RetrieveAllBotsResponse},
+    },
+    repositories::bot,
+    service,
+    utils::{
+        api_key::{generate_api_key, hash_api_key, BotAuth},
+        error::{format_error, AppResult},
+        jwt::UserClaims,
+        session::send_session_data,
+        widget::WidgetAuth,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use std::sync::Arc;
+use tracing::{error, info};
+use url::Url;
+use uuid::Uuid;
+
+pub async fn create_bot(
+    State(state): State<Arc<ServiceState>>,
+    mut user: UserClaims,
+    Json(req): Json<CreateBotRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!("User '{}' is creating the bot '{}'.", user.uid, req.name);
+    // The bot's credits come out of this balance.
+    user.refresh_session(&state).await?;
+
+    let name = req.name.trim().to_string();
+    if name.is_empty() {
+        return Err(format_error(
+            "Invalid bot name",
+            "name must not be empty",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let webhook_url = req
+        .webhook_url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    if let Some(webhook_url) = webhook_url.as_deref() {
+        let valid = Url::parse(webhook_url)
+            .is_ok_and(|url| url.scheme() == "https" || url.scheme() == "http");
+        if !valid {
+            return Err(format_error(
+                "Invalid webhook URL",
+                webhook_url,
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    let model = req
+        .model_name
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty());
+    if let Some(model) = model.as_deref() {
+        if model != AUTO_MODEL && state.registry.price(model).is_none() {
+            return Err(state.registry.unknown_model(model));
+        }
+    }
+    let credits_remaining = user
+        .session_data
+        .as_ref()
+        .map(|s| s.credits_remaining)
+        .unwrap_or_default();
+    if req.credits < 0 || req.credits > credits_remaining {
+        return Err(format_error(
+            "Invalid bot credits",
+            format!("credits must be between 0 and {}", credits_remaining),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let api_key = generate_api_key();
+    let api_key_hash = hash_api_key(&api_key);
+    let model = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            bot::create(
+                transaction,
+                user.uid,
+                name,
+                api_key_hash,
+                webhook_url,
+                model,
+                req.credits,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to create the bot due to a database error",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })
+        })
+    })
+    .await?;
+
+    // Debited once the bot is saved; a failed debit removes the unfunded bot again.
+    if req.credits > 0 {
+        if let Err(e) = send_session_data(
+            serde_json::json!({
+                "credits_remaining": credits_remaining - req.credits,
+                "user_id": user.uid
+            }),
+            state.config.server.auth_service.as_str(),
+            state.config.server.auth_secret_key.clone(),
+        )
+        .await
+        {
+            let bot_id = model.id;
+            let removed = handle_transaction(&state.db, |transaction| {
+                Box::pin(async move {
+                    bot::delete(transaction, model).await.map_err(|e| {
+                        format_error(
+                            "Failed to delete the bot due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })
+                })
+            })
+            .await;
+            if let Err(undo) = removed {
+                error!(
+                    "Bot '{}' was left without its credits: {}",
+                    bot_id, undo.message
+                );
+            }
+            return Err(format_error(
+                "Failed to move credits to the bot",
+                e,
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+        state.sessions.invalidate_user(user.uid);
+    }
+
+    info!(
+        "Successfully created bot with ID '{}' for user '{}'.",
+        model.id, user.uid
+    );
+    Ok(Json(CreateBotResponse {
+        bot: model,
+        api_key,
+    }))
+}
+
+pub async fn retrieve_all_bots(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let bot_list = bot::find_by_user_id(transaction, user.uid)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Database query failed while fetching the bots",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(RetrieveAllBotsResponse { bot_list }).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn delete_bot(
+    Path(bot_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    mut user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!("User '{}' is deleting the bot '{}'.", user.uid, bot_id);
+    user.refresh_session(&state).await?;
+    let credits_remaining = user
+        .session_data
+        .as_ref()
+        .map(|s| s.credits_remaining)
+        .unwrap_or_default();
+    // The credits leave the bot first, so a bot whose refund failed keeps them.
+    let refund = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = bot::find_by_user_id_and_id(transaction, user.uid, bot_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Database query failed while fetching the bot",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+                .ok_or_else(|| {
+                    format_error("Bot could not be found", bot_id, StatusCode::NOT_FOUND)
+                })?;
+            let refund = model.credits_remaining.max(0);
+            bot::spend_credits(transaction, bot_id, refund)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to take the credits of the bot due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(refund)
+        })
+    })
+    .await?;
+
+    if refund > 0 {
+        if let Err(e) = send_session_data(
+            serde_json::json!({
+                "credits_remaining": credits_remaining + refund,
+                "user_id": user.uid
+            }),
+            state.config.server.auth_service.as_str(),
+            state.config.server.auth_secret_key.clone(),
+        )
+        .await
+        {
+            let restored = handle_transaction(&state.db, |transaction| {
+                Box::pin(async move {
+                    bot::spend_credits(transaction, bot_id, -refund)
+                        .await
+                        .map_err(|e| {
+                            format_error(
+                                "Failed to restore the credits of the bot due to a database error",
+                                e,
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                            )
+                        })
+                })
+            })
+            .await;
+            if let Err(undo) = restored {
+                error!(
+                    "Bot '{}' lost {} credits that were not refunded: {}",
+                    bot_id, refund, undo.message
+                );
+            }
+            return Err(format_error(
+                "Failed to return the bot credits",
+                e,
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+        state.sessions.invalidate_user(user.uid);
+    }
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = bot::find_by_user_id_and_id(transaction, user.uid, bot_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Database query failed while fetching the bot",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            // Already deleted by a concurrent request.
+            let Some(model) = model else {
+                return Ok(());
+            };
+            bot::delete(transaction, model).await.map_err(|e| {
+                format_error(
+                    "Failed to delete the bot due to a database error",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })
+        })
+    })
+    .await?;
+
+    info!(
+        "Successfully deleted bot with ID '{}', refunding {} credits.",
+        bot_id, refund
+    );
+    Ok(Json(DeleteBotResponse {
+        message: "Bot successfully deleted".to_string(),
+        credits_refunded: refund,
+    }))
+}
+
+pub async fn post_bot_message(
+    State(state): State<Arc<ServiceState>>,
+    BotAuth(bot_model): BotAuth,
+    Json(req): Json<BotMessageRequest>,
+) -> AppResult<impl IntoResponse> {
+    service::bot::post_message(state, bot_model, req).await
+}
+
+pub async fn start_widget_session(
+    State(state): State<Arc<ServiceState>>,
+    BotAuth(bot_model): BotAuth,
+    Json(req): Json<WidgetSessionRequest>,
+) -> AppResult<impl IntoResponse> {
+    let response = service::bot::start_widget_session(state, bot_model, req).await?;
+    Ok(Json(response))
+}
+
+// Widget messages always wait for the reply and stay in the token's conversation.
+pub async fn post_widget_message(
+    State(state): State<Arc<ServiceState>>,
+    widget: WidgetAuth,
+    Json(req): Json<WidgetMessageRequest>,
+) -> AppResult<impl IntoResponse> {
+    let req = BotMessageRequest {
+        text: req.text,
+        conversation_id: Some(widget.conversation_id),
+        ..Default::default()
+    };
+    service::bot::post_message(state, widget.bot, req).await
+}
@@ -1,34 +1,66 @@
-use crate::dto::request::EditTitleRequest;
+use crate::config::auto_model::AUTO_MODEL;
+use crate::dto::request::{
+    BulkConversationRequest, BulkOperation, ConversationListQuery, EditBudgetRequest,
+    EditGenerationSettingsRequest, EditLanguageRequest, EditLockRequest, EditMessageContextRequest,
+    EditRetentionRequest, EditStylePresetRequest, EditSystemPromptRequest, EditTitleRequest,
+    ImportConversationRequest, MessageOptions, RegenerateRequest, SearchQuery,
+};
 use crate::dto::response::{
-    CreateNewConversationResponse, DeleteConversationResponse, EditTitleResponse,
-    GetConversationResponse, RetrieveAllConversationResponse,
+    BulkConversationResponse, BulkConversationResult, CancelGenerationResponse,
+    ConversationSummary, CreateNewConversationResponse, DeleteConversationResponse,
+    EditArchiveResponse, EditBudgetResponse, EditGenerationSettingsResponse, EditLanguageResponse,
+    EditLockResponse, EditMessageContextResponse, EditPinResponse, EditRetentionResponse,
+    EditStylePresetResponse, EditSystemPromptResponse, EditTitleResponse, ExportedConversation,
+    GetConversationResponse, ImportConversationResponse, ModelInfo, ModelsResponse,
+    RetrieveAllConversationResponse, SearchConversationsResponse, SearchResult, StylePreset,
+    StylePresetsResponse, SuggestionsResponse,
 };
-use crate::entity::conversation::Message;
-use crate::repositories::conversation;
+use crate::entity::conversation::{reply_threads, GenerationSettings, Message, MessageType};
+use crate::repositories::conversation::{self, ConversationFilter, ConversationPage};
+use crate::repositories::{attachment, draft, usage};
 use crate::service::chat::handle_user_message;
-use crate::utils::error::format_error;
+use crate::service::extraction::{recent_transcript, suggest_follow_ups};
+use crate::service::import;
+use crate::service::quarantine;
+use crate::service::search::{escape_like, search_conversation};
+use crate::service::upload::load_attachment;
+use crate::utils::audio::AudioFormat;
+use crate::utils::error::{format_error, AppError, AppResult, ErrorCode};
 use crate::utils::jwt::UserClaims;
+use crate::utils::language::normalize_language;
+use crate::utils::metrics;
+use crate::utils::openai::validate_tools;
+use crate::utils::title::generate_title;
 use crate::ServiceState;
 use axum::{
-    extract::{Json, Multipart, Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::{Body, Bytes},
+    extract::{Json, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
-use chrono::{DateTime, Utc};
+use chrono::{Datelike, TimeZone, Utc};
 use futures::future::BoxFuture;
-use sea_orm::{DatabaseConnection, ModelTrait, TransactionTrait};
-use std::sync::Arc;
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tower_http::services::ServeFile;
 use tracing::{error, info};
 use uuid::Uuid;
 
-type AppResult<T> = Result<T, (StatusCode, String)>;
+pub const MAX_CONVERSATION_PAGE_SIZE: u64 = 100;
+const MAX_SEARCH_RESULTS: u64 = 50;
+pub const MAX_SYSTEM_PROMPT_CHARS: usize = 4000;
+const MAX_BULK_CONVERSATIONS: usize = 100;
 
-async fn handle_transaction<T, F>(db: &DatabaseConnection, operation: F) -> AppResult<T>
+pub async fn handle_transaction<T, F>(db: &DatabaseConnection, operation: F) -> AppResult<T>
 where
     F: for<'a> FnOnce(&'a mut sea_orm::DatabaseTransaction) -> BoxFuture<'a, AppResult<T>> + Send,
     T: Send + 'static,
 {
     let mut transaction = db.begin().await.map_err(|e| {
+        metrics::transaction_failed("begin");
         format_error(
             "Starting a database transaction failed",
             e,
@@ -41,6 +73,7 @@ where
     match result {
         Ok(response) => {
             transaction.commit().await.map_err(|e| {
+                metrics::transaction_failed("commit");
                 format_error(
                     "Committing the database transaction failed",
                     e,
@@ -51,6 +84,7 @@ where
         }
         Err(e) => {
             if let Err(rollback_err) = transaction.rollback().await {
+                metrics::transaction_failed("rollback");
                 error!(
                     "Rolling back the database transaction failed. Possible data inconsistency: {}",
                     rollback_err
@@ -61,6 +95,283 @@ where
     }
 }
 
+pub fn wants_sse(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+fn parse_attachment_id(data: &[u8]) -> AppResult<Uuid> {
+    let attachment_id = std::str::from_utf8(data).map_err(|e| {
+        format_error(
+            "Error parsing attachment id as string",
+            e,
+            StatusCode::BAD_REQUEST,
+        )
+    })?;
+    Uuid::parse_str(attachment_id.trim())
+        .map_err(|e| format_error("Invalid attachment id", e, StatusCode::BAD_REQUEST))
+}
+
+fn parse_reply_to(data: &[u8]) -> AppResult<usize> {
+    let reply_to = std::str::from_utf8(data).map_err(|e| {
+        format_error(
+            "Error parsing the replied-to message id as string",
+            e,
+            StatusCode::BAD_REQUEST,
+        )
+    })?;
+    reply_to.trim().parse::<usize>().map_err(|e| {
+        format_error("Invalid replied-to message id", e, StatusCode::BAD_REQUEST)
+            .with_code(ErrorCode::InvalidMessageId)
+    })
+}
+
+fn parse_tools(data: &[u8]) -> AppResult<serde_json::Value> {
+    let tools: serde_json::Value = serde_json::from_slice(data)
+        .map_err(|e| format_error("Error parsing tools as JSON", e, StatusCode::BAD_REQUEST))?;
+    validate_tools(&tools)
+        .map_err(|e| format_error("Invalid tools", e, StatusCode::BAD_REQUEST))?;
+    Ok(tools)
+}
+
+fn parse_collection_ids(data: &[u8]) -> AppResult<Vec<Uuid>> {
+    let collection_ids = std::str::from_utf8(data).map_err(|e| {
+        format_error(
+            "Error parsing collection ids as string",
+            e,
+            StatusCode::BAD_REQUEST,
+        )
+    })?;
+    collection_ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            Uuid::parse_str(id)
+                .map_err(|e| format_error("Invalid collection id", e, StatusCode::BAD_REQUEST))
+        })
+        .collect()
+}
+
+// Fields that only set a `MessageOptions` value. Unknown fields are ignored.
+fn parse_message_option(options: &mut MessageOptions, name: &str, data: &[u8]) -> AppResult<()> {
+    if name == "transcription_prompt" {
+        options.transcription_prompt = Some(String::from_utf8(data.to_vec()).map_err(|e| {
+            format_error(
+                "Error parsing transcription prompt as string",
+                e,
+                StatusCode::BAD_REQUEST,
+            )
+        })?);
+    } else if name == "markdown_mode" {
+        let markdown_mode = String::from_utf8(data.iter().as_slice().to_vec()).map_err(|e| {
+            format_error(
+                "Error parsing markdown mode as string",
+                e,
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        options.markdown_mode = Some(
+            markdown_mode
+                .parse()
+                .map_err(|e| format_error("Invalid markdown mode", e, StatusCode::BAD_REQUEST))?,
+        );
+    } else if name == "reply_mode" {
+        let reply_mode = String::from_utf8(data.to_vec()).map_err(|e| {
+            format_error(
+                "Error parsing reply mode as string",
+                e,
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        options.reply_mode = Some(
+            reply_mode
+                .parse()
+                .map_err(|e| format_error("Invalid reply mode", e, StatusCode::BAD_REQUEST))?,
+        );
+    } else if name == "style_preset" {
+        let style_preset = String::from_utf8(data.to_vec()).map_err(|e| {
+            format_error(
+                "Error parsing style preset as string",
+                e,
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        options.style_preset = Some(style_preset).filter(|name| !name.trim().is_empty());
+    } else if name == "voice_profile" {
+        let voice_profile = String::from_utf8(data.to_vec()).map_err(|e| {
+            format_error(
+                "Error parsing voice profile as string",
+                e,
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        options.voice_profile =
+            Some(voice_profile.trim().to_lowercase()).filter(|name| !name.is_empty());
+    } else if name == "stream_format" {
+        options.sse = data.trim_ascii().eq_ignore_ascii_case(b"sse");
+    } else if name == "collection_ids" {
+        options.collection_ids = parse_collection_ids(data)?;
+    } else if name == "reply_to" {
+        options.reply_to = Some(parse_reply_to(data)?);
+    }
+    Ok(())
+}
+
+// The multipart body shared by sending and editing a message.
+struct MessageForm {
+    message_type: String,
+    message_data: Vec<u8>,
+    message_model: String,
+    /// Only read when editing.
+    message_id: i64,
+    images: Vec<Bytes>,
+    image_filenames: Vec<Option<String>>,
+    voice_filename: Option<String>,
+    options: MessageOptions,
+}
+
+async fn read_message_form(
+    state: &Arc<ServiceState>,
+    user_id: i64,
+    headers: &HeaderMap,
+    multipart: &mut Multipart,
+) -> AppResult<MessageForm> {
+    let mut form = MessageForm {
+        message_type: String::new(),
+        message_data: vec![],
+        message_model: String::new(),
+        message_id: 0,
+        images: vec![],
+        image_filenames: vec![],
+        voice_filename: None,
+        options: MessageOptions {
+            sse: wants_sse(headers),
+            audio_format: AudioFormat::from_accept(headers),
+            ..Default::default()
+        },
+    };
+    let mut attachment_id: Option<Uuid> = None;
+    let mut tool_result = false;
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        format_error(
+            "Failed to read multipart fields",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })? {
+        let name = field.name();
+
+        if name.is_none() {
+            error!("Name field is empty");
+            continue;
+        }
+        let filename = field.file_name().map(|s| s.to_string());
+        let name = name.unwrap().to_string();
+        let data = field.bytes().await;
+        if data.is_err() {
+            error!("Data is missing");
+            continue;
+        }
+        let data = data.unwrap();
+        if name == "message_type" {
+            form.message_type = String::from_utf8(data.to_vec()).map_err(|e| {
+                format_error(
+                    "Error parsing message type as string",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+        } else if name == "user_message" {
+            form.message_data = data.to_vec();
+            form.voice_filename = filename;
+        } else if name == "message_id" {
+            form.message_id = String::from_utf8(data.to_vec())
+                .map_err(|e| {
+                    format_error(
+                        "Error parsing message id as string",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+                .parse::<i64>()
+                .map_err(|e| {
+                    format_error(
+                        "Error parsing string as u32",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+        } else if name == "model_name" {
+            form.message_model = String::from_utf8(data.to_vec()).map_err(|e| {
+                format_error(
+                    "Error parsing message model as string",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+        } else if name == "attachment_id" {
+            attachment_id = Some(parse_attachment_id(&data)?);
+        } else if name == "tools" {
+            form.options.tools = Some(parse_tools(&data)?);
+        } else if name == "role" {
+            // A `tool` message carries the result of a call the last reply asked for.
+            match data.trim_ascii() {
+                b"user" => {}
+                b"tool" => tool_result = true,
+                _ => {
+                    return Err(format_error(
+                        "Invalid message role",
+                        String::from_utf8_lossy(&data),
+                        StatusCode::BAD_REQUEST,
+                    ))
+                }
+            }
+        } else if name == "tool_call_id" {
+            let tool_call_id = String::from_utf8(data.to_vec()).map_err(|e| {
+                format_error(
+                    "Error parsing tool call id as string",
+                    e,
+                    StatusCode::BAD_REQUEST,
+                )
+            })?;
+            form.options.tool_call_id =
+                Some(tool_call_id.trim().to_string()).filter(|id| !id.is_empty());
+        } else if name == "images[]" {
+            info!("{:?}, {}", filename, data.len());
+            form.image_filenames.push(filename);
+            form.images.push(data);
+        } else {
+            parse_message_option(&mut form.options, &name, &data)?;
+        }
+    }
+    if tool_result {
+        form.message_type = String::from("tool-call");
+    }
+    if let Some(attachment_id) = attachment_id.filter(|_| form.message_data.is_empty()) {
+        let (filename, data) = load_attachment(state, user_id, attachment_id).await?;
+        form.message_data = data;
+        form.voice_filename = Some(filename);
+    }
+    // The model may be omitted in favour of the conversation's default model.
+    if form.message_type.is_empty() || form.message_data.is_empty() {
+        let error_message = format!(
+            "Something is missing in the payload: (type existing){}, (data existing){}",
+            !form.message_type.is_empty(),
+            !form.message_data.is_empty()
+        );
+        error!("{}", error_message);
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::BadRequest,
+            error_message,
+        ));
+    }
+    Ok(form)
+}
+
 pub async fn create_new_conversation(
     State(state): State<Arc<ServiceState>>,
     user: UserClaims,
@@ -70,17 +381,26 @@ pub async fn create_new_conversation(
         user.uid
     );
 
+    let retention_days = user
+        .session_data
+        .as_ref()
+        .and_then(|data| data.preference_i64("retention_days"))
+        .filter(|days| *days > 0)
+        .map(|days| days.min(i32::MAX as i64) as i32)
+        .or(state.config.retention.default_days);
+
     handle_transaction(&state.db, |transaction| {
         Box::pin(async move {
-            let conversation_id = conversation::new_conversation(transaction, user.uid)
-                .await
-                .map_err(|e| {
-                    format_error(
-                        "Failed to create a new conversation due to a database error",
-                        e,
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                    )
-                })?;
+            let conversation_id =
+                conversation::new_conversation(transaction, user.uid, retention_days)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to create a new conversation due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
 
             info!(
                 "Successfully created new conversation with ID '{}' for user '{}'.",
@@ -92,18 +412,156 @@ pub async fn create_new_conversation(
     .await
 }
 
+pub async fn import_conversation(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<ImportConversationRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User with ID '{}' is importing a conversation with {} messages.",
+        user.uid,
+        req.messages.len()
+    );
+    let imported = import::parse_messages(req.messages).map_err(|e| {
+        format_error(
+            "The imported conversation is invalid",
+            e,
+            StatusCode::BAD_REQUEST,
+        )
+    })?;
+    let system_prompt = req
+        .system_prompt
+        .map(|prompt| prompt.trim().to_string())
+        .filter(|prompt| !prompt.is_empty())
+        .or(imported.system_prompt);
+    if system_prompt
+        .as_ref()
+        .is_some_and(|prompt| prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS)
+    {
+        return Err(format_error(
+            "System prompt is too long. Maximum characters",
+            MAX_SYSTEM_PROMPT_CHARS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let language =
+        match req.language.as_deref() {
+            Some(code) => Some(normalize_language(code).ok_or_else(|| {
+                format_error("Unsupported language", code, StatusCode::BAD_REQUEST)
+            })?),
+            None => None,
+        };
+    let title = req
+        .title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .or_else(|| {
+            generate_title(
+                &imported.messages[0].content,
+                language.as_deref(),
+                state.config.chat.title_max_words,
+                state.config.chat.title_max_chars,
+            )
+        })
+        .unwrap_or_else(|| "New Chat".to_string());
+    let retention_days = user
+        .session_data
+        .as_ref()
+        .and_then(|data| data.preference_i64("retention_days"))
+        .filter(|days| *days > 0)
+        .map(|days| days.min(i32::MAX as i64) as i32)
+        .or(state.config.retention.default_days);
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let imported_messages = imported.messages.len();
+            let model = conversation::import_conversation(
+                transaction,
+                user.uid,
+                title,
+                imported.messages,
+                system_prompt,
+                language,
+                retention_days,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to import the conversation due to a database error",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+
+            info!(
+                "Successfully imported conversation with ID '{}' ({} messages) for user '{}'.",
+                model.id, imported_messages, user.uid
+            );
+            Ok(Json(ImportConversationResponse {
+                conversation_id: model.id,
+                title: model.title,
+                imported_messages,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
 pub async fn retrieve_all_conversations(
+    Query(query): Query<ConversationListQuery>,
     State(state): State<Arc<ServiceState>>,
     user: UserClaims,
 ) -> AppResult<impl IntoResponse> {
     info!(
-        "Retrieving all conversations for user with ID '{}'.",
-        user.uid
+        "Retrieving all conversations for user with ID '{}' with filters {:?}.",
+        user.uid, query
     );
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(format_error(
+                "Invalid date range",
+                "from must not be later than to",
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    if let Some(limit) = query.limit {
+        if limit == 0 || limit > MAX_CONVERSATION_PAGE_SIZE {
+            return Err(format_error(
+                "Invalid page size",
+                format!("limit must be between 1 and {}", MAX_CONVERSATION_PAGE_SIZE),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    let filter = ConversationFilter {
+        model: query.model.filter(|model| !model.trim().is_empty()),
+        from: query.from,
+        to: query.to,
+        has_images: query.has_images,
+        language: match query
+            .language
+            .as_deref()
+            .filter(|code| !code.trim().is_empty())
+        {
+            Some(code) => Some(normalize_language(code).ok_or_else(|| {
+                format_error("Unsupported language", code, StatusCode::BAD_REQUEST)
+            })?),
+            None => None,
+        },
+        include_archived: query.include_archived,
+    };
+    let page = ConversationPage {
+        limit: query.limit,
+        offset: query.offset.unwrap_or_default(),
+        order_by: query.order_by.unwrap_or_default(),
+        direction: query.order.unwrap_or_default(),
+    };
     handle_transaction(&state.db, |transaction| {
         Box::pin(async move {
-            let conversation_list: Vec<(Uuid, String, DateTime<Utc>)> =
-                conversation::find_by_user_id(transaction, user.uid)
+            let (conversations, total) =
+                conversation::find_page_by_user_id(transaction, user.uid, &filter, &page)
                     .await
                     .map_err(|e| {
                         format_error(
@@ -111,17 +569,71 @@ pub async fn retrieve_all_conversations(
                             e,
                             StatusCode::INTERNAL_SERVER_ERROR,
                         )
+                    })?;
+            let conversation_list: Vec<ConversationSummary> = conversations
+                .into_iter()
+                .map(ConversationSummary::from)
+                .collect();
+
+            info!(
+                "Successfully retrieved {} of {} conversations for user '{}'.",
+                conversation_list.len(),
+                total,
+                user.uid
+            );
+            Ok(Json(RetrieveAllConversationResponse {
+                conversation_list,
+                total,
+                offset: page.offset,
+                limit: page.limit,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn search_conversations(
+    Query(query): Query<SearchQuery>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    let text = query.q.trim().to_string();
+    info!(
+        "User '{}' is searching their conversations for '{}'.",
+        user.uid, text
+    );
+    if text.chars().count() < 2 {
+        return Err(format_error(
+            "Invalid search query",
+            "q must be at least 2 characters long",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_SEARCH_RESULTS);
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let pattern = format!("%{}%", escape_like(&text));
+            let results: Vec<SearchResult> =
+                conversation::search_by_user_id(transaction, user.uid, pattern, limit)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to search user's conversations due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
                     })?
                     .into_iter()
-                    .map(|x| (x.id, x.title, x.updated_at))
+                    .map(|model| search_conversation(model, &text))
                     .collect();
 
             info!(
-                "Successfully retrieved {} conversations for user '{}'.",
-                conversation_list.len(),
+                "Found {} conversations matching the search of user '{}'.",
+                results.len(),
                 user.uid
             );
-            Ok(Json(RetrieveAllConversationResponse { conversation_list }).into_response())
+            Ok(Json(SearchConversationsResponse { results }).into_response())
         })
     })
     .await
@@ -152,15 +664,27 @@ pub async fn delete_conversation(
                 )
             })?;
 
-            if conversation_model.is_none() {
+            let Some(conversation_model) = conversation_model else {
                 let error_message = "Conversation could not be found for deletion".to_string();
                 error!("Failed to delete: {}", error_message);
-                return Err((StatusCode::NOT_FOUND, error_message));
-            }
+                return Err(AppError::new(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::ConversationNotFound,
+                    error_message,
+                ));
+            };
 
-            conversation_model
-                .unwrap()
-                .delete(transaction)
+            draft::delete(transaction, user.uid, conversation_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the conversation draft due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            // The row and its media files are purged by the retention sweeper later.
+            conversation::soft_delete(transaction, conversation_model)
                 .await
                 .map_err(|e| {
                     format_error(
@@ -169,15 +693,151 @@ pub async fn delete_conversation(
                         StatusCode::INTERNAL_SERVER_ERROR,
                     )
                 })?;
+            Ok(())
+        })
+    })
+    .await?;
+
+    info!(
+        "Conversation with ID '{}' successfully deleted by user '{}'.",
+        conversation_id, user.uid
+    );
+    Ok(Json(DeleteConversationResponse {
+        message: "Conversation successfully deleted".to_string(),
+    })
+    .into_response())
+}
+
+// Applies one operation to several conversations in a single transaction. A
+// conversation that cannot be found is reported in its result instead of
+// failing the others; a database error rolls back the whole batch.
+pub async fn bulk_conversations(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<BulkConversationRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User with ID '{}' is applying {:?} to {} conversations.",
+        user.uid,
+        req.operation,
+        req.conversation_ids.len()
+    );
+    if req.conversation_ids.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::BadRequest,
+            "No conversation ids were given",
+        ));
+    }
+    if req.conversation_ids.len() > MAX_BULK_CONVERSATIONS {
+        return Err(format_error(
+            "Too many conversations in one request. Maximum",
+            MAX_BULK_CONVERSATIONS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let mut seen = HashSet::new();
+    let conversation_ids: Vec<Uuid> = req
+        .conversation_ids
+        .into_iter()
+        .filter(|id| seen.insert(*id))
+        .collect();
+    let operation = req.operation;
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let mut models: HashMap<Uuid, _> = conversation::find_by_user_id_and_ids(
+                transaction,
+                user.uid,
+                conversation_ids.clone(),
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversations from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .into_iter()
+            .map(|model| (model.id, model))
+            .collect();
+
+            let mut response = BulkConversationResponse::default();
+            for conversation_id in conversation_ids {
+                let Some(model) = models.remove(&conversation_id) else {
+                    response.failed += 1;
+                    response.results.push(BulkConversationResult {
+                        conversation_id,
+                        success: false,
+                        error: Some("Conversation could not be found".to_string()),
+                        conversation: None,
+                    });
+                    continue;
+                };
+                let exported = match operation {
+                    BulkOperation::Delete => {
+                        draft::delete(transaction, user.uid, conversation_id)
+                            .await
+                            .map_err(|e| {
+                                format_error(
+                                    "Failed to delete the conversation draft due to a database error",
+                                    e,
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                )
+                            })?;
+                        conversation::soft_delete(transaction, model)
+                            .await
+                            .map_err(|e| {
+                                format_error(
+                                    "Failed to delete the conversation due to a database error",
+                                    e,
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                )
+                            })?;
+                        None
+                    }
+                    BulkOperation::Archive | BulkOperation::Unarchive => {
+                        let archived = operation == BulkOperation::Archive;
+                        conversation::set_archived(transaction, model, archived)
+                            .await
+                            .map_err(|e| {
+                                format_error(
+                                    "Error updating the conversation archive state in the database",
+                                    e,
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                )
+                            })?;
+                        None
+                    }
+                    BulkOperation::Export => {
+                        let (messages, _) =
+                            quarantine::load_messages(conversation_id, model.conversation);
+                        Some(ExportedConversation {
+                            title: model.title,
+                            system_prompt: model.system_prompt,
+                            language: model.language,
+                            messages,
+                        })
+                    }
+                };
+                response.succeeded += 1;
+                response.results.push(BulkConversationResult {
+                    conversation_id,
+                    success: true,
+                    error: None,
+                    conversation: exported,
+                });
+            }
 
             info!(
-                "Conversation with ID '{}' successfully deleted by user '{}'.",
-                conversation_id, user.uid
+                "Applied {:?} to {} of {} conversations for user '{}'.",
+                operation,
+                response.succeeded,
+                response.results.len(),
+                user.uid
             );
-            Ok(Json(DeleteConversationResponse {
-                message: "Conversation successfully deleted".to_string(),
-            })
-            .into_response())
+            Ok(Json(response).into_response())
         })
     })
     .await
@@ -209,30 +869,35 @@ pub async fn get_conversation(
             })?;
 
             if let Some(model) = conversation_model {
+                let generation_settings = model.generation_settings();
                 info!(
                     "Successfully retrieved details for conversation with ID '{}' for user '{}'.",
                     conversation_id, user.uid
                 );
-                let message_result: Result<Vec<Message>, serde_json::Error> = model
-                    .conversation
-                    .into_iter()
-                    .map(|v| serde_json::from_value::<Message>(v))
-                    .collect();
-                let message_result = message_result.map_err(|e| {
-                    format_error(
-                        "Error converting to Message array",
-                        e,
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                    )
-                })?;
+                let (messages, corrupted) =
+                    quarantine::load_messages(conversation_id, model.conversation);
+                let replies = reply_threads(&messages);
                 Ok(Json(GetConversationResponse {
-                    messages: message_result,
+                    messages,
+                    replies,
+                    corrupted,
+                    locked: model.locked,
+                    max_credits: model.max_credits,
+                    credits_spent: model.credits_spent,
+                    system_prompt: model.system_prompt,
+                    style_preset: model.style_preset,
+                    voice_profile: model.voice_profile,
+                    generation_settings,
                 })
                 .into_response())
             } else {
                 let error_message = "Requested conversation could not be found".to_string();
                 error!("Failed to retrieve: {}", error_message);
-                Err((StatusCode::NOT_FOUND, error_message))
+                Err(AppError::new(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::ConversationNotFound,
+                    error_message,
+                ))
             }
         })
     })
@@ -242,171 +907,143 @@ pub async fn get_conversation(
 pub async fn send_message(
     Path(conversation_id): Path<Uuid>,
     State(state): State<Arc<ServiceState>>,
-    user: UserClaims,
+    mut user: UserClaims,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> AppResult<impl IntoResponse> {
-    let mut message_type = String::from("");
-    let mut message_data: Vec<u8> = vec![];
-    let mut message_model: String = String::from("");
-    let mut images = vec![];
-    let mut image_filenames = vec![];
-    let mut voice_filename: Option<String> = None;
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        format_error(
-            "Failed to read multipart fields",
-            e,
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })? {
-        let name = field.name();
-
-        if name.is_none() {
-            error!("Name field is empty");
-            continue;
-        }
-        let filename = field.file_name().map(|s| s.to_string());
-        let name = name.unwrap().to_string();
-        let data = field.bytes().await;
-        if data.is_err() {
-            error!("Data is missing");
-            continue;
-        }
-        let data = data.unwrap();
-        if name == String::from("message_type") {
-            message_type = String::from_utf8(data.iter().as_slice().to_vec()).map_err(|e| {
-                format_error(
-                    "Error parsing message type as string",
-                    e,
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                )
-            })?;
-        } else if name == String::from("user_message") {
-            message_data = data.iter().as_slice().to_vec();
-            voice_filename = filename;
-        } else if name == String::from("model_name") {
-            message_model = String::from_utf8(data.iter().as_slice().to_vec()).map_err(|e| {
-                format_error(
-                    "Error parsing message model as string",
-                    e,
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                )
-            })?;
-        } else if name == String::from("images[]") {
-            info!("{:?}, {}", filename, data.len());
-            image_filenames.push(filename);
-            images.push(data.clone());
-        }
-    }
-    if message_type.is_empty() || message_data.is_empty() || message_model.is_empty() {
-        let error_message = format!("Something is missing in the payload: (type existing){}, (data existing){}, (model existing){}", !message_type.is_empty(), !message_data.is_empty(), !message_model.is_empty());
-        error!("{}", error_message);
-        return Err((StatusCode::BAD_REQUEST, error_message));
-    }
-    info!(
-        "User '{}' is attempting to send a message to conversation '{}'. Message type: {}, Message Model: {}",
-        user.uid, conversation_id, message_type, message_model
-    );
+    // The turn is charged against this balance, so a cached one is not good enough.
+    user.refresh_session(&state).await?;
+    let form = read_message_form(&state, user.uid, &headers, &mut multipart).await?;
+    info!(
+        "User '{}' is attempting to send a message to conversation '{}'. Message type: {}, Message Model: {}",
+        user.uid, conversation_id, form.message_type, form.message_model
+    );
 
     handle_user_message(
         state.clone(),
         user.uid,
         user.session_data,
         conversation_id,
-        message_type,
-        message_data,
-        message_model,
-        images,
+        form.message_type,
+        form.message_data,
+        form.message_model,
+        form.images,
         -1,
-        voice_filename,
-        image_filenames,
+        form.voice_filename,
+        form.image_filenames,
+        form.options,
     )
     .await
 }
 
-pub async fn edit_message(
+pub async fn regenerate_message(
     Path(conversation_id): Path<Uuid>,
     State(state): State<Arc<ServiceState>>,
-    user: UserClaims,
-    mut multipart: Multipart,
+    mut user: UserClaims,
+    headers: HeaderMap,
+    Json(req): Json<RegenerateRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let mut message_type = String::from("");
-    let mut message_data: Vec<u8> = vec![];
-    let mut message_model: String = String::from("");
-    let mut message_id = 0 as i64;
-    let mut images = vec![];
-    let mut image_filenames = vec![];
-    let mut voice_filename: Option<String> = None;
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        format_error(
-            "Failed to read multipart fields",
-            e,
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })? {
-        let name = field.name();
-
-        if name.is_none() {
-            error!("Name field is empty");
-            continue;
-        }
-        let filename = field.file_name().map(|s| s.to_string());
-        let name = name.unwrap().to_string();
-        let data = field.bytes().await;
-        if data.is_err() {
-            error!("Data is missing");
-            continue;
-        }
-        let data = data.unwrap();
-
-        if name == String::from("message_type") {
-            message_type = String::from_utf8(data.iter().as_slice().to_vec()).map_err(|e| {
+    info!(
+        "User '{}' is regenerating message {:?} of conversation '{}'.",
+        user.uid, req.message_id, conversation_id
+    );
+    user.refresh_session(&state).await?;
+    let messages = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
                 format_error(
-                    "Error parsing message type as string",
+                    "Error fetching the conversation from the database",
                     e,
                     StatusCode::INTERNAL_SERVER_ERROR,
                 )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
             })?;
-        } else if name == String::from("user_message") {
-            message_data = data.iter().as_slice().to_vec();
-            voice_filename = filename;
-        } else if name == String::from("message_id") {
-            message_id = String::from_utf8(data.iter().as_slice().to_vec())
-                .map_err(|e| {
-                    format_error(
-                        "Error parsing message id as string",
-                        e,
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                    )
-                })?
-                .parse::<i64>()
+            model
+                .conversation
+                .into_iter()
+                .map(Message::from_stored)
+                .collect::<Result<Vec<_>, _>>()
                 .map_err(|e| {
                     format_error(
-                        "Error parsing string as u32",
+                        "Failed to read the stored conversation history",
                         e,
                         StatusCode::INTERNAL_SERVER_ERROR,
                     )
-                })?;
-        } else if name == String::from("model_name") {
-            message_model = String::from_utf8(data.iter().as_slice().to_vec()).map_err(|e| {
-                format_error(
-                    "Error parsing message model as string",
-                    e,
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                )
-            })?;
-        } else if name == String::from("images[]") {
-            image_filenames.push(filename);
-            images.push(data.clone());
-        }
-    }
-    if message_type.is_empty() || message_data.is_empty() || message_model.is_empty() {
-        let error_message = format!("Something is missing in the payload: (type existing){}, (data existing){}, (model existing){}", message_type.is_empty(), message_data.is_empty(), message_model.is_empty());
-        error!("{}", error_message);
-        return Err((StatusCode::BAD_REQUEST, error_message));
+                })
+        })
+    })
+    .await?;
+
+    let turns = (messages.len() / 2) as i64;
+    let message_id = req.message_id.unwrap_or(turns - 1);
+    if message_id < 0 || message_id >= turns {
+        return Err(
+            format_error("Invalid Message Id", message_id, StatusCode::BAD_REQUEST)
+                .with_code(ErrorCode::InvalidMessageId),
+        );
     }
+    let stored = messages[(message_id * 2) as usize].clone();
+    let previous_model = messages
+        .get((message_id * 2 + 1) as usize)
+        .and_then(|answer| answer.model.clone());
+    // Without either, the conversation's default model is used.
+    let message_model = req.model_name.or(previous_model).unwrap_or_default();
+    let message_type = match stored.msgtype {
+        MessageType::Text => "text",
+        MessageType::Voice => "voice",
+        MessageType::ImageGeneration => "image-generation",
+        MessageType::ToolCall => "tool-call",
+    };
+    let options = MessageOptions {
+        reply_mode: req.reply_mode.or(stored.reply_mode),
+        sse: wants_sse(&headers),
+        audio_format: AudioFormat::from_accept(&headers),
+        regenerate: Some(stored),
+        ..Default::default()
+    };
+
+    handle_user_message(
+        state.clone(),
+        user.uid,
+        user.session_data,
+        conversation_id,
+        message_type.to_string(),
+        vec![],
+        message_model,
+        vec![],
+        message_id,
+        None,
+        vec![],
+        options,
+    )
+    .await
+}
+
+pub async fn edit_message(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    mut user: UserClaims,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> AppResult<impl IntoResponse> {
+    user.refresh_session(&state).await?;
+    let form = read_message_form(&state, user.uid, &headers, &mut multipart).await?;
     info!(
         "User '{}' is attempting to send a message to conversation '{}'. Message type: {}, Message Model: {}",
-        user.uid, conversation_id, message_type, message_model
+        user.uid, conversation_id, form.message_type, form.message_model
     );
 
     handle_user_message(
@@ -414,17 +1051,83 @@ pub async fn edit_message(
         user.uid,
         user.session_data,
         conversation_id,
-        message_type,
-        message_data,
-        message_model,
-        images,
-        message_id,
-        voice_filename,
-        image_filenames,
+        form.message_type,
+        form.message_data,
+        form.message_model,
+        form.images,
+        form.message_id,
+        form.voice_filename,
+        form.image_filenames,
+        form.options,
     )
     .await
 }
 
+// Replays the stored voice answer of a turn. `ServeFile` answers `Range`
+// requests, so clients can seek without downloading the whole file.
+pub async fn get_message_audio(
+    Path((conversation_id, message_id)): Path<(Uuid, usize)>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    req: Request,
+) -> AppResult<Response> {
+    info!(
+        "User '{}' is requesting the audio of message {} in conversation '{}'.",
+        user.uid, message_id, conversation_id
+    );
+    let audio = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            // Both messages of a turn share its id; only the answer has audio.
+            let (messages, _) = quarantine::load_messages(conversation_id, model.conversation);
+            messages
+                .into_iter()
+                .filter(|message| message.id == message_id)
+                .find_map(|message| message.audio)
+                .ok_or_else(|| {
+                    format_error(
+                        "The message has no stored audio",
+                        message_id,
+                        StatusCode::NOT_FOUND,
+                    )
+                })
+        })
+    })
+    .await?;
+
+    let response = ServeFile::new(format!("./public/{}", audio))
+        .try_call(req)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to read the stored audio",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    Ok(response.map(Body::new))
+}
+
 pub async fn edit_title(
     Path(conversation_id): Path<Uuid>,
     State(state): State<Arc<ServiceState>>,
@@ -437,6 +1140,27 @@ pub async fn edit_title(
     );
     handle_transaction(&state.db, |transaction| {
         Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            if model.is_some_and(|model| model.locked) {
+                return Err(format_error(
+                    "The conversation is locked and can no longer be edited",
+                    conversation_id,
+                    StatusCode::LOCKED,
+                )
+                .with_code(ErrorCode::ConversationLocked));
+            }
             conversation::edit_title(transaction, user.uid, conversation_id, req.title.clone())
                 .await
                 .map_err(|e| {
@@ -459,3 +1183,899 @@ pub async fn edit_title(
     })
     .await
 }
+
+pub async fn edit_retention(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditRetentionRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the retention of conversation '{}' to {:?} days.",
+        user.uid, conversation_id, req.retention_days
+    );
+    if req.retention_days.is_some_and(|days| days <= 0) {
+        return Err(format_error(
+            "Invalid retention period",
+            "retention_days must be a positive number of days",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::set_retention(
+                transaction,
+                user.uid,
+                conversation_id,
+                req.retention_days,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error updating the conversation retention in the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+
+            info!(
+                "Successfully updated retention for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditRetentionResponse {
+                message: "Retention successfully updated".to_string(),
+                expires_at: model.expires_at,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_language(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditLanguageRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the language of conversation '{}' to {:?}.",
+        user.uid, conversation_id, req.language
+    );
+    let language =
+        match req.language.as_deref() {
+            Some(code) => Some(normalize_language(code).ok_or_else(|| {
+                format_error("Unsupported language", code, StatusCode::BAD_REQUEST)
+            })?),
+            None => None,
+        };
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model =
+                conversation::set_language(transaction, user.uid, conversation_id, language)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Error updating the conversation language in the database",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+
+            info!(
+                "Successfully updated language for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditLanguageResponse {
+                message: "Language successfully updated".to_string(),
+                language: model.language,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_message_context(
+    Path((conversation_id, message_id)): Path<(Uuid, usize)>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditMessageContextRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the context exclusion of message {} in conversation '{}' to {}.",
+        user.uid, message_id, conversation_id, req.excluded
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            if model.locked {
+                return Err(format_error(
+                    "The conversation is locked and can no longer be edited",
+                    conversation_id,
+                    StatusCode::LOCKED,
+                )
+                .with_code(ErrorCode::ConversationLocked));
+            }
+
+            // Message ids are 1-based positions in the conversation.
+            let mut messages = model.conversation.clone();
+            let Some(stored) = message_id
+                .checked_sub(1)
+                .and_then(|index| messages.get_mut(index))
+            else {
+                return Err(format_error(
+                    "Invalid Message Id",
+                    message_id,
+                    StatusCode::BAD_REQUEST,
+                )
+                .with_code(ErrorCode::InvalidMessageId));
+            };
+            let mut message = Message::from_stored(stored.clone()).map_err(|e| {
+                format_error(
+                    "The message could not be read and must be repaired first",
+                    e,
+                    StatusCode::CONFLICT,
+                )
+            })?;
+            message.excluded = req.excluded;
+            *stored = serde_json::to_value(&message).map_err(|e| {
+                format_error(
+                    "Failed to serialize the updated message",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            conversation::replace_messages(transaction, model, messages)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation messages in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated context exclusion of message {} in conversation '{}'.",
+                message_id, conversation_id
+            );
+            Ok(Json(EditMessageContextResponse {
+                message: "Message context successfully updated".to_string(),
+                message_id,
+                excluded: req.excluded,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn archive_conversation(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    set_archived(state, user, conversation_id, true).await
+}
+
+pub async fn unarchive_conversation(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    set_archived(state, user, conversation_id, false).await
+}
+
+async fn set_archived(
+    state: Arc<ServiceState>,
+    user: UserClaims,
+    conversation_id: Uuid,
+    archived: bool,
+) -> AppResult<Response> {
+    info!(
+        "User '{}' is setting the archive state of conversation '{}' to {}.",
+        user.uid, conversation_id, archived
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            let model = conversation::set_archived(transaction, model, archived)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation archive state in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated archive state for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditArchiveResponse {
+                message: "Archive state successfully updated".to_string(),
+                archived_at: model.archived_at,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn pin_conversation(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    set_pinned(state, user, conversation_id, true).await
+}
+
+pub async fn unpin_conversation(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    set_pinned(state, user, conversation_id, false).await
+}
+
+async fn set_pinned(
+    state: Arc<ServiceState>,
+    user: UserClaims,
+    conversation_id: Uuid,
+    pinned: bool,
+) -> AppResult<Response> {
+    info!(
+        "User '{}' is setting the pin state of conversation '{}' to {}.",
+        user.uid, conversation_id, pinned
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            let model = conversation::set_pinned(transaction, model, pinned)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation pin state in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            Ok(Json(EditPinResponse {
+                message: "Pin state successfully updated".to_string(),
+                pinned_at: model.pinned_at,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_lock(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditLockRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the lock of conversation '{}' to {}.",
+        user.uid, conversation_id, req.locked
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            if model.locked_by_admin && !req.locked {
+                return Err(format_error(
+                    "The conversation was locked by an administrator and cannot be unlocked",
+                    conversation_id,
+                    StatusCode::FORBIDDEN,
+                ));
+            }
+            let model = conversation::set_locked(transaction, model, req.locked, false)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation lock in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated lock for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditLockResponse {
+                message: "Lock successfully updated".to_string(),
+                locked: model.locked,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_system_prompt(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditSystemPromptRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is updating the system prompt of conversation '{}'.",
+        user.uid, conversation_id
+    );
+    let system_prompt = req
+        .system_prompt
+        .map(|prompt| prompt.trim().to_string())
+        .filter(|prompt| !prompt.is_empty());
+    if system_prompt
+        .as_ref()
+        .is_some_and(|prompt| prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS)
+    {
+        return Err(format_error(
+            "System prompt is too long. Maximum characters",
+            MAX_SYSTEM_PROMPT_CHARS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            if model.locked {
+                return Err(format_error(
+                    "The conversation is locked and can no longer be edited",
+                    conversation_id,
+                    StatusCode::LOCKED,
+                )
+                .with_code(ErrorCode::ConversationLocked));
+            }
+            let model = conversation::set_system_prompt(transaction, model, system_prompt)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation system prompt in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated system prompt for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditSystemPromptResponse {
+                message: "System prompt successfully updated".to_string(),
+                system_prompt: model.system_prompt,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_style_preset(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditStylePresetRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the style preset of conversation '{}' to {:?}.",
+        user.uid, conversation_id, req.style_preset
+    );
+    let style_preset = req
+        .style_preset
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty());
+    if let Some(name) = style_preset.as_deref() {
+        if state.config.style.prompt_for(name).is_none() {
+            return Err(format_error(
+                "Unknown style preset",
+                name,
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            if model.locked {
+                return Err(format_error(
+                    "The conversation is locked and can no longer be edited",
+                    conversation_id,
+                    StatusCode::LOCKED,
+                )
+                .with_code(ErrorCode::ConversationLocked));
+            }
+            let model = conversation::set_style_preset(transaction, model, style_preset)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation style preset in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated style preset for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditStylePresetResponse {
+                message: "Style preset successfully updated".to_string(),
+                style_preset: model.style_preset,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_generation_settings(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditGenerationSettingsRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the generation settings of conversation '{}' to {:?}.",
+        user.uid, conversation_id, req
+    );
+    let settings = GenerationSettings {
+        model: req
+            .model
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty()),
+        temperature: req.temperature,
+        top_p: req.top_p,
+        max_tokens: req.max_tokens,
+        tools: req.tools,
+    };
+    if let Some(model) = settings.model.as_deref() {
+        if model != AUTO_MODEL && state.registry.price(model).is_none() {
+            return Err(state.registry.unknown_model(model));
+        }
+    }
+    if settings
+        .temperature
+        .is_some_and(|temperature| !(0.0..=2.0).contains(&temperature))
+    {
+        return Err(format_error(
+            "Invalid temperature",
+            "temperature must be between 0 and 2",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if settings
+        .top_p
+        .is_some_and(|top_p| !(top_p > 0.0 && top_p <= 1.0))
+    {
+        return Err(format_error(
+            "Invalid top_p",
+            "top_p must be greater than 0 and at most 1",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if settings.max_tokens == Some(0) {
+        return Err(format_error(
+            "Invalid max_tokens",
+            "max_tokens must be at least 1",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if let Some(tools) = settings.tools.as_ref() {
+        validate_tools(tools)
+            .map_err(|e| format_error("Invalid tools", e, StatusCode::BAD_REQUEST))?;
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            if model.locked {
+                return Err(format_error(
+                    "The conversation is locked and can no longer be edited",
+                    conversation_id,
+                    StatusCode::LOCKED,
+                )
+                .with_code(ErrorCode::ConversationLocked));
+            }
+            let model = conversation::set_generation_settings(transaction, model, settings)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation generation settings in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated generation settings for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditGenerationSettingsResponse {
+                message: "Generation settings successfully updated".to_string(),
+                settings: model.generation_settings(),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn list_style_presets(State(state): State<Arc<ServiceState>>) -> impl IntoResponse {
+    let presets = state
+        .config
+        .style
+        .presets
+        .iter()
+        .map(|(name, prompt)| StylePreset {
+            name: name.clone(),
+            prompt: prompt.clone(),
+        })
+        .collect();
+    Json(StylePresetsResponse { presets })
+}
+
+pub async fn list_models(State(state): State<Arc<ServiceState>>) -> impl IntoResponse {
+    let models = state
+        .registry
+        .available_models()
+        .into_iter()
+        .filter_map(|name| {
+            Some(ModelInfo {
+                credits: state.registry.price(&name),
+                capabilities: state.registry.capabilities(&name)?,
+                name,
+            })
+        })
+        .collect();
+    Json(ModelsResponse { models })
+}
+
+pub async fn edit_budget(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditBudgetRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is setting the credit ceiling of conversation '{}' to {:?}.",
+        user.uid, conversation_id, req.max_credits
+    );
+    if req.max_credits.is_some_and(|max_credits| max_credits < 0) {
+        return Err(format_error(
+            "Invalid credit ceiling",
+            "max_credits must not be negative",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching the conversation from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .ok_or_else(|| {
+                format_error(
+                    "Requested conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound)
+            })?;
+            let model = conversation::set_max_credits(transaction, model, req.max_credits)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation credit ceiling in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Successfully updated credit ceiling for conversation with ID '{}'.",
+                conversation_id
+            );
+            Ok(Json(EditBudgetResponse {
+                message: "Credit ceiling successfully updated".to_string(),
+                max_credits: model.max_credits,
+                credits_spent: model.credits_spent,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn cancel_generation(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is cancelling the reply in conversation '{}'.",
+        user.uid, conversation_id
+    );
+    if !state.generations.cancel(conversation_id, user.uid) {
+        return Err(format_error(
+            "No reply is being generated in the conversation",
+            conversation_id,
+            StatusCode::NOT_FOUND,
+        ));
+    }
+    Ok(Json(CancelGenerationResponse {
+        message: "Generation successfully cancelled".to_string(),
+    }))
+}
+
+pub async fn get_suggestions(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is requesting follow-up suggestions for conversation '{}'.",
+        user.uid, conversation_id
+    );
+    let transcript = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let conversation_model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Error fetching conversation details from the database",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            let Some(model) = conversation_model else {
+                let error_message = "Requested conversation could not be found".to_string();
+                error!("Failed to retrieve: {}", error_message);
+                return Err(AppError::new(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::ConversationNotFound,
+                    error_message,
+                ));
+            };
+            recent_transcript(&model).map_err(|e| {
+                format_error(
+                    "Failed to read the stored conversation history",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })
+        })
+    })
+    .await?;
+
+    if transcript.trim().is_empty() {
+        return Ok(Json(SuggestionsResponse {
+            suggestions: vec![],
+        }));
+    }
+    let suggestions = suggest_follow_ups(&state, transcript).await.map_err(|e| {
+        format_error(
+            "Failed to generate follow-up suggestions",
+            e,
+            StatusCode::BAD_GATEWAY,
+        )
+    })?;
+    Ok(Json(SuggestionsResponse { suggestions }))
+}
+
+pub async fn get_summary(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!("Retrieving the usage summary for user '{}'.", user.uid);
+    let credits_remaining = user
+        .session_data
+        .as_ref()
+        .map(|s| s.credits_remaining)
+        .unwrap_or_default();
+    let now = Utc::now();
+    let period_start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now);
+    let ((conversations, messages, media_bytes), attachment_bytes, credits_spent) =
+        handle_transaction(&state.db, |transaction| {
+            Box::pin(async move {
+                let totals = conversation::usage_totals(transaction, user.uid)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to fetch user's conversations due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+                let attachment_bytes = attachment::total_size_by_user_id(transaction, user.uid)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to fetch user's attachments due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+                let credits_spent = usage::sum_since(transaction, user.uid, period_start)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to fetch user's credit usage due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+                Ok((totals, attachment_bytes, credits_spent))
+            })
+        })
+        .await?;
+
+    Ok(Json(UsageSummaryResponse {
+        total_conversations: conversations.max(0) as usize,
+        total_messages: messages.max(0) as usize,
+        storage_bytes: (media_bytes + attachment_bytes).max(0) as u64,
+        credits_spent,
+        credits_remaining,
+        period_start,
+    }))
+}
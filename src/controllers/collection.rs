@@ -0,0 +1,455 @@
+use crate::{
+    config::constant::EMBEDDING_MODELS,
+    controllers::chat::handle_transaction,
+    dto::{
+        request::{AddDocumentRequest, CreateCollectionRequest, ReindexCollectionRequest},
+        response::{
+            AddDocumentResponse, CreateCollectionResponse, DeleteCollectionResponse,
+            DeleteDocumentResponse, GetCollectionResponse, ReindexCollectionResponse,
+            RetrieveAllCollectionsResponse,
+        },
+    },
+    entity::{
+        collection::{self as collection_entity, CollectionScope},
+        document as document_entity,
+    },
+    repositories::collection,
+    service::retrieval,
+    utils::{
+        error::{format_error, AppResult, ErrorCode},
+        jwt::UserClaims,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use sea_orm::DatabaseTransaction;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+fn validate_embedding_model(embedding_model: &str) -> AppResult<()> {
+    if !EMBEDDING_MODELS.contains(&embedding_model) {
+        return Err(format_error(
+            "Unsupported embedding model",
+            embedding_model,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    Ok(())
+}
+
+async fn find_readable_collection(
+    tx: &DatabaseTransaction,
+    user: &UserClaims,
+    collection_id: Uuid,
+) -> AppResult<collection_entity::Model> {
+    let organization_id = user.session_data.as_ref().and_then(|s| s.organization_id());
+    let model = collection::find_by_id(tx, collection_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Database query failed while fetching the specified collection",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .filter(|model| model.is_readable_by(user.uid, organization_id.as_deref()));
+    model.ok_or_else(|| {
+        format_error(
+            "Collection could not be found",
+            collection_id,
+            StatusCode::NOT_FOUND,
+        )
+        .with_code(ErrorCode::CollectionNotFound)
+    })
+}
+
+async fn find_owned_collection(
+    tx: &DatabaseTransaction,
+    user: &UserClaims,
+    collection_id: Uuid,
+) -> AppResult<collection_entity::Model> {
+    let model = find_readable_collection(tx, user, collection_id).await?;
+    if model.user_id != user.uid {
+        return Err(format_error(
+            "Only the owner can modify the collection",
+            collection_id,
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    Ok(model)
+}
+
+async fn find_documents(
+    tx: &DatabaseTransaction,
+    collection_id: Uuid,
+) -> AppResult<Vec<document_entity::Model>> {
+    collection::find_documents(tx, collection_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to fetch the collection's documents due to a database error",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })
+}
+
+pub async fn create_collection(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<CreateCollectionRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is creating the collection '{}'.",
+        user.uid, req.name
+    );
+    if req.name.trim().is_empty() {
+        return Err(format_error(
+            "Invalid collection name",
+            "name must not be empty",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let embedding_model = req
+        .embedding_model
+        .clone()
+        .unwrap_or_else(|| state.config.rag.default_embedding_model.clone());
+    validate_embedding_model(&embedding_model)?;
+
+    let scope = req.scope.unwrap_or(CollectionScope::User);
+    let organization_id = user.session_data.as_ref().and_then(|s| s.organization_id());
+    if scope == CollectionScope::Organization && organization_id.is_none() {
+        return Err(format_error(
+            "Organization collections require an organization",
+            user.uid,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = collection::create(
+                transaction,
+                user.uid,
+                organization_id,
+                scope,
+                req.name.trim().to_string(),
+                embedding_model,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to create a new collection due to a database error",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+
+            info!(
+                "Successfully created collection with ID '{}' for user '{}'.",
+                model.id, user.uid
+            );
+            Ok(Json(CreateCollectionResponse {
+                collection_id: model.id,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn retrieve_all_collections(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "Retrieving all collections for user with ID '{}'.",
+        user.uid
+    );
+    let organization_id = user.session_data.as_ref().and_then(|s| s.organization_id());
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let collection_list =
+                collection::find_visible(transaction, user.uid, organization_id.as_deref())
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to fetch user's collections due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+
+            info!(
+                "Successfully retrieved {} collections for user '{}'.",
+                collection_list.len(),
+                user.uid
+            );
+            Ok(Json(RetrieveAllCollectionsResponse { collection_list }).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn get_collection(
+    Path(collection_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is requesting details for collection '{}'.",
+        user.uid, collection_id
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = find_readable_collection(transaction, &user, collection_id).await?;
+            let documents = find_documents(transaction, collection_id).await?;
+            Ok(Json(GetCollectionResponse {
+                collection: model,
+                documents,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn delete_collection(
+    Path(collection_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is attempting to delete collection '{}'.",
+        user.uid, collection_id
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            find_owned_collection(transaction, &user, collection_id).await?;
+            collection::delete(transaction, collection_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the collection due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Collection with ID '{}' successfully deleted by user '{}'.",
+                collection_id, user.uid
+            );
+            Ok(Json(DeleteCollectionResponse {
+                message: "Collection successfully deleted".to_string(),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn add_document(
+    Path(collection_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<AddDocumentRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is adding the document '{}' to collection '{}'.",
+        user.uid, req.title, collection_id
+    );
+    if req.content.trim().is_empty() {
+        return Err(format_error(
+            "Invalid document",
+            "content must not be empty",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    // Embedding is slow, so it happens between two short transactions.
+    let reader = user.clone();
+    let model = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move { find_owned_collection(transaction, &reader, collection_id).await })
+    })
+    .await?;
+    let embedding_model = model.embedding_model;
+    let chunks = retrieval::embed_document(&state, &embedding_model, &req.content)
+        .await
+        .map_err(|e| format_error("Failed to index the document", e, StatusCode::BAD_GATEWAY))?;
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = find_owned_collection(transaction, &user, collection_id).await?;
+            if model.embedding_model != embedding_model {
+                return Err(format_error(
+                    "The collection's embedding model changed while the document was indexed",
+                    collection_id,
+                    StatusCode::CONFLICT,
+                ));
+            }
+            let document =
+                collection::add_document(transaction, collection_id, req.title, req.content)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to save the document due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+            let document = collection::replace_chunks(transaction, document, chunks)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to save the document chunks due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+
+            info!(
+                "Indexed document '{}' into {} chunks for collection '{}'.",
+                document.id, document.chunk_count, collection_id
+            );
+            Ok(Json(AddDocumentResponse { document }).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn delete_document(
+    Path((collection_id, document_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is removing document '{}' from collection '{}'.",
+        user.uid, document_id, collection_id
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            find_owned_collection(transaction, &user, collection_id).await?;
+            let deleted = collection::delete_document(transaction, collection_id, document_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the document due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            if !deleted {
+                return Err(format_error(
+                    "Document could not be found in the collection",
+                    document_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::DocumentNotFound));
+            }
+            Ok(Json(DeleteDocumentResponse {
+                message: "Document successfully deleted".to_string(),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn reindex_collection(
+    Path(collection_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<ReindexCollectionRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is reindexing collection '{}' with embedding model {:?}.",
+        user.uid, collection_id, req.embedding_model
+    );
+    if let Some(embedding_model) = req.embedding_model.as_deref() {
+        validate_embedding_model(embedding_model)?;
+    }
+    let reader = user.clone();
+    let (model, documents) = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = find_owned_collection(transaction, &reader, collection_id).await?;
+            let documents = find_documents(transaction, collection_id).await?;
+            Ok((model, documents))
+        })
+    })
+    .await?;
+    let embedding_model = req.embedding_model.unwrap_or(model.embedding_model);
+    let mut indexed = Vec::with_capacity(documents.len());
+    for document in documents {
+        let chunks = retrieval::embed_document(&state, &embedding_model, &document.content)
+            .await
+            .map_err(|e| {
+                format_error("Failed to reindex the document", e, StatusCode::BAD_GATEWAY)
+            })?;
+        indexed.push((document, chunks));
+    }
+
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let mut model = find_owned_collection(transaction, &user, collection_id).await?;
+            let current = find_documents(transaction, collection_id).await?;
+            // A document added in the meantime has no chunks for the new model.
+            if current
+                .iter()
+                .any(|document| !indexed.iter().any(|(indexed, _)| indexed.id == document.id))
+            {
+                return Err(format_error(
+                    "The collection changed while it was reindexed",
+                    collection_id,
+                    StatusCode::CONFLICT,
+                ));
+            }
+            if model.embedding_model != embedding_model {
+                model = collection::set_embedding_model(transaction, model, embedding_model)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to update the collection's embedding model",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+            }
+
+            let document_count = current.len();
+            let mut chunks = 0;
+            for (document, document_chunks) in indexed {
+                // Deleted while it was being embedded.
+                if !current.iter().any(|current| current.id == document.id) {
+                    continue;
+                }
+                let document = collection::replace_chunks(transaction, document, document_chunks)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to save the document chunks due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+                chunks += document.chunk_count as i64;
+            }
+
+            info!(
+                "Reindexed {} documents into {} chunks for collection '{}'.",
+                document_count, chunks, collection_id
+            );
+            Ok(Json(ReindexCollectionResponse {
+                embedding_model: model.embedding_model,
+                documents: document_count,
+                chunks,
+            })
+            .into_response())
+        })
+    })
+    .await
+}
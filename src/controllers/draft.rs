@@ -0,0 +1,193 @@
+use crate::{
+    controllers::chat::handle_transaction,
+    dto::{
+        request::EditDraftRequest,
+        response::{DeleteDraftResponse, DraftResponse},
+    },
+    repositories::{attachment, conversation, draft},
+    utils::{
+        error::{format_error, AppResult, ErrorCode},
+        jwt::UserClaims,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use sea_orm::DatabaseTransaction;
+use std::{collections::HashSet, sync::Arc};
+use tracing::info;
+use uuid::Uuid;
+
+const MAX_DRAFT_CHARS: usize = 20000;
+const MAX_DRAFT_ATTACHMENTS: usize = 10;
+
+async fn ensure_conversation(
+    transaction: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> AppResult<()> {
+    conversation::find_by_user_id_and_conversation_id(transaction, user_id, conversation_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Error fetching the conversation from the database",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .ok_or_else(|| {
+            format_error(
+                "Requested conversation could not be found",
+                conversation_id,
+                StatusCode::NOT_FOUND,
+            )
+            .with_code(ErrorCode::ConversationNotFound)
+        })?;
+    Ok(())
+}
+
+pub async fn get_draft(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "Retrieving the draft of conversation '{}' for user '{}'.",
+        conversation_id, user.uid
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            ensure_conversation(transaction, user.uid, conversation_id).await?;
+            let model =
+                draft::find_by_user_id_and_conversation_id(transaction, user.uid, conversation_id)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to fetch the draft due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+            let response = match model {
+                Some(model) => DraftResponse {
+                    content: model.content,
+                    attachment_ids: model.attachment_ids,
+                    updated_at: Some(model.updated_at),
+                },
+                None => DraftResponse::default(),
+            };
+            Ok(Json(response).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_draft(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditDraftRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is saving a draft for conversation '{}'.",
+        user.uid, conversation_id
+    );
+    if req.content.chars().count() > MAX_DRAFT_CHARS {
+        return Err(format_error(
+            "Draft is too long. Maximum characters",
+            MAX_DRAFT_CHARS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let mut seen = HashSet::new();
+    let mut attachment_ids = req.attachment_ids;
+    attachment_ids.retain(|id| seen.insert(*id));
+    if attachment_ids.len() > MAX_DRAFT_ATTACHMENTS {
+        return Err(format_error(
+            "Draft has too many attachments. Maximum",
+            MAX_DRAFT_ATTACHMENTS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            ensure_conversation(transaction, user.uid, conversation_id).await?;
+            for attachment_id in &attachment_ids {
+                attachment::find_by_user_id_and_attachment_id(
+                    transaction,
+                    user.uid,
+                    *attachment_id,
+                )
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error fetching the attachment from the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+                .ok_or_else(|| {
+                    format_error(
+                        "Attachment could not be found",
+                        attachment_id,
+                        StatusCode::NOT_FOUND,
+                    )
+                })?;
+            }
+            let model = draft::upsert(
+                transaction,
+                user.uid,
+                conversation_id,
+                req.content,
+                attachment_ids,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to save the draft due to a database error",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            Ok(Json(DraftResponse {
+                content: model.content,
+                attachment_ids: model.attachment_ids,
+                updated_at: Some(model.updated_at),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn delete_draft(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is deleting the draft of conversation '{}'.",
+        user.uid, conversation_id
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            draft::delete(transaction, user.uid, conversation_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the draft due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(DeleteDraftResponse {
+                message: "Draft successfully deleted".to_string(),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
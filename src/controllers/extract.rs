@@ -0,0 +1,248 @@
+use crate::{
+    config::constant,
+    controllers::chat::handle_transaction,
+    dto::{
+        request::{ActionItemsRequest, ExtractRequest},
+        response::{ActionItemsResponse, ExtractResponse},
+    },
+    repositories::{collection, conversation},
+    service::{
+        extraction::{
+            conversation_transcript, extract_structured, ACTION_ITEMS_INSTRUCTION,
+            ACTION_ITEMS_SCHEMA, EXTRACTION_INSTRUCTION,
+        },
+        outbox,
+    },
+    utils::{
+        error::{format_error, AppResult, ErrorCode},
+        jwt::UserClaims,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub async fn extract(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<ExtractRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!("User '{}' is requesting a structured extraction.", user.uid);
+
+    if req.schema.get("type").and_then(|t| t.as_str()) != Some("object") {
+        return Err(format_error(
+            "Invalid schema",
+            "the top-level schema must be of type object",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let model_name = req
+        .model_name
+        .clone()
+        .unwrap_or_else(|| state.config.extraction.model.clone());
+    if !constant::MODEL_TO_PRICE.contains_key(model_name.as_str()) {
+        return Err(format_error(
+            "Invalid model name",
+            model_name,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let mut text = req.text.clone().unwrap_or_default();
+    if let Some(document_id) = req.document_id {
+        let organization_id = user.session_data.as_ref().and_then(|s| s.organization_id());
+        let document = handle_transaction(&state.db, |transaction| {
+            Box::pin(async move {
+                let document = collection::find_document_by_id(transaction, document_id)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Database query failed while fetching the document",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+                let readable = match &document {
+                    Some(document) => collection::find_by_id(transaction, document.collection_id)
+                        .await
+                        .map_err(|e| {
+                            format_error(
+                                "Database query failed while fetching the document's collection",
+                                e,
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                            )
+                        })?
+                        .is_some_and(|model| {
+                            model.is_readable_by(user.uid, organization_id.as_deref())
+                        }),
+                    None => false,
+                };
+                match document {
+                    Some(document) if readable => Ok(document),
+                    _ => Err(format_error(
+                        "Document could not be found",
+                        document_id,
+                        StatusCode::NOT_FOUND,
+                    )
+                    .with_code(ErrorCode::DocumentNotFound)),
+                }
+            })
+        })
+        .await?;
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(&document.content);
+    }
+
+    let mut content = vec![];
+    if !text.trim().is_empty() {
+        content.push(json!({ "type": "text", "text": text }));
+    }
+    if let Some(image) = req.image.as_deref().filter(|i| !i.is_empty()) {
+        let url = if image.starts_with("data:") || image.starts_with("https://") {
+            image.to_string()
+        } else {
+            format!("data:image/png;base64,{}", image)
+        };
+        content.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+    }
+    if content.is_empty() {
+        return Err(format_error(
+            "Nothing to extract from",
+            "provide text, a document_id or an image",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let data = extract_structured(
+        &state,
+        &model_name,
+        req.instruction.as_deref().unwrap_or(EXTRACTION_INSTRUCTION),
+        content,
+        req.schema_name.as_deref().unwrap_or("extraction"),
+        &req.schema,
+    )
+    .await
+    .map_err(|e| format_error("Structured extraction failed", e, StatusCode::BAD_GATEWAY))?;
+
+    info!("Structured extraction succeeded for user '{}'.", user.uid);
+    Ok(Json(ExtractResponse { data }))
+}
+
+pub async fn extract_action_items(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<ActionItemsRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is extracting action items from conversation '{}'.",
+        user.uid, conversation_id
+    );
+    let webhook_url = state.config.extraction.action_items_webhook.clone();
+    if req.push_webhook && webhook_url.is_none() {
+        return Err(format_error(
+            "No action items webhook is configured",
+            conversation_id,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let model_name = req
+        .model_name
+        .clone()
+        .unwrap_or_else(|| state.config.extraction.model.clone());
+    if !constant::MODEL_TO_PRICE.contains_key(model_name.as_str()) {
+        return Err(format_error(
+            "Invalid model name",
+            model_name,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let transcript = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_user_id_and_conversation_id(
+                transaction,
+                user.uid,
+                conversation_id,
+            )
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Database query failed while fetching the specified conversation",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            let Some(model) = model else {
+                return Err(format_error(
+                    "Conversation could not be found",
+                    conversation_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::ConversationNotFound));
+            };
+            conversation_transcript(&model).map_err(|e| {
+                format_error(
+                    "Failed to read the stored conversation history",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })
+        })
+    })
+    .await?;
+    if transcript.trim().is_empty() {
+        return Err(format_error(
+            "Conversation has no messages",
+            conversation_id,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let data = extract_structured(
+        &state,
+        &model_name,
+        ACTION_ITEMS_INSTRUCTION,
+        vec![json!({ "type": "text", "text": transcript })],
+        "action_items",
+        &ACTION_ITEMS_SCHEMA,
+    )
+    .await
+    .map_err(|e| format_error("Action item extraction failed", e, StatusCode::BAD_GATEWAY))?;
+
+    let mut webhook_delivered = false;
+    if let Some(webhook_url) = webhook_url.filter(|_| req.push_webhook) {
+        let payload = json!({
+            "user_id": user.uid,
+            "conversation_id": conversation_id,
+            "data": data,
+        });
+        // Queued in the outbox, so a failed push is retried in the background.
+        webhook_delivered =
+            outbox::publish(&state, outbox::ACTION_ITEMS_EXTRACTED, webhook_url, payload).await;
+        if !webhook_delivered {
+            error!(
+                "Failed to push action items of conversation '{}' to the webhook, will retry.",
+                conversation_id
+            );
+        }
+    }
+
+    info!(
+        "Extracted action items from conversation '{}' for user '{}'.",
+        conversation_id, user.uid
+    );
+    Ok(Json(ActionItemsResponse {
+        data,
+        webhook_delivered,
+    }))
+}
@@ -0,0 +1,36 @@
+use crate::{dto::response::ReadinessResponse, ServiceState};
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use std::sync::Arc;
+
+/// Ready once the database answers and the startup provider probe has run.
+/// A failing provider only shows up in the body, since the circuit breaker
+/// already keeps requests away from it.
+pub async fn readiness(State(state): State<Arc<ServiceState>>) -> impl IntoResponse {
+    let database = state.db.ping().await.is_ok();
+    let warmed_up = state.health.is_warmed_up();
+    let providers = state
+        .health
+        .snapshot()
+        .into_iter()
+        .map(|(name, health)| (name, health.healthy))
+        .collect();
+    let ready = database && warmed_up;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadinessResponse {
+            ready,
+            database,
+            warmed_up,
+            providers,
+        }),
+    )
+}
@@ -1,64 +1,185 @@
 use crate::{
-    dto::request::ImageGenerationRequest,
-    utils::{error, jwt::UserClaims, openai::text_to_image},
+    client::provider::ImageOptions,
+    controllers::chat::handle_transaction,
+    dto::{request::ImageGenerationRequest, response::ImageGenerationResponse},
+    repositories::{generated_image, usage},
+    routes::public::PUBLIC_MEDIA_PREFIX,
+    service::{
+        budget,
+        chat::data_region,
+        image::{self, image_credits},
+    },
+    utils::{
+        error::{format_error, AppResult, ErrorCode},
+        file::{content_filename, save_file},
+        jwt::UserClaims,
+        metrics,
+        openai::TokenUsage,
+        session::send_session_data,
+    },
     ServiceState,
 };
 use axum::{
-    body::Body,
     extract::{Json, State},
-    http::{header, StatusCode},
-    response::{IntoResponse, Response},
+    http::StatusCode,
+    response::IntoResponse,
 };
-use reqwest::Client;
 use std::sync::Arc;
 use tracing::{error, info};
-type AppResult<T> = Result<T, (StatusCode, String)>;
 
 pub async fn image_generate(
     State(state): State<Arc<ServiceState>>,
-    user: UserClaims,
+    mut user: UserClaims,
     Json(req): Json<ImageGenerationRequest>,
 ) -> AppResult<impl IntoResponse> {
     info!(
         "User '{}' is generating the image of the text '{}'.",
         user.uid, req.text
     );
+    user.refresh_session(&state).await?;
 
-    let url = text_to_image(&state.config.openai.openai_key, &req.text)
-        .await
-        .map_err(|e| {
-            error!("{}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, e)
-        })?;
-
-    let client = Client::new();
-    let res = client.get(url).send().await.map_err(|e| {
-        error::format_error(
-            "Failed to get image data from the url",
-            e,
-            StatusCode::INTERNAL_SERVER_ERROR,
+    let options = ImageOptions {
+        model: req.model,
+        aspect_ratio: req.aspect_ratio,
+        size: req
+            .size
+            .map(|size| size.trim().to_lowercase())
+            .filter(|size| !size.is_empty()),
+        quality: req.quality,
+        style: req.style,
+        count: req.n.unwrap_or(1),
+        negative_prompt: req.negative_prompt.clone(),
+    };
+    options
+        .validate()
+        .map_err(|e| format_error("Invalid image options", e, StatusCode::BAD_REQUEST))?;
+    // A cached set may end up cheaper; the full price is what must be affordable.
+    let credits_remaining = user
+        .session_data
+        .as_ref()
+        .map(|s| s.credits_remaining)
+        .unwrap_or_default();
+    let cost = image_credits(&options);
+    if cost > credits_remaining {
+        return Err(format_error(
+            "Insufficient credits to proceed with the action. Required",
+            cost,
+            StatusCode::BAD_REQUEST,
         )
-    })?;
-    if res.status().is_success() {
-        let bytes = res.bytes().await.map_err(|e| {
-            (
+        .with_code(ErrorCode::InsufficientCredits));
+    }
+
+    budget::admit_expensive(&state, "image generation")?;
+
+    let prompt = if req.enhance_prompt {
+        let enhanced = state
+            .provider
+            .enhance_image_prompt(&state.config.image.prompt_enhancer_model, &req.text)
+            .await
+            .map_err(|e| {
+                error!("{}", e);
+                (StatusCode::BAD_GATEWAY, e)
+            })?;
+        info!(
+            "Enhanced the image prompt for user '{}' to '{}'.",
+            user.uid, enhanced
+        );
+        enhanced
+    } else {
+        req.text.clone()
+    };
+
+    let generated = image::generate_image(&state, &prompt, &options).await?;
+    let credits = generated.credits(&state, &options);
+    let region = data_region(&state, user.session_data.as_ref());
+    let media_dir = state
+        .config
+        .residency
+        .media_dir("images/generated", region.as_deref());
+    let mut filenames = Vec::with_capacity(generated.images.len());
+    for bytes in &generated.images {
+        let filename = content_filename(&media_dir, &user.uid.to_string(), bytes, Some("png"));
+        save_file(filename.as_str(), bytes.to_vec()).map_err(|e| {
+            format_error(
+                "Error in saving the generated image",
+                e,
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to get bytes of the image: {}", e),
             )
         })?;
-        Ok(Response::builder()
-            .header(header::CONTENT_TYPE, "image/png")
-            .body(Body::from(bytes))
+        filenames.push(filename);
+    }
+    let images = filenames
+        .iter()
+        .map(|filename| format!("{}/{}", PUBLIC_MEDIA_PREFIX, filename))
+        .collect();
+
+    let model = options.model.name().to_string();
+    let db = state.db.clone();
+    handle_transaction(&db, |transaction| {
+        Box::pin(async move {
+            for filename in filenames {
+                generated_image::create(
+                    transaction,
+                    user.uid,
+                    prompt.clone(),
+                    model.clone(),
+                    filename,
+                )
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to record the generated image",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            }
+            usage::record(
+                transaction,
+                user.uid,
+                model.clone(),
+                credits,
+                &TokenUsage::default(),
+            )
+            .await
             .map_err(|e| {
-                (
+                format_error(
+                    "Failed to record credit usage",
+                    e,
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to build response: {}", e),
                 )
-            })?)
-    } else {
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            format!("Failed to access to the generated image"),
-        ));
-    }
+            })?;
+            // Debited before committing, so a failed debit leaves no usage record behind.
+            if credits > 0 {
+                send_session_data(
+                    serde_json::json!({
+                        "credits_remaining": credits_remaining - credits,
+                        "user_id": user.uid
+                    }),
+                    state.config.server.auth_service.as_str(),
+                    state.config.server.auth_secret_key.clone(),
+                )
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to charge the image credits",
+                        e,
+                        StatusCode::BAD_GATEWAY,
+                    )
+                })?;
+                state.sessions.invalidate_user(user.uid);
+            }
+            metrics::add_credits(&model, credits);
+
+            Ok(Json(ImageGenerationResponse {
+                prompt,
+                model,
+                images,
+                credits,
+                cached: generated.cached,
+            })
+            .into_response())
+        })
+    })
+    .await
 }
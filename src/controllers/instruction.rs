@@ -0,0 +1,122 @@
+use crate::{
+    controllers::chat::handle_transaction,
+    dto::{
+        request::EditInstructionsRequest,
+        response::{DeleteInstructionsResponse, InstructionsResponse},
+    },
+    repositories::instruction,
+    utils::{
+        error::{format_error, AppResult},
+        jwt::UserClaims,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use std::sync::Arc;
+use tracing::info;
+
+const MAX_INSTRUCTION_CHARS: usize = 1500;
+
+fn clean(text: Option<String>) -> AppResult<Option<String>> {
+    let text = text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    if text
+        .as_ref()
+        .is_some_and(|t| t.chars().count() > MAX_INSTRUCTION_CHARS)
+    {
+        return Err(format_error(
+            "Custom instructions are too long. Maximum characters",
+            MAX_INSTRUCTION_CHARS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    Ok(text)
+}
+
+pub async fn get_instructions(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!("Retrieving custom instructions for user '{}'.", user.uid);
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = instruction::find_by_user_id(transaction, user.uid)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to fetch custom instructions due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            let response = match model {
+                Some(model) => InstructionsResponse {
+                    about_me: model.about_me,
+                    response_style: model.response_style,
+                    updated_at: Some(model.updated_at),
+                },
+                None => InstructionsResponse::default(),
+            };
+            Ok(Json(response).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn edit_instructions(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<EditInstructionsRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!("User '{}' is updating their custom instructions.", user.uid);
+    let about_me = clean(req.about_me)?;
+    let response_style = clean(req.response_style)?;
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = instruction::upsert(transaction, user.uid, about_me, response_style)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to save custom instructions due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(InstructionsResponse {
+                about_me: model.about_me,
+                response_style: model.response_style,
+                updated_at: Some(model.updated_at),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
+
+pub async fn delete_instructions(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!("User '{}' is deleting their custom instructions.", user.uid);
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            instruction::delete_by_user_id(transaction, user.uid)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete custom instructions due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(DeleteInstructionsResponse {
+                message: "Custom instructions successfully deleted".to_string(),
+            })
+            .into_response())
+        })
+    })
+    .await
+}
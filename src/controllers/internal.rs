@@ -0,0 +1,199 @@
+use crate::{
+    controllers::chat::handle_transaction,
+    dto::{
+        request::{DeleteUserDataRequest, InvalidateSessionsRequest, LockConversationRequest},
+        response::{DeleteUserDataResponse, EditLockResponse, InvalidateSessionsResponse},
+    },
+    repositories::{
+        attachment, bot, collection, conversation, draft, generated_image, instruction, usage,
+    },
+    utils::{
+        error::{format_error, AppResult, ErrorCode},
+        file::delete_files,
+        signature::SignedJson,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+pub async fn delete_user_data(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<DeleteUserDataRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!("Deleting all conversation data of user '{}'.", req.user_id);
+
+    let (attachments, images, conversations) = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            collection::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's collections due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            instruction::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's custom instructions due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            bot::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's bots due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            draft::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's drafts due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            usage::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's credit usage due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            let images = generated_image::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's generated images due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            let attachments = attachment::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's attachments due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            let conversations = conversation::delete_by_user_id(transaction, req.user_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to delete the user's conversations due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok((attachments, images, conversations))
+        })
+    })
+    .await?;
+
+    let media_files: Vec<String> = conversations
+        .iter()
+        .flat_map(|c| c.media_files())
+        .chain(images.into_iter().map(|image| image.filename))
+        .collect();
+    let deleted_files = delete_files(&media_files);
+    for model in &attachments {
+        let path = state.config.upload.path_for(model.id);
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("Failed to delete attachment file '{}': {}", path, e);
+            }
+        }
+    }
+    state.sessions.invalidate_user(req.user_id);
+    info!(
+        "Deleted {} conversations and {} media files of user '{}'.",
+        conversations.len(),
+        deleted_files,
+        req.user_id
+    );
+    Ok(Json(DeleteUserDataResponse {
+        deleted_conversations: conversations.len(),
+        deleted_files,
+        deleted_attachments: attachments.len(),
+    }))
+}
+
+/// Called by the auth service when a user's balance or preferences change
+/// outside of this service, e.g. after a purchase, or when a session is revoked.
+pub async fn invalidate_sessions(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<InvalidateSessionsRequest>,
+) -> AppResult<impl IntoResponse> {
+    let invalidated = match req.session_id {
+        Some(session_id) => state.sessions.invalidate_session(session_id, req.user_id),
+        None => state.sessions.invalidate_user(req.user_id),
+    };
+    info!(
+        "Invalidated {} cached sessions of user '{}'.",
+        invalidated, req.user_id
+    );
+    Ok(Json(InvalidateSessionsResponse { invalidated }))
+}
+
+pub async fn lock_conversation(
+    State(state): State<Arc<ServiceState>>,
+    SignedJson(req): SignedJson<LockConversationRequest>,
+) -> AppResult<impl IntoResponse> {
+    warn!(
+        target: "audit",
+        "Administrator set the lock of conversation '{}' to {}.",
+        req.conversation_id, req.locked
+    );
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = conversation::find_by_id(transaction, req.conversation_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error fetching the conversation from the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+                .ok_or_else(|| {
+                    format_error(
+                        "Requested conversation could not be found",
+                        req.conversation_id,
+                        StatusCode::NOT_FOUND,
+                    )
+                    .with_code(ErrorCode::ConversationNotFound)
+                })?;
+            let model = conversation::set_locked(transaction, model, req.locked, true)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error updating the conversation lock in the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(EditLockResponse {
+                message: "Lock successfully updated".to_string(),
+                locked: model.locked,
+            }))
+        })
+    })
+    .await
+}
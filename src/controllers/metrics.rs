@@ -0,0 +1,41 @@
+use crate::{
+    utils::{
+        error::{format_error, AppError, AppResult, ErrorCode},
+        metrics::{gather, CONTENT_TYPE},
+    },
+    ServiceState,
+};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use std::sync::Arc;
+
+pub async fn export_metrics(
+    State(state): State<Arc<ServiceState>>,
+    headers: HeaderMap,
+) -> AppResult<impl IntoResponse> {
+    if let Some(token) = state.config.server.metrics_token.as_deref() {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|v| v == token);
+        if !authorized {
+            return Err(AppError::new(
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                "A valid metrics token is required",
+            ));
+        }
+    }
+    let body = gather().map_err(|e| {
+        format_error(
+            "Failed to encode the metrics",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], body))
+}
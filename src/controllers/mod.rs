@@ -1,3 +1,14 @@
+pub mod admin;
+pub mod bot;
 pub mod chat;
+pub mod collection;
+pub mod draft;
+pub mod extract;
+pub mod health;
 pub mod image;
+pub mod instruction;
+pub mod internal;
+pub mod metrics;
+pub mod upload;
 pub mod voice;
+pub mod ws;
@@ -0,0 +1,298 @@
+use crate::{
+    controllers::chat::handle_transaction,
+    dto::{request::InitUploadRequest, response::UploadStatusResponse},
+    entity::attachment as attachment_entity,
+    repositories::attachment,
+    service::upload::{truncate_chunks, write_chunk},
+    utils::{
+        error::{format_error, AppResult},
+        jwt::UserClaims,
+        waveform::from_audio_file,
+    },
+    ServiceState,
+};
+use axum::{
+    body::Bytes,
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use sea_orm::DatabaseTransaction;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+
+fn upload_status(model: &attachment_entity::Model) -> UploadStatusResponse {
+    UploadStatusResponse {
+        attachment_id: model.id,
+        offset: model.size,
+        total_size: model.total_size,
+        completed: model.is_complete(),
+        waveform: model
+            .waveform
+            .clone()
+            .and_then(|waveform| serde_json::from_value(waveform).ok()),
+    }
+}
+
+async fn find_upload(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    attachment_id: Uuid,
+) -> AppResult<attachment_entity::Model> {
+    attachment::find_by_user_id_and_attachment_id(tx, user_id, attachment_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Database query failed while fetching the upload",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .ok_or_else(|| {
+            format_error(
+                "Upload could not be found",
+                attachment_id,
+                StatusCode::NOT_FOUND,
+            )
+        })
+}
+
+// Like `find_upload`, but keeps other requests off the row until the transaction ends.
+async fn lock_upload(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    attachment_id: Uuid,
+) -> AppResult<attachment_entity::Model> {
+    attachment::lock_by_user_id_and_attachment_id(tx, user_id, attachment_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Database query failed while fetching the upload",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .ok_or_else(|| {
+            format_error(
+                "Upload could not be found",
+                attachment_id,
+                StatusCode::NOT_FOUND,
+            )
+        })
+}
+
+pub async fn init_upload(
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    Json(req): Json<InitUploadRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is starting a chunked upload of '{}'.",
+        user.uid, req.filename
+    );
+    let filename = req.filename.trim().to_string();
+    if filename.is_empty() || filename.contains(['/', '\\']) {
+        return Err(format_error(
+            "Invalid upload file name",
+            filename,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if req
+        .total_size
+        .is_some_and(|size| size <= 0 || size > state.config.upload.max_bytes)
+    {
+        return Err(format_error(
+            "Invalid upload size",
+            format!(
+                "total_size must be between 1 and {} bytes",
+                state.config.upload.max_bytes
+            ),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = attachment::create(transaction, user.uid, filename, req.total_size)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to create the upload due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            Ok(Json(upload_status(&model)).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn get_upload(
+    Path(attachment_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = find_upload(transaction, user.uid, attachment_id).await?;
+            Ok(Json(upload_status(&model)).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn append_upload(
+    Path(attachment_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<impl IntoResponse> {
+    let offset = headers
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .ok_or_else(|| {
+            format_error(
+                "Missing or invalid header",
+                UPLOAD_OFFSET_HEADER,
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+    info!(
+        "User '{}' is appending {} bytes at offset {} to upload '{}'.",
+        user.uid,
+        body.len(),
+        offset,
+        attachment_id
+    );
+    let path = state.config.upload.path_for(attachment_id);
+    let max_bytes = state.config.upload.max_bytes;
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = lock_upload(transaction, user.uid, attachment_id).await?;
+            if model.is_complete() {
+                return Err(format_error(
+                    "Upload is already completed",
+                    attachment_id,
+                    StatusCode::CONFLICT,
+                ));
+            }
+            if offset != model.size {
+                return Err(format_error(
+                    "Upload offset does not match the received size",
+                    model.size,
+                    StatusCode::CONFLICT,
+                ));
+            }
+            let size = model.size + body.len() as i64;
+            if size > model.total_size.unwrap_or(max_bytes).min(max_bytes) {
+                return Err(format_error(
+                    "Upload exceeds its allowed size",
+                    size,
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                ));
+            }
+            let committed_size = model.size;
+            write_chunk(&path, committed_size, &body)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to write the upload chunk",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            let model = match attachment::set_size(transaction, model, size).await {
+                Ok(model) => model,
+                Err(e) => {
+                    if let Err(e) = truncate_chunks(&path, committed_size).await {
+                        error!(
+                            "Failed to cut upload '{}' back to {} bytes: {}",
+                            attachment_id, committed_size, e
+                        );
+                    }
+                    return Err(format_error(
+                        "Failed to update the upload due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ));
+                }
+            };
+            Ok(Json(upload_status(&model)).into_response())
+        })
+    })
+    .await
+}
+
+pub async fn complete_upload(
+    Path(attachment_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is completing upload '{}'.",
+        user.uid, attachment_id
+    );
+    let path = state.config.upload.path_for(attachment_id);
+    let model = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move { find_upload(transaction, user.uid, attachment_id).await })
+    })
+    .await?;
+    if model.is_complete() {
+        return Ok(Json(upload_status(&model)).into_response());
+    }
+    if model.size == 0 || model.total_size.is_some_and(|total| total != model.size) {
+        return Err(format_error(
+            "Upload is not fully received",
+            model.size,
+            StatusCode::CONFLICT,
+        ));
+    }
+    // Decoding a long recording takes a while, so it happens outside the transaction.
+    let received_size = model.size;
+    let extension = std::path::Path::new(&model.filename)
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_string);
+    let waveform =
+        tokio::task::spawn_blocking(move || from_audio_file(&path, extension.as_deref()))
+            .await
+            .ok()
+            .flatten()
+            .and_then(|waveform| serde_json::to_value(waveform).ok());
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let model = lock_upload(transaction, user.uid, attachment_id).await?;
+            if model.is_complete() {
+                return Ok(Json(upload_status(&model)).into_response());
+            }
+            if model.size != received_size {
+                return Err(format_error(
+                    "Upload changed while it was being completed",
+                    model.size,
+                    StatusCode::CONFLICT,
+                ));
+            }
+            let model = attachment::complete(transaction, model, waveform)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to complete the upload due to a database error",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+            info!(
+                "Upload '{}' of user '{}' completed with {} bytes.",
+                attachment_id, user.uid, model.size
+            );
+            Ok(Json(upload_status(&model)).into_response())
+        })
+    })
+    .await
+}
@@ -1,23 +1,53 @@
 use crate::{
-    utils::{error::format_error, jwt::UserClaims, openai},
+    controllers::{chat::wants_sse, ws::send_event},
+    dto::{
+        request::{LiveClientMessage, LiveTranscriptionQuery, RegisterVoiceRequest},
+        response::{RegisterVoiceResponse, VoiceProfile, VoiceProfilesResponse},
+    },
+    service::{
+        budget,
+        transcription::{stream_transcription, transcribe},
+        upload::load_attachment,
+    },
+    utils::{
+        deepgram::{connect_live, parse_live_message, LiveOptions},
+        error::{format_error, AppError, AppResult, ErrorCode},
+        jwt::UserClaims,
+        language::normalize_language,
+        request_id::spawn_in_request,
+        session::send_session_data,
+        streaming::{self, StreamFormat},
+    },
     ServiceState,
 };
 use axum::{
-    extract::{Multipart, State},
-    http::StatusCode,
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Json, Multipart, Query, State,
+    },
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use std::sync::Arc;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio_tungstenite::tungstenite::Message as UpstreamMessage;
 use tracing::{error, info};
+use uuid::Uuid;
 
-type AppResult<T> = Result<T, (StatusCode, String)>;
+// Deepgram closes idle sessions after about ten seconds without audio.
+const LIVE_KEEP_ALIVE: Duration = Duration::from_secs(5);
 
 pub async fn speech_to_text(
     State(state): State<Arc<ServiceState>>,
     user: UserClaims,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> AppResult<impl IntoResponse> {
     info!("Speech to text API from the user: {}", user.uid);
+    let mut voice: Option<(String, Vec<u8>)> = None;
+    let mut prompt: Option<String> = None;
+    let mut language: Option<String> = None;
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         format_error(
             "Failed to read multipart fields",
@@ -29,8 +59,8 @@ pub async fn speech_to_text(
         if name.is_none() {
             continue;
         }
-        let name = name.unwrap();
-        if name != "voice" {
+        let name = name.unwrap().to_string();
+        if name != "voice" && name != "prompt" && name != "language" && name != "attachment_id" {
             return Err(format_error(
                 "Unknown Multipart field name",
                 name,
@@ -42,23 +72,281 @@ pub async fn speech_to_text(
             Some(name) => name,
             _ => "speech_to_text".into(),
         };
-        info!("{}", filename);
         let data = field.bytes().await;
         if data.is_err() {
             continue;
         }
         let data = data.unwrap();
-        let res = openai::speech_to_text(
-            &state.config.openai.openai_key,
-            data.to_vec(),
-            filename.clone(),
+        if name == "prompt" {
+            prompt = Some(String::from_utf8(data.to_vec()).map_err(|e| {
+                format_error("Error parsing prompt as string", e, StatusCode::BAD_REQUEST)
+            })?);
+            continue;
+        }
+        if name == "language" {
+            let code = String::from_utf8(data.to_vec()).map_err(|e| {
+                format_error(
+                    "Error parsing language as string",
+                    e,
+                    StatusCode::BAD_REQUEST,
+                )
+            })?;
+            language = Some(normalize_language(&code).ok_or_else(|| {
+                format_error("Unsupported language", code, StatusCode::BAD_REQUEST)
+            })?);
+            continue;
+        }
+        if name == "attachment_id" {
+            let attachment_id = String::from_utf8(data.to_vec())
+                .ok()
+                .and_then(|id| Uuid::parse_str(id.trim()).ok())
+                .ok_or_else(|| {
+                    format_error(
+                        "Invalid attachment id",
+                        "attachment_id must be a UUID",
+                        StatusCode::BAD_REQUEST,
+                    )
+                })?;
+            voice = Some(load_attachment(&state, user.uid, attachment_id).await?);
+            continue;
+        }
+        info!("{}", filename);
+        voice = Some((filename, data.to_vec()));
+    }
+    let Some((filename, data)) = voice else {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::BadRequest,
+            "No voice field specified.",
+        ));
+    };
+    let prompt = prompt.or_else(|| {
+        user.session_data
+            .as_ref()
+            .and_then(|s| s.preference("transcription_prompt"))
+    });
+    let language = language.or_else(|| {
+        user.session_data
+            .as_ref()
+            .and_then(|s| s.preference("language"))
+            .and_then(|code| normalize_language(&code))
+    });
+    // Long recordings can take minutes, so SSE clients get progress and partial text.
+    if wants_sse(&headers) {
+        let (writer, stream_response) = streaming::channel(StreamFormat::Sse, 16);
+        spawn_in_request(stream_transcription(
+            state, writer, user.uid, filename, data, prompt, language,
+        ));
+        return stream_response.into_response();
+    }
+    let res = transcribe(&state, filename, data, prompt, language).await?;
+    Ok(res.into_response())
+}
+
+pub async fn register_custom_voice(
+    State(state): State<Arc<ServiceState>>,
+    mut user: UserClaims,
+    Json(req): Json<RegisterVoiceRequest>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is registering the custom voice {:?}.",
+        user.uid, req.voice_id
+    );
+    // The preferences are written back whole, so they must not come from the cache.
+    user.refresh_session(&state).await?;
+    let voice_id = req
+        .voice_id
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty());
+    if let Some(voice_id) = &voice_id {
+        if !state.config.tts.allows_custom_voice(voice_id) {
+            return Err(format_error(
+                "Custom voice is not allowed",
+                voice_id,
+                StatusCode::FORBIDDEN,
+            ));
+        }
+    }
+
+    let mut preferences = user
+        .session_data
+        .as_ref()
+        .map(|s| s.preferences.clone())
+        .filter(|p| p.is_object())
+        .unwrap_or_else(|| json!({}));
+    preferences["custom_voice_id"] = json!(voice_id);
+    send_session_data(
+        json!({
+            "user_id": user.uid,
+            "preferences": preferences,
+        }),
+        state.config.server.auth_service.as_str(),
+        state.config.server.auth_secret_key.clone(),
+    )
+    .await
+    .map_err(|e| {
+        format_error(
+            "Failed to save the custom voice preference",
+            e,
+            StatusCode::BAD_GATEWAY,
         )
-        .await
-        .map_err(|e| {
+    })?;
+    state.sessions.invalidate_user(user.uid);
+
+    Ok(Json(RegisterVoiceResponse {
+        message: "Custom voice successfully updated".to_string(),
+        voice_id,
+    }))
+}
+
+pub async fn live_transcription(
+    Query(query): Query<LiveTranscriptionQuery>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    ws: WebSocketUpgrade,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        "User '{}' is opening a live transcription socket.",
+        user.uid
+    );
+    budget::admit_expensive(&state, "live transcription")?;
+    let language =
+        match query.language.as_deref() {
+            Some(code) => Some(normalize_language(code).ok_or_else(|| {
+                format_error("Unsupported language", code, StatusCode::BAD_REQUEST)
+            })?),
+            None => user
+                .session_data
+                .as_ref()
+                .and_then(|s| s.preference("language"))
+                .and_then(|code| normalize_language(&code)),
+        };
+    let options = LiveOptions {
+        language,
+        encoding: query.encoding,
+        sample_rate: query.sample_rate,
+    };
+    Ok(ws.on_upgrade(move |socket| proxy_live_transcription(socket, state, user.uid, options)))
+}
+
+// Forwards audio frames to Deepgram and relays interim and final transcripts back.
+async fn proxy_live_transcription(
+    mut socket: WebSocket,
+    state: Arc<ServiceState>,
+    user_id: i64,
+    options: LiveOptions,
+) {
+    let upstream = match connect_live(&state.config.deepgram.deepgram_key, &options).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
             error!("{}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, e)
-        })?;
-        return Ok(res);
+            let _ = send_event(&mut socket, "error", json!({ "message": e })).await;
+            return;
+        }
+    };
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let mut keep_alive = tokio::time::interval(LIVE_KEEP_ALIVE);
+    let mut transcribed_secs: f64 = 0.0;
+    let mut closing = false;
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv(), if !closing => {
+                let control = match incoming {
+                    Some(Ok(WsMessage::Binary(audio))) => {
+                        keep_alive.reset();
+                        if let Err(e) = upstream_tx.send(UpstreamMessage::Binary(audio)).await {
+                            error!("Forwarding audio to Deepgram failed: {}", e);
+                            break;
+                        }
+                        continue;
+                    }
+                    Some(Ok(WsMessage::Text(text))) => {
+                        match serde_json::from_str::<LiveClientMessage>(&text) {
+                            Ok(LiveClientMessage::Finalize) => "Finalize",
+                            Ok(LiveClientMessage::Close) => "CloseStream",
+                            Err(e) => {
+                                let message = format!("Invalid frame: {}", e);
+                                if send_event(&mut socket, "error", json!({ "message": message }))
+                                    .await
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                    Some(Ok(WsMessage::Close(_))) | None | Some(Err(_)) => "CloseStream",
+                    Some(Ok(_)) => continue,
+                };
+                // After CloseStream, Deepgram sends the remaining results and closes the socket.
+                closing = control == "CloseStream";
+                let frame = json!({ "type": control }).to_string();
+                if upstream_tx.send(UpstreamMessage::Text(frame)).await.is_err() {
+                    break;
+                }
+            }
+            result = upstream_rx.next() => match result {
+                Some(Ok(UpstreamMessage::Text(text))) => {
+                    let Some(transcript) = parse_live_message(&text) else {
+                        continue;
+                    };
+                    transcribed_secs = transcribed_secs.max(transcript.start + transcript.duration);
+                    if send_event(&mut socket, "transcript", json!(transcript)).await.is_err()
+                        && !closing
+                    {
+                        let frame = json!({ "type": "CloseStream" }).to_string();
+                        let _ = upstream_tx.send(UpstreamMessage::Text(frame)).await;
+                        closing = true;
+                    }
+                }
+                Some(Ok(UpstreamMessage::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    error!("Deepgram live transcription failed: {}", e);
+                    let _ = send_event(
+                        &mut socket,
+                        "error",
+                        json!({ "message": "Live transcription was interrupted" }),
+                    )
+                    .await;
+                    break;
+                }
+            },
+            _ = keep_alive.tick(), if !closing => {
+                let frame = json!({ "type": "KeepAlive" }).to_string();
+                if upstream_tx.send(UpstreamMessage::Text(frame)).await.is_err() {
+                    break;
+                }
+            }
+        }
     }
-    Err((StatusCode::BAD_REQUEST, "No voice field specified.".into()))
+
+    let _ = socket.send(WsMessage::Close(None)).await;
+    budget::record_spend(
+        &state,
+        budget::estimate_transcription((transcribed_secs * 1000.0) as u64),
+    )
+    .await;
+    info!(
+        "Live transcription socket of user '{}' closed after {:.1}s of audio.",
+        user_id, transcribed_secs
+    );
+}
+
+pub async fn list_voice_profiles(State(state): State<Arc<ServiceState>>) -> impl IntoResponse {
+    let profiles = state
+        .config
+        .tts
+        .voice_profiles
+        .iter()
+        .map(|(name, profile)| VoiceProfile {
+            name: name.clone(),
+            model: profile.model.clone(),
+            sample_rate: profile.sample_rate,
+            encoding: "linear16",
+        })
+        .collect();
+    Json(VoiceProfilesResponse { profiles })
 }
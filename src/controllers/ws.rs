@@ -0,0 +1,258 @@
+use crate::{
+    dto::request::{MessageOptions, WsChatMessage, WsClientMessage},
+    service::chat::handle_user_message,
+    utils::{error::AppError, jwt::UserClaims},
+    ServiceState,
+};
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::StatusCode,
+    response::IntoResponse,
+};
+use base64::prelude::*;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio_stream::StreamExt;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub async fn chat_socket(
+    Path(conversation_id): Path<Uuid>,
+    State(state): State<Arc<ServiceState>>,
+    user: UserClaims,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    info!(
+        "User '{}' is opening a realtime chat socket for conversation '{}'.",
+        user.uid, conversation_id
+    );
+    ws.on_upgrade(move |socket| handle_socket(socket, state, user, conversation_id))
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: Arc<ServiceState>,
+    mut user: UserClaims,
+    conversation_id: Uuid,
+) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let text = match message {
+            WsMessage::Text(text) => text,
+            WsMessage::Close(_) => break,
+            _ => continue,
+        };
+        let result = match serde_json::from_str::<WsClientMessage>(&text) {
+            // Every reply is charged, so each message reads the balance afresh.
+            Ok(WsClientMessage::Message(message)) => match user.refresh_session(&state).await {
+                Ok(()) => stream_reply(&mut socket, &state, &user, conversation_id, message).await,
+                Err(error) if error.status == StatusCode::UNAUTHORIZED => {
+                    let _ = send_app_error(&mut socket, error).await;
+                    break;
+                }
+                Err(error) => send_app_error(&mut socket, error).await,
+            },
+            // Nothing is streaming, so there is nothing to cancel.
+            Ok(WsClientMessage::Cancel) => Ok(()),
+            Err(e) => {
+                send_event(
+                    &mut socket,
+                    "error",
+                    json!({ "message": format!("Invalid frame: {}", e) }),
+                )
+                .await
+            }
+        };
+        if let Err(e) = result {
+            error!(
+                "Realtime chat socket of conversation '{}' failed: {}",
+                conversation_id, e
+            );
+            break;
+        }
+    }
+    info!(
+        "Realtime chat socket for conversation '{}' of user '{}' closed.",
+        conversation_id, user.uid
+    );
+}
+
+pub async fn send_event(
+    socket: &mut WebSocket,
+    event: &str,
+    data: serde_json::Value,
+) -> Result<(), axum::Error> {
+    socket
+        .send(WsMessage::Text(
+            json!({ "type": event, "data": data }).to_string(),
+        ))
+        .await
+}
+
+async fn send_error(
+    socket: &mut WebSocket,
+    status: StatusCode,
+    message: String,
+) -> Result<(), axum::Error> {
+    send_app_error(socket, AppError::from((status, message))).await
+}
+
+async fn send_app_error(socket: &mut WebSocket, error: AppError) -> Result<(), axum::Error> {
+    let mut payload = error.body();
+    payload["status"] = json!(error.status.as_u16());
+    send_event(socket, "error", payload).await
+}
+
+// Turns one server-sent event of the chat stream into a socket frame. Audio is sent as binary WAV data.
+fn to_socket_message(frame: &[u8]) -> Option<WsMessage> {
+    let frame = std::str::from_utf8(frame).ok()?;
+    let mut event = "message";
+    let mut data = serde_json::Value::Null;
+    for line in frame.lines() {
+        if let Some(value) = line.strip_prefix("event: ") {
+            event = value;
+        } else if let Some(value) = line.strip_prefix("data: ") {
+            data = serde_json::from_str(value).ok()?;
+        }
+    }
+    if event == "audio" {
+        let audio = BASE64_STANDARD.decode(data["data"].as_str()?).ok()?;
+        return Some(WsMessage::Binary(audio));
+    }
+    Some(WsMessage::Text(
+        json!({ "type": event, "data": data }).to_string(),
+    ))
+}
+
+async fn stream_reply(
+    socket: &mut WebSocket,
+    state: &Arc<ServiceState>,
+    user: &UserClaims,
+    conversation_id: Uuid,
+    message: WsChatMessage,
+) -> Result<(), axum::Error> {
+    let config = &state.config.rate_limit;
+    if config.is_enabled() {
+        let status = state.rate_limiter.check(
+            user.uid,
+            config.requests,
+            Duration::from_secs(config.window_secs),
+        );
+        if !status.allowed {
+            return send_error(
+                socket,
+                StatusCode::TOO_MANY_REQUESTS,
+                format!(
+                    "Rate limit exceeded. Retry in {} seconds.",
+                    status.reset.as_secs().max(1)
+                ),
+            )
+            .await;
+        }
+    }
+
+    let (message_data, voice_filename) = match message.audio.as_deref() {
+        Some(audio) => match BASE64_STANDARD.decode(audio) {
+            Ok(data) => (
+                data,
+                Some(message.filename.unwrap_or_else(|| "voice.wav".to_string())),
+            ),
+            Err(e) => {
+                return send_error(
+                    socket,
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid audio encoding: {}", e),
+                )
+                .await;
+            }
+        },
+        None => (
+            message.content.unwrap_or_default().into_bytes(),
+            message.filename,
+        ),
+    };
+    let markdown_mode = match message.markdown_mode.as_deref().map(str::parse) {
+        Some(Ok(mode)) => Some(mode),
+        Some(Err(e)) => return send_error(socket, StatusCode::BAD_REQUEST, e).await,
+        None => None,
+    };
+    let options = MessageOptions {
+        markdown_mode,
+        transcription_prompt: message.transcription_prompt,
+        reply_mode: message.reply_mode,
+        sse: true,
+        audio_format: message.audio_format,
+        style_preset: message.style_preset,
+        voice_profile: message.voice_profile,
+        collection_ids: message.collection_ids,
+        reply_to: message.reply_to,
+        ..Default::default()
+    };
+
+    send_event(socket, "typing", json!({})).await?;
+    let response = match handle_user_message(
+        state.clone(),
+        user.uid,
+        user.session_data.clone(),
+        conversation_id,
+        message.message_type,
+        message_data,
+        message.model_name,
+        vec![],
+        message.message_id.unwrap_or(-1),
+        voice_filename,
+        vec![],
+        options,
+    )
+    .await
+    {
+        Ok(response) => response.into_response(),
+        Err(error) => return send_app_error(socket, error).await,
+    };
+
+    // Cancelling keeps the socket reading until the partial reply has been saved.
+    let mut body = response.into_body().into_data_stream();
+    loop {
+        tokio::select! {
+            frame = body.next() => match frame {
+                Some(Ok(data)) => {
+                    if let Some(message) = to_socket_message(&data) {
+                        socket.send(message).await?;
+                    }
+                }
+                Some(Err(e)) => {
+                    return send_error(socket, StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                        .await;
+                }
+                None => return Ok(()),
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(WsMessage::Text(text))) => {
+                    match serde_json::from_str::<WsClientMessage>(&text) {
+                        Ok(WsClientMessage::Cancel) => {
+                            info!(
+                                "User '{}' cancelled the reply in conversation '{}'.",
+                                user.uid, conversation_id
+                            );
+                            state.generations.cancel(conversation_id, user.uid);
+                            send_event(socket, "cancelled", json!({})).await?;
+                        }
+                        _ => {
+                            send_error(
+                                socket,
+                                StatusCode::CONFLICT,
+                                "A reply is still streaming. Cancel it first.".to_string(),
+                            )
+                            .await?;
+                        }
+                    }
+                }
+                Some(Ok(WsMessage::Close(_))) | None => return Ok(()),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -1,10 +1,352 @@
+use crate::{
+    client::provider::{AspectRatio, ImageModel, ImageQuality, ImageStyle},
+    config::chat::MarkdownMode,
+    entity::{
+        collection::CollectionScope,
+        conversation::{ConversationOrder, Message, ReplyMode, SortDirection},
+    },
+    service::retrieval::RetrievedChunk,
+    utils::audio::AudioFormat,
+};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct EditTitleRequest {
     pub title: String,
 }
 #[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditRetentionRequest {
+    pub retention_days: Option<i32>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditLanguageRequest {
+    pub language: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConversationListQuery {
+    pub model: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub has_images: Option<bool>,
+    pub language: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+    pub order_by: Option<ConversationOrder>,
+    pub order: Option<SortDirection>,
+    #[serde(default)]
+    pub include_archived: bool,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<u64>,
+}
+/// `messages` holds either messages as returned by the conversation endpoint
+/// or OpenAI-style `{ "role", "content" }` entries.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImportConversationRequest {
+    pub title: Option<String>,
+    pub system_prompt: Option<String>,
+    pub language: Option<String>,
+    pub messages: Vec<serde_json::Value>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkOperation {
+    Delete,
+    Archive,
+    Unarchive,
+    Export,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkConversationRequest {
+    pub operation: BulkOperation,
+    pub conversation_ids: Vec<Uuid>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditBudgetRequest {
+    pub max_credits: Option<i64>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditSystemPromptRequest {
+    pub system_prompt: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditStylePresetRequest {
+    pub style_preset: Option<String>,
+}
+/// Replaces all generation settings of a conversation; omitted fields are cleared.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditGenerationSettingsRequest {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub tools: Option<serde_json::Value>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RegenerateRequest {
+    pub message_id: Option<i64>,
+    pub model_name: Option<String>,
+    pub reply_mode: Option<ReplyMode>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditLockRequest {
+    pub locked: bool,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditMessageContextRequest {
+    pub excluded: bool,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RegisterVoiceRequest {
+    pub voice_id: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct ImageGenerationRequest {
     pub text: String,
+    #[serde(default)]
+    pub enhance_prompt: bool,
+    #[serde(default)]
+    pub aspect_ratio: AspectRatio,
+    /// Best effort; providers without native support only see it as text in the prompt.
+    pub negative_prompt: Option<String>,
+    #[serde(default)]
+    pub model: ImageModel,
+    /// `WxH`; the aspect ratio picks the size when it is missing.
+    pub size: Option<String>,
+    #[serde(default)]
+    pub quality: ImageQuality,
+    pub style: Option<ImageStyle>,
+    /// Number of images, 1 unless the model supports more.
+    pub n: Option<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateCollectionRequest {
+    pub name: String,
+    pub scope: Option<CollectionScope>,
+    pub embedding_model: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CreateBotRequest {
+    pub name: String,
+    pub webhook_url: Option<String>,
+    pub model_name: Option<String>,
+    /// Credits moved from the owner's balance into the bot's pool.
+    pub credits: i64,
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotReplyDelivery {
+    /// The reply is returned in the response.
+    #[default]
+    Sync,
+    /// The request returns at once and the reply is posted to the bot's webhook.
+    Webhook,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BotMessageRequest {
+    pub text: String,
+    /// Id of the thread in the external system; each thread gets its own conversation.
+    pub thread_id: Option<String>,
+    /// Posts into an existing conversation of the bot's owner instead.
+    pub conversation_id: Option<Uuid>,
+    pub model_name: Option<String>,
+    #[serde(default)]
+    pub reply: BotReplyDelivery,
+}
+/// Sets up the conversation an embedded widget will chat in.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WidgetSessionRequest {
+    /// Name of a style preset the assistant answers in.
+    pub persona: Option<String>,
+    pub system_prompt: Option<String>,
+    pub language: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WidgetMessageRequest {
+    pub text: String,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AddDocumentRequest {
+    pub title: String,
+    pub content: String,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReindexCollectionRequest {
+    pub embedding_model: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExtractRequest {
+    pub text: Option<String>,
+    pub document_id: Option<Uuid>,
+    pub image: Option<String>,
+    pub schema: serde_json::Value,
+    pub schema_name: Option<String>,
+    pub instruction: Option<String>,
+    pub model_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ActionItemsRequest {
+    #[serde(default)]
+    pub push_webhook: bool,
+    pub model_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InitUploadRequest {
+    pub filename: String,
+    pub total_size: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditInstructionsRequest {
+    pub about_me: Option<String>,
+    pub response_style: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EditDraftRequest {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub attachment_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LockConversationRequest {
+    pub conversation_id: Uuid,
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SupportAccessQuery {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageReplacement {
+    pub index: usize,
+    pub message: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RepairConversationRequest {
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub replacements: Vec<MessageReplacement>,
+    /// Removes the whole turn around any message that is still unreadable.
+    #[serde(default)]
+    pub drop_corrupted: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeleteUserDataRequest {
+    pub user_id: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InvalidateSessionsRequest {
+    pub user_id: i64,
+    /// Set when a single session was revoked; otherwise every session of the user is dropped.
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServiceConversationsRequest {
+    pub user_id: i64,
+    #[serde(default)]
+    pub include_archived: bool,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+/// Names one conversation of a user for a support action; the reason is
+/// required and goes to the audit log.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServiceConversationRequest {
+    pub user_id: i64,
+    pub conversation_id: Uuid,
+    pub reason: Option<String>,
+}
+
+/// Aggregates every user's usage when `user_id` is left out.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServiceUsageRequest {
+    pub user_id: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsClientMessage {
+    Message(WsChatMessage),
+    Cancel,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WsChatMessage {
+    pub message_type: String,
+    #[serde(default)]
+    pub model_name: String,
+    pub content: Option<String>,
+    pub audio: Option<String>,
+    pub filename: Option<String>,
+    pub message_id: Option<i64>,
+    pub transcription_prompt: Option<String>,
+    pub markdown_mode: Option<String>,
+    pub reply_mode: Option<ReplyMode>,
+    #[serde(default)]
+    pub collection_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub audio_format: AudioFormat,
+    pub style_preset: Option<String>,
+    pub voice_profile: Option<String>,
+    pub reply_to: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LiveTranscriptionQuery {
+    pub language: Option<String>,
+    /// Raw audio encoding such as `linear16`; leave unset for WebM or Ogg.
+    pub encoding: Option<String>,
+    pub sample_rate: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveClientMessage {
+    /// Flushes the audio received so far into a final transcript.
+    Finalize,
+    /// Ends the session once the remaining transcripts have been sent.
+    Close,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MessageOptions {
+    pub markdown_mode: Option<MarkdownMode>,
+    pub transcription_prompt: Option<String>,
+    pub reply_mode: Option<ReplyMode>,
+    pub sse: bool,
+    pub audio_format: AudioFormat,
+    pub style_preset: Option<String>,
+    pub voice_profile: Option<String>,
+    pub collection_ids: Vec<Uuid>,
+    pub retrieved_context: Vec<RetrievedChunk>,
+    pub regenerate: Option<Message>,
+    /// The `id` of the earlier message the new one replies to.
+    pub reply_to: Option<usize>,
+    /// Set when an integration bot sent the message; its credit pool pays for the reply.
+    pub bot_id: Option<Uuid>,
+    /// Overrides the conversation's tool definitions for this turn.
+    pub tools: Option<serde_json::Value>,
+    /// The call a `tool-call` message carries the result of.
+    pub tool_call_id: Option<String>,
 }
@@ -1,6 +1,16 @@
-use crate::entity::conversation::Message;
+use crate::{
+    config::providers::ModelCapabilities,
+    entity::{
+        bot, collection,
+        conversation::{self, GenerationSettings, Message, Waveform},
+        document,
+    },
+    service::health::ProviderHealth,
+};
 use chrono::{DateTime, Utc};
+use rs_openai::chat::Role;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -11,14 +21,141 @@ pub struct SessionData {
     pub subscription_status: bool,
 }
 
+impl SessionData {
+    pub fn preference(&self, key: &str) -> Option<String> {
+        self.preferences
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    }
+
+    pub fn preference_i64(&self, key: &str) -> Option<i64> {
+        self.preferences.get(key).and_then(|v| v.as_i64())
+    }
+
+    pub fn organization_id(&self) -> Option<String> {
+        match self.session_metadata.get("organization_id")? {
+            serde_json::Value::String(id) if !id.is_empty() => Some(id.clone()),
+            serde_json::Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn region(&self) -> Option<String> {
+        self.session_metadata
+            .get("region")
+            .and_then(|v| v.as_str())
+            .filter(|region| !region.is_empty())
+            .map(|region| region.to_string())
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct GetConversationResponse {
     pub messages: Vec<Message>,
+    pub corrupted: Vec<CorruptedMessage>,
+    pub locked: bool,
+    pub max_credits: Option<i64>,
+    pub credits_spent: i64,
+    pub system_prompt: Option<String>,
+    pub style_preset: Option<String>,
+    pub voice_profile: Option<String>,
+    pub generation_settings: GenerationSettings,
+    /// Message ids that were replied to, each with the ids of its replies.
+    pub replies: BTreeMap<usize, Vec<usize>>,
+}
+
+/// A stored message that could not be read, left untouched in the database
+/// until an admin repairs it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CorruptedMessage {
+    pub index: usize,
+    pub error: String,
+    pub raw: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairConversationResponse {
+    pub repaired: usize,
+    pub dropped_turns: usize,
+    pub corrupted: Vec<CorruptedMessage>,
+}
+
+const PREVIEW_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The language pinned by the user, or the detected one otherwise.
+    pub language: Option<String>,
+    /// User and assistant messages together.
+    pub message_count: usize,
+    pub last_message_preview: Option<String>,
+    pub pinned: bool,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub archived: bool,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+impl From<conversation::Model> for ConversationSummary {
+    fn from(model: conversation::Model) -> Self {
+        ConversationSummary {
+            language: model.effective_language(),
+            message_count: model.conversation.len(),
+            last_message_preview: model.last_message_preview(PREVIEW_CHARS),
+            pinned: model.pinned_at.is_some(),
+            archived: model.archived_at.is_some(),
+            id: model.id,
+            title: model.title,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+            expires_at: model.expires_at,
+            pinned_at: model.pinned_at,
+            archived_at: model.archived_at,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct RetrieveAllConversationResponse {
-    pub conversation_list: Vec<(Uuid, String, DateTime<Utc>)>,
+    pub conversation_list: Vec<ConversationSummary>,
+    pub total: u64,
+    pub offset: u64,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchMatch {
+    pub message_index: usize,
+    pub role: Role,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub conversation_id: Uuid,
+    pub title: String,
+    pub title_match: bool,
+    pub updated_at: DateTime<Utc>,
+    pub matches: Vec<SearchMatch>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchConversationsResponse {
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageSummaryResponse {
+    pub total_conversations: usize,
+    pub total_messages: usize,
+    pub storage_bytes: u64,
+    pub credits_spent: i64,
+    pub credits_remaining: i64,
+    pub period_start: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Default, Serialize)]
@@ -26,12 +163,339 @@ pub struct CreateNewConversationResponse {
     pub conversation_id: Uuid,
 }
 
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportConversationResponse {
+    pub conversation_id: Uuid,
+    pub title: String,
+    pub imported_messages: usize,
+}
+
+/// A conversation in the shape `POST /api/chat/conversation/import` accepts.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedConversation {
+    pub title: String,
+    pub system_prompt: Option<String>,
+    pub language: Option<String>,
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkConversationResult {
+    pub conversation_id: Uuid,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Only set by the `export` operation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation: Option<ExportedConversation>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BulkConversationResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkConversationResult>,
+}
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct EditTitleResponse {
     pub message: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditRetentionResponse {
+    pub message: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditLanguageResponse {
+    pub message: String,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditLockResponse {
+    pub message: String,
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditMessageContextResponse {
+    pub message: String,
+    pub message_id: usize,
+    pub excluded: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditArchiveResponse {
+    pub message: String,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditPinResponse {
+    pub message: String,
+    pub pinned_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditSystemPromptResponse {
+    pub message: String,
+    pub system_prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditStylePresetResponse {
+    pub message: String,
+    pub style_preset: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditGenerationSettingsResponse {
+    pub message: String,
+    pub settings: GenerationSettings,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StylePreset {
+    pub name: String,
+    pub prompt: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StylePresetsResponse {
+    pub presets: Vec<StylePreset>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub name: String,
+    pub credits: Option<i64>,
+    pub capabilities: ModelCapabilities,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelsResponse {
+    pub models: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VoiceProfile {
+    pub name: String,
+    pub model: String,
+    pub sample_rate: u32,
+    pub encoding: &'static str,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VoiceProfilesResponse {
+    pub profiles: Vec<VoiceProfile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EditBudgetResponse {
+    pub message: String,
+    pub max_credits: Option<i64>,
+    pub credits_spent: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CancelGenerationResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RegisterVoiceResponse {
+    pub message: String,
+    pub voice_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UploadStatusResponse {
+    pub attachment_id: Uuid,
+    pub offset: i64,
+    pub total_size: Option<i64>,
+    pub completed: bool,
+    pub waveform: Option<Waveform>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SuggestionsResponse {
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstructionsResponse {
+    pub about_me: Option<String>,
+    pub response_style: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteInstructionsResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DraftResponse {
+    pub content: String,
+    pub attachment_ids: Vec<Uuid>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteDraftResponse {
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct DeleteConversationResponse {
     pub message: String,
 }
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteUserDataResponse {
+    pub deleted_conversations: usize,
+    pub deleted_files: usize,
+    pub deleted_attachments: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ForceDeleteConversationResponse {
+    pub conversation_id: Uuid,
+    pub deleted_files: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelUsage {
+    pub model: String,
+    pub requests: i64,
+    pub credits: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageAggregateResponse {
+    pub user_id: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub requests: i64,
+    pub credits: i64,
+    pub models: Vec<ModelUsage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InvalidateSessionsResponse {
+    pub invalidated: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImageGenerationResponse {
+    pub prompt: String,
+    pub model: String,
+    /// URLs of the stored images, in the order the provider returned them.
+    pub images: Vec<String>,
+    pub credits: i64,
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExtractResponse {
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ActionItemsResponse {
+    pub data: serde_json::Value,
+    pub webhook_delivered: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateCollectionResponse {
+    pub collection_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateBotResponse {
+    pub bot: bot::Model,
+    /// Shown only once; the service keeps a hash.
+    pub api_key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetrieveAllBotsResponse {
+    pub bot_list: Vec<bot::Model>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteBotResponse {
+    pub message: String,
+    pub credits_refunded: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BotMessageResponse {
+    pub conversation_id: Uuid,
+    pub thread_id: Option<String>,
+    /// `None` when the reply is delivered to the webhook.
+    pub reply: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WidgetSessionResponse {
+    pub conversation_id: Uuid,
+    /// Bearer token for `POST /api/chat/integrations/widget/messages`.
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetrieveAllCollectionsResponse {
+    pub collection_list: Vec<collection::Model>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetCollectionResponse {
+    pub collection: collection::Model,
+    pub documents: Vec<document::Model>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AddDocumentResponse {
+    pub document: document::Model,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteCollectionResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteDocumentResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReindexCollectionResponse {
+    pub embedding_model: String,
+    pub documents: usize,
+    pub chunks: i64,
+}
+
+/// Public readiness: whether each provider passed its last probe, without the errors.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub database: bool,
+    pub warmed_up: bool,
+    pub providers: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminStatusResponse {
+    pub ready: bool,
+    pub database: bool,
+    pub warmed_up: bool,
+    pub providers: BTreeMap<String, ProviderHealth>,
+}
@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// An external system (a Slack app, a support desk) allowed to post into
+/// its owner's conversations with an API key. Bots spend from their own
+/// credit pool, set aside from the owner's balance when they are created.
+#[derive(Debug, PartialEq, Eq, Clone, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "integration_bots")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub user_id: i64,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub api_key_hash: String,
+    /// Where replies are delivered when the caller does not wait for them.
+    pub webhook_url: Option<String>,
+    pub model: Option<String>,
+    pub credits_remaining: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Maps a thread of the external system to the conversation holding it.
+#[derive(Debug, PartialEq, Eq, Clone, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "integration_bot_threads")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bot_id: Uuid,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub external_thread_id: String,
+    pub conversation_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
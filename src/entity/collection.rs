@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionScope {
+    #[sea_orm(string_value = "user")]
+    User,
+    #[sea_orm(string_value = "organization")]
+    Organization,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "collections")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub user_id: i64,
+    pub organization_id: Option<String>,
+    pub scope: CollectionScope,
+    pub name: String,
+    pub embedding_model: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub fn is_readable_by(&self, user_id: i64, organization_id: Option<&str>) -> bool {
+        self.user_id == user_id
+            || (self.scope == CollectionScope::Organization
+                && organization_id.is_some()
+                && self.organization_id.as_deref() == organization_id)
+    }
+}
@@ -1,7 +1,9 @@
+use crate::client::provider::ChatParams;
 use chrono::{DateTime, Utc};
 use rs_openai::chat::Role;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid; // Importing Uuid
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -9,6 +11,9 @@ use uuid::Uuid; // Importing Uuid
 pub enum MessageType {
     Text,
     Voice,
+    ImageGeneration,
+    /// An assistant reply asking for tool calls, or the tool result answering one.
+    ToolCall,
 }
 impl Default for MessageType {
     fn default() -> Self {
@@ -16,8 +21,94 @@ impl Default for MessageType {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationOrder {
+    #[default]
+    UpdatedAt,
+    CreatedAt,
+    Title,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplyMode {
+    Text,
+    Voice,
+    Both,
+}
+impl ReplyMode {
+    pub fn has_text(&self) -> bool {
+        *self != ReplyMode::Voice
+    }
+    pub fn has_voice(&self) -> bool {
+        *self != ReplyMode::Text
+    }
+}
+impl From<&MessageType> for ReplyMode {
+    fn from(message_type: &MessageType) -> Self {
+        match message_type {
+            MessageType::Text | MessageType::ImageGeneration | MessageType::ToolCall => {
+                ReplyMode::Text
+            }
+            MessageType::Voice => ReplyMode::Voice,
+        }
+    }
+}
+impl std::str::FromStr for ReplyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(ReplyMode::Text),
+            "voice" => Ok(ReplyMode::Voice),
+            "both" => Ok(ReplyMode::Both),
+            other => Err(format!("Unknown reply mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Citation {
+    pub document_id: Uuid,
+    pub chunk_index: i32,
+    pub score: f32,
+}
+
+/// A function call the model asked for; `arguments` is the JSON text it produced.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+    /// When the call was handed to the client, which has until the tool's
+    /// timeout to send the result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Waveform {
+    pub duration_ms: u64,
+    pub peaks: Vec<u8>,
+}
+
+/// Version written into every stored message. Bump it whenever `Message`
+/// changes shape and teach `upcast_message` how to bring older payloads forward.
+pub const MESSAGE_SCHEMA_VERSION: u32 = 6;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Message {
+    #[serde(default)]
+    pub schema_version: u32,
     #[serde(rename = "type")]
     pub msgtype: MessageType,
     pub id: usize,
@@ -25,6 +116,141 @@ pub struct Message {
     pub content: String,
     pub transcription: Option<String>,
     pub images: Vec<String>,
+    pub reply_mode: Option<ReplyMode>,
+    pub citations: Option<Vec<Citation>>,
+    pub audio: Option<String>,
+    pub waveform: Option<Waveform>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub truncated: bool,
+    /// The `id` of an earlier message in the same conversation this one quotes.
+    pub reply_to: Option<usize>,
+    /// Kept out of the model context of later turns, but still shown.
+    #[serde(default)]
+    pub excluded: bool,
+    /// The calls an assistant message of type `ToolCall` asked for.
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Set on a tool result, which takes the place of the user message of its turn.
+    pub tool_call_id: Option<String>,
+    /// Moderation categories a user message was flagged for when it was let through.
+    pub moderation_flags: Option<Vec<String>>,
+}
+
+impl Message {
+    /// Deserializes a message as stored in `conversations.conversation`,
+    /// upgrading payloads written by older versions of the service first.
+    pub fn from_stored(value: serde_json::Value) -> Result<Message, serde_json::Error> {
+        serde_json::from_value(upcast_message(value))
+    }
+}
+
+/// Maps the `id` of every message that was replied to onto the ids of its replies.
+pub fn reply_threads(messages: &[Message]) -> BTreeMap<usize, Vec<usize>> {
+    let mut threads: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for message in messages {
+        if let Some(parent_id) = message.reply_to {
+            threads.entry(parent_id).or_default().push(message.id);
+        }
+    }
+    threads
+}
+
+/// Brings a stored message up to `MESSAGE_SCHEMA_VERSION` one step at a time.
+/// Messages written before versioning existed carry no `schema_version` and
+/// are treated as version 1.
+fn upcast_message(mut value: serde_json::Value) -> serde_json::Value {
+    let Some(object) = value.as_object_mut() else {
+        return value;
+    };
+    let mut version = object
+        .get("schema_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1) as u32;
+    while version < MESSAGE_SCHEMA_VERSION {
+        match version {
+            // v1 -> v2: `type`, `images` and `truncated` became mandatory fields
+            // with well-defined defaults.
+            1 => {
+                object
+                    .entry("type")
+                    .or_insert_with(|| serde_json::json!("text"));
+                let images = object.entry("images").or_insert(serde_json::Value::Null);
+                if images.is_null() {
+                    *images = serde_json::json!([]);
+                }
+                object
+                    .entry("truncated")
+                    .or_insert(serde_json::Value::Bool(false));
+            }
+            // v2 -> v3: messages can reply to an earlier message.
+            2 => {
+                object.entry("reply_to").or_insert(serde_json::Value::Null);
+            }
+            // v3 -> v4: messages can be left out of the model context.
+            3 => {
+                object
+                    .entry("excluded")
+                    .or_insert(serde_json::Value::Bool(false));
+            }
+            // v4 -> v5: messages can carry tool calls and tool results.
+            4 => {
+                object
+                    .entry("tool_calls")
+                    .or_insert(serde_json::Value::Null);
+                object
+                    .entry("tool_call_id")
+                    .or_insert(serde_json::Value::Null);
+            }
+            // v5 -> v6: user messages can carry moderation flags.
+            5 => {
+                object
+                    .entry("moderation_flags")
+                    .or_insert(serde_json::Value::Null);
+            }
+            _ => break,
+        }
+        version += 1;
+    }
+    object.insert(
+        "schema_version".to_string(),
+        serde_json::json!(MESSAGE_SCHEMA_VERSION),
+    );
+    value
+}
+
+/// Generation parameters pinned on a conversation. Unset fields fall back to
+/// the request or the provider's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationSettings {
+    /// Used when a message is sent without a model.
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// OpenAI `tools` definitions offered to the model on every turn.
+    pub tools: Option<serde_json::Value>,
+}
+
+impl GenerationSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == GenerationSettings::default()
+    }
+
+    pub fn to_stored(&self) -> Option<serde_json::Value> {
+        if self.is_empty() {
+            return None;
+        }
+        serde_json::to_value(self).ok()
+    }
+
+    pub fn chat_params(&self) -> ChatParams {
+        ChatParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            tools: self.tools.clone(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, DeriveEntityModel)]
@@ -35,17 +261,88 @@ pub struct Model {
     pub user_id: i64,
     pub conversation: Vec<serde_json::Value>,
     pub title: String,
+    /// False once the user renamed the conversation, so automatic titles stop replacing it.
+    pub title_generated: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub retention_days: Option<i32>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub pinned_at: Option<DateTime<Utc>>,
+    /// Set when the user deletes the conversation; the row is purged later.
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub language: Option<String>,
+    /// Dominant language of the messages, refreshed whenever a turn is saved.
+    pub detected_language: Option<String>,
+    pub locked: bool,
+    pub locked_by_admin: bool,
+    pub max_credits: Option<i64>,
+    pub credits_spent: i64,
+    /// Size of the media files written for the conversation.
+    pub media_bytes: i64,
+    pub system_prompt: Option<String>,
+    pub style_preset: Option<String>,
+    /// Name of the TTS voice profile last picked for the conversation.
+    pub voice_profile: Option<String>,
+    /// `GenerationSettings` stored as JSON, read through `generation_settings()`.
+    pub generation_settings: Option<serde_json::Value>,
 }
 
-#[derive(Copy, Clone, Debug, EnumIter)]
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
-impl RelationTrait for Relation {
-    fn def(&self) -> RelationDef {
-        panic!("No relations are defined for this model!")
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// The language pinned by the user, or the detected one otherwise.
+    pub fn effective_language(&self) -> Option<String> {
+        self.language
+            .clone()
+            .or_else(|| self.detected_language.clone())
     }
-}
 
-impl ActiveModelBehavior for ActiveModel {}
+    pub fn generation_settings(&self) -> GenerationSettings {
+        self.generation_settings
+            .clone()
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default()
+    }
+
+    /// Text of the latest message with any, cut to `max_chars`, for conversation lists.
+    pub fn last_message_preview(&self, max_chars: usize) -> Option<String> {
+        self.conversation
+            .iter()
+            .rev()
+            .filter_map(|v| Message::from_stored(v.clone()).ok())
+            .find_map(|message| {
+                let text = match message.msgtype {
+                    MessageType::Voice => message.transcription.unwrap_or_default(),
+                    _ => message.content,
+                };
+                let text = text.trim();
+                if text.is_empty() {
+                    return None;
+                }
+                let mut preview: String = text.chars().take(max_chars).collect();
+                if text.chars().count() > max_chars {
+                    preview.push('…');
+                }
+                Some(preview)
+            })
+    }
+
+    pub fn media_files(&self) -> Vec<String> {
+        self.conversation
+            .iter()
+            .filter_map(|v| Message::from_stored(v.clone()).ok())
+            .flat_map(|message| {
+                let mut files = message.images;
+                if message.msgtype == MessageType::Voice && !message.content.is_empty() {
+                    files.push(message.content);
+                }
+                files.extend(message.audio);
+                files
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Eq, Clone, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "user_instructions")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    pub about_me: Option<String>,
+    pub response_style: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub fn system_prompt(&self) -> Option<String> {
+        let mut sections = vec![];
+        if let Some(about_me) = self.about_me.as_deref().filter(|s| !s.trim().is_empty()) {
+            sections.push(format!(
+                "What the user shared about themselves:\n{}",
+                about_me.trim()
+            ));
+        }
+        if let Some(style) = self
+            .response_style
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+        {
+            sections.push(format!(
+                "How the user wants you to respond:\n{}",
+                style.trim()
+            ));
+        }
+        if sections.is_empty() {
+            None
+        } else {
+            Some(sections.join("\n\n"))
+        }
+    }
+}
@@ -1 +1,11 @@
+pub mod attachment;
+pub mod bot;
+pub mod bot_thread;
+pub mod collection;
 pub mod conversation;
+pub mod document;
+pub mod draft;
+pub mod generated_image;
+pub mod instruction;
+pub mod outbox;
+pub mod usage;
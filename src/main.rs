@@ -9,10 +9,31 @@ mod service;
 mod utils;
 
 use crate::{
-    client::db::{DatabaseClient, DatabaseClientExt},
+    client::{
+        db::{run_migrations, DatabaseClient, DatabaseClientExt},
+        event_bus::EventBus,
+        openai::OpenAIClient,
+        provider::InferenceProvider,
+        registry::ProviderRegistry,
+    },
     config::{tracing::subscribe_tracing, ServiceConfig},
     routes::create_router,
+    service::{
+        budget::SpendTracker,
+        generation::GenerationRegistry,
+        health::{spawn_health_probe, HealthTracker},
+        latency::LatencyTracker,
+        outbox::spawn_outbox_dispatcher,
+        pipeline::ChatPipeline,
+        queue::ChatQueue,
+        retention::spawn_retention_sweeper,
+    },
+    utils::{
+        encryption, rate_limit::RateLimiter, retry::Resilience, session::SessionCache,
+        unix_socket::serve_unix_socket,
+    },
 };
+use axum_server::tls_rustls::RustlsConfig;
 use std::sync::Arc;
 use tracing::{error, info};
 
@@ -20,6 +41,18 @@ use tracing::{error, info};
 pub struct ServiceState {
     pub config: Arc<ServiceConfig>,
     pub db: Arc<DatabaseClient>,
+    pub provider: Arc<dyn InferenceProvider>,
+    pub registry: Arc<ProviderRegistry>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub spend: Arc<SpendTracker>,
+    pub generations: Arc<GenerationRegistry>,
+    pub chat_queue: Arc<ChatQueue>,
+    pub latency: Arc<LatencyTracker>,
+    pub pipeline: Arc<ChatPipeline>,
+    pub event_bus: Option<Arc<EventBus>>,
+    pub sessions: Arc<SessionCache>,
+    pub resilience: Arc<Resilience>,
+    pub health: Arc<HealthTracker>,
 }
 
 #[tokio::main]
@@ -32,6 +65,10 @@ async fn main() -> Result<(), String> {
         e
     })?;
     info!("✔ Configuration data is loaded!");
+    encryption::install(&service_config.encryption).map_err(|e| {
+        error!("💥 Error in setting up message encryption: {}", e);
+        e
+    })?;
 
     let db_client = DatabaseClient::build_from_config(&service_config)
         .await
@@ -40,11 +77,58 @@ async fn main() -> Result<(), String> {
             "Failed to build database client" // Provide a descriptive error message
         })?;
     info!("✔ Connected to the database!");
+    run_migrations(&db_client).await.map_err(|e| {
+        error!("💥 Error in migrating the database: {}", e);
+        "Failed to migrate the database"
+    })?;
+    info!("✔ Database schema is up to date!");
+
+    let resilience = Arc::new(Resilience::new(&service_config.retry));
+    let openai_client = OpenAIClient::build_from_config(&service_config, resilience.clone())
+        .map_err(|e| {
+            error!("💥 Error in building OpenAI client: {}", e);
+            "Failed to build OpenAI client"
+        })?;
+
+    let provider: Arc<dyn InferenceProvider> = Arc::new(openai_client);
+    let registry =
+        ProviderRegistry::build_from_config(&service_config, provider.clone(), resilience.clone())
+            .map_err(|e| {
+                error!("💥 Error in building the provider registry: {}", e);
+                "Failed to build provider registry"
+            })?;
+
+    let event_bus = EventBus::build_from_config(&service_config)
+        .await
+        .map_err(|e| {
+            error!("💥 Error in building the event bus client: {}", e);
+            "Failed to build event bus client"
+        })?;
+
+    let registry = Arc::new(registry);
+    let sessions = Arc::new(SessionCache::new(&service_config.session));
+    let pipeline =
+        ChatPipeline::build_from_config(&service_config, registry.clone(), sessions.clone());
 
     let service_state = Arc::new(ServiceState {
         config: Arc::new(service_config.clone()),
         db: Arc::new(db_client),
+        provider,
+        registry,
+        rate_limiter: Arc::new(RateLimiter::default()),
+        spend: Arc::new(SpendTracker::default()),
+        generations: Arc::new(GenerationRegistry::default()),
+        chat_queue: Arc::new(ChatQueue::new(&service_config.queue)),
+        latency: Arc::new(LatencyTracker::default()),
+        pipeline: Arc::new(pipeline),
+        event_bus: event_bus.map(Arc::new),
+        sessions,
+        resilience,
+        health: Arc::new(HealthTracker::default()),
     });
+    spawn_retention_sweeper(service_state.clone());
+    spawn_outbox_dispatcher(service_state.clone());
+    spawn_health_probe(service_state.clone());
 
     let listener_addr = service_config
         .clone()
@@ -65,13 +149,42 @@ async fn main() -> Result<(), String> {
         error!("💥 Failed to get addr of the listener: {}", e);
         "Failed to get local listener address"
     })?;
-    info!("🚀 The server is listening on: {}", addr); // Move logging before serving
-
     let router = create_router(service_state);
-    axum::serve(tcp_listener, router).await.map_err(|e| {
-        error!("💥 Server error: {}", e);
-        "Server error occurred"
-    })?;
+    if let Some(socket_path) = service_config.server.unix_socket_path.clone() {
+        let unix_router = router.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_unix_socket(socket_path, unix_router).await {
+                error!("💥 Unix socket server error: {}", e);
+            }
+        });
+    }
+    if let Some((cert_path, key_path)) = service_config.server.tls_paths() {
+        let tls_config = RustlsConfig::from_pem_file(cert_path, key_path)
+            .await
+            .map_err(|e| {
+                error!("💥 Failed to load the TLS certificate or key: {}", e);
+                "Failed to load TLS configuration"
+            })?;
+        let std_listener = tcp_listener.into_std().map_err(|e| {
+            error!("💥 Failed to convert the TCP listener: {}", e);
+            "Failed to prepare TLS listener"
+        })?;
+        info!("🚀 The server is listening on: https://{}", addr);
+        // HTTP/2 is negotiated through ALPN, falling back to HTTP/1.1.
+        axum_server::from_tcp_rustls(std_listener, tls_config)
+            .serve(router.into_make_service())
+            .await
+            .map_err(|e| {
+                error!("💥 Server error: {}", e);
+                "Server error occurred"
+            })?;
+    } else {
+        info!("🚀 The server is listening on: {}", addr); // Move logging before serving
+        axum::serve(tcp_listener, router).await.map_err(|e| {
+            error!("💥 Server error: {}", e);
+            "Server error occurred"
+        })?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,148 @@
+use crate::entity::attachment;
+use chrono::Utc;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter,
+    QuerySelect, Set,
+};
+use uuid::Uuid;
+
+pub async fn create(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    filename: String,
+    total_size: Option<i64>,
+) -> Result<attachment::Model, String> {
+    let now = Utc::now();
+    let new_attachment = attachment::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        filename: Set(filename),
+        size: Set(0),
+        total_size: Set(total_size),
+        waveform: Set(None),
+        completed_at: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    };
+
+    match new_attachment.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "New attachment record is not saved successfully: {}",
+            e
+        )),
+    }
+}
+
+pub async fn find_by_user_id_and_attachment_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    attachment_id: Uuid,
+) -> Result<Option<attachment::Model>, String> {
+    match attachment::Entity::find()
+        .filter(attachment::Column::UserId.eq(user_id))
+        .filter(attachment::Column::Id.eq(attachment_id))
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "Error finding attachment by user_id and attachment_id: {}",
+            e
+        )),
+    }
+}
+
+// Locks the row until the transaction ends, so appends to one upload run one at a time.
+pub async fn lock_by_user_id_and_attachment_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    attachment_id: Uuid,
+) -> Result<Option<attachment::Model>, String> {
+    match attachment::Entity::find()
+        .filter(attachment::Column::UserId.eq(user_id))
+        .filter(attachment::Column::Id.eq(attachment_id))
+        .lock_exclusive()
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "Error locking attachment by user_id and attachment_id: {}",
+            e
+        )),
+    }
+}
+
+pub async fn set_size(
+    tx: &DatabaseTransaction,
+    model: attachment::Model,
+    size: i64,
+) -> Result<attachment::Model, String> {
+    let mut updated_model: attachment::ActiveModel = model.into();
+    updated_model.size = Set(size);
+    updated_model.updated_at = Set(Utc::now());
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error updating the attachment size: {}", e)),
+    }
+}
+
+pub async fn complete(
+    tx: &DatabaseTransaction,
+    model: attachment::Model,
+    waveform: Option<serde_json::Value>,
+) -> Result<attachment::Model, String> {
+    let now = Utc::now();
+    let mut updated_model: attachment::ActiveModel = model.into();
+    updated_model.waveform = Set(waveform);
+    updated_model.completed_at = Set(Some(now));
+    updated_model.updated_at = Set(now);
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error completing the attachment: {}", e)),
+    }
+}
+
+pub async fn total_size_by_user_id(tx: &DatabaseTransaction, user_id: i64) -> Result<i64, String> {
+    match attachment::Entity::find()
+        .select_only()
+        .column_as(Expr::cust("COALESCE(SUM(size), 0)::BIGINT"), "total")
+        .filter(attachment::Column::UserId.eq(user_id))
+        .into_tuple::<i64>()
+        .one(tx)
+        .await
+    {
+        Ok(total) => Ok(total.unwrap_or_default()),
+        Err(e) => Err(format!("Error summing attachment sizes by user_id: {}", e)),
+    }
+}
+
+pub async fn find_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Vec<attachment::Model>, String> {
+    match attachment::Entity::find()
+        .filter(attachment::Column::UserId.eq(user_id))
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models),
+        Err(e) => Err(format!("Error finding attachments by user_id: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Vec<attachment::Model>, String> {
+    let attachments = find_by_user_id(tx, user_id).await?;
+    match attachment::Entity::delete_many()
+        .filter(attachment::Column::UserId.eq(user_id))
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(attachments),
+        Err(e) => Err(format!("Error deleting attachments by user_id: {}", e)),
+    }
+}
@@ -0,0 +1,162 @@
+use crate::entity::{bot, bot_thread};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use uuid::Uuid;
+
+pub async fn create(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    name: String,
+    api_key_hash: String,
+    webhook_url: Option<String>,
+    model: Option<String>,
+    credits: i64,
+) -> Result<bot::Model, String> {
+    let new_bot = bot::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        name: Set(name),
+        api_key_hash: Set(api_key_hash),
+        webhook_url: Set(webhook_url),
+        model: Set(model),
+        credits_remaining: Set(credits),
+        created_at: Set(Utc::now()),
+    };
+
+    match new_bot.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("New bot record is not saved successfully: {}", e)),
+    }
+}
+
+pub async fn find_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Vec<bot::Model>, String> {
+    match bot::Entity::find()
+        .filter(bot::Column::UserId.eq(user_id))
+        .order_by_asc(bot::Column::CreatedAt)
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models),
+        Err(e) => Err(format!("Error finding bots by user_id: {}", e)),
+    }
+}
+
+pub async fn find_by_user_id_and_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    bot_id: Uuid,
+) -> Result<Option<bot::Model>, String> {
+    match bot::Entity::find_by_id(bot_id)
+        .filter(bot::Column::UserId.eq(user_id))
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error finding bot by user_id and id: {}", e)),
+    }
+}
+
+pub async fn find_by_id(
+    tx: &DatabaseTransaction,
+    bot_id: Uuid,
+) -> Result<Option<bot::Model>, String> {
+    match bot::Entity::find_by_id(bot_id).one(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error finding bot by id: {}", e)),
+    }
+}
+
+pub async fn find_by_api_key_hash(
+    tx: &DatabaseTransaction,
+    api_key_hash: &str,
+) -> Result<Option<bot::Model>, String> {
+    match bot::Entity::find()
+        .filter(bot::Column::ApiKeyHash.eq(api_key_hash))
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error finding bot by API key: {}", e)),
+    }
+}
+
+pub async fn spend_credits(
+    tx: &DatabaseTransaction,
+    bot_id: Uuid,
+    credits: i64,
+) -> Result<(), String> {
+    match bot::Entity::update_many()
+        .col_expr(
+            bot::Column::CreditsRemaining,
+            Expr::col(bot::Column::CreditsRemaining).sub(credits),
+        )
+        .filter(bot::Column::Id.eq(bot_id))
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error updating the bot credits: {}", e)),
+    }
+}
+
+// Removes the bot together with its thread mappings; the conversations stay with the owner.
+pub async fn delete(tx: &DatabaseTransaction, bot_model: bot::Model) -> Result<(), String> {
+    bot_thread::Entity::delete_many()
+        .filter(bot_thread::Column::BotId.eq(bot_model.id))
+        .exec(tx)
+        .await
+        .map_err(|e| format!("Error deleting the bot threads: {}", e))?;
+    bot_model
+        .delete(tx)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Error deleting the bot: {}", e))
+}
+
+pub async fn delete_by_user_id(tx: &DatabaseTransaction, user_id: i64) -> Result<u64, String> {
+    let bots = find_by_user_id(tx, user_id).await?;
+    let deleted = bots.len() as u64;
+    for bot_model in bots {
+        delete(tx, bot_model).await?;
+    }
+    Ok(deleted)
+}
+
+pub async fn find_thread_conversation(
+    tx: &DatabaseTransaction,
+    bot_id: Uuid,
+    external_thread_id: &str,
+) -> Result<Option<Uuid>, String> {
+    match bot_thread::Entity::find_by_id((bot_id, external_thread_id.to_string()))
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model.map(|model| model.conversation_id)),
+        Err(e) => Err(format!("Error finding the bot thread: {}", e)),
+    }
+}
+
+pub async fn map_thread(
+    tx: &DatabaseTransaction,
+    bot_id: Uuid,
+    external_thread_id: String,
+    conversation_id: Uuid,
+) -> Result<(), String> {
+    let new_thread = bot_thread::ActiveModel {
+        bot_id: Set(bot_id),
+        external_thread_id: Set(external_thread_id),
+        conversation_id: Set(conversation_id),
+        created_at: Set(Utc::now()),
+    };
+
+    match new_thread.insert(tx).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("New bot thread is not saved successfully: {}", e)),
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{
+    entity::{
+        collection::{self, CollectionScope},
+        document,
+    },
+    service::retrieval::RetrievedChunk,
+};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseTransaction, DbBackend,
+    EntityTrait, QueryFilter, QueryOrder, QueryResult, Set, Statement,
+};
+use uuid::Uuid;
+
+pub async fn create(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    organization_id: Option<String>,
+    scope: CollectionScope,
+    name: String,
+    embedding_model: String,
+) -> Result<collection::Model, String> {
+    let now = Utc::now();
+    let new_collection = collection::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        organization_id: Set(organization_id),
+        scope: Set(scope),
+        name: Set(name),
+        embedding_model: Set(embedding_model),
+        created_at: Set(now),
+        updated_at: Set(now),
+    };
+
+    match new_collection.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "New collection record is not saved successfully: {}",
+            e
+        )),
+    }
+}
+
+pub async fn find_visible(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    organization_id: Option<&str>,
+) -> Result<Vec<collection::Model>, String> {
+    let mut condition = Condition::any().add(collection::Column::UserId.eq(user_id));
+    if let Some(organization_id) = organization_id {
+        condition = condition.add(
+            Condition::all()
+                .add(collection::Column::Scope.eq(CollectionScope::Organization))
+                .add(collection::Column::OrganizationId.eq(organization_id)),
+        );
+    }
+    match collection::Entity::find()
+        .filter(condition)
+        .order_by(collection::Column::UpdatedAt, sea_orm::Order::Desc)
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models),
+        Err(e) => Err(format!("Error finding collections: {}", e)),
+    }
+}
+
+pub async fn find_by_id(
+    tx: &DatabaseTransaction,
+    collection_id: Uuid,
+) -> Result<Option<collection::Model>, String> {
+    match collection::Entity::find_by_id(collection_id).one(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error finding collection by id: {}", e)),
+    }
+}
+
+pub async fn set_embedding_model(
+    tx: &DatabaseTransaction,
+    model: collection::Model,
+    embedding_model: String,
+) -> Result<collection::Model, String> {
+    let mut updated_model: collection::ActiveModel = model.into();
+    updated_model.embedding_model = Set(embedding_model);
+    updated_model.updated_at = Set(Utc::now());
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error updating the collection: {}", e)),
+    }
+}
+
+pub async fn delete(tx: &DatabaseTransaction, collection_id: Uuid) -> Result<(), String> {
+    delete_chunks(tx, ChunkOwner::Collection(collection_id)).await?;
+    document::Entity::delete_many()
+        .filter(document::Column::CollectionId.eq(collection_id))
+        .exec(tx)
+        .await
+        .map_err(|e| format!("Error deleting documents of the collection: {}", e))?;
+    match collection::Entity::delete_by_id(collection_id)
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error deleting the collection: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(tx: &DatabaseTransaction, user_id: i64) -> Result<usize, String> {
+    let collections = match collection::Entity::find()
+        .filter(collection::Column::UserId.eq(user_id))
+        .all(tx)
+        .await
+    {
+        Ok(models) => models,
+        Err(e) => return Err(format!("Error finding collections by user_id: {}", e)),
+    };
+    for model in &collections {
+        delete(tx, model.id).await?;
+    }
+    Ok(collections.len())
+}
+
+pub async fn add_document(
+    tx: &DatabaseTransaction,
+    collection_id: Uuid,
+    title: String,
+    content: String,
+) -> Result<document::Model, String> {
+    let now = Utc::now();
+    let new_document = document::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        collection_id: Set(collection_id),
+        title: Set(title),
+        content: Set(content),
+        chunk_count: Set(0),
+        created_at: Set(now),
+        updated_at: Set(now),
+    };
+
+    match new_document.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "New document record is not saved successfully: {}",
+            e
+        )),
+    }
+}
+
+pub async fn find_document_by_id(
+    tx: &DatabaseTransaction,
+    document_id: Uuid,
+) -> Result<Option<document::Model>, String> {
+    match document::Entity::find_by_id(document_id).one(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error finding document by id: {}", e)),
+    }
+}
+
+pub async fn find_documents(
+    tx: &DatabaseTransaction,
+    collection_id: Uuid,
+) -> Result<Vec<document::Model>, String> {
+    match document::Entity::find()
+        .filter(document::Column::CollectionId.eq(collection_id))
+        .order_by(document::Column::CreatedAt, sea_orm::Order::Asc)
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models),
+        Err(e) => Err(format!("Error finding documents of the collection: {}", e)),
+    }
+}
+
+pub async fn delete_document(
+    tx: &DatabaseTransaction,
+    collection_id: Uuid,
+    document_id: Uuid,
+) -> Result<bool, String> {
+    let deleted = match document::Entity::delete_many()
+        .filter(document::Column::CollectionId.eq(collection_id))
+        .filter(document::Column::Id.eq(document_id))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => result.rows_affected > 0,
+        Err(e) => return Err(format!("Error deleting the document: {}", e)),
+    };
+    // Only once the document is known to belong to the collection.
+    if deleted {
+        delete_chunks(tx, ChunkOwner::Document(document_id)).await?;
+    }
+    Ok(deleted)
+}
+
+pub async fn replace_chunks(
+    tx: &DatabaseTransaction,
+    document: document::Model,
+    chunks: Vec<(String, Vec<f32>)>,
+) -> Result<document::Model, String> {
+    delete_chunks(tx, ChunkOwner::Document(document.id)).await?;
+    for (index, (content, embedding)) in chunks.iter().enumerate() {
+        tx.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "INSERT INTO document_chunks (id, document_id, collection_id, chunk_index, content, embedding) \
+             VALUES ($1, $2, $3, $4, $5, $6::vector)",
+            [
+                Uuid::new_v4().into(),
+                document.id.into(),
+                document.collection_id.into(),
+                (index as i32).into(),
+                content.clone().into(),
+                vector_literal(embedding).into(),
+            ],
+        ))
+        .await
+        .map_err(|e| format!("Error saving document chunk: {}", e))?;
+    }
+
+    let mut updated_model: document::ActiveModel = document.into();
+    updated_model.chunk_count = Set(chunks.len() as i32);
+    updated_model.updated_at = Set(Utc::now());
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error updating the document: {}", e)),
+    }
+}
+
+pub async fn search_chunks(
+    tx: &DatabaseTransaction,
+    collection_ids: &[Uuid],
+    embedding: &[f32],
+    limit: u64,
+) -> Result<Vec<RetrievedChunk>, String> {
+    let rows = tx
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT document_id, chunk_index, content, \
+             (1 - (embedding <=> $1::vector))::real AS score \
+             FROM document_chunks WHERE collection_id = ANY($2) \
+             ORDER BY embedding <=> $1::vector LIMIT $3",
+            [
+                vector_literal(embedding).into(),
+                collection_ids.to_vec().into(),
+                (limit as i64).into(),
+            ],
+        ))
+        .await
+        .map_err(|e| format!("Error searching document chunks: {}", e))?;
+
+    rows.iter()
+        .map(read_chunk)
+        .collect::<Result<_, sea_orm::DbErr>>()
+        .map_err(|e| format!("Error reading document chunk: {}", e))
+}
+
+pub async fn search_chunks_by_text(
+    tx: &DatabaseTransaction,
+    collection_ids: &[Uuid],
+    query: &str,
+    text_search_config: &str,
+    limit: u64,
+) -> Result<Vec<RetrievedChunk>, String> {
+    let rows = tx
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT document_id, chunk_index, content, \
+             ts_rank_cd(to_tsvector($1::regconfig, content), query)::real AS score \
+             FROM document_chunks, websearch_to_tsquery($1::regconfig, $2) query \
+             WHERE collection_id = ANY($3) AND to_tsvector($1::regconfig, content) @@ query \
+             ORDER BY score DESC LIMIT $4",
+            [
+                text_search_config.into(),
+                query.into(),
+                collection_ids.to_vec().into(),
+                (limit as i64).into(),
+            ],
+        ))
+        .await
+        .map_err(|e| format!("Error searching document chunks by text: {}", e))?;
+
+    rows.iter()
+        .map(read_chunk)
+        .collect::<Result<_, sea_orm::DbErr>>()
+        .map_err(|e| format!("Error reading document chunk: {}", e))
+}
+
+fn read_chunk(row: &QueryResult) -> Result<RetrievedChunk, sea_orm::DbErr> {
+    Ok(RetrievedChunk {
+        document_id: row.try_get("", "document_id")?,
+        chunk_index: row.try_get("", "chunk_index")?,
+        content: row.try_get("", "content")?,
+        score: row.try_get("", "score")?,
+    })
+}
+
+enum ChunkOwner {
+    Collection(Uuid),
+    Document(Uuid),
+}
+
+async fn delete_chunks(tx: &DatabaseTransaction, owner: ChunkOwner) -> Result<(), String> {
+    let (sql, id) = match owner {
+        ChunkOwner::Collection(id) => ("DELETE FROM document_chunks WHERE collection_id = $1", id),
+        ChunkOwner::Document(id) => ("DELETE FROM document_chunks WHERE document_id = $1", id),
+    };
+    tx.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        [id.into()],
+    ))
+    .await
+    .map_err(|e| format!("Error deleting document chunks: {}", e))?;
+    Ok(())
+}
+
+fn vector_literal(embedding: &[f32]) -> String {
+    let values: Vec<String> = embedding.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
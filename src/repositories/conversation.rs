@@ -1,19 +1,68 @@
-use crate::entity::conversation::{self, Message, MessageType};
-use chrono::Utc;
+use crate::entity::conversation::{
+    self, Citation, ConversationOrder, GenerationSettings, Message, MessageType, ReplyMode,
+    SortDirection, ToolCall, Waveform, MESSAGE_SCHEMA_VERSION,
+};
+use crate::utils::{
+    encryption::{decrypt_messages, decrypt_model, encrypt_messages},
+    language::detect_dominant_language,
+};
+use chrono::{DateTime, Duration, Utc};
 use rs_openai::chat::Role;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, Set,
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Select, Set,
 };
 use uuid::Uuid;
 
-pub async fn new_conversation(tx: &DatabaseTransaction, user_id: i64) -> Result<Uuid, String> {
+// Only the most recent messages are considered, so detection stays cheap on long conversations.
+const LANGUAGE_DETECTION_WINDOW: usize = 50;
+
+fn dominant_language(messages: &[Message]) -> Option<String> {
+    let start = messages.len().saturating_sub(LANGUAGE_DETECTION_WINDOW);
+    detect_dominant_language(
+        messages[start..]
+            .iter()
+            .map(|message| match message.msgtype {
+                MessageType::Voice => message.transcription.as_deref().unwrap_or_default(),
+                _ => message.content.as_str(),
+            }),
+    )
+}
+
+fn expiry_from(now: DateTime<Utc>, retention_days: Option<i32>) -> Option<DateTime<Utc>> {
+    retention_days.map(|days| now + Duration::days(days as i64))
+}
+
+pub async fn new_conversation(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    retention_days: Option<i32>,
+) -> Result<Uuid, String> {
+    let now = Utc::now();
     let new_conversation = conversation::ActiveModel {
         id: Set(Uuid::new_v4()),
         user_id: Set(user_id),
         conversation: Set(vec![]),
         title: Set(String::from("New Chat")),
-        created_at: Set(Utc::now()),
-        updated_at: Set(Utc::now()),
+        title_generated: Set(true),
+        created_at: Set(now),
+        updated_at: Set(now),
+        retention_days: Set(retention_days),
+        expires_at: Set(expiry_from(now, retention_days)),
+        archived_at: Set(None),
+        pinned_at: Set(None),
+        deleted_at: Set(None),
+        language: Set(None),
+        detected_language: Set(None),
+        locked: Set(false),
+        locked_by_admin: Set(false),
+        max_credits: Set(None),
+        credits_spent: Set(0),
+        media_bytes: Set(0),
+        system_prompt: Set(None),
+        style_preset: Set(None),
+        voice_profile: Set(None),
+        generation_settings: Set(None),
     };
 
     match new_conversation.insert(tx).await {
@@ -25,18 +74,188 @@ pub async fn new_conversation(tx: &DatabaseTransaction, user_id: i64) -> Result<
     }
 }
 
+// Creates a conversation that already holds an imported history.
+pub async fn import_conversation(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    title: String,
+    messages: Vec<Message>,
+    system_prompt: Option<String>,
+    language: Option<String>,
+    retention_days: Option<i32>,
+) -> Result<conversation::Model, String> {
+    let detected_language = dominant_language(&messages);
+    let messages = messages
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Error to converting JSON Value from Message: {}", e))?;
+    let messages = encrypt_messages(messages)?;
+    let now = Utc::now();
+    let imported_conversation = conversation::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        conversation: Set(messages),
+        title: Set(title),
+        title_generated: Set(false),
+        created_at: Set(now),
+        updated_at: Set(now),
+        retention_days: Set(retention_days),
+        expires_at: Set(expiry_from(now, retention_days)),
+        archived_at: Set(None),
+        pinned_at: Set(None),
+        deleted_at: Set(None),
+        language: Set(language),
+        detected_language: Set(detected_language),
+        locked: Set(false),
+        locked_by_admin: Set(false),
+        max_credits: Set(None),
+        credits_spent: Set(0),
+        media_bytes: Set(0),
+        system_prompt: Set(system_prompt),
+        style_preset: Set(None),
+        voice_profile: Set(None),
+        generation_settings: Set(None),
+    };
+
+    match imported_conversation.insert(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Imported conversation record is not saved successfully: {}",
+            e
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConversationFilter {
+    pub model: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub has_images: Option<bool>,
+    pub language: Option<String>,
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConversationPage {
+    pub limit: Option<u64>,
+    pub offset: u64,
+    pub order_by: ConversationOrder,
+    pub direction: SortDirection,
+}
+
+fn filtered_query(user_id: i64, filter: &ConversationFilter) -> Select<conversation::Entity> {
+    let mut query = conversation::Entity::find()
+        .filter(conversation::Column::UserId.eq(user_id))
+        .filter(conversation::Column::DeletedAt.is_null());
+    if !filter.include_archived {
+        query = query.filter(conversation::Column::ArchivedAt.is_null());
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(conversation::Column::UpdatedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(conversation::Column::UpdatedAt.lte(to));
+    }
+    if let Some(model) = filter.model.clone() {
+        query = query.filter(Expr::cust_with_values(
+            "EXISTS (SELECT 1 FROM unnest(conversation) AS m WHERE m->>'model' = $1)",
+            [model],
+        ));
+    }
+    if let Some(language) = filter.language.clone() {
+        query = query.filter(Expr::cust_with_values(
+            "COALESCE(language, detected_language) = $1",
+            [language],
+        ));
+    }
+    if let Some(has_images) = filter.has_images {
+        let condition = "EXISTS (SELECT 1 FROM unnest(conversation) AS m WHERE jsonb_array_length(m->'images') > 0)";
+        query = query.filter(if has_images {
+            Expr::cust(condition)
+        } else {
+            Expr::cust(format!("NOT {}", condition))
+        });
+    }
+    query
+}
+
 pub async fn find_by_user_id(
     tx: &DatabaseTransaction,
     user_id: i64,
+    filter: &ConversationFilter,
+) -> Result<Vec<conversation::Model>, String> {
+    match find_page_by_user_id(tx, user_id, filter, &ConversationPage::default()).await {
+        Ok((models, _)) => Ok(models),
+        Err(e) => Err(e),
+    }
+}
+
+// Returns one page of the user's conversations together with the total number matching the filter.
+pub async fn find_page_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    filter: &ConversationFilter,
+    page: &ConversationPage,
+) -> Result<(Vec<conversation::Model>, u64), String> {
+    let query = filtered_query(user_id, filter);
+    let total = match query.clone().count(tx).await {
+        Ok(total) => Ok(total),
+        Err(e) => Err(format!("Error counting conversations by user_id: {}", e)),
+    }?;
+
+    let order = match page.direction {
+        SortDirection::Asc => sea_orm::Order::Asc,
+        SortDirection::Desc => sea_orm::Order::Desc,
+    };
+    let column = match page.order_by {
+        ConversationOrder::UpdatedAt => conversation::Column::UpdatedAt,
+        ConversationOrder::CreatedAt => conversation::Column::CreatedAt,
+        ConversationOrder::Title => conversation::Column::Title,
+    };
+    // Pinned conversations come first whatever the sort. The id tiebreak keeps
+    // pages stable when several rows share the sort value.
+    let mut query = query
+        .order_by(
+            Expr::col(conversation::Column::PinnedAt).is_null(),
+            sea_orm::Order::Asc,
+        )
+        .order_by(column, order.clone())
+        .order_by(conversation::Column::Id, order)
+        .offset(page.offset);
+    if let Some(limit) = page.limit {
+        query = query.limit(limit);
+    }
+    match query.all(tx).await {
+        Ok(models) => Ok((models.into_iter().map(decrypt_model).collect(), total)),
+        Err(e) => Err(format!("Error finding conversation by user_id: {}", e)),
+    }
+}
+
+// Finds the user's conversations whose title or message text contains the escaped LIKE pattern.
+// Encrypted message text cannot be matched in SQL, so only titles and plain-text messages are searched.
+pub async fn search_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    pattern: String,
+    limit: u64,
 ) -> Result<Vec<conversation::Model>, String> {
     match conversation::Entity::find()
         .filter(conversation::Column::UserId.eq(user_id))
+        .filter(conversation::Column::ArchivedAt.is_null())
+        .filter(conversation::Column::DeletedAt.is_null())
+        .filter(Expr::cust_with_values(
+            "(title ILIKE $1 OR EXISTS (SELECT 1 FROM unnest(conversation) AS m WHERE (m->>'type' = 'text' AND m->>'content' ILIKE $2) OR m->>'transcription' ILIKE $3))",
+            [pattern.clone(), pattern.clone(), pattern],
+        ))
         .order_by(conversation::Column::UpdatedAt, sea_orm::Order::Desc)
+        .limit(limit)
         .all(tx)
         .await
     {
-        Ok(model) => Ok(model),
-        Err(e) => Err(format!("Error finding conversation by user_id: {}", e)),
+        Ok(models) => Ok(models.into_iter().map(decrypt_model).collect()),
+        Err(e) => Err(format!("Error searching conversations by user_id: {}", e)),
     }
 }
 
@@ -48,10 +267,11 @@ pub async fn find_by_user_id_and_conversation_id(
     match conversation::Entity::find()
         .filter(conversation::Column::UserId.eq(user_id))
         .filter(conversation::Column::Id.eq(conversation_id))
+        .filter(conversation::Column::DeletedAt.is_null())
         .one(tx)
         .await
     {
-        Ok(model) => Ok(model),
+        Ok(model) => Ok(model.map(decrypt_model)),
         Err(e) => Err(format!(
             "Error finding conversation by user_id and conversation_id: {}",
             e
@@ -59,6 +279,54 @@ pub async fn find_by_user_id_and_conversation_id(
     }
 }
 
+// Postgres changes a row's `xmin` on every update, so it tells whether a copy read earlier is stale.
+pub async fn find_row_version(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> Result<Option<String>, String> {
+    match conversation::Entity::find()
+        .select_only()
+        .column_as(Expr::cust("xmin::text"), "row_version")
+        .filter(conversation::Column::UserId.eq(user_id))
+        .filter(conversation::Column::Id.eq(conversation_id))
+        .filter(conversation::Column::DeletedAt.is_null())
+        .into_tuple::<String>()
+        .one(tx)
+        .await
+    {
+        Ok(row_version) => Ok(row_version),
+        Err(e) => Err(format!("Error finding the conversation row version: {}", e)),
+    }
+}
+
+pub async fn find_by_user_id_and_ids(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    ids: Vec<Uuid>,
+) -> Result<Vec<conversation::Model>, String> {
+    match conversation::Entity::find()
+        .filter(conversation::Column::UserId.eq(user_id))
+        .filter(conversation::Column::Id.is_in(ids))
+        .filter(conversation::Column::DeletedAt.is_null())
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models.into_iter().map(decrypt_model).collect()),
+        Err(e) => Err(format!(
+            "Error finding conversations by user_id and ids: {}",
+            e
+        )),
+    }
+}
+
+// Drops the message at `index` and everything after it, so that turn can be written again.
+pub fn truncate_from<T>(messages: &mut Vec<T>, index: usize) {
+    if index < messages.len() {
+        messages.truncate(index);
+    }
+}
+
 pub async fn add_message(
     tx: &DatabaseTransaction,
     user_id: i64,
@@ -67,12 +335,26 @@ pub async fn add_message(
     user_message: String,
     transcription: Option<String>,
     images: Vec<String>,
+    reply_mode: ReplyMode,
     answer: String,
+    citations: Vec<Citation>,
+    reply_audio: Option<String>,
+    user_waveform: Option<Waveform>,
+    reply_waveform: Option<Waveform>,
+    title: Option<String>,
+    model: String,
+    truncated: bool,
     message_id: i64,
+    reply_images: Vec<String>,
+    reply_to: Option<usize>,
+    tool_call_id: Option<String>,
+    tool_calls: Vec<ToolCall>,
+    moderation_flags: Option<Vec<String>>,
 ) -> Result<conversation::Model, String> {
     let conversation_model = match conversation::Entity::find()
         .filter(conversation::Column::UserId.eq(user_id))
         .filter(conversation::Column::Id.eq(conversation_id))
+        .filter(conversation::Column::DeletedAt.is_null())
         .one(tx)
         .await
     {
@@ -82,61 +364,117 @@ pub async fn add_message(
         )),
         Err(e) => Err(format!("Error finding user by user_id: {}", e)),
     }?;
-
-    let mut updated_conversation = conversation_model.conversation.clone();
-    let mut conversation_title = conversation_model.title;
-    if message_id < updated_conversation.len() as i64 {
-        let _ = updated_conversation.split_off(message_id as usize);
+    if conversation_model.locked {
+        return Err("The conversation is locked".to_string());
     }
-    if message_id == 0 {
-        let words: Vec<&str> = user_message.split_whitespace().collect();
-        let first_three_words = words
-            .iter()
-            .take(3)
-            .cloned()
-            .collect::<Vec<&str>>()
-            .join(" ");
 
-        if first_three_words.len() > 30 {
-            conversation_title = conversation_title.chars().take(30).collect();
-        } else {
-            conversation_title = first_three_words;
-        };
+    let mut updated_conversation = decrypt_messages(conversation_model.conversation.clone());
+    let mut conversation_title = conversation_model.title;
+    truncate_from(&mut updated_conversation, message_id as usize);
+    if message_id == 0 && conversation_model.title_generated {
+        if let Some(title) = title {
+            conversation_title = title;
+        }
     }
     updated_conversation.push(
         serde_json::to_value(&Message {
+            schema_version: MESSAGE_SCHEMA_VERSION,
             msgtype: user_message_type,
             id: updated_conversation.len() + 1,
             role: Role::User,
             content: user_message,
             transcription: transcription,
             images: images,
+            reply_mode: Some(reply_mode),
+            citations: None,
+            audio: None,
+            waveform: user_waveform,
+            model: None,
+            truncated: false,
+            reply_to,
+            excluded: false,
+            tool_calls: None,
+            tool_call_id,
+            moderation_flags,
         })
         .map_err(|e| format!("Error to converting JSON Value from Message: {}", e))?,
     );
     updated_conversation.push(
         serde_json::to_value(&Message {
-            msgtype: MessageType::Text,
+            schema_version: MESSAGE_SCHEMA_VERSION,
+            msgtype: if tool_calls.is_empty() {
+                MessageType::Text
+            } else {
+                MessageType::ToolCall
+            },
             id: updated_conversation.len(),
             role: Role::Assistant,
             transcription: None,
             content: answer,
-            images: vec![],
+            images: reply_images,
+            reply_mode: None,
+            citations: if citations.is_empty() {
+                None
+            } else {
+                Some(citations)
+            },
+            audio: reply_audio,
+            waveform: reply_waveform,
+            model: Some(model),
+            truncated,
+            reply_to: None,
+            excluded: false,
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
+            },
+            tool_call_id: None,
+            moderation_flags: None,
         })
         .map_err(|e| format!("Error to converting JSON Value from Message: {}", e))?,
     );
 
+    let detected_language = dominant_language(
+        &updated_conversation
+            .iter()
+            .rev()
+            .take(LANGUAGE_DETECTION_WINDOW)
+            .filter_map(|v| Message::from_stored(v.clone()).ok())
+            .collect::<Vec<_>>(),
+    )
+    .or(conversation_model.detected_language);
+    let updated_conversation = encrypt_messages(updated_conversation)?;
+    let now = Utc::now();
     let updated_model = conversation::ActiveModel {
         id: Set(conversation_model.id),
         user_id: Set(conversation_model.user_id),
         conversation: Set(updated_conversation),
         title: Set(conversation_title.clone()),
+        title_generated: Set(conversation_model.title_generated),
         created_at: Set(conversation_model.created_at),
-        updated_at: Set(Utc::now()),
+        updated_at: Set(now),
+        retention_days: Set(conversation_model.retention_days),
+        expires_at: Set(expiry_from(now, conversation_model.retention_days)),
+        // A new turn brings an archived conversation back into the list.
+        archived_at: Set(None),
+        pinned_at: Set(conversation_model.pinned_at),
+        deleted_at: Set(conversation_model.deleted_at),
+        language: Set(conversation_model.language),
+        detected_language: Set(detected_language),
+        locked: Set(conversation_model.locked),
+        locked_by_admin: Set(conversation_model.locked_by_admin),
+        max_credits: Set(conversation_model.max_credits),
+        credits_spent: Set(conversation_model.credits_spent),
+        media_bytes: Set(conversation_model.media_bytes),
+        system_prompt: Set(conversation_model.system_prompt),
+        style_preset: Set(conversation_model.style_preset),
+        voice_profile: Set(conversation_model.voice_profile),
+        generation_settings: Set(conversation_model.generation_settings),
     };
 
     match updated_model.update(tx).await {
-        Ok(model) => Ok(model),
+        Ok(model) => Ok(decrypt_model(model)),
         Err(e) => Err(format!("Error updating the conversation data: {}", e)),
     }
 }
@@ -150,6 +488,7 @@ pub async fn edit_title(
     let conversation_model = match conversation::Entity::find()
         .filter(conversation::Column::UserId.eq(user_id))
         .filter(conversation::Column::Id.eq(conversation_id))
+        .filter(conversation::Column::DeletedAt.is_null())
         .one(tx)
         .await
     {
@@ -159,18 +498,438 @@ pub async fn edit_title(
         )),
         Err(e) => Err(format!("Error finding user by user_id: {}", e)),
     }?;
+    if conversation_model.locked {
+        return Err("The conversation is locked".to_string());
+    }
 
+    let now = Utc::now();
     let updated_model = conversation::ActiveModel {
         id: Set(conversation_model.id),
         user_id: Set(conversation_model.user_id),
         conversation: Set(conversation_model.conversation),
         title: Set(title),
+        title_generated: Set(false),
         created_at: Set(conversation_model.created_at),
-        updated_at: Set(Utc::now()),
+        updated_at: Set(now),
+        retention_days: Set(conversation_model.retention_days),
+        // Renaming is not activity, so the retention clock keeps running.
+        expires_at: Set(conversation_model.expires_at),
+        archived_at: Set(conversation_model.archived_at),
+        pinned_at: Set(conversation_model.pinned_at),
+        deleted_at: Set(conversation_model.deleted_at),
+        language: Set(conversation_model.language),
+        detected_language: Set(conversation_model.detected_language),
+        locked: Set(conversation_model.locked),
+        locked_by_admin: Set(conversation_model.locked_by_admin),
+        max_credits: Set(conversation_model.max_credits),
+        credits_spent: Set(conversation_model.credits_spent),
+        media_bytes: Set(conversation_model.media_bytes),
+        system_prompt: Set(conversation_model.system_prompt),
+        style_preset: Set(conversation_model.style_preset),
+        voice_profile: Set(conversation_model.voice_profile),
+        generation_settings: Set(conversation_model.generation_settings),
     };
 
     match updated_model.update(tx).await {
-        Ok(model) => Ok(model),
+        Ok(model) => Ok(decrypt_model(model)),
         Err(e) => Err(format!("Error updating the conversation title: {}", e)),
     }
 }
+
+// Replaces the title unless the user renamed the conversation in the meantime.
+pub async fn set_generated_title(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+    title: String,
+) -> Result<bool, String> {
+    match conversation::Entity::update_many()
+        .col_expr(conversation::Column::Title, Expr::value(title))
+        .filter(conversation::Column::Id.eq(conversation_id))
+        .filter(conversation::Column::UserId.eq(user_id))
+        .filter(conversation::Column::TitleGenerated.eq(true))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected > 0),
+        Err(e) => Err(format!("Error updating the generated title: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Vec<conversation::Model>, String> {
+    let conversations = match conversation::Entity::find()
+        .filter(conversation::Column::UserId.eq(user_id))
+        .all(tx)
+        .await
+    {
+        Ok(models) => models.into_iter().map(decrypt_model).collect(),
+        Err(e) => return Err(format!("Error finding conversations by user_id: {}", e)),
+    };
+    match conversation::Entity::delete_many()
+        .filter(conversation::Column::UserId.eq(user_id))
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(conversations),
+        Err(e) => Err(format!("Error deleting conversations by user_id: {}", e)),
+    }
+}
+
+pub async fn set_retention(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+    retention_days: Option<i32>,
+) -> Result<conversation::Model, String> {
+    let conversation_model = match find_by_user_id_and_conversation_id(tx, user_id, conversation_id)
+        .await?
+    {
+        Some(model) => model,
+        None => return Err("Not found the conversation by user_id and conversation_id".to_string()),
+    };
+
+    let mut updated_model: conversation::ActiveModel = conversation_model.clone().into();
+    updated_model.retention_days = Set(retention_days);
+    updated_model.expires_at = Set(expiry_from(conversation_model.updated_at, retention_days));
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!("Error updating the conversation retention: {}", e)),
+    }
+}
+
+pub async fn set_language(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+    language: Option<String>,
+) -> Result<conversation::Model, String> {
+    let conversation_model = match find_by_user_id_and_conversation_id(tx, user_id, conversation_id)
+        .await?
+    {
+        Some(model) => model,
+        None => return Err("Not found the conversation by user_id and conversation_id".to_string()),
+    };
+
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.language = Set(language);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!("Error updating the conversation language: {}", e)),
+    }
+}
+
+pub async fn set_voice_profile(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+    voice_profile: Option<String>,
+) -> Result<conversation::Model, String> {
+    let conversation_model = match find_by_user_id_and_conversation_id(tx, user_id, conversation_id)
+        .await?
+    {
+        Some(model) => model,
+        None => return Err("Not found the conversation by user_id and conversation_id".to_string()),
+    };
+
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.voice_profile = Set(voice_profile);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Error updating the conversation voice profile: {}",
+            e
+        )),
+    }
+}
+
+pub async fn find_by_id(
+    tx: &DatabaseTransaction,
+    conversation_id: Uuid,
+) -> Result<Option<conversation::Model>, String> {
+    match conversation::Entity::find_by_id(conversation_id)
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model.map(decrypt_model)),
+        Err(e) => Err(format!("Error finding conversation by id: {}", e)),
+    }
+}
+
+pub async fn set_locked(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    locked: bool,
+    by_admin: bool,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.locked = Set(locked);
+    updated_model.locked_by_admin = Set(locked && by_admin);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!("Error updating the conversation lock: {}", e)),
+    }
+}
+
+pub async fn set_max_credits(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    max_credits: Option<i64>,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.max_credits = Set(max_credits);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Error updating the conversation credit ceiling: {}",
+            e
+        )),
+    }
+}
+
+pub async fn set_system_prompt(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    system_prompt: Option<String>,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.system_prompt = Set(system_prompt);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Error updating the conversation system prompt: {}",
+            e
+        )),
+    }
+}
+
+pub async fn set_style_preset(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    style_preset: Option<String>,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.style_preset = Set(style_preset);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Error updating the conversation style preset: {}",
+            e
+        )),
+    }
+}
+
+pub async fn set_generation_settings(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    settings: GenerationSettings,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.generation_settings = Set(settings.to_stored());
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Error updating the conversation generation settings: {}",
+            e
+        )),
+    }
+}
+
+pub async fn replace_messages(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    messages: Vec<serde_json::Value>,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.conversation = Set(encrypt_messages(messages)?);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!("Error replacing the conversation messages: {}", e)),
+    }
+}
+
+pub async fn add_credits_spent(
+    tx: &DatabaseTransaction,
+    conversation_id: Uuid,
+    credits: i64,
+) -> Result<(), String> {
+    match conversation::Entity::update_many()
+        .col_expr(
+            conversation::Column::CreditsSpent,
+            Expr::col(conversation::Column::CreditsSpent).add(credits),
+        )
+        .filter(conversation::Column::Id.eq(conversation_id))
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "Error updating the conversation credits spent: {}",
+            e
+        )),
+    }
+}
+
+// Counted once per file written, as the files are stored on disk and not in the row.
+pub async fn add_media_bytes(
+    tx: &DatabaseTransaction,
+    conversation_id: Uuid,
+    bytes: i64,
+) -> Result<(), String> {
+    match conversation::Entity::update_many()
+        .col_expr(
+            conversation::Column::MediaBytes,
+            Expr::col(conversation::Column::MediaBytes).add(bytes),
+        )
+        .filter(conversation::Column::Id.eq(conversation_id))
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error updating the conversation media size: {}", e)),
+    }
+}
+
+// Conversations, messages and media bytes of the user, archived conversations included.
+pub async fn usage_totals(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<(i64, i64, i64), String> {
+    match conversation::Entity::find()
+        .select_only()
+        .column_as(Expr::cust("COUNT(*)::BIGINT"), "conversations")
+        .column_as(
+            Expr::cust("COALESCE(SUM(cardinality(conversation)), 0)::BIGINT"),
+            "messages",
+        )
+        .column_as(
+            Expr::cust("COALESCE(SUM(media_bytes), 0)::BIGINT"),
+            "media_bytes",
+        )
+        .filter(conversation::Column::UserId.eq(user_id))
+        .filter(conversation::Column::DeletedAt.is_null())
+        .into_tuple::<(i64, i64, i64)>()
+        .one(tx)
+        .await
+    {
+        Ok(totals) => Ok(totals.unwrap_or_default()),
+        Err(e) => Err(format!("Error summing conversations by user_id: {}", e)),
+    }
+}
+
+pub async fn find_expired(
+    tx: &DatabaseTransaction,
+    limit: u64,
+) -> Result<Vec<conversation::Model>, String> {
+    match conversation::Entity::find()
+        .filter(conversation::Column::ExpiresAt.lte(Utc::now()))
+        .filter(conversation::Column::ArchivedAt.is_null())
+        .filter(conversation::Column::DeletedAt.is_null())
+        .order_by(conversation::Column::ExpiresAt, sea_orm::Order::Asc)
+        .limit(limit)
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models.into_iter().map(decrypt_model).collect()),
+        Err(e) => Err(format!("Error finding expired conversations: {}", e)),
+    }
+}
+
+pub async fn archive_by_ids(tx: &DatabaseTransaction, ids: Vec<Uuid>) -> Result<u64, String> {
+    match conversation::Entity::update_many()
+        .col_expr(
+            conversation::Column::ArchivedAt,
+            sea_orm::sea_query::Expr::value(Utc::now()),
+        )
+        .filter(conversation::Column::Id.is_in(ids))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected),
+        Err(e) => Err(format!("Error archiving conversations: {}", e)),
+    }
+}
+
+pub async fn set_archived(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    archived: bool,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.archived_at = Set(archived.then(Utc::now));
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!(
+            "Error updating the conversation archive state: {}",
+            e
+        )),
+    }
+}
+
+pub async fn set_pinned(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+    pinned: bool,
+) -> Result<conversation::Model, String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.pinned_at = Set(pinned.then(Utc::now));
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(decrypt_model(model)),
+        Err(e) => Err(format!("Error updating the conversation pin state: {}", e)),
+    }
+}
+
+pub async fn soft_delete(
+    tx: &DatabaseTransaction,
+    conversation_model: conversation::Model,
+) -> Result<(), String> {
+    let mut updated_model: conversation::ActiveModel = conversation_model.into();
+    updated_model.deleted_at = Set(Some(Utc::now()));
+
+    match updated_model.update(tx).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error marking the conversation as deleted: {}", e)),
+    }
+}
+
+// Conversations the user deleted before `before`, oldest first.
+pub async fn find_purgeable(
+    tx: &DatabaseTransaction,
+    before: DateTime<Utc>,
+    limit: u64,
+) -> Result<Vec<conversation::Model>, String> {
+    match conversation::Entity::find()
+        .filter(conversation::Column::DeletedAt.lte(before))
+        .order_by(conversation::Column::DeletedAt, sea_orm::Order::Asc)
+        .limit(limit)
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models.into_iter().map(decrypt_model).collect()),
+        Err(e) => Err(format!("Error finding deleted conversations: {}", e)),
+    }
+}
+
+pub async fn delete_by_ids(tx: &DatabaseTransaction, ids: Vec<Uuid>) -> Result<u64, String> {
+    match conversation::Entity::delete_many()
+        .filter(conversation::Column::Id.is_in(ids))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected),
+        Err(e) => Err(format!("Error deleting conversations: {}", e)),
+    }
+}
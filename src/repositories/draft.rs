@@ -0,0 +1,95 @@
+use crate::entity::draft;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set};
+use uuid::Uuid;
+
+pub async fn find_by_user_id_and_conversation_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> Result<Option<draft::Model>, String> {
+    match draft::Entity::find_by_id(conversation_id)
+        .filter(draft::Column::UserId.eq(user_id))
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "Error finding draft by user_id and conversation_id: {}",
+            e
+        )),
+    }
+}
+
+pub async fn upsert(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+    content: String,
+    attachment_ids: Vec<Uuid>,
+) -> Result<draft::Model, String> {
+    let now = Utc::now();
+    let result = match find_by_user_id_and_conversation_id(tx, user_id, conversation_id).await? {
+        Some(model) => {
+            let mut updated_model: draft::ActiveModel = model.into();
+            updated_model.content = Set(content);
+            updated_model.attachment_ids = Set(attachment_ids);
+            updated_model.updated_at = Set(now);
+            updated_model.update(tx).await
+        }
+        None => {
+            draft::ActiveModel {
+                conversation_id: Set(conversation_id),
+                user_id: Set(user_id),
+                content: Set(content),
+                attachment_ids: Set(attachment_ids),
+                created_at: Set(now),
+                updated_at: Set(now),
+            }
+            .insert(tx)
+            .await
+        }
+    };
+    result.map_err(|e| format!("Error saving the draft: {}", e))
+}
+
+pub async fn delete(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> Result<bool, String> {
+    match draft::Entity::delete_many()
+        .filter(draft::Column::ConversationId.eq(conversation_id))
+        .filter(draft::Column::UserId.eq(user_id))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected > 0),
+        Err(e) => Err(format!("Error deleting the draft: {}", e)),
+    }
+}
+
+pub async fn delete_by_conversation_ids(
+    tx: &DatabaseTransaction,
+    conversation_ids: Vec<Uuid>,
+) -> Result<u64, String> {
+    match draft::Entity::delete_many()
+        .filter(draft::Column::ConversationId.is_in(conversation_ids))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected),
+        Err(e) => Err(format!("Error deleting drafts by conversation ids: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(tx: &DatabaseTransaction, user_id: i64) -> Result<u64, String> {
+    match draft::Entity::delete_many()
+        .filter(draft::Column::UserId.eq(user_id))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected),
+        Err(e) => Err(format!("Error deleting drafts by user_id: {}", e)),
+    }
+}
@@ -0,0 +1,58 @@
+use crate::entity::generated_image;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set};
+use uuid::Uuid;
+
+pub async fn create(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    prompt: String,
+    model: String,
+    filename: String,
+) -> Result<generated_image::Model, String> {
+    let new_image = generated_image::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        prompt: Set(prompt),
+        model: Set(model),
+        filename: Set(filename),
+        created_at: Set(Utc::now()),
+    };
+
+    match new_image.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "New generated image record is not saved successfully: {}",
+            e
+        )),
+    }
+}
+
+pub async fn find_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Vec<generated_image::Model>, String> {
+    match generated_image::Entity::find()
+        .filter(generated_image::Column::UserId.eq(user_id))
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models),
+        Err(e) => Err(format!("Error finding generated images by user_id: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Vec<generated_image::Model>, String> {
+    let images = find_by_user_id(tx, user_id).await?;
+    match generated_image::Entity::delete_many()
+        .filter(generated_image::Column::UserId.eq(user_id))
+        .exec(tx)
+        .await
+    {
+        Ok(_) => Ok(images),
+        Err(e) => Err(format!("Error deleting generated images by user_id: {}", e)),
+    }
+}
@@ -0,0 +1,57 @@
+use crate::entity::instruction;
+use chrono::Utc;
+use sea_orm::{sea_query::OnConflict, DatabaseTransaction, EntityTrait, Set};
+
+pub async fn find_by_user_id(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+) -> Result<Option<instruction::Model>, String> {
+    match instruction::Entity::find_by_id(user_id).one(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "Error finding custom instructions by user_id: {}",
+            e
+        )),
+    }
+}
+
+/// Inserts or updates in one statement, so two concurrent first saves cannot
+/// both try to insert the row.
+pub async fn upsert(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    about_me: Option<String>,
+    response_style: Option<String>,
+) -> Result<instruction::Model, String> {
+    let now = Utc::now();
+    let model = instruction::ActiveModel {
+        user_id: Set(user_id),
+        about_me: Set(about_me),
+        response_style: Set(response_style),
+        created_at: Set(now),
+        updated_at: Set(now),
+    };
+    match instruction::Entity::insert(model)
+        .on_conflict(
+            OnConflict::column(instruction::Column::UserId)
+                .update_columns([
+                    instruction::Column::AboutMe,
+                    instruction::Column::ResponseStyle,
+                    instruction::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_with_returning(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error saving the custom instructions: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(tx: &DatabaseTransaction, user_id: i64) -> Result<bool, String> {
+    match instruction::Entity::delete_by_id(user_id).exec(tx).await {
+        Ok(result) => Ok(result.rows_affected > 0),
+        Err(e) => Err(format!("Error deleting the custom instructions: {}", e)),
+    }
+}
@@ -1 +1,9 @@
+pub mod attachment;
+pub mod bot;
+pub mod collection;
 pub mod conversation;
+pub mod draft;
+pub mod generated_image;
+pub mod instruction;
+pub mod outbox;
+pub mod usage;
@@ -0,0 +1,107 @@
+use crate::entity::outbox;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::{LockBehavior, LockType},
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use uuid::Uuid;
+
+pub async fn enqueue(
+    tx: &DatabaseTransaction,
+    topic: &str,
+    destination: String,
+    payload: serde_json::Value,
+) -> Result<outbox::Model, String> {
+    let now = Utc::now();
+    let new_event = outbox::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        topic: Set(topic.to_string()),
+        destination: Set(destination),
+        payload: Set(payload),
+        attempts: Set(0),
+        next_attempt_at: Set(now),
+        delivered_at: Set(None),
+        last_error: Set(None),
+        created_at: Set(now),
+    };
+
+    match new_event.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Outbox event is not saved successfully: {}", e)),
+    }
+}
+
+// Rows are locked with SKIP LOCKED so several instances can dispatch side by side.
+pub async fn find_due(
+    tx: &DatabaseTransaction,
+    now: DateTime<Utc>,
+    max_attempts: i32,
+    limit: u64,
+) -> Result<Vec<outbox::Model>, String> {
+    match outbox::Entity::find()
+        .filter(outbox::Column::DeliveredAt.is_null())
+        .filter(outbox::Column::Attempts.lt(max_attempts))
+        .filter(outbox::Column::NextAttemptAt.lte(now))
+        .order_by_asc(outbox::Column::NextAttemptAt)
+        .limit(limit)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .all(tx)
+        .await
+    {
+        Ok(models) => Ok(models),
+        Err(e) => Err(format!("Error fetching due outbox events: {}", e)),
+    }
+}
+
+pub async fn find_pending_by_id(
+    tx: &DatabaseTransaction,
+    id: Uuid,
+) -> Result<Option<outbox::Model>, String> {
+    match outbox::Entity::find_by_id(id)
+        .filter(outbox::Column::DeliveredAt.is_null())
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .one(tx)
+        .await
+    {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error fetching the outbox event by id: {}", e)),
+    }
+}
+
+pub async fn mark_delivered(
+    tx: &DatabaseTransaction,
+    event: outbox::Model,
+) -> Result<outbox::Model, String> {
+    let attempts = event.attempts + 1;
+    let mut updated_model: outbox::ActiveModel = event.into();
+    updated_model.attempts = Set(attempts);
+    updated_model.delivered_at = Set(Some(Utc::now()));
+    updated_model.last_error = Set(None);
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!("Error marking the outbox event delivered: {}", e)),
+    }
+}
+
+pub async fn mark_failed(
+    tx: &DatabaseTransaction,
+    event: outbox::Model,
+    error: String,
+    next_attempt_at: DateTime<Utc>,
+) -> Result<outbox::Model, String> {
+    let attempts = event.attempts + 1;
+    let mut updated_model: outbox::ActiveModel = event.into();
+    updated_model.attempts = Set(attempts);
+    updated_model.next_attempt_at = Set(next_attempt_at);
+    updated_model.last_error = Set(Some(error));
+
+    match updated_model.update(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "Error recording the outbox delivery failure: {}",
+            e
+        )),
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{entity::usage, utils::openai::TokenUsage};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set,
+};
+use uuid::Uuid;
+
+pub async fn record(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    model: String,
+    credits: i64,
+    tokens: &TokenUsage,
+) -> Result<usage::Model, String> {
+    let new_usage = usage::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        model: Set(model),
+        credits: Set(credits),
+        prompt_tokens: Set(tokens.prompt_tokens as i64),
+        completion_tokens: Set(tokens.completion_tokens as i64),
+        created_at: Set(Utc::now()),
+    };
+
+    match new_usage.insert(tx).await {
+        Ok(model) => Ok(model),
+        Err(e) => Err(format!(
+            "Credit usage record is not saved successfully: {}",
+            e
+        )),
+    }
+}
+
+pub async fn sum_since(
+    tx: &DatabaseTransaction,
+    user_id: i64,
+    since: DateTime<Utc>,
+) -> Result<i64, String> {
+    match usage::Entity::find()
+        .select_only()
+        .column_as(Expr::cust("COALESCE(SUM(credits), 0)::BIGINT"), "total")
+        .filter(usage::Column::UserId.eq(user_id))
+        .filter(usage::Column::CreatedAt.gte(since))
+        .into_tuple::<i64>()
+        .one(tx)
+        .await
+    {
+        Ok(total) => Ok(total.unwrap_or_default()),
+        Err(e) => Err(format!("Error summing credit usage by user_id: {}", e)),
+    }
+}
+
+// Requests, credits, prompt tokens and completion tokens per model in [from, to).
+pub async fn aggregate_by_model(
+    tx: &DatabaseTransaction,
+    user_id: Option<i64>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<Vec<(String, i64, i64, i64, i64)>, String> {
+    let mut query = usage::Entity::find()
+        .select_only()
+        .column(usage::Column::Model)
+        .column_as(Expr::cust("COUNT(*)::BIGINT"), "requests")
+        .column_as(Expr::cust("COALESCE(SUM(credits), 0)::BIGINT"), "credits")
+        .column_as(
+            Expr::cust("COALESCE(SUM(prompt_tokens), 0)::BIGINT"),
+            "prompt_tokens",
+        )
+        .column_as(
+            Expr::cust("COALESCE(SUM(completion_tokens), 0)::BIGINT"),
+            "completion_tokens",
+        )
+        .group_by(usage::Column::Model)
+        .order_by_asc(usage::Column::Model);
+    if let Some(user_id) = user_id {
+        query = query.filter(usage::Column::UserId.eq(user_id));
+    }
+    if let Some(from) = from {
+        query = query.filter(usage::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = to {
+        query = query.filter(usage::Column::CreatedAt.lt(to));
+    }
+    match query
+        .into_tuple::<(String, i64, i64, i64, i64)>()
+        .all(tx)
+        .await
+    {
+        Ok(rows) => Ok(rows),
+        Err(e) => Err(format!("Error aggregating credit usage by model: {}", e)),
+    }
+}
+
+pub async fn delete_by_user_id(tx: &DatabaseTransaction, user_id: i64) -> Result<u64, String> {
+    match usage::Entity::delete_many()
+        .filter(usage::Column::UserId.eq(user_id))
+        .exec(tx)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected),
+        Err(e) => Err(format!("Error deleting credit usage by user_id: {}", e)),
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use crate::controllers::admin;
+use crate::ServiceState;
+use axum::routing::{get, post};
+
+// Routes under /api/chat/admin/service are for the auth service and are
+// authenticated by the X-Signature of their body rather than a user token.
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router
+        .route("/api/chat/admin/status", get(admin::get_status))
+        .route(
+            "/api/chat/admin/users/:user_id/conversation/:conversation_id",
+            get(admin::get_user_conversation),
+        )
+        .route(
+            "/api/chat/admin/users/:user_id/conversation/:conversation_id/repair",
+            post(admin::repair_user_conversation),
+        )
+        .route(
+            "/api/chat/admin/service/conversations",
+            post(admin::service_list_conversations),
+        )
+        .route(
+            "/api/chat/admin/service/conversation",
+            post(admin::service_get_conversation).delete(admin::service_delete_conversation),
+        )
+        .route("/api/chat/admin/service/usage", post(admin::service_usage))
+}
@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use crate::controllers::bot;
+use crate::ServiceState;
+use axum::routing::{delete, get, post};
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router
+        .route("/api/chat/bots", get(bot::retrieve_all_bots))
+        .route("/api/chat/bots", post(bot::create_bot))
+        .route("/api/chat/bots/:bot_id", delete(bot::delete_bot))
+        .route(
+            "/api/chat/integrations/messages",
+            post(bot::post_bot_message),
+        )
+        .route(
+            "/api/chat/integrations/widget",
+            post(bot::start_widget_session),
+        )
+        .route(
+            "/api/chat/integrations/widget/messages",
+            post(bot::post_widget_message),
+        )
+}
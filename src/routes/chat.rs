@@ -22,16 +22,88 @@ pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<
             "/api/chat/conversation/:conversation_id",
             delete(chat::delete_conversation),
         )
+        .route(
+            "/api/chat/conversation/:conversation_id/regenerate",
+            post(chat::regenerate_message),
+        )
         .route(
             "/api/chat/conversation/:conversation_id/title",
             patch(chat::edit_title),
         )
+        .route(
+            "/api/chat/conversation/:conversation_id/retention",
+            patch(chat::edit_retention),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/language",
+            patch(chat::edit_language),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/lock",
+            patch(chat::edit_lock),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/messages/:message_id/context",
+            patch(chat::edit_message_context),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/message/:message_id/audio",
+            get(chat::get_message_audio),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/archive",
+            post(chat::archive_conversation),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/archive",
+            delete(chat::unarchive_conversation),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/pin",
+            post(chat::pin_conversation).delete(chat::unpin_conversation),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/system",
+            patch(chat::edit_system_prompt),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/style",
+            patch(chat::edit_style_preset),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/settings",
+            patch(chat::edit_generation_settings),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/budget",
+            patch(chat::edit_budget),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/generation",
+            delete(chat::cancel_generation),
+        )
+        .route(
+            "/api/chat/conversation/:conversation_id/suggestions",
+            get(chat::get_suggestions),
+        )
         .route(
             "/api/chat/conversation",
             get(chat::retrieve_all_conversations),
         )
+        .route("/api/chat/me/summary", get(chat::get_summary))
+        .route("/api/chat/search", get(chat::search_conversations))
+        .route("/api/chat/styles", get(chat::list_style_presets))
+        .route("/api/chat/models", get(chat::list_models))
         .route(
             "/api/chat/conversation",
             post(chat::create_new_conversation),
         )
+        .route(
+            "/api/chat/conversation/import",
+            post(chat::import_conversation),
+        )
+        .route(
+            "/api/chat/conversation/bulk",
+            post(chat::bulk_conversations),
+        )
 }
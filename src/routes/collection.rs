@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use crate::controllers::collection;
+use crate::ServiceState;
+use axum::routing::{delete, get, post};
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router
+        .route(
+            "/api/chat/collection/:collection_id",
+            get(collection::get_collection),
+        )
+        .route(
+            "/api/chat/collection/:collection_id",
+            delete(collection::delete_collection),
+        )
+        .route(
+            "/api/chat/collection/:collection_id/document",
+            post(collection::add_document),
+        )
+        .route(
+            "/api/chat/collection/:collection_id/document/:document_id",
+            delete(collection::delete_document),
+        )
+        .route(
+            "/api/chat/collection/:collection_id/reindex",
+            post(collection::reindex_collection),
+        )
+        .route(
+            "/api/chat/collection",
+            get(collection::retrieve_all_collections),
+        )
+        .route("/api/chat/collection", post(collection::create_collection))
+}
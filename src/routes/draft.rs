@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::draft;
+use crate::ServiceState;
+use axum::routing::get;
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router.route(
+        "/api/chat/conversation/:conversation_id/draft",
+        get(draft::get_draft)
+            .put(draft::edit_draft)
+            .delete(draft::delete_draft),
+    )
+}
@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::extract;
+use crate::ServiceState;
+use axum::routing::post;
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router
+        .route("/api/chat/extract", post(extract::extract))
+        .route(
+            "/api/chat/conversation/:conversation_id/action-items",
+            post(extract::extract_action_items),
+        )
+}
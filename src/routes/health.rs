@@ -0,0 +1,9 @@
+use std::sync::Arc;
+
+use crate::controllers::health;
+use crate::ServiceState;
+use axum::routing::get;
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router.route("/readyz", get(health::readiness))
+}
@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::instruction;
+use crate::ServiceState;
+use axum::routing::get;
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router.route(
+        "/api/chat/instructions",
+        get(instruction::get_instructions)
+            .put(instruction::edit_instructions)
+            .delete(instruction::delete_instructions),
+    )
+}
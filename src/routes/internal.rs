@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use crate::controllers::internal;
+use crate::ServiceState;
+use axum::routing::{delete, post};
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router
+        .route(
+            "/api/chat/internal/users",
+            delete(internal::delete_user_data),
+        )
+        .route(
+            "/api/chat/internal/conversations/lock",
+            post(internal::lock_conversation),
+        )
+        .route(
+            "/api/chat/internal/sessions/invalidate",
+            post(internal::invalidate_sessions),
+        )
+}
@@ -1,20 +1,81 @@
+pub mod admin;
+pub mod bot;
 pub mod chat;
+pub mod collection;
+pub mod draft;
+pub mod extract;
+pub mod health;
 pub mod image;
+pub mod instruction;
+pub mod internal;
+pub mod metrics;
 pub mod public;
+pub mod upload;
 pub mod voice;
+pub mod ws;
 use std::sync::Arc;
 
-use crate::ServiceState;
-use axum::{extract::DefaultBodyLimit, Router};
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use crate::{
+    config::tracing::RedactedHeaders,
+    utils::{
+        i18n::localize_errors, metrics::track_requests, rate_limit::rate_limit,
+        request_id::assign_request_id,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{DefaultBodyLimit, MatchedPath, Request},
+    middleware, Router,
+};
+use tower_http::trace::TraceLayer;
+use tracing::{field::Empty, Span};
 pub fn create_router(state: Arc<ServiceState>) -> Router {
     let router = Router::new();
     let router = chat::add_routers(router);
+    let router = collection::add_routers(router);
     let router = public::add_routers(router);
     let router = voice::add_routers(router);
     let router = image::add_routers(router);
+    let router = extract::add_routers(router);
+    let router = upload::add_routers(router);
+    let router = instruction::add_routers(router);
+    let router = draft::add_routers(router);
+    let router = admin::add_routers(router);
+    let router = ws::add_routers(router);
+    let router = internal::add_routers(router);
+    let router = bot::add_routers(router);
+    let router = metrics::add_routers(router);
+    let router = health::add_routers(router);
     let router = router.layer(DefaultBodyLimit::max(300 * 1024 * 1024));
+    let router = router.layer(middleware::from_fn_with_state(state.clone(), rate_limit));
+    let router = router.layer(middleware::from_fn(localize_errors));
+    let router = router.layer(middleware::from_fn(assign_request_id));
+    let router = router.layer(middleware::from_fn(track_requests));
+    // `assign_request_id` records the id on this span once it has settled on one.
+    // Debug events, the headers among them, are logged for sampled requests and
+    // for the others once they log an error.
+    let sampling = state.config.trace_sampling.clone();
     router.with_state(state).layer(
-        TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::default().include_headers(true)),
+        TraceLayer::new_for_http()
+            .make_span_with(move |req: &Request| {
+                let route = req
+                    .extensions()
+                    .get::<MatchedPath>()
+                    .map_or(req.uri().path(), |path| path.as_str());
+                tracing::info_span!(
+                    "request",
+                    method = %req.method(),
+                    uri = %req.uri(),
+                    version = ?req.version(),
+                    request_id = Empty,
+                    sampled = sampling.sample(route),
+                )
+            })
+            .on_request(|req: &Request, _span: &Span| {
+                tracing::debug!(
+                    headers = ?RedactedHeaders(req.headers()),
+                    "started processing request"
+                );
+            }),
     )
 }
@@ -1,9 +1,24 @@
-use crate::ServiceState;
+use crate::{utils::media_cache::cache_media, ServiceState};
+use axum::{middleware, Router};
 use std::sync::Arc;
 use tower_http::services::ServeDir;
 
+pub const PUBLIC_MEDIA_PREFIX: &str = "/api/chat/public";
+
+fn media_service(dir: &'static str) -> Router {
+    Router::new()
+        .fallback_service(ServeDir::new(dir))
+        .layer(middleware::from_fn_with_state(dir, cache_media))
+}
+
 pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
     router
-        .nest_service("/api/chat/public/images", ServeDir::new("./public/images"))
-        .nest_service("/api/chat/public/voice", ServeDir::new("./public/voice"))
+        .nest_service(
+            &format!("{}/images", PUBLIC_MEDIA_PREFIX),
+            media_service("./public/images"),
+        )
+        .nest_service(
+            &format!("{}/voice", PUBLIC_MEDIA_PREFIX),
+            media_service("./public/voice"),
+        )
 }
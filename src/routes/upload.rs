@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use crate::controllers::upload;
+use crate::ServiceState;
+use axum::routing::{get, patch, post};
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router
+        .route("/api/chat/upload", post(upload::init_upload))
+        .route("/api/chat/upload/:attachment_id", get(upload::get_upload))
+        .route(
+            "/api/chat/upload/:attachment_id",
+            patch(upload::append_upload),
+        )
+        .route(
+            "/api/chat/upload/:attachment_id/complete",
+            post(upload::complete_upload),
+        )
+}
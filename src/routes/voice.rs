@@ -2,8 +2,12 @@ use std::sync::Arc;
 
 use crate::controllers::voice;
 use crate::ServiceState;
-use axum::routing::post;
+use axum::routing::{get, post};
 
 pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
-    router.route("/api/chat/voice", post(voice::speech_to_text))
+    router
+        .route("/api/chat/voice", post(voice::speech_to_text))
+        .route("/api/chat/voice/custom", post(voice::register_custom_voice))
+        .route("/api/chat/voice/live", get(voice::live_transcription))
+        .route("/api/chat/voice/voices", get(voice::list_voice_profiles))
 }
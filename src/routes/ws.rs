@@ -0,0 +1,9 @@
+use std::sync::Arc;
+
+use crate::controllers::ws;
+use crate::ServiceState;
+use axum::routing::get;
+
+pub fn add_routers(router: axum::Router<Arc<ServiceState>>) -> axum::Router<Arc<ServiceState>> {
+    router.route("/api/chat/ws/:conversation_id", get(ws::chat_socket))
+}
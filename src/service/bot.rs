@@ -0,0 +1,344 @@
+use crate::{
+    controllers::chat::{handle_transaction, MAX_SYSTEM_PROMPT_CHARS},
+    dto::{
+        request::{BotMessageRequest, BotReplyDelivery, MessageOptions, WidgetSessionRequest},
+        response::{BotMessageResponse, SessionData, WidgetSessionResponse},
+    },
+    entity::{bot, conversation::ReplyMode},
+    repositories::{bot as bot_repository, conversation},
+    service::{chat::handle_user_message, outbox},
+    utils::{
+        error::{format_error, AppResult, ErrorCode},
+        language::normalize_language,
+        widget::WidgetClaims,
+    },
+    ServiceState,
+};
+use axum::{
+    body::{to_bytes, Body},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const MAX_THREAD_ID_LEN: usize = 255;
+
+/// Posts a message from an integration bot and returns the reply, or
+/// `202 Accepted` when the reply goes to the bot's webhook.
+pub async fn post_message(
+    state: Arc<ServiceState>,
+    bot_model: bot::Model,
+    req: BotMessageRequest,
+) -> AppResult<Response> {
+    if req.text.trim().is_empty() {
+        return Err(format_error(
+            "Invalid bot message",
+            "text must not be empty",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let thread_id = req
+        .thread_id
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty());
+    if thread_id
+        .as_ref()
+        .is_some_and(|id| id.len() > MAX_THREAD_ID_LEN)
+    {
+        return Err(format_error(
+            "Invalid thread id",
+            format!("thread_id must be at most {} bytes", MAX_THREAD_ID_LEN),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let webhook_url = match req.reply {
+        BotReplyDelivery::Sync => None,
+        BotReplyDelivery::Webhook => Some(bot_model.webhook_url.clone().ok_or_else(|| {
+            format_error(
+                "The bot has no webhook to deliver replies to",
+                bot_model.id,
+                StatusCode::BAD_REQUEST,
+            )
+        })?),
+    };
+
+    let conversation_id =
+        resolve_conversation(&state, &bot_model, req.conversation_id, thread_id.clone()).await?;
+    info!(
+        "Bot '{}' is posting a message to conversation '{}'.",
+        bot_model.id, conversation_id
+    );
+
+    // Bots have no session; their own pool stands in for the owner's balance.
+    let session_data = SessionData {
+        credits_remaining: bot_model.credits_remaining,
+        preferences: json!({}),
+        session_metadata: json!({}),
+        subscription_status: true,
+    };
+    let options = MessageOptions {
+        reply_mode: Some(ReplyMode::Text),
+        bot_id: Some(bot_model.id),
+        ..Default::default()
+    };
+    let response = handle_user_message(
+        state.clone(),
+        bot_model.user_id,
+        Some(session_data),
+        conversation_id,
+        "text".to_string(),
+        req.text.into_bytes(),
+        req.model_name
+            .or(bot_model.model.clone())
+            .unwrap_or_default(),
+        vec![],
+        -1,
+        None,
+        vec![],
+        options,
+    )
+    .await?
+    .into_response();
+
+    let Some(webhook_url) = webhook_url else {
+        let reply = collect_reply(response.into_body()).await.map_err(|e| {
+            format_error("The assistant failed to reply", e, StatusCode::BAD_GATEWAY)
+                .with_code(ErrorCode::UpstreamUnavailable)
+        })?;
+        return Ok(Json(BotMessageResponse {
+            conversation_id,
+            thread_id,
+            reply: Some(reply),
+        })
+        .into_response());
+    };
+
+    let payload_thread_id = thread_id.clone();
+    let bot_id = bot_model.id;
+    tokio::spawn(async move {
+        let payload = match collect_reply(response.into_body()).await {
+            Ok(reply) => json!({
+                "bot_id": bot_id,
+                "conversation_id": conversation_id,
+                "thread_id": payload_thread_id,
+                "reply": reply,
+            }),
+            Err(e) => {
+                error!(
+                    "Bot '{}' reply in conversation '{}' failed: {}",
+                    bot_id, conversation_id, e
+                );
+                json!({
+                    "bot_id": bot_id,
+                    "conversation_id": conversation_id,
+                    "thread_id": payload_thread_id,
+                    "error": e,
+                })
+            }
+        };
+        outbox::publish(&state, outbox::BOT_REPLY, webhook_url, payload).await;
+    });
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(BotMessageResponse {
+            conversation_id,
+            thread_id,
+            reply: None,
+        }),
+    )
+        .into_response())
+}
+
+/// Creates a conversation for an embedded widget with its persona and system
+/// context already applied, and a token scoped to it, so the page embedding
+/// the widget never sees the bot's API key.
+pub async fn start_widget_session(
+    state: Arc<ServiceState>,
+    bot_model: bot::Model,
+    req: WidgetSessionRequest,
+) -> AppResult<WidgetSessionResponse> {
+    let persona = req
+        .persona
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty());
+    if let Some(name) = persona.as_deref() {
+        if state.config.style.prompt_for(name).is_none() {
+            return Err(format_error(
+                "Unknown style preset",
+                name,
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    let system_prompt = req
+        .system_prompt
+        .map(|prompt| prompt.trim().to_string())
+        .filter(|prompt| !prompt.is_empty());
+    if system_prompt
+        .as_ref()
+        .is_some_and(|prompt| prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS)
+    {
+        return Err(format_error(
+            "System prompt is too long. Maximum characters",
+            MAX_SYSTEM_PROMPT_CHARS,
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let language =
+        match req.language.as_deref() {
+            Some(code) => Some(normalize_language(code).ok_or_else(|| {
+                format_error("Unsupported language", code, StatusCode::BAD_REQUEST)
+            })?),
+            None => None,
+        };
+
+    let bot_id = bot_model.id;
+    let user_id = bot_model.user_id;
+    let retention_days = state.config.retention.default_days;
+    let conversation_id = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            let database_error = |e: String| {
+                format_error(
+                    "Failed to prepare the widget conversation due to a database error",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            };
+            let conversation_id =
+                conversation::new_conversation(transaction, user_id, retention_days)
+                    .await
+                    .map_err(database_error)?;
+            let model = conversation::set_language(transaction, user_id, conversation_id, language)
+                .await
+                .map_err(database_error)?;
+            let model = conversation::set_system_prompt(transaction, model, system_prompt)
+                .await
+                .map_err(database_error)?;
+            conversation::set_style_preset(transaction, model, persona)
+                .await
+                .map_err(database_error)?;
+            Ok(conversation_id)
+        })
+    })
+    .await?;
+
+    let claims = WidgetClaims::new(
+        bot_id,
+        conversation_id,
+        state.config.jwt.widget_token_ttl_secs,
+    );
+    let token = claims
+        .encode(&state.config.jwt.access_token_secret)
+        .map_err(|e| {
+            format_error(
+                "Failed to sign the widget token",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    info!(
+        "Bot '{}' started a widget session in conversation '{}'.",
+        bot_id, conversation_id
+    );
+    Ok(WidgetSessionResponse {
+        conversation_id,
+        token,
+        expires_at: Utc
+            .timestamp_opt(claims.exp, 0)
+            .single()
+            .unwrap_or_else(Utc::now),
+    })
+}
+
+// An explicit conversation must belong to the bot's owner; a thread is mapped
+// to a conversation the first time it is seen.
+async fn resolve_conversation(
+    state: &Arc<ServiceState>,
+    bot_model: &bot::Model,
+    conversation_id: Option<Uuid>,
+    thread_id: Option<String>,
+) -> AppResult<Uuid> {
+    let bot_id = bot_model.id;
+    let user_id = bot_model.user_id;
+    let retention_days = state.config.retention.default_days;
+    handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            if let Some(conversation_id) = conversation_id {
+                let found = conversation::find_by_user_id_and_conversation_id(
+                    transaction,
+                    user_id,
+                    conversation_id,
+                )
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Error fetching the conversation from the database",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+                if found.is_none() {
+                    return Err(format_error(
+                        "Requested conversation could not be found",
+                        conversation_id,
+                        StatusCode::NOT_FOUND,
+                    )
+                    .with_code(ErrorCode::ConversationNotFound));
+                }
+                return Ok(conversation_id);
+            }
+
+            if let Some(thread_id) = thread_id.as_deref() {
+                let mapped =
+                    bot_repository::find_thread_conversation(transaction, bot_id, thread_id)
+                        .await
+                        .map_err(|e| {
+                            format_error(
+                                "Error fetching the bot thread from the database",
+                                e,
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                            )
+                        })?;
+                if let Some(conversation_id) = mapped {
+                    return Ok(conversation_id);
+                }
+            }
+
+            let conversation_id =
+                conversation::new_conversation(transaction, user_id, retention_days)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to create a new conversation due to a database error",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+            if let Some(thread_id) = thread_id {
+                bot_repository::map_thread(transaction, bot_id, thread_id, conversation_id)
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to map the thread to a conversation",
+                            e,
+                            StatusCode::CONFLICT,
+                        )
+                    })?;
+            }
+            Ok(conversation_id)
+        })
+    })
+    .await
+}
+
+// Streamed plain-text replies abort the body on failure, so a complete body is a complete reply.
+async fn collect_reply(body: Body) -> Result<String, String> {
+    let bytes = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+}
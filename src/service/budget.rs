@@ -0,0 +1,135 @@
+use crate::{
+    config::{budget::BudgetAction, constant},
+    service::outbox,
+    utils::error::{format_error, AppResult, ErrorCode},
+    ServiceState,
+};
+use axum::http::StatusCode;
+use chrono::{NaiveDate, Utc};
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+#[derive(Default)]
+pub struct SpendTracker {
+    day: Mutex<(Option<NaiveDate>, f64, bool)>,
+}
+
+impl SpendTracker {
+    // Adds the spend to today's total and returns the new total, plus whether this call crossed the budget.
+    fn record(&self, usd: f64, budget: Option<f64>) -> (f64, bool) {
+        let today = Utc::now().date_naive();
+        let mut day = self.day.lock().unwrap_or_else(|e| e.into_inner());
+        if day.0 != Some(today) {
+            *day = (Some(today), 0.0, false);
+        }
+        day.1 += usd;
+        let crossed = !day.2 && budget.is_some_and(|budget| day.1 >= budget);
+        if crossed {
+            day.2 = true;
+        }
+        (day.1, crossed)
+    }
+
+    pub fn spent_today(&self) -> f64 {
+        let today = Utc::now().date_naive();
+        let day = self.day.lock().unwrap_or_else(|e| e.into_inner());
+        if day.0 == Some(today) {
+            day.1
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn is_over_budget(state: &ServiceState) -> bool {
+    state
+        .config
+        .budget
+        .daily_usd
+        .is_some_and(|budget| state.spend.spent_today() >= budget)
+}
+
+pub fn estimate_tokens(chars: usize) -> u64 {
+    (chars as f64 / CHARS_PER_TOKEN).ceil() as u64
+}
+
+pub fn estimate_chat(model: &str, tokens: u64) -> f64 {
+    let per_1k_tokens = constant::MODEL_TO_USD_PER_1K_TOKENS
+        .get(model)
+        .copied()
+        .unwrap_or_default();
+    tokens as f64 / 1000.0 * per_1k_tokens
+}
+
+pub fn estimate_speech(chars: usize) -> f64 {
+    chars as f64 / 1000.0 * constant::SPEECH_USD_PER_1K_CHARS
+}
+
+pub fn estimate_transcription(duration_ms: u64) -> f64 {
+    duration_ms as f64 / 60_000.0 * constant::TRANSCRIPTION_USD_PER_MINUTE
+}
+
+pub async fn record_spend(state: &Arc<ServiceState>, usd: f64) {
+    if usd <= 0.0 {
+        return;
+    }
+    let config = &state.config.budget;
+    let (total, crossed) = state.spend.record(usd, config.daily_usd);
+    if crossed {
+        let action = match config.action {
+            BudgetAction::Degrade => format!("only '{}' is served", config.fallback_model),
+            BudgetAction::Disable => "paid generations are disabled".to_string(),
+        };
+        outbox::alert(
+            state,
+            format!(
+                "Daily provider spend reached ${:.2} of the ${:.2} budget; {} until midnight UTC.",
+                total,
+                config.daily_usd.unwrap_or_default(),
+                action
+            ),
+        )
+        .await;
+    }
+}
+
+// Returns the model to serve a chat request with once the daily budget is taken into account.
+pub fn admit_chat_model(state: &ServiceState, model: String) -> AppResult<String> {
+    if !is_over_budget(state) {
+        return Ok(model);
+    }
+    let config = &state.config.budget;
+    match config.action {
+        BudgetAction::Degrade
+            if model != config.fallback_model
+                && constant::MODEL_TO_PRICE.contains_key(model.as_str()) =>
+        {
+            info!(
+                "Daily budget exceeded, serving '{}' instead of '{}'.",
+                config.fallback_model, model
+            );
+            Ok(config.fallback_model.clone())
+        }
+        BudgetAction::Degrade => Ok(model),
+        BudgetAction::Disable => Err(format_error(
+            "The daily provider budget is exhausted. Model",
+            model,
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .with_code(ErrorCode::BudgetExhausted)),
+    }
+}
+
+pub fn admit_expensive(state: &ServiceState, feature: &str) -> AppResult<()> {
+    if is_over_budget(state) {
+        return Err(format_error(
+            "The daily provider budget is exhausted. Unavailable",
+            feature,
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .with_code(ErrorCode::BudgetExhausted));
+    }
+    Ok(())
+}
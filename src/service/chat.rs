@@ -1,14 +1,47 @@
 use crate::{
-    config::constant,
-    dto::response::SessionData,
-    entity::conversation::{Message, MessageType},
-    repositories::conversation,
+    client::provider::{ChatParams, ImageOptions, ToolTurn},
+    config::{
+        auto_model::{RequestTraits, AUTO_MODEL},
+        chat::MarkdownMode,
+        constant::{IMAGE_GENERATION_CREDITS, IMAGE_GENERATION_MODEL},
+        redaction::PiiKind,
+        retention::VoiceRetention,
+    },
+    dto::{request::MessageOptions, response::SessionData},
+    entity::conversation::{Citation, Message, MessageType, ReplyMode, ToolCall},
+    repositories::{conversation, draft, outbox as outbox_repository, usage},
+    routes::public::PUBLIC_MEDIA_PREFIX,
+    service::{
+        budget,
+        generation::GenerationGuard,
+        image::generate_image,
+        latency, moderation, outbox,
+        pipeline::{ChatPipeline, ChunkStream, PromptMessage, Turn},
+        queue::{Admission, QueueEvent, QueueSlot, QueueTicket},
+        redaction, retrieval, title,
+        tools::{self, ToolBudget},
+        transcription::transcribe,
+    },
     utils::{
-        deepgram::text_to_speech,
-        error::format_error,
-        file::save_file,
-        openai::{chunk_to_content_list, send_chat_completion, speech_to_text},
+        audio::{AudioFormat, Transcoder},
+        error::{format_error, AppError, AppResult, ErrorCode},
+        file::{
+            content_filename, delete_files, file_exists, save_audio_file, save_file, wav_header_len,
+        },
+        language::{detect_language, language_instruction, normalize_language},
+        loudness::normalize_pcm_bytes,
+        markdown::{MarkdownSanitizer, SpeechFilter},
+        metrics,
+        openai::{chunk_to_content_list, merge_tool_calls, TokenUsage},
+        postprocess::PostProcessChain,
+        request_id::spawn_in_request,
+        retry::upstream_status,
+        segmenter::SentenceSegmenter,
         session::send_session_data,
+        speech::{synthesize, SpeechVoice},
+        streaming::{self, StreamFormat, StreamWriter},
+        title::generate_title,
+        waveform::{from_pcm, from_wav_bytes},
     },
     ServiceState,
 };
@@ -17,16 +50,15 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
-use http_body_util::StreamBody;
-use hyper::body::{Bytes, Frame};
-use regex::Regex;
+use base64::prelude::*;
+use chrono::Utc;
+use hyper::body::Bytes;
 use rs_openai::{chat::Role, OpenAI};
-use sea_orm::TransactionTrait;
+use sea_orm::{DatabaseTransaction, TransactionTrait};
 use serde::Deserialize;
 use serde_json::json;
-use std::{path::Path, sync::Arc};
-use tokio::sync::mpsc;
-use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use std::{collections::BTreeSet, path::Path, sync::Arc, time::Instant};
+use tokio_stream::StreamExt;
 use tracing::{error, info};
 use uuid::Uuid;
 
@@ -49,6 +81,107 @@ pub struct ChatCompletionChunk {
     choices: Vec<ChatChunkChoice>,
 }
 
+/// The message as it came in, shared by every stage of the turn.
+struct TurnRequest {
+    user_id: i64,
+    session_data: Option<SessionData>,
+    conversation_id: Uuid,
+    message_type: MessageType,
+    message_data: Vec<u8>,
+    message_model: String,
+    region: Option<String>,
+    images: Vec<Bytes>,
+    image_filenames: Vec<Option<String>>,
+    message_id: i64,
+    voice_filename: Option<String>,
+    // A regenerated turn reuses the stored user message instead of the uploaded data.
+    regenerate: Option<Message>,
+    bot_id: Option<Uuid>,
+    started_at: Instant,
+    // What a new voice message said, transcribed before the transaction opens.
+    transcription: Option<String>,
+}
+
+/// What the history stage works out for a turn: the prompt for the provider
+/// and what the persister saves next to the reply.
+struct PreparedTurn {
+    user_message: String,
+    images: Vec<String>,
+    prompt_messages: Vec<PromptMessage>,
+    prompt_chars: usize,
+    chat_params: ChatParams,
+    language: Option<String>,
+    voice_profile: Option<String>,
+    title: Option<String>,
+    title_redaction: Option<BTreeSet<PiiKind>>,
+    citations: Vec<Citation>,
+    turn_index: i64,
+    reply_to: Option<usize>,
+    tool_call_id: Option<String>,
+    // Calls already made in this turn, which the reply's calls are counted on top of.
+    tool_budget: ToolBudget,
+    moderation_flags: Option<Vec<String>>,
+    voice_retention: VoiceRetention,
+    // What the conversation's credit ceiling still allows, if it has one.
+    ceiling_room: Option<i64>,
+    // Media saved for this turn only, removed again if the turn fails.
+    written_files: Vec<String>,
+    written_bytes: i64,
+}
+
+/// The reply as far as the provider streamed it.
+#[derive(Default)]
+struct StreamedReply {
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    voice: Vec<u8>,
+    usage: Option<TokenUsage>,
+    upstream_started: bool,
+    cancelled: bool,
+}
+
+impl StreamedReply {
+    // Streams that end without a usage chunk, such as cancelled ones, fall back to an estimate.
+    fn usage(&self, prompt_chars: usize) -> TokenUsage {
+        self.usage.unwrap_or(TokenUsage {
+            prompt_tokens: budget::estimate_tokens(prompt_chars),
+            completion_tokens: budget::estimate_tokens(self.content.chars().count()),
+        })
+    }
+
+    // What the provider calls behind the reply cost, whether or not the turn is saved.
+    fn spend(&self, request: &TurnRequest, prompt_chars: usize, reply_mode: ReplyMode) -> f64 {
+        let usage = self.usage(prompt_chars);
+        let mut spend = budget::estimate_chat(
+            &request.message_model,
+            usage.prompt_tokens + usage.completion_tokens,
+        );
+        if reply_mode.has_voice() {
+            spend += budget::estimate_speech(self.content.chars().count());
+        }
+        if request.regenerate.is_none() && request.message_type == MessageType::Voice {
+            if let Some(waveform) = from_wav_bytes(&request.message_data) {
+                spend += budget::estimate_transcription(waveform.duration_ms);
+            }
+        }
+        spend
+    }
+}
+
+/// What the biller needs to know about a saved turn.
+struct SavedTurn {
+    usage: TokenUsage,
+    spend: f64,
+    reply_chars: usize,
+    truncated: bool,
+    cancelled: bool,
+    title_exchange: Option<(String, String, BTreeSet<PiiKind>)>,
+    ceiling_room: Option<i64>,
+}
+
+// Sent upstream right away, or waiting in the queue to be sent by the streaming task.
+type PendingReply = Result<(QueueSlot, ChunkStream), QueueTicket>;
+
 pub async fn handle_user_message(
     state: Arc<ServiceState>,
     user_id: i64,
@@ -61,7 +194,12 @@ pub async fn handle_user_message(
     message_id: i64,
     voice_filename: Option<String>,
     image_filnames: Vec<Option<String>>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+    mut options: MessageOptions,
+) -> AppResult<impl IntoResponse> {
+    let started_at = Instant::now();
+    // A regenerated turn reuses the stored user message instead of the uploaded data.
+    let regenerate = options.regenerate.take();
+    let bot_id = options.bot_id;
     if session_data.is_none() {
         return Err(format_error(
             "Session data is required but missing for the user",
@@ -75,6 +213,7 @@ pub async fn handle_user_message(
     );
 
     let credits_remaining: i64;
+    let cost: i64;
     let message_type = format!("\"{}\"", message_type);
 
     let message_type: Result<MessageType, serde_json::Error> =
@@ -88,43 +227,87 @@ pub async fn handle_user_message(
     }
     let message_type = message_type.unwrap();
 
-    if let Some(&cost) = constant::MODEL_TO_PRICE.get(message_model.as_str()) {
+    if message_type == MessageType::ImageGeneration {
+        let prompt = match regenerate.as_ref() {
+            Some(stored) => stored.content.clone(),
+            None => String::from_utf8(message_data).map_err(|e| {
+                format_error(
+                    "Failed to convert message data into string",
+                    e,
+                    StatusCode::BAD_REQUEST,
+                )
+            })?,
+        };
+        return handle_image_generation(
+            state,
+            user_id,
+            session_data,
+            conversation_id,
+            prompt,
+            message_id,
+            options.sse,
+        )
+        .await;
+    }
+
+    let message_model = choose_model(
+        &state,
+        user_id,
+        conversation_id,
+        message_model,
+        &message_type,
+        &message_data,
+        &images,
+        regenerate.as_ref(),
+        message_id,
+    )
+    .await?;
+    let region = data_region(&state, session_data.as_ref());
+    if region.is_some() {
+        // Surface a residency refusal before anything is saved or charged.
+        state
+            .registry
+            .chat_provider(&message_model, region.as_deref())?;
+    }
+    let pipeline = state.pipeline.clone();
+    if let Some(price) = state.registry.price(&message_model) {
+        cost = price;
         credits_remaining = session_data.clone().unwrap().credits_remaining;
         if cost > credits_remaining {
             return Err(format_error(
                 "Insufficient credits to proceed with the action. Required",
                 cost,
                 StatusCode::BAD_REQUEST,
-            ));
+            )
+            .with_code(ErrorCode::InsufficientCredits));
         }
     } else {
-        return Err(format_error(
-            "Invalid model name",
-            message_model,
-            StatusCode::BAD_REQUEST,
-        ));
+        return Err(state.registry.unknown_model(&message_model));
     }
-    let user_message = match message_type {
-        MessageType::Text => String::from_utf8(message_data.clone()).map_err(|e| {
-            format_error(
-                "Failed to convert message data into string",
-                e,
-                StatusCode::BAD_REQUEST,
-            )
-        })?,
-        _ => speech_to_text(
-            &state.config.openai.openai_key,
-            message_data.clone(),
-            voice_filename.clone().unwrap(),
-        )
-        .await
-        .map_err(|e| {
-            error!("{}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, e)
-        })?,
+
+    let mut request = TurnRequest {
+        user_id,
+        session_data,
+        conversation_id,
+        message_type,
+        message_data,
+        message_model,
+        region,
+        images,
+        image_filenames: image_filnames,
+        message_id,
+        voice_filename,
+        regenerate,
+        bot_id,
+        started_at,
+        transcription: None,
     };
+    if request.message_type == MessageType::Voice && request.regenerate.is_none() {
+        request.transcription = Some(transcribe_voice(&state, &request, &options).await?);
+    }
 
     let transaction = state.db.begin().await.map_err(|e| {
+        metrics::transaction_failed("begin");
         format_error(
             "Could not start a database transaction due to an error",
             e,
@@ -132,271 +315,2515 @@ pub async fn handle_user_message(
         )
     })?;
 
-    let conversation_model =
-        conversation::find_by_user_id_and_conversation_id(&transaction, user_id, conversation_id)
+    let mut prepared = load_history(
+        &state,
+        &pipeline,
+        &transaction,
+        &request,
+        &mut options,
+        cost,
+    )
+    .await?;
+    let citations_json = serde_json::to_string(&prepared.citations).map_err(|e| {
+        format_error(
+            "Failed to serialize citations",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let pending = match call_provider(&state, &pipeline, &request, &mut prepared).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            delete_files(&prepared.written_files);
+            return Err(e);
+        }
+    };
+
+    let reply_mode = options
+        .reply_mode
+        .unwrap_or_else(|| ReplyMode::from(&request.message_type));
+    let mut renderer = ReplyRenderer::new(
+        &state,
+        reply_mode,
+        options.audio_format,
+        options
+            .markdown_mode
+            .unwrap_or(state.config.chat.markdown_mode),
+        prepared.language.as_deref(),
+        request
+            .session_data
+            .as_ref()
+            .and_then(|s| s.preference("custom_voice_id"))
+            .as_deref(),
+        prepared.voice_profile.as_deref(),
+    )?;
+
+    let stream_format = match reply_mode {
+        _ if options.sse => StreamFormat::Sse,
+        ReplyMode::Text => StreamFormat::Raw("text/plain"),
+        ReplyMode::Voice => StreamFormat::Raw(options.audio_format.content_type()),
+        ReplyMode::Both => StreamFormat::Multiplexed,
+    };
+    let (writer, stream_response) = streaming::channel(stream_format, 1000000);
+
+    let generation = state.generations.register(conversation_id, user_id);
+    // Tells `auto` clients which model answered.
+    let message_model_header = request.message_model.clone();
+
+    spawn_in_request(async move {
+        let (reply, streamed) = stream_reply(
+            &state,
+            &pipeline,
+            &request,
+            &mut prepared,
+            pending,
+            &mut renderer,
+            &writer,
+            &generation,
+        )
+        .await;
+        if !reply.upstream_started {
+            match streamed {
+                Err(error_message) if writer.is_closed() => info!("{}", error_message),
+                Err(error_message) => {
+                    error!("{}", error_message);
+                    writer.error(error_message).await;
+                }
+                Ok(()) => {}
+            }
+            abandon_turn(&state, Some(transaction), &prepared.written_files, 0.0).await;
+            return;
+        }
+        // The provider has been paid from here on, whether or not the turn is saved.
+        let spend = reply.spend(&request, prepared.prompt_chars, reply_mode);
+        let mut written_files = std::mem::take(&mut prepared.written_files);
+        // A client that went away mid-stream still gets the turn saved, marked as truncated.
+        let truncated = if writer.is_closed() {
+            info!(
+                "Client disconnected from conversation '{}', saving the partial reply.",
+                conversation_id
+            );
+            true
+        } else if let Err(error_message) = streamed {
+            error!("{}", error_message);
+            writer.error(error_message).await;
+            abandon_turn(&state, Some(transaction), &written_files, spend).await;
+            return;
+        } else {
+            reply.cancelled
+        };
+
+        let saved = match persist_turn(
+            &state,
+            &pipeline,
+            &transaction,
+            &request,
+            prepared,
+            reply,
+            reply_mode,
+            renderer.voice.sample_rate(),
+            truncated,
+            spend,
+            &mut written_files,
+        )
+        .await
+        {
+            Ok(saved) => saved,
+            Err(error_message) => {
+                error!("{}", error_message);
+                writer.error(error_message).await;
+                abandon_turn(&state, Some(transaction), &written_files, spend).await;
+                return;
+            }
+        };
+        let (charged, message_events) = match bill_turn(
+            &state,
+            &pipeline,
+            &transaction,
+            &request,
+            &saved,
+            reply_mode,
+            credits_remaining,
+        )
+        .await
+        {
+            Ok(billed) => billed,
+            Err(error_message) => {
+                error!("{}", error_message);
+                writer.error(error_message).await;
+                abandon_turn(&state, Some(transaction), &written_files, spend).await;
+                return;
+            }
+        };
+
+        if let Err(e) = transaction.commit().await {
+            metrics::transaction_failed("commit");
+            let error_message = format!("Committing the database transaction failed: {}", e);
+            error!("{}", error_message);
+            writer.error(error_message).await;
+            abandon_turn(&state, None, &written_files, spend).await;
+            return;
+        };
+        finish_turn(
+            &state,
+            &pipeline,
+            &request,
+            saved,
+            charged,
+            message_events,
+            &writer,
+            credits_remaining,
+        )
+        .await;
+    });
+
+    let stream_response = stream_response.header("X-Model", message_model_header);
+    if citations_json == "[]" {
+        stream_response.into_response()
+    } else {
+        stream_response
+            .header("X-Citations", citations_json)
+            .into_response()
+    }
+}
+
+// Picks the model for the turn: the conversation default when none was asked
+// for, and a concrete one for `auto`.
+async fn choose_model(
+    state: &ServiceState,
+    user_id: i64,
+    conversation_id: Uuid,
+    message_model: String,
+    message_type: &MessageType,
+    message_data: &[u8],
+    images: &[Bytes],
+    regenerate: Option<&Message>,
+    message_id: i64,
+) -> AppResult<String> {
+    let message_model = if message_model.trim().is_empty() {
+        default_model(state, user_id, conversation_id).await?
+    } else {
+        message_model
+    };
+    let message_model = if message_model == AUTO_MODEL {
+        let (voice, message_chars) = match regenerate {
+            Some(stored) if stored.msgtype == MessageType::Voice => (
+                true,
+                stored
+                    .transcription
+                    .as_ref()
+                    .map(|text| text.chars().count()),
+            ),
+            Some(stored) => (false, Some(stored.content.chars().count())),
+            None if *message_type == MessageType::Voice => (true, None),
+            None => (
+                false,
+                Some(String::from_utf8_lossy(message_data).chars().count()),
+            ),
+        };
+        let traits = RequestTraits {
+            images: !images.is_empty()
+                || regenerate.is_some_and(|stored| !stored.images.is_empty()),
+            voice,
+            message_chars,
+            context_tokens: 0,
+        };
+        auto_model(state, user_id, conversation_id, message_id, traits).await?
+    } else {
+        message_model
+    };
+    budget::admit_chat_model(state, message_model)
+}
+
+// The history stage: reads the conversation and builds the prompt for the new message.
+async fn load_history(
+    state: &Arc<ServiceState>,
+    pipeline: &ChatPipeline,
+    transaction: &DatabaseTransaction,
+    request: &TurnRequest,
+    options: &mut MessageOptions,
+    cost: i64,
+) -> AppResult<PreparedTurn> {
+    let user_id = request.user_id;
+    let conversation_id = request.conversation_id;
+    let message_id = request.message_id;
+    let message_type = &request.message_type;
+    let session_data = request.session_data.as_ref();
+    let regenerate = request.regenerate.as_ref();
+
+    let conversation_model = pipeline
+        .history
+        .conversation(transaction, user_id, conversation_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to find the specific conversation of the user",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let Some(conversation_model) = conversation_model else {
+        return Err(format_error(
+            "No conversation found for the user",
+            user_id,
+            StatusCode::NOT_FOUND,
+        )
+        .with_code(ErrorCode::ConversationNotFound));
+    };
+
+    if conversation_model.locked {
+        return Err(format_error(
+            "The conversation is locked and no longer accepts messages",
+            conversation_id,
+            StatusCode::LOCKED,
+        )
+        .with_code(ErrorCode::ConversationLocked));
+    }
+    if let Some(max_credits) = conversation_model.max_credits {
+        if conversation_model.credits_spent + cost > max_credits {
+            error!(
+                "Conversation '{}' reached its credit ceiling of {}.",
+                conversation_id, max_credits
+            );
+            return Err(AppError::new(
+                StatusCode::PAYMENT_REQUIRED,
+                ErrorCode::CreditLimitReached,
+                "The conversation has reached its credit ceiling",
+            )
+            .with_details(json!({
+                "max_credits": max_credits,
+                "credits_spent": conversation_model.credits_spent,
+                "required": cost,
+            })));
+        }
+    }
+    // The reply's real cost is only known afterwards, so the charge is clamped to this.
+    let ceiling_room = conversation_model
+        .max_credits
+        .map(|max_credits| (max_credits - conversation_model.credits_spent).max(0));
+
+    let mut chat_params = conversation_model.generation_settings().chat_params();
+    if let Some(tools) = options.tools.take() {
+        chat_params.tools = Some(tools);
+    }
+
+    if message_id >= (conversation_model.conversation.len() / 2) as i64 {
+        return Err(
+            format_error("Invalid Message Id", message_id, StatusCode::BAD_REQUEST)
+                .with_code(ErrorCode::InvalidMessageId),
+        );
+    }
+
+    let preferred_language = conversation_model.language.clone().or_else(|| {
+        session_data
+            .and_then(|s| s.preference("language"))
+            .and_then(|code| normalize_language(&code))
+    });
+
+    let user_message = match message_type {
+        _ if regenerate.is_some() => regenerate
+            .map(|stored| match stored.msgtype {
+                MessageType::Voice => stored.transcription.clone().unwrap_or_default(),
+                _ => stored.content.clone(),
+            })
+            .unwrap_or_default(),
+        MessageType::Text | MessageType::ToolCall => {
+            String::from_utf8(request.message_data.clone()).map_err(|e| {
+                format_error(
+                    "Failed to convert message data into string",
+                    e,
+                    StatusCode::BAD_REQUEST,
+                )
+            })?
+        }
+        _ => request.transcription.clone().unwrap_or_default(),
+    };
+
+    // A regenerated turn was screened when it was first sent.
+    let moderation_flags = match regenerate {
+        Some(stored) => stored.moderation_flags.clone(),
+        None => moderation::screen(state, &user_message, &request.images).await?,
+    };
+
+    let language = preferred_language.or_else(|| detect_language(&user_message));
+    if conversation_model.language.is_none() && language.is_some() {
+        pipeline
+            .persister
+            .save_language(transaction, user_id, conversation_id, language.clone())
             .await
             .map_err(|e| {
                 format_error(
-                    "Failed to find the specific conversation of the user",
+                    "Failed to save the conversation language",
                     e,
                     StatusCode::INTERNAL_SERVER_ERROR,
                 )
             })?;
+    }
 
-    if conversation_model.is_none() {
-        return Err(format_error(
-            "No conversation found for the user",
+    // A profile picked for this request must exist and becomes the conversation's preference.
+    let voice_profile = match options.voice_profile.clone() {
+        Some(name) => {
+            let name = name.trim().to_lowercase();
+            if state.config.tts.voice_profile(&name).is_none() {
+                return Err(format_error(
+                    "Unknown voice profile",
+                    name,
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+            if conversation_model.voice_profile.as_deref() != Some(name.as_str()) {
+                pipeline
+                    .persister
+                    .save_voice_profile(transaction, user_id, conversation_id, Some(name.clone()))
+                    .await
+                    .map_err(|e| {
+                        format_error(
+                            "Failed to save the conversation voice profile",
+                            e,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+            }
+            Some(name)
+        }
+        None => conversation_model.voice_profile.clone(),
+    };
+
+    if !options.collection_ids.is_empty() {
+        let organization_id = session_data.and_then(|s| s.organization_id());
+        let mut collections = vec![];
+        for collection_id in &options.collection_ids {
+            let model = pipeline
+                .history
+                .collection(transaction, *collection_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to find the requested collection",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+                .filter(|model| model.is_readable_by(user_id, organization_id.as_deref()));
+            let Some(model) = model else {
+                return Err(format_error(
+                    "No collection found for the user",
+                    collection_id,
+                    StatusCode::NOT_FOUND,
+                )
+                .with_code(ErrorCode::CollectionNotFound));
+            };
+            collections.push(model);
+        }
+        let retrieved_context =
+            retrieval::retrieve(state, transaction, &collections, &user_message)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Failed to retrieve context from the collections",
+                        e,
+                        StatusCode::BAD_GATEWAY,
+                    )
+                })?;
+        options.retrieved_context = retrieval::screen_injections(
+            retrieved_context,
+            state.config.chat.injection_policy,
             user_id,
-            StatusCode::NOT_FOUND,
-        ));
+            conversation_id,
+        );
     }
 
-    if message_id >= (conversation_model.clone().unwrap().conversation.len() / 2) as i64 {
-        return Err(format_error(
-            "Invalid Message Id",
-            message_id,
-            StatusCode::BAD_REQUEST,
-        ));
-    }
+    let custom_instructions = pipeline
+        .history
+        .custom_instructions(transaction, user_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to load the user's custom instructions",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
 
-    let mut message_list: Vec<(String, Role, Vec<String>)> = conversation_model
-        .clone()
-        .unwrap()
-        .conversation
-        .clone()
-        .into_iter()
-        .map(|e| {
-            let message: Message = serde_json::from_value(e).unwrap();
-            match message.msgtype {
-                MessageType::Text => (message.content, message.role, message.images),
-                _ => (
-                    message.transcription.unwrap_or_default(),
-                    message.role,
-                    message.images,
-                ),
+    let system_prompt = conversation_model.system_prompt.clone();
+    // A preset picked for this request must exist; a stored one that was since removed is ignored.
+    let style_prompt =
+        match options.style_preset.as_deref() {
+            Some(name) => Some(state.config.style.prompt_for(name).ok_or_else(|| {
+                format_error("Unknown style preset", name, StatusCode::BAD_REQUEST)
+            })?),
+            None => conversation_model
+                .style_preset
+                .as_deref()
+                .and_then(|name| state.config.style.prompt_for(name)),
+        }
+        .map(str::to_string);
+    let (mut message_list, message_flags): (Vec<PromptMessage>, Vec<(Option<usize>, bool)>) =
+        conversation_model
+            .conversation
+            .into_iter()
+            .map(|e| {
+                let message = Message::from_stored(e)?;
+                // Generated images belong to the assistant and cannot be sent back as input.
+                let images = match message.role {
+                    Role::Assistant => vec![],
+                    _ => message.images,
+                };
+                let tool_turn = match (message.tool_calls, message.tool_call_id) {
+                    (Some(calls), _) => Some(ToolTurn::Calls(calls)),
+                    (None, Some(tool_call_id)) => Some(ToolTurn::Result(tool_call_id)),
+                    (None, None) => None,
+                };
+                let prompt_message = match message.msgtype {
+                    MessageType::Voice => (
+                        message.transcription.unwrap_or_default(),
+                        message.role,
+                        images,
+                        tool_turn,
+                    ),
+                    _ => (message.content, message.role, images, tool_turn),
+                };
+                Ok((prompt_message, (message.reply_to, message.excluded)))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()
+            .map_err(|e| {
+                format_error(
+                    "Failed to read the stored conversation history",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?
+            .into_iter()
+            .unzip();
+    let (mut reply_links, mut excluded): (Vec<Option<usize>>, Vec<bool>) =
+        message_flags.into_iter().unzip();
+    if message_id != -1 {
+        conversation::truncate_from(&mut message_list, (message_id * 2) as usize);
+        conversation::truncate_from(&mut reply_links, (message_id * 2) as usize);
+        conversation::truncate_from(&mut excluded, (message_id * 2) as usize);
+    }
+    let reply_to = match regenerate {
+        Some(stored) => stored.reply_to,
+        None => options.reply_to,
+    };
+    if let Some(parent_id) = reply_to {
+        if parent_id == 0 || parent_id > message_list.len() {
+            return Err(format_error(
+                "The replied-to message does not exist",
+                parent_id,
+                StatusCode::BAD_REQUEST,
+            )
+            .with_code(ErrorCode::InvalidMessageId));
+        }
+    }
+    let tool_call_id = match regenerate {
+        Some(stored) => stored.tool_call_id.clone(),
+        None => options.tool_call_id.clone(),
+    }
+    .filter(|_| *message_type == MessageType::ToolCall);
+    let mut tool_budget = ToolBudget::new(&state.config.tools);
+    let user_message = if *message_type == MessageType::ToolCall {
+        // A tool result has to answer a call of the reply right before it.
+        let call = match (message_list.last(), tool_call_id.as_deref()) {
+            (Some((_, _, _, Some(ToolTurn::Calls(calls)))), Some(tool_call_id)) => {
+                calls.iter().find(|call| call.id == tool_call_id)
             }
-        })
-        .collect();
+            _ => None,
+        };
+        let Some(call) = call else {
+            return Err(format_error(
+                "The tool result does not answer a pending tool call",
+                tool_call_id.unwrap_or_default(),
+                StatusCode::BAD_REQUEST,
+            ));
+        };
+        let limits = state.config.tools.limits_for(&call.name);
+        if regenerate.is_none() && tools::timed_out(call, limits, Utc::now()) {
+            return Err(format_error(
+                "The tool result arrived after the call timed out",
+                &call.id,
+                StatusCode::REQUEST_TIMEOUT,
+            ));
+        }
+        tool_budget.record_history(&message_list);
+        let output = tools::cap_output(&call.name, user_message, limits.max_output_chars);
+        // Screened before it goes back to the provider, like retrieved context.
+        if tools::screen_injection(
+            &output,
+            state.config.chat.injection_policy,
+            user_id,
+            conversation_id,
+            call,
+            "result",
+        ) {
+            output
+        } else {
+            tools::WITHHELD_RESULT.to_string()
+        }
+    } else {
+        user_message
+    };
     let mut last_message = vec![];
+    let mut written_files = vec![];
+    let mut written_bytes = 0;
 
-    for (index, image) in images.iter().enumerate() {
-        let saved_filename;
+    for (index, image) in request.images.iter().enumerate() {
         let mut file_extension: Option<&str> = None;
-        if let Some(ref filename) = image_filnames[index] {
+        if let Some(ref filename) = request.image_filenames[index] {
             file_extension = Path::new(filename.as_str())
                 .extension()
                 .and_then(std::ffi::OsStr::to_str);
         }
-        if let Some(extension) = file_extension {
-            saved_filename = format!(
-                "images/{}-{}-{}.{}",
-                conversation_id,
-                message_list.len(),
-                index,
-                extension,
-            );
-        } else {
-            saved_filename = format!(
-                "images/{}-{}-{}",
-                conversation_id,
-                message_list.len(),
-                index
-            );
+        let saved_filename = content_filename(
+            &state
+                .config
+                .residency
+                .media_dir("images", request.region.as_deref()),
+            &conversation_id.to_string(),
+            image,
+            file_extension,
+        );
+        // Names are derived from the content, so an earlier turn may already use the file.
+        if !file_exists(&saved_filename) {
+            save_file(saved_filename.as_str(), image.to_vec().clone()).map_err(|e| {
+                delete_files(&written_files);
+                format_error(
+                    "Error in saving user's image file",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+            written_files.push(saved_filename.clone());
+            written_bytes += image.len() as i64;
         }
-        save_file(saved_filename.as_str(), image.to_vec().clone()).map_err(|e| {
+        last_message.push(saved_filename);
+    }
+    if let Some(stored) = regenerate {
+        last_message = stored.images.clone();
+    }
+    message_list.push((
+        user_message.clone(),
+        Role::User,
+        last_message.clone(),
+        tool_call_id.clone().map(ToolTurn::Result),
+    ));
+    reply_links.push(reply_to);
+    excluded.push(false);
+    let message_list = inline_replies(message_list, &reply_links);
+    let turn_index = if message_id == -1 {
+        (message_list.len() - 1) as i64
+    } else {
+        message_id * 2
+    };
+
+    let title = generate_title(
+        &user_message,
+        language.as_deref(),
+        state.config.chat.title_max_words,
+        state.config.chat.title_max_chars,
+    );
+
+    // Excluded messages stay in the conversation but are never sent to the model.
+    let mut prompt_messages: Vec<PromptMessage> = message_list
+        .iter()
+        .zip(&excluded)
+        .filter(|(_, excluded)| !**excluded)
+        .map(|(message, _)| message.clone())
+        .collect();
+    if let Some(context) = retrieval::context_prompt(&options.retrieved_context) {
+        prompt_messages.insert(0, (context, Role::System, vec![], None));
+    }
+    // Everything so far came from the user; the instructions added below do not need masking.
+    let organization_id = session_data.and_then(|s| s.organization_id());
+    let redaction_kinds = state.config.redaction.kinds_for(organization_id.as_deref());
+    let voice_retention = state
+        .config
+        .retention
+        .voice_retention_for(organization_id.as_deref());
+    let redacted = redaction::redact_messages(state, redaction_kinds, &mut prompt_messages)
+        .await
+        .map_err(|e| {
             format_error(
-                "Error in saving user's image file",
+                "Failed to redact personal data from the prompt",
                 e,
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
             )
+            .with_code(ErrorCode::UpstreamUnavailable)
         })?;
-        last_message.push(saved_filename);
+    if redacted > 0 {
+        info!(
+            "Redacted {} personal data matches from the prompt of conversation '{}'.",
+            redacted, conversation_id
+        );
     }
-    message_list.push((user_message.clone(), Role::User, last_message.clone()));
-
-    let openai_response = send_chat_completion(
-        state.config.openai.openai_key.clone(),
-        message_model,
-        message_list.clone(),
-    )
-    .await
-    .map_err(|e| {
-        error!("{}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e))
-    })?;
+    // The title model is reached without regional routing, so users bound to a region keep the heuristic title.
+    let title_redaction = (request.region.is_none() && state.config.chat.title_model.is_some())
+        .then(|| redaction_kinds.clone());
+    if let Some(instruction) = language.as_deref().and_then(language_instruction) {
+        prompt_messages.insert(0, (instruction, Role::System, vec![], None));
+    }
+    if let Some(style_prompt) = style_prompt {
+        prompt_messages.insert(0, (style_prompt, Role::System, vec![], None));
+    }
+    if let Some(system_prompt) = system_prompt {
+        prompt_messages.insert(0, (system_prompt, Role::System, vec![], None));
+    }
+    if let Some(custom_instructions) = custom_instructions {
+        prompt_messages.insert(0, (custom_instructions, Role::System, vec![], None));
+    }
+    if let Some(preamble) = state.config.chat.system_preamble.clone() {
+        prompt_messages.insert(0, (preamble, Role::System, vec![], None));
+    }
+    let prompt_chars: usize = prompt_messages
+        .iter()
+        .map(|(text, _, _, _)| text.chars().count())
+        .sum();
 
-    let mut openai_stream = openai_response.bytes_stream();
+    Ok(PreparedTurn {
+        user_message,
+        images: last_message,
+        prompt_messages,
+        prompt_chars,
+        chat_params,
+        language,
+        voice_profile,
+        title,
+        title_redaction,
+        citations: retrieval::citations(&options.retrieved_context),
+        turn_index,
+        reply_to,
+        tool_call_id,
+        tool_budget,
+        moderation_flags,
+        voice_retention,
+        ceiling_room,
+        written_files,
+        written_bytes,
+    })
+}
 
-    let mut total_content = "".to_string();
-    let mut total_voice: Vec<u8> = vec![];
-    let sentence_regex = Regex::new(r"(?m)(?:[.!?]\s+|\n|\r\n)").map_err(|e| {
+// The provider stage. A request that has to wait is sent upstream from the
+// streaming task once it is admitted.
+async fn call_provider(
+    state: &ServiceState,
+    pipeline: &ChatPipeline,
+    request: &TurnRequest,
+    prepared: &mut PreparedTurn,
+) -> AppResult<PendingReply> {
+    let admission = state.chat_queue.admit(request.user_id).map_err(|e| {
         format_error(
-            "Sentence split regex creation failed",
+            "The assistant is busy, please try again later",
             e,
-            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
         )
+        .with_code(ErrorCode::QueueFull)
     })?;
+    match admission {
+        Admission::Ready(slot) => {
+            let chunks = pipeline
+                .caller
+                .stream_chat(
+                    request.message_model.clone(),
+                    request.region.clone(),
+                    std::mem::take(&mut prepared.prompt_messages),
+                    prepared.chat_params.clone(),
+                )
+                .await
+                .map_err(|e| {
+                    error!("{}", e);
+                    (upstream_status(&e), e)
+                })?;
+            Ok(Ok((slot, chunks)))
+        }
+        Admission::Queued(ticket) => Ok(Err(ticket)),
+    }
+}
 
-    let (tx, rx) = mpsc::channel::<Result<Frame<Bytes>, String>>(1000000);
-    let message_type_clone = message_type.clone();
-
-    tokio::spawn(async move {
-        let mut buffer = String::new();
-        let mut is_started = false;
-        while let Some(response) = openai_stream.next().await {
-            match response {
-                Ok(result) => {
-                    let content = match chunk_to_content_list(result) {
-                        Ok(content_list) => content_list,
-                        _ => {
-                            continue;
-                        }
-                    };
-                    for content_str in content {
-                        total_content.push_str(content_str.clone().as_str());
-                        match message_type {
-                            MessageType::Voice => {
-                                let stream_result = text_to_speech(
-                                    &state.config.deepgram.deepgram_key,
-                                    &content_str,
-                                    is_started,
-                                )
-                                .await;
-                                is_started = true;
-                                if stream_result.is_err() {
-                                    continue;
-                                }
-                                let mut audio_stream = stream_result.unwrap();
-                                while let Some(data) = audio_stream.next().await {
-                                    total_voice.append(&mut data.to_vec());
-                                    if tx.send(Ok(Frame::data(data))).await.is_err() {
-                                        error!("Failed to send voice stream data to buffer");
-                                        return Err(());
-                                    }
-                                }
-                            }
-                            MessageType::Text => {
-                                if tx
-                                    .send(Ok(Frame::data(Bytes::from(content_str.clone()))))
-                                    .await
-                                    .is_err()
-                                {
-                                    error!("Failed send openaai text response to buffer");
-                                    return Err(());
-                                }
-                            }
+/// Turns the streamed text into what the client asked for: processed text,
+/// speech or both.
+struct ReplyRenderer {
+    reply_mode: ReplyMode,
+    audio_format: AudioFormat,
+    voice: SpeechVoice,
+    segmenter: SentenceSegmenter,
+    speech_filter: SpeechFilter,
+    speech_sanitizer: MarkdownSanitizer,
+    post_processors: PostProcessChain,
+    transcoder: Transcoder,
+    is_started: bool,
+    total_voice: Vec<u8>,
+}
+
+impl ReplyRenderer {
+    fn new(
+        state: &ServiceState,
+        reply_mode: ReplyMode,
+        audio_format: AudioFormat,
+        markdown_mode: MarkdownMode,
+        language: Option<&str>,
+        custom_voice: Option<&str>,
+        voice_profile: Option<&str>,
+    ) -> AppResult<Self> {
+        let voice = SpeechVoice::resolve(&state.config.tts, language, custom_voice, voice_profile);
+        let transcoder = Transcoder::new(audio_format, voice.sample_rate()).map_err(|e| {
+            format_error(
+                "Failed to set up the audio encoder",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        Ok(ReplyRenderer {
+            reply_mode,
+            audio_format,
+            voice,
+            segmenter: SentenceSegmenter::for_language(
+                &state.config.tts,
+                language.unwrap_or(&state.config.tts.segment_language),
+            ),
+            speech_filter: SpeechFilter::from_config(&state.config.tts),
+            speech_sanitizer: MarkdownSanitizer::new(MarkdownMode::Strip),
+            post_processors: PostProcessChain::from_config(
+                &state.config.postprocess,
+                markdown_mode,
+            ),
+            transcoder,
+            is_started: false,
+            total_voice: vec![],
+        })
+    }
+
+    async fn push(
+        &mut self,
+        state: &Arc<ServiceState>,
+        writer: &StreamWriter,
+        content: &str,
+    ) -> Result<(), String> {
+        if self.reply_mode.has_text() {
+            send_text(writer, self.post_processors.push(content)).await?;
+        }
+        if self.reply_mode.has_voice() {
+            let speech_text = self
+                .speech_sanitizer
+                .push(&self.speech_filter.push(content));
+            for segment in self.segmenter.push(&speech_text) {
+                self.speak(state, writer, &segment).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush_text(&mut self, writer: &StreamWriter) -> Result<(), String> {
+        if self.reply_mode.has_text() {
+            send_text(writer, self.post_processors.flush()).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_voice(
+        &mut self,
+        state: &Arc<ServiceState>,
+        writer: &StreamWriter,
+    ) -> Result<(), String> {
+        if !self.reply_mode.has_voice() {
+            return Ok(());
+        }
+        let mut speech_text = self.speech_sanitizer.push(&self.speech_filter.flush());
+        speech_text.push_str(&self.speech_sanitizer.flush());
+        let mut segments = self.segmenter.push(&speech_text);
+        segments.extend(self.segmenter.flush());
+        for segment in segments {
+            self.speak(state, writer, &segment).await?;
+        }
+        send_audio(writer, self.audio_format, self.transcoder.flush()?).await
+    }
+
+    async fn speak(
+        &mut self,
+        state: &Arc<ServiceState>,
+        writer: &StreamWriter,
+        segment: &str,
+    ) -> Result<(), String> {
+        stream_speech(
+            state,
+            writer,
+            self.audio_format,
+            &self.voice,
+            segment,
+            &mut self.is_started,
+            &mut self.total_voice,
+            &mut self.transcoder,
+        )
+        .await
+    }
+}
+
+// Streams the reply to the client as it arrives and gathers it for the persister.
+async fn stream_reply(
+    state: &Arc<ServiceState>,
+    pipeline: &ChatPipeline,
+    request: &TurnRequest,
+    prepared: &mut PreparedTurn,
+    pending: PendingReply,
+    renderer: &mut ReplyRenderer,
+    writer: &StreamWriter,
+    generation: &GenerationGuard,
+) -> (StreamedReply, Result<(), String>) {
+    let mut reply = StreamedReply::default();
+    let mut first_token_seen = false;
+    let streamed: Result<(), String> = async {
+        let (_queue_slot, mut openai_stream) = match pending {
+            Ok(started) => started,
+            Err(mut ticket) => {
+                let slot = loop {
+                    let event = tokio::select! {
+                        event = ticket.next() => event,
+                        _ = generation.cancel.notified() => {
+                            return Err("The request was cancelled while queued".to_string());
+                        }
+                        _ = writer.closed() => {
+                            return Err("The client left while the request was queued".to_string());
+                        }
+                    };
+                    match event {
+                        QueueEvent::Admitted(slot) => break slot,
+                        QueueEvent::Position(position) => {
+                            writer.event("queue", json!({ "position": position })).await?
                         }
                     }
+                };
+                let chunks = pipeline
+                    .caller
+                    .stream_chat(
+                        request.message_model.clone(),
+                        request.region.clone(),
+                        std::mem::take(&mut prepared.prompt_messages),
+                        prepared.chat_params.clone(),
+                    )
+                    .await?;
+                (slot, chunks)
+            }
+        };
+        reply.upstream_started = true;
+
+        if !prepared.citations.is_empty() {
+            writer.event("citations", json!(prepared.citations)).await?;
+        }
+        loop {
+            let response = tokio::select! {
+                response = openai_stream.next() => response,
+                _ = generation.cancel.notified() => {
+                    info!(
+                        "Generation in conversation '{}' was cancelled by the user.",
+                        request.conversation_id
+                    );
+                    reply.cancelled = true;
+                    None
                 }
-                Err(e) => {
-                    let error_message = format!("Stream error occurred while processing OpenAI response for conversation '{}': {}", conversation_id, e);
-                    error!(error_message);
-                    let _ = tx.send(Err(error_message)).await;
-                    return Err(());
+                _ = writer.closed() => None,
+            };
+            let Some(response) = response else {
+                break;
+            };
+            let result = response.map_err(|e| {
+                format!(
+                    "Stream error occurred while processing OpenAI response for conversation '{}': {}",
+                    request.conversation_id, e
+                )
+            })?;
+            let content = match chunk_to_content_list(result) {
+                Ok((content_list, tool_call_deltas, chunk_usage)) => {
+                    merge_tool_calls(&mut reply.tool_calls, tool_call_deltas);
+                    if chunk_usage.is_some() {
+                        reply.usage = chunk_usage;
+                    }
+                    content_list
+                }
+                _ => {
+                    continue;
                 }
+            };
+            if !first_token_seen && !content.is_empty() {
+                first_token_seen = true;
+                latency::record_first_token(
+                    state,
+                    &request.message_model,
+                    request.started_at.elapsed(),
+                );
+                metrics::observe_first_token(
+                    &request.message_model,
+                    request.started_at.elapsed().as_secs_f64(),
+                );
             }
-        }
-        let mut saved_filename = String::from("");
-        let mut file_extension: Option<&str> = None;
-        if message_type != MessageType::Text {
-            if let Some(ref filename) = voice_filename {
-                file_extension = Path::new(filename.as_str())
-                    .extension()
-                    .and_then(std::ffi::OsStr::to_str);
+            for content_str in content {
+                reply.content.push_str(&content_str);
+                renderer.push(state, writer, &content_str).await?;
             }
-            if let Some(extension) = file_extension {
-                saved_filename = format!(
-                    "voice/{}-{}.{}",
-                    conversation_id,
-                    message_list.len() - 1,
-                    extension
-                );
-            } else {
-                saved_filename = format!("voice/{}-{}", conversation_id, message_list.len() - 1);
+        }
+        renderer.flush_text(writer).await?;
+        // Calls are screened before the client gets to run them.
+        reply.tool_calls.retain(|call| {
+            tools::screen_injection(
+                &call.arguments,
+                state.config.chat.injection_policy,
+                request.user_id,
+                request.conversation_id,
+                call,
+                "arguments",
+            )
+        });
+        reply.tool_calls = prepared
+            .tool_budget
+            .admit(std::mem::take(&mut reply.tool_calls), Utc::now());
+        // The client runs the tools and answers with a `tool` message.
+        for call in &reply.tool_calls {
+            writer.event("tool_call", json!(call)).await?;
+        }
+        renderer.flush_voice(state, writer).await
+    }
+    .await;
+    reply.voice = std::mem::take(&mut renderer.total_voice);
+    (reply, streamed)
+}
+
+// The persister stage: saves the user's voice message, the spoken reply and the turn.
+async fn persist_turn(
+    state: &Arc<ServiceState>,
+    pipeline: &ChatPipeline,
+    transaction: &DatabaseTransaction,
+    request: &TurnRequest,
+    prepared: PreparedTurn,
+    reply: StreamedReply,
+    reply_mode: ReplyMode,
+    sample_rate: u32,
+    truncated: bool,
+    spend: f64,
+    written_files: &mut Vec<String>,
+) -> Result<SavedTurn, String> {
+    let conversation_id = request.conversation_id;
+    let region = request.region.as_deref();
+    let is_voice = request.message_type == MessageType::Voice;
+    let mut saved_filename = String::from("");
+    let mut file_extension: Option<&str> = None;
+    let mut media_bytes = prepared.written_bytes;
+    if let Some(stored) = request.regenerate.as_ref() {
+        saved_filename = stored.content.clone();
+    } else if is_voice && prepared.voice_retention != VoiceRetention::TranscriptOnly {
+        if let Some(ref filename) = request.voice_filename {
+            file_extension = Path::new(filename.as_str())
+                .extension()
+                .and_then(std::ffi::OsStr::to_str);
+        }
+        let mut voice_dir = state.config.residency.media_dir("voice", region);
+        if let Some(subdir) = prepared.voice_retention.media_subdir() {
+            voice_dir = format!("{}/{}", voice_dir, subdir);
+        }
+        saved_filename = content_filename(
+            &voice_dir,
+            &conversation_id.to_string(),
+            &request.message_data,
+            file_extension,
+        );
+
+        if !file_exists(&saved_filename) {
+            match save_file(saved_filename.as_str(), request.message_data.clone()) {
+                Ok(()) => {
+                    written_files.push(saved_filename.clone());
+                    media_bytes += request.message_data.len() as i64;
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to save the voice file of conversation '{}', keeping only its transcription: {}",
+                        conversation_id, e
+                    );
+                    saved_filename = String::from("");
+                }
             }
+        }
+    }
 
-            save_file(saved_filename.as_str(), message_data.clone()).unwrap();
-            // let mut reader = hound::WavReader::new(Cursor::new(total_voice)).map_err(|e| {
-            //     let error_message = format!("Failed to create wav reader: {}", e);
-            //     error!("{}", error_message);
-            //     ()
-            // })?;
-            // let samples: Vec<i16> = reader.samples::<i16>().filter_map(Result::ok)  .collect();
-            // save_audio_file(&format!("voice/{}-{}.mp3", conversation_id, conversation_list.len()), samples);
+    let user_waveform = if let Some(stored) = request.regenerate.as_ref() {
+        stored.waveform.clone()
+    } else if !is_voice {
+        None
+    } else {
+        from_wav_bytes(&request.message_data)
+    };
+    let mut reply_audio = None;
+    let mut reply_waveform = None;
+    if !reply.voice.is_empty() {
+        let pcm = &reply.voice[wav_header_len(&reply.voice)..];
+        // Encoding is deterministic, so the PCM data identifies the MP3 as well.
+        let audio_filename = content_filename(
+            &state.config.residency.media_dir("voice", region),
+            &format!("{}-reply", conversation_id),
+            pcm,
+            Some("mp3"),
+        );
+        let samples: Vec<i16> = pcm
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        reply_waveform = from_pcm(&samples, sample_rate);
+        let encode_filename = audio_filename.clone();
+        let encoded = if file_exists(&audio_filename) {
+            Ok(())
+        } else {
+            tokio::task::spawn_blocking(move || {
+                save_audio_file(&encode_filename, samples, sample_rate)
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+            .map(|size| {
+                written_files.push(audio_filename.clone());
+                media_bytes += size as i64;
+            })
+        };
+        match encoded {
+            Ok(()) => reply_audio = Some(audio_filename),
+            Err(e) => error!(
+                "Failed to save the voice reply of conversation '{}' as MP3: {}",
+                conversation_id, e
+            ),
         }
+    }
 
-        if conversation::add_message(
-            &transaction,
+    let reply_chars = reply.content.chars().count();
+    let usage = reply.usage(prepared.prompt_chars);
+
+    let title_exchange = prepared
+        .title_redaction
+        .filter(|_| prepared.turn_index == 0 && !reply.content.is_empty())
+        .map(|kinds| (prepared.user_message.clone(), reply.content.clone(), kinds));
+    let cancelled = reply.cancelled;
+    let turn = Turn {
+        user_message_type: request.message_type.clone(),
+        user_message: if !is_voice {
+            prepared.user_message.clone()
+        } else {
+            saved_filename
+        },
+        transcription: if !is_voice {
+            None
+        } else {
+            Some(prepared.user_message)
+        },
+        images: prepared.images,
+        reply_mode,
+        answer: reply.content,
+        citations: prepared.citations,
+        reply_audio,
+        user_waveform,
+        reply_waveform,
+        title: prepared.title,
+        model: request.message_model.clone(),
+        truncated,
+        message_id: prepared.turn_index,
+        reply_to: prepared.reply_to,
+        tool_call_id: prepared.tool_call_id,
+        tool_calls: reply.tool_calls,
+        moderation_flags: prepared.moderation_flags,
+        media_bytes,
+    };
+    pipeline
+        .persister
+        .save_turn(transaction, request.user_id, conversation_id, turn)
+        .await?;
+
+    Ok(SavedTurn {
+        usage,
+        spend,
+        reply_chars,
+        truncated,
+        cancelled,
+        title_exchange,
+        ceiling_room: prepared.ceiling_room,
+    })
+}
+
+// The biller stage: charges the turn and records its events in the turn's transaction.
+async fn bill_turn(
+    state: &Arc<ServiceState>,
+    pipeline: &ChatPipeline,
+    transaction: &DatabaseTransaction,
+    request: &TurnRequest,
+    saved: &SavedTurn,
+    reply_mode: ReplyMode,
+    credits_remaining: i64,
+) -> Result<(i64, Vec<Uuid>), String> {
+    let user_id = request.user_id;
+    let conversation_id = request.conversation_id;
+    let message_model = &request.message_model;
+    // A reply cancelled before any content arrived is not charged.
+    let charged = if saved.cancelled && saved.reply_chars == 0 {
+        0
+    } else {
+        pipeline
+            .biller
+            .credits_for_usage(message_model, &saved.usage)
+            .min(credits_remaining)
+            .min(saved.ceiling_room.unwrap_or(i64::MAX))
+    };
+    pipeline
+        .biller
+        .charge(
+            transaction,
             user_id,
             conversation_id,
-            message_type.clone(),
-            if message_type == MessageType::Text {
-                user_message.clone()
-            } else {
-                saved_filename
-            },
-            if message_type == MessageType::Text {
-                None
-            } else {
-                Some(user_message)
-            },
-            last_message,
-            total_content,
-            if message_id == -1 {
-                (message_list.len() - 1) as i64
-            } else {
-                message_id * 2
-            },
+            message_model.clone(),
+            charged,
+            &saved.usage,
         )
+        .await?;
+    if let Some(bot_id) = request.bot_id {
+        pipeline
+            .biller
+            .charge_bot(transaction, bot_id, charged)
+            .await?;
+    }
+
+    // Saved with the message so the event is delivered if and only if the reply was.
+    let mut message_events = vec![];
+    if let Some(webhook_url) = state.config.outbox.message_events_webhook.clone() {
+        let payload = json!({
+            "conversation_id": conversation_id,
+            "user_id": user_id,
+            "model": message_model,
+            "credits": charged,
+            "prompt_tokens": saved.usage.prompt_tokens,
+            "completion_tokens": saved.usage.completion_tokens,
+            "truncated": saved.truncated,
+            "regenerated": request.regenerate.is_some(),
+            "created_at": Utc::now(),
+        });
+        let event =
+            outbox_repository::enqueue(transaction, outbox::MESSAGE_CREATED, webhook_url, payload)
+                .await
+                .map_err(|e| format!("Failed to record the message event: {}", e))?;
+        message_events.push(event.id);
+    }
+    let mut domain_events = vec![
+        (
+            outbox::MESSAGE_SENT,
+            json!({
+                "conversation_id": conversation_id,
+                "user_id": user_id,
+                "message_type": request.message_type,
+                "regenerated": request.regenerate.is_some(),
+            }),
+        ),
+        (
+            outbox::GENERATION_COMPLETED,
+            json!({
+                "conversation_id": conversation_id,
+                "user_id": user_id,
+                "model": message_model,
+                "reply_mode": reply_mode,
+                "prompt_tokens": saved.usage.prompt_tokens,
+                "completion_tokens": saved.usage.completion_tokens,
+                "truncated": saved.truncated,
+                "cancelled": saved.cancelled,
+                "duration_ms": request.started_at.elapsed().as_millis() as u64,
+            }),
+        ),
+    ];
+    if charged > 0 {
+        domain_events.push((
+            outbox::CREDITS_CHARGED,
+            json!({
+                "conversation_id": conversation_id,
+                "user_id": user_id,
+                "model": message_model,
+                "credits": charged,
+            }),
+        ));
+    }
+    let ids = outbox::enqueue_domain_events(state, transaction, domain_events)
         .await
-        .is_err()
-        {
-            let error_message = format!("Failed to save message in database");
+        .map_err(|e| format!("Failed to record the domain events: {}", e))?;
+    message_events.extend(ids);
+    Ok((charged, message_events))
+}
+
+// Runs once the turn has committed: delivers its events, refreshes the title
+// and reports the new balance.
+async fn finish_turn(
+    state: &Arc<ServiceState>,
+    pipeline: &ChatPipeline,
+    request: &TurnRequest,
+    saved: SavedTurn,
+    charged: i64,
+    message_events: Vec<Uuid>,
+    writer: &StreamWriter,
+    credits_remaining: i64,
+) {
+    let user_id = request.user_id;
+    let conversation_id = request.conversation_id;
+    let message_model = &request.message_model;
+    for event_id in message_events {
+        let state = state.clone();
+        tokio::spawn(async move { outbox::deliver_now(&state, event_id).await });
+    }
+    if let Some((question, answer, kinds)) = saved.title_exchange {
+        spawn_in_request(title::refresh_title(
+            state.clone(),
+            user_id,
+            conversation_id,
+            question,
+            answer,
+            kinds,
+        ));
+    }
+
+    budget::record_spend(state, saved.spend).await;
+    metrics::add_credits(message_model, charged);
+    metrics::add_token_usage(
+        message_model,
+        saved.usage.prompt_tokens,
+        saved.usage.completion_tokens,
+    );
+
+    // A bot's pool is kept here; its owner's balance was debited when the pool was funded.
+    let reported = match request.bot_id {
+        Some(_) => Ok(()),
+        None => {
+            pipeline
+                .biller
+                .report_balance(user_id, credits_remaining - charged)
+                .await
+        }
+    };
+    if let Err(e) = reported {
+        error!(
+            "Error sending updated session data for user '{}', the message was saved without updating credits: {}",
+            user_id, e
+        );
+    };
+
+    if writer.format() == StreamFormat::Sse {
+        let _ = writer
+            .event(
+                "usage",
+                json!({
+                    "model": message_model,
+                    "credits": charged,
+                    "prompt_tokens": saved.usage.prompt_tokens,
+                    "completion_tokens": saved.usage.completion_tokens,
+                }),
+            )
+            .await;
+        let _ = writer
+            .event("done", json!({ "conversation_id": conversation_id }))
+            .await;
+    }
+}
+
+// Generates an image for an `image-generation` message and saves it as the assistant reply.
+async fn handle_image_generation(
+    state: Arc<ServiceState>,
+    user_id: i64,
+    session_data: Option<SessionData>,
+    conversation_id: Uuid,
+    prompt: String,
+    message_id: i64,
+    sse: bool,
+) -> AppResult<Response> {
+    if prompt.trim().is_empty() {
+        return Err(format_error(
+            "Invalid image prompt",
+            "prompt must not be empty",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    budget::admit_expensive(&state, "image generation")?;
+    let region = data_region(&state, session_data.as_ref());
+    // A cached image may end up cheaper; the full price is what must be affordable.
+    let cost = IMAGE_GENERATION_CREDITS;
+    let credits_remaining = session_data
+        .as_ref()
+        .map(|s| s.credits_remaining)
+        .unwrap_or_default();
+    if cost > credits_remaining {
+        return Err(format_error(
+            "Insufficient credits to proceed with the action. Required",
+            cost,
+            StatusCode::BAD_REQUEST,
+        )
+        .with_code(ErrorCode::InsufficientCredits));
+    }
+
+    let transaction = state.db.begin().await.map_err(|e| {
+        metrics::transaction_failed("begin");
+        format_error(
+            "Could not start a database transaction due to an error",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let conversation_model =
+        conversation::find_by_user_id_and_conversation_id(&transaction, user_id, conversation_id)
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to find the specific conversation of the user",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+    rollback(transaction).await;
+    let Some(conversation_model) = conversation_model else {
+        return Err(format_error(
+            "No conversation found for the user",
+            user_id,
+            StatusCode::NOT_FOUND,
+        )
+        .with_code(ErrorCode::ConversationNotFound));
+    };
+    if conversation_model.locked {
+        return Err(format_error(
+            "The conversation is locked and no longer accepts messages",
+            conversation_id,
+            StatusCode::LOCKED,
+        )
+        .with_code(ErrorCode::ConversationLocked));
+    }
+    if let Some(max_credits) = conversation_model.max_credits {
+        if conversation_model.credits_spent + cost > max_credits {
+            return Err(AppError::new(
+                StatusCode::PAYMENT_REQUIRED,
+                ErrorCode::CreditLimitReached,
+                "The conversation has reached its credit ceiling",
+            )
+            .with_details(json!({
+                "max_credits": max_credits,
+                "credits_spent": conversation_model.credits_spent,
+                "required": cost,
+            })));
+        }
+    }
+    // The generated image's real cost is only known afterwards, so the charge is clamped to this.
+    let ceiling_room = conversation_model
+        .max_credits
+        .map(|max_credits| (max_credits - conversation_model.credits_spent).max(0));
+    let turns = (conversation_model.conversation.len() / 2) as i64;
+    if message_id >= turns {
+        return Err(
+            format_error("Invalid Message Id", message_id, StatusCode::BAD_REQUEST)
+                .with_code(ErrorCode::InvalidMessageId),
+        );
+    }
+    let truncate_index = if message_id == -1 {
+        conversation_model.conversation.len() as i64
+    } else {
+        message_id * 2
+    };
+    let title = generate_title(
+        &prompt,
+        conversation_model.language.as_deref(),
+        state.config.chat.title_max_words,
+        state.config.chat.title_max_chars,
+    );
+    // Screened like a text message before the prompt reaches the provider.
+    let moderation_flags = moderation::screen(&state, &prompt, &[]).await?;
+
+    let stream_format = if sse {
+        StreamFormat::Sse
+    } else {
+        StreamFormat::Multiplexed
+    };
+    let (writer, stream_response) = streaming::channel(stream_format, 16);
+    let generation = state.generations.register(conversation_id, user_id);
+
+    spawn_in_request(async move {
+        let options = ImageOptions::default();
+        let bytes = tokio::select! {
+            result = generate_image(&state, &prompt, &options) => result,
+            _ = generation.cancel.notified() => {
+                writer.error("The image generation was cancelled".to_string()).await;
+                return;
+            }
+        };
+        let (bytes, cost, cached) = match bytes {
+            Ok(generated) => {
+                let cost = generated
+                    .credits(&state, &options)
+                    .min(credits_remaining)
+                    .min(ceiling_room.unwrap_or(i64::MAX));
+                let cached = generated.cached;
+                let Some(bytes) = generated.images.into_iter().next() else {
+                    writer
+                        .error("The provider returned no image".to_string())
+                        .await;
+                    return;
+                };
+                (bytes, cost, cached)
+            }
+            Err(e) => {
+                writer.error(e.message).await;
+                return;
+            }
+        };
+        let saved_filename = content_filename(
+            &state
+                .config
+                .residency
+                .media_dir("images", region.as_deref()),
+            &conversation_id.to_string(),
+            &bytes,
+            Some("png"),
+        );
+        // An identical image generated earlier in the conversation is already
+        // counted, and must not be removed if this turn fails.
+        let (media_bytes, written_files) = if file_exists(&saved_filename) {
+            (0, vec![])
+        } else {
+            (bytes.len() as i64, vec![saved_filename.clone()])
+        };
+        if let Err(e) = save_file(saved_filename.as_str(), bytes.to_vec()) {
+            let error_message = format!("Error in saving the generated image: {}", e);
             error!("{}", error_message);
-            let _ = tx.send(Err(error_message)).await;
-            return Err(());
+            writer.error(error_message).await;
+            return;
+        }
+
+        let transaction = match state.db.begin().await {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                metrics::transaction_failed("begin");
+                let error_message = format!("Starting a database transaction failed: {}", e);
+                error!("{}", error_message);
+                delete_files(&written_files);
+                writer.error(error_message).await;
+                return;
+            }
         };
+        let saved: Result<Vec<Uuid>, String> = async {
+            conversation::add_message(
+                &transaction,
+                user_id,
+                conversation_id,
+                MessageType::ImageGeneration,
+                prompt.clone(),
+                None,
+                vec![],
+                ReplyMode::Text,
+                prompt.clone(),
+                vec![],
+                None,
+                None,
+                None,
+                title,
+                IMAGE_GENERATION_MODEL.to_string(),
+                false,
+                truncate_index,
+                vec![saved_filename.clone()],
+                None,
+                None,
+                vec![],
+                moderation_flags,
+            )
+            .await
+            .map_err(|e| format!("Failed to save message in database: {}", e))?;
+            draft::delete(&transaction, user_id, conversation_id)
+                .await
+                .map_err(|e| format!("Failed to clear the conversation draft: {}", e))?;
+            usage::record(
+                &transaction,
+                user_id,
+                IMAGE_GENERATION_MODEL.to_string(),
+                cost,
+                &TokenUsage::default(),
+            )
+            .await
+            .map_err(|e| format!("Failed to record credit usage: {}", e))?;
+            conversation::add_credits_spent(&transaction, conversation_id, cost)
+                .await
+                .map_err(|e| format!("Failed to record conversation credit usage: {}", e))?;
+            conversation::add_media_bytes(&transaction, conversation_id, media_bytes)
+                .await
+                .map_err(|e| format!("Failed to record the conversation media size: {}", e))?;
+            let mut domain_events = vec![
+                (
+                    outbox::MESSAGE_SENT,
+                    json!({
+                        "conversation_id": conversation_id,
+                        "user_id": user_id,
+                        "message_type": MessageType::ImageGeneration,
+                        "regenerated": message_id != -1,
+                    }),
+                ),
+                (
+                    outbox::IMAGE_GENERATED,
+                    json!({
+                        "conversation_id": conversation_id,
+                        "user_id": user_id,
+                        "model": IMAGE_GENERATION_MODEL,
+                        "cached": cached,
+                    }),
+                ),
+            ];
+            if cost > 0 {
+                domain_events.push((
+                    outbox::CREDITS_CHARGED,
+                    json!({
+                        "conversation_id": conversation_id,
+                        "user_id": user_id,
+                        "model": IMAGE_GENERATION_MODEL,
+                        "credits": cost,
+                    }),
+                ));
+            }
+            let mut events = outbox::enqueue_domain_events(&state, &transaction, domain_events)
+                .await
+                .map_err(|e| format!("Failed to record the domain events: {}", e))?;
+            let Some(webhook_url) = state.config.outbox.message_events_webhook.clone() else {
+                return Ok(events);
+            };
+            let payload = json!({
+                "conversation_id": conversation_id,
+                "user_id": user_id,
+                "model": IMAGE_GENERATION_MODEL,
+                "credits": cost,
+                "images": [saved_filename],
+                "created_at": Utc::now(),
+            });
+            let event = outbox_repository::enqueue(
+                &transaction,
+                outbox::MESSAGE_CREATED,
+                webhook_url,
+                payload,
+            )
+            .await
+            .map_err(|e| format!("Failed to record the message event: {}", e))?;
+            events.push(event.id);
+            Ok(events)
+        }
+        .await;
+        let message_events = match saved {
+            Ok(message_events) => message_events,
+            Err(error_message) => {
+                error!("{}", error_message);
+                rollback(transaction).await;
+                delete_files(&written_files);
+                writer.error(error_message).await;
+                return;
+            }
+        };
+        if let Err(e) = transaction.commit().await {
+            metrics::transaction_failed("commit");
+            let error_message = format!("Committing the database transaction failed: {}", e);
+            error!("{}", error_message);
+            delete_files(&written_files);
+            writer.error(error_message).await;
+            return;
+        };
+        for event_id in message_events {
+            let state = state.clone();
+            tokio::spawn(async move { outbox::deliver_now(&state, event_id).await });
+        }
+        metrics::add_credits(IMAGE_GENERATION_MODEL, cost);
 
-        if send_session_data(
+        if let Err(e) = send_session_data(
             json!({
-                "credits_remaining" : credits_remaining,
+                "credits_remaining" : credits_remaining - cost,
                 "user_id" : user_id
             }),
             state.config.server.auth_service.as_str(),
             state.config.server.auth_secret_key.clone(),
         )
         .await
-        .is_err()
         {
-            let error_message =
-                format!("Error sending updated session data for user '{}'", user_id);
-            error!("{}", error_message);
-            let _ = tx.send(Err(error_message)).await;
-            return Err(());
+            error!(
+                "Error sending updated session data for user '{}', the image was saved without updating credits: {}",
+                user_id, e
+            );
         };
+        state.sessions.invalidate_user(user_id);
 
-        if transaction.commit().await.is_err() {
-            let error_message = format!("Committing the database transaction failed");
-            error!("{error_message}");
-            let _ = tx.send(Err(error_message)).await;
-            return Err(());
-        };
-        Ok(())
+        let image = json!({
+            "url": format!("{}/{}", PUBLIC_MEDIA_PREFIX, saved_filename),
+            "prompt": prompt,
+            "cached": cached,
+        });
+        let _ = writer.event("image", image).await;
+        if writer.format() == StreamFormat::Sse {
+            let _ = writer
+                .event(
+                    "usage",
+                    json!({ "model": IMAGE_GENERATION_MODEL, "credits": cost }),
+                )
+                .await;
+            let _ = writer
+                .event("done", json!({ "conversation_id": conversation_id }))
+                .await;
+        }
     });
-    let stream = ReceiverStream::new(rx);
-    let body_openai = StreamBody::new(stream);
-
-    return Ok(Response::builder()
-        .header("Cache-Control", "no-cache")
-        .header("Connection", "keep-alive")
-        .header(
-            "Content-Type",
-            if message_type_clone == MessageType::Text {
-                "text/plain"
-            } else {
-                "audio/wav"
-            },
+
+    stream_response.into_response()
+}
+
+// Transcribes a new voice message in the conversation's language. It runs
+// before the turn's transaction is opened, so no connection is held while the
+// recording is uploaded.
+async fn transcribe_voice(
+    state: &Arc<ServiceState>,
+    request: &TurnRequest,
+    options: &MessageOptions,
+) -> AppResult<String> {
+    let Some(filename) = request.voice_filename.clone() else {
+        return Err(format_error(
+            "Voice message is missing a file name",
+            request.user_id,
+            StatusCode::BAD_REQUEST,
+        ));
+    };
+    let transaction = state.db.begin().await.map_err(|e| {
+        metrics::transaction_failed("begin");
+        format_error(
+            "Could not start a database transaction due to an error",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let conversation_model = state
+        .pipeline
+        .history
+        .conversation(&transaction, request.user_id, request.conversation_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to find the specific conversation of the user",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    rollback(transaction).await;
+    let Some(conversation_model) = conversation_model else {
+        return Err(format_error(
+            "No conversation found for the user",
+            request.user_id,
+            StatusCode::NOT_FOUND,
+        )
+        .with_code(ErrorCode::ConversationNotFound));
+    };
+    let session_data = request.session_data.as_ref();
+    let language = conversation_model.language.or_else(|| {
+        session_data
+            .and_then(|s| s.preference("language"))
+            .and_then(|code| normalize_language(&code))
+    });
+    transcribe(
+        state,
+        filename,
+        request.message_data.clone(),
+        options
+            .transcription_prompt
+            .clone()
+            .or_else(|| session_data.and_then(|s| s.preference("transcription_prompt"))),
+        language,
+    )
+    .await
+}
+
+// The model pinned in the conversation settings, for messages sent without one.
+async fn default_model(
+    state: &ServiceState,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> AppResult<String> {
+    let transaction = state.db.begin().await.map_err(|e| {
+        metrics::transaction_failed("begin");
+        format_error(
+            "Could not start a database transaction due to an error",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
         )
-        .body(body_openai)
-        .unwrap());
+    })?;
+    let conversation_model = state
+        .pipeline
+        .history
+        .conversation(&transaction, user_id, conversation_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to find the specific conversation of the user",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    rollback(transaction).await;
+    conversation_model
+        .and_then(|model| model.generation_settings().model)
+        .ok_or_else(|| {
+            format_error(
+                "A model is required, and the conversation has no default model",
+                conversation_id,
+                StatusCode::BAD_REQUEST,
+            )
+        })
+}
+
+// Picks the model for an `auto` message from the configured rules, once the
+// stored history has been added to the context estimate.
+async fn auto_model(
+    state: &ServiceState,
+    user_id: i64,
+    conversation_id: Uuid,
+    message_id: i64,
+    mut traits: RequestTraits,
+) -> AppResult<String> {
+    let transaction = state.db.begin().await.map_err(|e| {
+        metrics::transaction_failed("begin");
+        format_error(
+            "Could not start a database transaction due to an error",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let conversation_model = state
+        .pipeline
+        .history
+        .conversation(&transaction, user_id, conversation_id)
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to find the specific conversation of the user",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    rollback(transaction).await;
+    let mut context_chars = traits.message_chars.unwrap_or_default();
+    if let Some(model) = conversation_model {
+        let mut stored = model.conversation;
+        if message_id != -1 {
+            conversation::truncate_from(&mut stored, (message_id * 2) as usize);
+        }
+        context_chars += model
+            .system_prompt
+            .as_ref()
+            .map_or(0, |prompt| prompt.chars().count());
+        context_chars += stored
+            .into_iter()
+            .filter_map(|value| Message::from_stored(value).ok())
+            .map(|message| match message.msgtype {
+                MessageType::Voice => message.transcription.unwrap_or_default(),
+                _ => message.content,
+            })
+            .map(|text| text.chars().count())
+            .sum::<usize>();
+    }
+    traits.context_tokens = budget::estimate_tokens(context_chars);
+    let model = state.config.auto_model.select(&traits).to_string();
+    info!(
+        "Picked the model '{}' for the conversation '{}' ({:?}).",
+        model, conversation_id, traits
+    );
+    Ok(model)
+}
+
+pub fn data_region(state: &ServiceState, session_data: Option<&SessionData>) -> Option<String> {
+    let session_data = session_data?;
+    state.config.residency.region_for(
+        session_data.region().as_deref(),
+        session_data.organization_id().as_deref(),
+    )
+}
+
+// Long quotes are cut so a reply to a lengthy answer does not double the prompt.
+const MAX_QUOTE_CHARS: usize = 1000;
+
+/// Prefixes every reply with the text it quotes. `reply_links[i]` is the
+/// 1-based `id` of the message `messages[i]` replies to, if any.
+fn inline_replies(
+    messages: Vec<PromptMessage>,
+    reply_links: &[Option<usize>],
+) -> Vec<PromptMessage> {
+    let quoted_texts: Vec<String> = messages
+        .iter()
+        .map(|(text, _, _, _)| text.chars().take(MAX_QUOTE_CHARS).collect())
+        .collect();
+    let quoted_roles: Vec<Role> = messages
+        .iter()
+        .map(|(_, role, _, _)| role.clone())
+        .collect();
+    messages
+        .into_iter()
+        .zip(reply_links)
+        .map(|((text, role, images, tool_turn), parent_id)| {
+            let parent = parent_id
+                .and_then(|id| id.checked_sub(1))
+                .and_then(|index| quoted_texts.get(index).zip(quoted_roles.get(index)));
+            let Some((quoted, quoted_role)) = parent else {
+                return (text, role, images, tool_turn);
+            };
+            let speaker = match quoted_role {
+                Role::Assistant => "your earlier message",
+                _ => "an earlier message of mine",
+            };
+            let quote = quoted
+                .lines()
+                .map(|line| format!("> {}", line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            (
+                format!("In reply to {}:\n{}\n\n{}", speaker, quote, text),
+                role,
+                images,
+                tool_turn,
+            )
+        })
+        .collect()
+}
+
+// Cleans up after a turn that failed: nothing is charged, the media saved for
+// it is removed and the provider spend, if any, is still recorded.
+async fn abandon_turn(
+    state: &Arc<ServiceState>,
+    transaction: Option<DatabaseTransaction>,
+    written_files: &[String],
+    spend: f64,
+) {
+    if let Some(transaction) = transaction {
+        rollback(transaction).await;
+    }
+    let deleted = delete_files(written_files);
+    if deleted > 0 {
+        info!("Removed {} media files of a failed turn.", deleted);
+    }
+    budget::record_spend(state, spend).await;
+}
+
+async fn rollback(transaction: DatabaseTransaction) {
+    if let Err(e) = transaction.rollback().await {
+        metrics::transaction_failed("rollback");
+        error!(
+            "Rolling back the database transaction failed. Possible data inconsistency: {}",
+            e
+        );
+    }
+}
+
+async fn send_text(writer: &StreamWriter, text: String) -> Result<(), String> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    match writer.format() {
+        StreamFormat::Sse => writer.event("delta", json!({ "text": text })).await,
+        StreamFormat::Multiplexed => writer.event("text", json!(text)).await,
+        StreamFormat::Raw(_) => writer.send(Bytes::from(text)).await,
+    }
+}
+
+async fn stream_speech(
+    state: &Arc<ServiceState>,
+    writer: &StreamWriter,
+    audio_format: AudioFormat,
+    voice: &SpeechVoice,
+    text: &str,
+    is_started: &mut bool,
+    total_voice: &mut Vec<u8>,
+    transcoder: &mut Transcoder,
+) -> Result<(), String> {
+    let requested_at = Instant::now();
+    let stream_result = state
+        .resilience
+        .call(voice.provider(), || {
+            synthesize(
+                &state.config.tts,
+                &state.config.deepgram,
+                voice,
+                text,
+                *is_started,
+            )
+        })
+        .await;
+    metrics::observe_tts(voice.provider(), requested_at.elapsed().as_secs_f64());
+    let mut audio_stream = match stream_result {
+        Ok(audio_stream) => audio_stream,
+        Err(e) => {
+            error!("{}", e);
+            return Ok(());
+        }
+    };
+    *is_started = true;
+    if let Some(target_lufs) = state.config.tts.target_lufs {
+        let mut segment = vec![];
+        while let Some(data) = audio_stream.next().await {
+            segment.extend_from_slice(&data);
+        }
+        let leveled = Bytes::from(normalize_pcm_bytes(
+            &segment,
+            target_lufs,
+            state.config.tts.max_gain_db,
+        ));
+        audio_stream = Box::pin(futures::stream::iter([leveled]));
+    }
+    while let Some(data) = audio_stream.next().await {
+        total_voice.extend_from_slice(&data);
+        let encoded = transcoder.push(&data)?;
+        send_audio(writer, audio_format, encoded).await?;
+    }
+    Ok(())
+}
+
+async fn send_audio(
+    writer: &StreamWriter,
+    audio_format: AudioFormat,
+    data: Bytes,
+) -> Result<(), String> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    match writer.format() {
+        StreamFormat::Raw(_) => writer.send(data).await,
+        _ => {
+            writer
+                .event(
+                    "audio",
+                    json!({
+                        "data": BASE64_STANDARD.encode(&data),
+                        "format": audio_format.name(),
+                    }),
+                )
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::{
+            provider::{InferenceProvider, ModerationResult, TranscriptSegment},
+            registry::ProviderRegistry,
+        },
+        config::ServiceConfig,
+        entity::{collection as collection_entity, conversation as conversation_entity},
+        service::{
+            generation::GenerationRegistry,
+            health::HealthTracker,
+            pipeline::{Biller, HistoryLoader, ProviderCaller, TurnPersister},
+            queue::ChatQueue,
+        },
+        utils::{rate_limit::RateLimiter, retry::Resilience, session::SessionCache},
+    };
+    use futures::stream;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use std::sync::Mutex;
+
+    const MODEL: &str = "gpt-4o-mini";
+
+    struct StubProvider;
+
+    #[async_trait::async_trait]
+    impl InferenceProvider for StubProvider {
+        async fn send_chat_completion(
+            &self,
+            _model_name: String,
+            _conversations: Vec<PromptMessage>,
+            _params: &ChatParams,
+        ) -> Result<reqwest::Response, String> {
+            Err("not used".to_string())
+        }
+
+        async fn speech_to_text(
+            &self,
+            _audio_data: Vec<u8>,
+            _filename: String,
+            _prompt: Option<String>,
+            _language: Option<String>,
+        ) -> Result<String, String> {
+            Err("not used".to_string())
+        }
+
+        async fn speech_to_segments(
+            &self,
+            _audio_data: Vec<u8>,
+            _filename: String,
+            _prompt: Option<String>,
+            _language: Option<String>,
+        ) -> Result<Vec<TranscriptSegment>, String> {
+            Err("not used".to_string())
+        }
+
+        async fn text_to_image(
+            &self,
+            _prompt: &str,
+            _options: &ImageOptions,
+        ) -> Result<Vec<String>, String> {
+            Err("not used".to_string())
+        }
+
+        async fn enhance_image_prompt(
+            &self,
+            _model_name: &str,
+            _prompt: &str,
+        ) -> Result<String, String> {
+            Err("not used".to_string())
+        }
+
+        async fn embed(
+            &self,
+            _model_name: &str,
+            _inputs: Vec<String>,
+        ) -> Result<Vec<Vec<f32>>, String> {
+            Err("not used".to_string())
+        }
+
+        async fn structured_completion(
+            &self,
+            _model_name: &str,
+            _messages: Vec<serde_json::Value>,
+            _schema_name: &str,
+            _schema: &serde_json::Value,
+        ) -> Result<String, String> {
+            Err("not used".to_string())
+        }
+
+        async fn moderate(
+            &self,
+            _model_name: &str,
+            _text: &str,
+            _images: Vec<String>,
+        ) -> Result<ModerationResult, String> {
+            Err("not used".to_string())
+        }
+
+        async fn probe(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    struct StubHistory;
+
+    #[async_trait::async_trait]
+    impl HistoryLoader for StubHistory {
+        async fn conversation(
+            &self,
+            _tx: &DatabaseTransaction,
+            user_id: i64,
+            conversation_id: Uuid,
+        ) -> Result<Option<conversation_entity::Model>, String> {
+            Ok(Some(conversation_entity::Model {
+                id: conversation_id,
+                user_id,
+                conversation: vec![],
+                title: String::new(),
+                title_generated: true,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                retention_days: None,
+                expires_at: None,
+                archived_at: None,
+                pinned_at: None,
+                deleted_at: None,
+                language: Some("en".to_string()),
+                detected_language: None,
+                locked: false,
+                locked_by_admin: false,
+                max_credits: None,
+                credits_spent: 0,
+                media_bytes: 0,
+                system_prompt: None,
+                style_preset: None,
+                voice_profile: None,
+                generation_settings: None,
+            }))
+        }
+
+        async fn custom_instructions(
+            &self,
+            _tx: &DatabaseTransaction,
+            _user_id: i64,
+        ) -> Result<Option<String>, String> {
+            Ok(None)
+        }
+
+        async fn collection(
+            &self,
+            _tx: &DatabaseTransaction,
+            _collection_id: Uuid,
+        ) -> Result<Option<collection_entity::Model>, String> {
+            Ok(None)
+        }
+    }
+
+    // Streams the given SSE chunks, or fails the call when there are none.
+    struct StubCaller(Vec<Result<String, String>>);
+
+    #[async_trait::async_trait]
+    impl ProviderCaller for StubCaller {
+        async fn stream_chat(
+            &self,
+            _model: String,
+            _region: Option<String>,
+            _messages: Vec<PromptMessage>,
+            _params: ChatParams,
+        ) -> Result<ChunkStream, String> {
+            if self.0.is_empty() {
+                return Err("The provider is unavailable".to_string());
+            }
+            let chunks: Vec<Result<Bytes, String>> = self
+                .0
+                .iter()
+                .map(|chunk| chunk.clone().map(Bytes::from))
+                .collect();
+            Ok(Box::pin(stream::iter(chunks)))
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingPersister {
+        fail: bool,
+        answers: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl TurnPersister for RecordingPersister {
+        async fn save_turn(
+            &self,
+            _tx: &DatabaseTransaction,
+            _user_id: i64,
+            _conversation_id: Uuid,
+            turn: Turn,
+        ) -> Result<(), String> {
+            if self.fail {
+                return Err("Failed to save the turn".to_string());
+            }
+            self.answers.lock().unwrap().push(turn.answer);
+            Ok(())
+        }
+
+        async fn save_language(
+            &self,
+            _tx: &DatabaseTransaction,
+            _user_id: i64,
+            _conversation_id: Uuid,
+            _language: Option<String>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn save_voice_profile(
+            &self,
+            _tx: &DatabaseTransaction,
+            _user_id: i64,
+            _conversation_id: Uuid,
+            _voice_profile: Option<String>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingBiller {
+        fail: bool,
+        charges: Mutex<Vec<i64>>,
+        balances: Mutex<Vec<i64>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Biller for RecordingBiller {
+        fn credits_for_usage(&self, _model: &str, _usage: &TokenUsage) -> i64 {
+            3
+        }
+
+        async fn charge(
+            &self,
+            _tx: &DatabaseTransaction,
+            _user_id: i64,
+            _conversation_id: Uuid,
+            _model: String,
+            credits: i64,
+            _usage: &TokenUsage,
+        ) -> Result<(), String> {
+            if self.fail {
+                return Err("Failed to record credit usage".to_string());
+            }
+            self.charges.lock().unwrap().push(credits);
+            Ok(())
+        }
+
+        async fn charge_bot(
+            &self,
+            _tx: &DatabaseTransaction,
+            _bot_id: Uuid,
+            _credits: i64,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn report_balance(
+            &self,
+            _user_id: i64,
+            credits_remaining: i64,
+        ) -> Result<(), String> {
+            self.balances.lock().unwrap().push(credits_remaining);
+            Ok(())
+        }
+    }
+
+    fn content_chunk(content: &str) -> Result<String, String> {
+        Ok(format!(
+            "data: {}\n\n",
+            json!({
+                "id": "chunk",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": MODEL,
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }],
+            })
+        ))
+    }
+
+    fn reply_chunks() -> Vec<Result<String, String>> {
+        vec![content_chunk("Hello"), content_chunk(" there")]
+    }
+
+    struct Harness {
+        state: Arc<ServiceState>,
+        persister: Arc<RecordingPersister>,
+        biller: Arc<RecordingBiller>,
+        conversation_id: Uuid,
+        // Unique per test, so each test has its own image file.
+        image: Vec<u8>,
+    }
+
+    impl Harness {
+        fn image_filename(&self) -> String {
+            content_filename(
+                "images",
+                &self.conversation_id.to_string(),
+                &self.image,
+                Some("png"),
+            )
+        }
+
+        // Sends "Hi" with the image over SSE and returns the streamed body.
+        async fn send_message(&self) -> AppResult<String> {
+            let response = handle_user_message(
+                self.state.clone(),
+                7,
+                Some(SessionData {
+                    credits_remaining: 100,
+                    ..Default::default()
+                }),
+                self.conversation_id,
+                "text".to_string(),
+                b"Hi".to_vec(),
+                MODEL.to_string(),
+                vec![Bytes::from(self.image.clone())],
+                -1,
+                None,
+                vec![Some("photo.png".to_string())],
+                MessageOptions {
+                    sse: true,
+                    ..Default::default()
+                },
+            )
+            .await?
+            .into_response();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            Ok(String::from_utf8_lossy(&body).to_string())
+        }
+    }
+
+    // Media is written under `./public`, so every test removes its image again.
+    impl Drop for Harness {
+        fn drop(&mut self) {
+            delete_files(&[self.image_filename()]);
+        }
+    }
+
+    fn harness(
+        chunks: Vec<Result<String, String>>,
+        persister: RecordingPersister,
+        biller: RecordingBiller,
+    ) -> Harness {
+        let config = ServiceConfig::default();
+        let provider: Arc<dyn InferenceProvider> = Arc::new(StubProvider);
+        let resilience = Arc::new(Resilience::new(&config.retry));
+        let registry =
+            ProviderRegistry::build_from_config(&config, provider.clone(), resilience.clone())
+                .unwrap();
+        let persister = Arc::new(persister);
+        let biller = Arc::new(biller);
+        let pipeline = ChatPipeline {
+            history: Arc::new(StubHistory),
+            caller: Arc::new(StubCaller(chunks)),
+            persister: persister.clone(),
+            biller: biller.clone(),
+        };
+        let state = Arc::new(ServiceState {
+            db: Arc::new(MockDatabase::new(DatabaseBackend::Postgres).into_connection()),
+            provider,
+            registry: Arc::new(registry),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            spend: Arc::new(budget::SpendTracker::default()),
+            generations: Arc::new(GenerationRegistry::default()),
+            chat_queue: Arc::new(ChatQueue::new(&config.queue)),
+            latency: Arc::new(latency::LatencyTracker::default()),
+            pipeline: Arc::new(pipeline),
+            event_bus: None,
+            sessions: Arc::new(SessionCache::new(&config.session)),
+            resilience,
+            health: Arc::new(HealthTracker::default()),
+            config: Arc::new(config),
+        });
+        Harness {
+            state,
+            persister,
+            biller,
+            conversation_id: Uuid::new_v4(),
+            image: Uuid::new_v4().as_bytes().to_vec(),
+        }
+    }
+
+    struct Case {
+        name: &'static str,
+        chunks: Vec<Result<String, String>>,
+        fail_save: bool,
+        fail_charge: bool,
+        // Parts of the streamed body, or `None` when the request itself fails.
+        body: Option<&'static [&'static str]>,
+        answers: &'static [&'static str],
+        charges: &'static [i64],
+        balances: &'static [i64],
+        keeps_image: bool,
+        // Whether the request reached the provider, whose spend is recorded either way.
+        spends: bool,
+    }
+
+    // A turn that fails after reaching the provider keeps nothing and charges
+    // nothing, but its provider spend is still recorded.
+    #[tokio::test]
+    async fn a_turn_is_saved_and_billed_or_leaves_nothing_behind() {
+        let cases = vec![
+            Case {
+                name: "success",
+                chunks: reply_chunks(),
+                fail_save: false,
+                fail_charge: false,
+                body: Some(&["event: done"]),
+                answers: &["Hello there"],
+                charges: &[3],
+                balances: &[97],
+                keeps_image: true,
+                spends: true,
+            },
+            Case {
+                name: "failed provider call",
+                chunks: vec![],
+                fail_save: false,
+                fail_charge: false,
+                body: None,
+                answers: &[],
+                charges: &[],
+                balances: &[],
+                keeps_image: false,
+                spends: false,
+            },
+            Case {
+                name: "stream error",
+                chunks: vec![content_chunk("Hello"), Err("connection reset".to_string())],
+                fail_save: false,
+                fail_charge: false,
+                body: Some(&["event: error", "connection reset"]),
+                answers: &[],
+                charges: &[],
+                balances: &[],
+                keeps_image: false,
+                spends: true,
+            },
+            Case {
+                name: "failed save",
+                chunks: reply_chunks(),
+                fail_save: true,
+                fail_charge: false,
+                body: Some(&["Failed to save the turn"]),
+                answers: &[],
+                charges: &[],
+                balances: &[],
+                keeps_image: false,
+                spends: true,
+            },
+            Case {
+                name: "failed charge",
+                chunks: reply_chunks(),
+                fail_save: false,
+                fail_charge: true,
+                body: Some(&["Failed to record credit usage"]),
+                answers: &["Hello there"],
+                charges: &[],
+                balances: &[],
+                keeps_image: false,
+                spends: true,
+            },
+        ];
+
+        for case in cases {
+            let harness = harness(
+                case.chunks,
+                RecordingPersister {
+                    fail: case.fail_save,
+                    ..Default::default()
+                },
+                RecordingBiller {
+                    fail: case.fail_charge,
+                    ..Default::default()
+                },
+            );
+
+            match (harness.send_message().await, case.body) {
+                (Ok(body), Some(parts)) => {
+                    for part in parts {
+                        assert!(
+                            body.contains(part),
+                            "{}: {:?} not in {}",
+                            case.name,
+                            part,
+                            body
+                        );
+                    }
+                }
+                (Err(error), None) => assert!(error.status.is_server_error(), "{}", case.name),
+                (result, _) => panic!("{}: unexpected result {:?}", case.name, result.map(|_| ())),
+            }
+            assert_eq!(
+                *harness.persister.answers.lock().unwrap(),
+                case.answers,
+                "{}",
+                case.name
+            );
+            assert_eq!(
+                *harness.biller.charges.lock().unwrap(),
+                case.charges,
+                "{}",
+                case.name
+            );
+            assert_eq!(
+                *harness.biller.balances.lock().unwrap(),
+                case.balances,
+                "{}",
+                case.name
+            );
+            assert_eq!(
+                file_exists(&harness.image_filename()),
+                case.keeps_image,
+                "{}",
+                case.name
+            );
+            assert_eq!(
+                harness.state.spend.spent_today() > 0.0,
+                case.spends,
+                "{}",
+                case.name
+            );
+        }
+    }
 }
@@ -0,0 +1,168 @@
+use crate::{
+    entity::conversation::{self, Message, MessageType},
+    utils::schema,
+    ServiceState,
+};
+use once_cell::sync::Lazy;
+use rs_openai::chat::Role;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::warn;
+
+pub const EXTRACTION_INSTRUCTION: &str = "Extract the requested information from the user's \
+content. Reply with JSON that matches the provided schema and use null or empty values for \
+anything the content does not mention. Do not invent facts.";
+
+pub const ACTION_ITEMS_INSTRUCTION: &str = "Read the conversation transcript and list the \
+concrete action items and decisions it contains. Name the owner and due date of an action item \
+only when the transcript states them, otherwise use null.";
+
+pub static ACTION_ITEMS_SCHEMA: Lazy<Value> = Lazy::new(|| {
+    json!({
+        "type": "object",
+        "properties": {
+            "action_items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "task": { "type": "string" },
+                        "owner": { "type": ["string", "null"] },
+                        "due_date": { "type": ["string", "null"] },
+                    },
+                    "required": ["task", "owner", "due_date"],
+                    "additionalProperties": false,
+                },
+            },
+            "decisions": {
+                "type": "array",
+                "items": { "type": "string" },
+            },
+        },
+        "required": ["action_items", "decisions"],
+        "additionalProperties": false,
+    })
+});
+
+const SUGGESTION_COUNT: usize = 3;
+const SUGGESTION_HISTORY: usize = 10;
+
+pub const SUGGESTIONS_INSTRUCTION: &str = "Read the end of the conversation transcript and \
+suggest exactly three short follow-up questions the user is likely to ask next. Write them in the \
+user's language and from the user's point of view.";
+
+pub static SUGGESTIONS_SCHEMA: Lazy<Value> = Lazy::new(|| {
+    json!({
+        "type": "object",
+        "properties": {
+            "suggestions": {
+                "type": "array",
+                "items": { "type": "string" },
+            },
+        },
+        "required": ["suggestions"],
+        "additionalProperties": false,
+    })
+});
+
+pub async fn suggest_follow_ups(
+    state: &Arc<ServiceState>,
+    transcript: String,
+) -> Result<Vec<String>, String> {
+    let data = extract_structured(
+        state,
+        &state.config.extraction.model,
+        SUGGESTIONS_INSTRUCTION,
+        vec![json!({ "type": "text", "text": transcript })],
+        "suggestions",
+        &SUGGESTIONS_SCHEMA,
+    )
+    .await?;
+    Ok(data["suggestions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .take(SUGGESTION_COUNT)
+        .collect())
+}
+
+pub fn conversation_transcript(model: &conversation::Model) -> Result<String, serde_json::Error> {
+    transcript_of(&model.conversation)
+}
+
+pub fn recent_transcript(model: &conversation::Model) -> Result<String, serde_json::Error> {
+    let start = model.conversation.len().saturating_sub(SUGGESTION_HISTORY);
+    transcript_of(&model.conversation[start..])
+}
+
+fn transcript_of(messages: &[Value]) -> Result<String, serde_json::Error> {
+    let lines = messages
+        .iter()
+        .map(|value| Message::from_stored(value.clone()))
+        .filter(|message| !message.as_ref().is_ok_and(|message| message.excluded))
+        .map(|message| {
+            let message = message?;
+            let speaker = if matches!(message.role, Role::User) {
+                "User"
+            } else {
+                "Assistant"
+            };
+            let text = match message.msgtype {
+                MessageType::Voice => message.transcription.unwrap_or_default(),
+                _ => message.content,
+            };
+            Ok(format!("{}: {}", speaker, text.trim()))
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()?;
+    Ok(lines.join("\n\n"))
+}
+
+pub async fn extract_structured(
+    state: &Arc<ServiceState>,
+    model_name: &str,
+    instruction: &str,
+    content: Vec<Value>,
+    schema_name: &str,
+    schema: &Value,
+) -> Result<Value, String> {
+    let max_attempts = state.config.extraction.max_attempts;
+    let mut messages = vec![
+        json!({ "role": "system", "content": instruction }),
+        json!({ "role": "user", "content": content }),
+    ];
+    let mut last_error = String::new();
+    for attempt in 1..=max_attempts {
+        let reply = state
+            .provider
+            .structured_completion(model_name, messages.clone(), schema_name, schema)
+            .await?;
+        let parsed = serde_json::from_str::<Value>(&reply)
+            .map_err(|e| e.to_string())
+            .and_then(|value| schema::validate(&value, schema).map(|_| value));
+        match parsed {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                warn!(
+                    "Structured extraction attempt {} of {} returned invalid JSON: {}",
+                    attempt, max_attempts, e
+                );
+                messages.push(json!({ "role": "assistant", "content": reply }));
+                messages.push(json!({
+                    "role": "user",
+                    "content": format!(
+                        "That reply was not valid for the schema: {}. Reply again with JSON that matches the schema.",
+                        e
+                    ),
+                }));
+                last_error = e;
+            }
+        }
+    }
+    Err(format!(
+        "No valid structured output after {} attempts: {}",
+        max_attempts, last_error
+    ))
+}
@@ -0,0 +1,80 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+struct ActiveGeneration {
+    id: u64,
+    user_id: i64,
+    cancel: Arc<Notify>,
+}
+
+// Tracks the reply currently streaming in each conversation so that it can be cancelled.
+#[derive(Default)]
+pub struct GenerationRegistry {
+    active: Mutex<HashMap<Uuid, ActiveGeneration>>,
+    next_id: AtomicU64,
+}
+
+pub struct GenerationGuard {
+    registry: Arc<GenerationRegistry>,
+    conversation_id: Uuid,
+    id: u64,
+    pub cancel: Arc<Notify>,
+}
+
+impl Drop for GenerationGuard {
+    fn drop(&mut self) {
+        let mut active = self
+            .registry
+            .active
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if active
+            .get(&self.conversation_id)
+            .is_some_and(|generation| generation.id == self.id)
+        {
+            active.remove(&self.conversation_id);
+        }
+    }
+}
+
+impl GenerationRegistry {
+    pub fn register(self: &Arc<Self>, conversation_id: Uuid, user_id: i64) -> GenerationGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = Arc::new(Notify::new());
+        self.active
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                conversation_id,
+                ActiveGeneration {
+                    id,
+                    user_id,
+                    cancel: cancel.clone(),
+                },
+            );
+        GenerationGuard {
+            registry: self.clone(),
+            conversation_id,
+            id,
+            cancel,
+        }
+    }
+
+    pub fn cancel(&self, conversation_id: Uuid, user_id: i64) -> bool {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        match active.get(&conversation_id) {
+            Some(generation) if generation.user_id == user_id => {
+                generation.cancel.notify_one();
+                true
+            }
+            _ => false,
+        }
+    }
+}
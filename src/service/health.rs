@@ -0,0 +1,123 @@
+use crate::{
+    utils::{deepgram, elevenlabs, metrics},
+    ServiceState,
+};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub healthy: bool,
+    pub latency_ms: u64,
+    pub checked_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+// Latest probe result per provider, plus whether the startup round has finished.
+#[derive(Default)]
+pub struct HealthTracker {
+    providers: RwLock<BTreeMap<String, ProviderHealth>>,
+    warmed_up: AtomicBool,
+}
+
+impl HealthTracker {
+    pub fn snapshot(&self) -> BTreeMap<String, ProviderHealth> {
+        self.providers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn is_warmed_up(&self) -> bool {
+        self.warmed_up.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, provider: String, health: ProviderHealth) {
+        metrics::set_provider_up(&provider, health.healthy);
+        self.providers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(provider, health);
+    }
+}
+
+/// Probes every provider once at startup, then on the configured interval.
+pub fn spawn_health_probe(state: Arc<ServiceState>) {
+    let interval_secs = state.config.health.probe_interval_secs;
+    tokio::spawn(async move {
+        probe_providers(&state).await;
+        state.health.warmed_up.store(true, Ordering::Relaxed);
+        info!("Provider warmup probe finished.");
+        if interval_secs == 0 {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            probe_providers(&state).await;
+        }
+    });
+}
+
+type Probe<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+pub async fn probe_providers(state: &Arc<ServiceState>) {
+    let mut probes: Vec<(String, Probe)> = state
+        .registry
+        .providers()
+        .iter()
+        .map(|(name, provider)| (name.clone(), provider.probe()))
+        .collect();
+    let deepgram_key = state.config.deepgram.deepgram_key.as_str();
+    if !deepgram_key.is_empty() {
+        probes.push((
+            "deepgram".to_string(),
+            Box::pin(deepgram::probe(deepgram_key)),
+        ));
+    }
+    if let Some(api_key) = state.config.tts.elevenlabs_key.as_deref() {
+        probes.push((
+            "elevenlabs".to_string(),
+            Box::pin(elevenlabs::probe(api_key)),
+        ));
+    }
+
+    let timeout = Duration::from_secs(state.config.health.probe_timeout_secs);
+    let results = join_all(probes.into_iter().map(|(name, probe)| async move {
+        let started_at = Instant::now();
+        let result = match tokio::time::timeout(timeout, probe).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("No answer within {} seconds", timeout.as_secs())),
+        };
+        (name, started_at.elapsed(), result)
+    }))
+    .await;
+
+    for (name, elapsed, result) in results {
+        if let Err(e) = &result {
+            warn!("Health probe of the provider '{}' failed: {}", name, e);
+        }
+        state.health.record(
+            name,
+            ProviderHealth {
+                healthy: result.is_ok(),
+                latency_ms: elapsed.as_millis() as u64,
+                checked_at: Utc::now(),
+                error: result.err(),
+            },
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{
+    client::provider::ImageOptions,
+    config::constant::{IMAGE_GENERATION_CREDITS, IMAGE_USD},
+    service::budget,
+    utils::{
+        error::{format_error, AppError, AppResult, ErrorCode},
+        retry::upstream_status,
+    },
+    ServiceState,
+};
+use axum::http::StatusCode;
+use hyper::body::Bytes;
+use reqwest::Client;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tracing::{error, info};
+
+pub struct GeneratedImages {
+    pub images: Vec<Bytes>,
+    /// Whether the images were served from the cache instead of the provider.
+    pub cached: bool,
+}
+
+impl GeneratedImages {
+    pub fn credits(&self, state: &ServiceState, options: &ImageOptions) -> i64 {
+        if self.cached {
+            state.config.image.cache_hit_credits * self.images.len() as i64
+        } else {
+            image_credits(options)
+        }
+    }
+}
+
+/// Credits for a generation, priced against the standard square DALL·E 3 image.
+pub fn image_credits(options: &ImageOptions) -> i64 {
+    let per_image =
+        (IMAGE_GENERATION_CREDITS as f64 * options.usd_per_image() / IMAGE_USD).ceil() as i64;
+    per_image * options.count as i64
+}
+
+/// Generates the images for the prompt and downloads them from the provider.
+/// Identical requests within the cache TTL get the stored images instead.
+pub async fn generate_image(
+    state: &Arc<ServiceState>,
+    prompt: &str,
+    options: &ImageOptions,
+) -> AppResult<GeneratedImages> {
+    let cache_paths = cache_paths(state, prompt, options);
+    if let Some(paths) = cache_paths.as_deref() {
+        if let Some(images) = read_cached(paths, state.config.image.cache_ttl_secs).await {
+            info!("Serving cached images for the prompt '{}'.", prompt);
+            return Ok(GeneratedImages {
+                images,
+                cached: true,
+            });
+        }
+    }
+
+    let images = download_images(state, prompt, options).await?;
+    for (path, bytes) in cache_paths.iter().flatten().zip(&images) {
+        if let Err(e) = write_cached(path, bytes).await {
+            error!("Failed to cache the generated image '{}': {}", path, e);
+        }
+    }
+    Ok(GeneratedImages {
+        images,
+        cached: false,
+    })
+}
+
+async fn download_images(
+    state: &Arc<ServiceState>,
+    prompt: &str,
+    options: &ImageOptions,
+) -> AppResult<Vec<Bytes>> {
+    let urls = state
+        .provider
+        .text_to_image(prompt, options)
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            (upstream_status(&e), e)
+        })?;
+
+    budget::record_spend(state, options.usd_per_image() * urls.len() as f64).await;
+
+    let client = Client::new();
+    let mut images = Vec::with_capacity(urls.len());
+    for url in urls {
+        images.push(download_image(&client, url).await?);
+    }
+    Ok(images)
+}
+
+async fn download_image(client: &Client, url: String) -> AppResult<Bytes> {
+    let res = client.get(url).send().await.map_err(|e| {
+        format_error(
+            "Failed to get image data from the url",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    if !res.status().is_success() {
+        return Err(AppError::new(
+            StatusCode::BAD_GATEWAY,
+            ErrorCode::UpstreamUnavailable,
+            "Failed to access to the generated image",
+        ));
+    }
+    res.bytes().await.map_err(|e| {
+        format_error(
+            "Failed to get bytes of the image",
+            e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })
+}
+
+// Images are keyed by everything sent to the provider, so cached images are
+// ones the provider could have returned for this exact request.
+fn cache_paths(state: &ServiceState, prompt: &str, options: &ImageOptions) -> Option<Vec<String>> {
+    if state.config.image.cache_ttl_secs == 0 {
+        return None;
+    }
+    let key = json!({
+        "model": options.model.name(),
+        "prompt": prompt.trim(),
+        "size": options.size(),
+        "quality": format!("{:?}", options.quality),
+        "style": options.style.map(|style| format!("{:?}", style)),
+        "n": options.count,
+        "negative_prompt": options
+            .negative_prompt
+            .as_deref()
+            .map(str::trim)
+            .filter(|negative| !negative.is_empty()),
+    });
+    let hash = hex::encode(Sha256::digest(key.to_string().as_bytes()));
+    let paths = (0..options.count)
+        .map(|index| format!("{}/{}-{}.png", state.config.image.cache_dir, hash, index))
+        .collect();
+    Some(paths)
+}
+
+// A set with any image missing or expired is generated again as a whole.
+async fn read_cached(paths: &[String], ttl_secs: u64) -> Option<Vec<Bytes>> {
+    let mut images = Vec::with_capacity(paths.len());
+    for path in paths {
+        images.push(read_cached_image(path, ttl_secs).await?);
+    }
+    Some(images)
+}
+
+async fn read_cached_image(path: &str, ttl_secs: u64) -> Option<Bytes> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    let age = SystemTime::now()
+        .duration_since(metadata.modified().ok()?)
+        .unwrap_or_default();
+    if age > Duration::from_secs(ttl_secs) {
+        return None;
+    }
+    tokio::fs::read(path).await.ok().map(Bytes::from)
+}
+
+async fn write_cached(path: &str, bytes: &Bytes) -> std::io::Result<()> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    // Written aside and renamed, so concurrent readers never see a partial image.
+    let partial = format!("{}.partial", path);
+    tokio::fs::write(&partial, bytes).await?;
+    tokio::fs::rename(&partial, path).await
+}
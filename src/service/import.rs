@@ -0,0 +1,147 @@
+use crate::entity::conversation::{Message, MessageType, MESSAGE_SCHEMA_VERSION};
+use rs_openai::chat::Role;
+use serde::Deserialize;
+use serde_json::Value;
+
+pub const MAX_IMPORTED_MESSAGES: usize = 1000;
+
+pub struct ImportedConversation {
+    pub messages: Vec<Message>,
+    pub system_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiMessage {
+    role: String,
+    content: Value,
+}
+
+/// Validates an imported history and turns it into stored messages. Each
+/// entry is either a message as returned by `GET /api/chat/conversation/:id`
+/// or an OpenAI-style `{ "role", "content" }` object; system messages of the
+/// latter become the conversation system prompt.
+pub fn parse_messages(values: Vec<Value>) -> Result<ImportedConversation, String> {
+    if values.is_empty() {
+        return Err("The conversation has no messages".to_string());
+    }
+    if values.len() > MAX_IMPORTED_MESSAGES {
+        return Err(format!(
+            "The conversation has too many messages. Maximum: {}",
+            MAX_IMPORTED_MESSAGES
+        ));
+    }
+
+    let mut messages = vec![];
+    let mut system_prompts = vec![];
+    for (index, value) in values.into_iter().enumerate() {
+        let message = if is_exported(&value) {
+            Message::from_stored(value)
+                .map_err(|e| format!("Message {} is invalid: {}", index, e))?
+        } else {
+            let message: OpenAiMessage = serde_json::from_value(value)
+                .map_err(|e| format!("Message {} is invalid: {}", index, e))?;
+            let content = text_content(&message.content)
+                .ok_or_else(|| format!("Message {} has no text content", index))?;
+            let role = match message.role.as_str() {
+                "system" | "developer" => {
+                    system_prompts.push(content);
+                    continue;
+                }
+                "user" => Role::User,
+                "assistant" => Role::Assistant,
+                other => {
+                    return Err(format!(
+                        "Message {} has unsupported role '{}'",
+                        index, other
+                    ))
+                }
+            };
+            Message {
+                schema_version: MESSAGE_SCHEMA_VERSION,
+                msgtype: MessageType::Text,
+                id: 0,
+                role,
+                content,
+                transcription: None,
+                images: vec![],
+                reply_mode: None,
+                citations: None,
+                audio: None,
+                waveform: None,
+                model: None,
+                truncated: false,
+                reply_to: None,
+                excluded: false,
+                tool_calls: None,
+                tool_call_id: None,
+                moderation_flags: None,
+            }
+        };
+        messages.push(message);
+    }
+
+    if messages.len() % 2 != 0 {
+        return Err("Every user message must be followed by an assistant reply".to_string());
+    }
+    for (index, message) in messages.iter_mut().enumerate() {
+        let (in_order, expected) = match message.role {
+            Role::User => (index % 2 == 0, "assistant"),
+            Role::Assistant => (index % 2 == 1, "user"),
+            _ => (false, if index % 2 == 0 { "user" } else { "assistant" }),
+        };
+        if !in_order {
+            return Err(format!(
+                "Message {} should have the role '{}'",
+                index, expected
+            ));
+        }
+        strip_media(message);
+        if message.content.trim().is_empty() {
+            return Err(format!("Message {} has no text content", index));
+        }
+        // Both messages of a turn share the id of the user message.
+        message.id = index / 2 * 2 + 1;
+        message.schema_version = MESSAGE_SCHEMA_VERSION;
+    }
+
+    let system_prompt = Some(system_prompts.join("\n\n")).filter(|prompt| !prompt.is_empty());
+    Ok(ImportedConversation {
+        messages,
+        system_prompt,
+    })
+}
+
+fn is_exported(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|object| object.contains_key("id") && object.contains_key("type"))
+}
+
+fn text_content(content: &Value) -> Option<String> {
+    let text = match content {
+        Value::String(text) => text.clone(),
+        // Content parts: only the text parts are kept.
+        Value::Array(parts) => parts
+            .iter()
+            .filter(|part| part["type"] == "text")
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return None,
+    };
+    Some(text).filter(|text| !text.trim().is_empty())
+}
+
+// Media and citations point at files and documents owned by the exporting
+// account. Keeping them would let the import read, or later delete, files it
+// does not own, so only the text survives.
+fn strip_media(message: &mut Message) {
+    if message.msgtype == MessageType::Voice {
+        message.content = message.transcription.take().unwrap_or_default();
+    }
+    message.msgtype = MessageType::Text;
+    message.images = vec![];
+    message.audio = None;
+    message.waveform = None;
+    message.citations = None;
+}
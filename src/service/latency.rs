@@ -0,0 +1,98 @@
+use crate::{service::outbox, ServiceState};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+}
+
+#[derive(Default)]
+struct ModelLatency {
+    samples: VecDeque<(Instant, u64)>,
+    last_alert: Option<Instant>,
+}
+
+impl ModelLatency {
+    fn stats(&self) -> LatencyStats {
+        let mut values: Vec<u64> = self.samples.iter().map(|(_, ms)| *ms).collect();
+        values.sort_unstable();
+        let percentile = |p: f64| {
+            let rank = ((values.len() as f64 * p).ceil() as usize).max(1);
+            values.get(rank - 1).copied().unwrap_or_default()
+        };
+        LatencyStats {
+            samples: values.len(),
+            p50_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+        }
+    }
+}
+
+// Rolling time-to-first-token samples per model.
+#[derive(Default)]
+pub struct LatencyTracker {
+    models: Mutex<HashMap<String, ModelLatency>>,
+}
+
+impl LatencyTracker {
+    // Adds a sample and returns the model's stats over the window, plus whether an alert is due.
+    fn record(&self, model: &str, ms: u64, state: &ServiceState) -> (LatencyStats, bool) {
+        let config = &state.config.slo;
+        let now = Instant::now();
+        let window = Duration::from_secs(config.window_secs);
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let latency = models.entry(model.to_string()).or_default();
+        latency.samples.push_back((now, ms));
+        while latency
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > window)
+        {
+            latency.samples.pop_front();
+        }
+
+        let stats = latency.stats();
+        let breached = config
+            .first_token_p95_ms
+            .is_some_and(|slo| stats.samples >= config.min_samples && stats.p95_ms > slo);
+        let cooled_down = latency.last_alert.map_or(true, |at| {
+            now.duration_since(at) >= Duration::from_secs(config.alert_cooldown_secs)
+        });
+        let alert = breached && cooled_down;
+        if alert {
+            latency.last_alert = Some(now);
+        }
+        (stats, alert)
+    }
+}
+
+pub fn record_first_token(state: &Arc<ServiceState>, model: &str, elapsed: Duration) {
+    let ms = elapsed.as_millis() as u64;
+    let (stats, alert) = state.latency.record(model, ms, state);
+    info!(
+        "First token from '{}' after {} ms (p50 {} ms, p95 {} ms over {} requests).",
+        model, ms, stats.p50_ms, stats.p95_ms, stats.samples
+    );
+    if !alert {
+        return;
+    }
+
+    let text = format!(
+        "Time to first token for '{}' is over the SLO: p95 {} ms (p50 {} ms) across {} requests in the last {} seconds, target {} ms.",
+        model,
+        stats.p95_ms,
+        stats.p50_ms,
+        stats.samples,
+        state.config.slo.window_secs,
+        state.config.slo.first_token_p95_ms.unwrap_or_default()
+    );
+    let state = state.clone();
+    tokio::spawn(async move { outbox::alert(&state, text).await });
+}
@@ -1 +1,22 @@
+pub mod bot;
+pub mod budget;
 pub mod chat;
+pub mod extraction;
+pub mod generation;
+pub mod health;
+pub mod image;
+pub mod import;
+pub mod latency;
+pub mod moderation;
+pub mod outbox;
+pub mod pipeline;
+pub mod quarantine;
+pub mod queue;
+pub mod redaction;
+pub mod retention;
+pub mod retrieval;
+pub mod search;
+pub mod title;
+pub mod tools;
+pub mod transcription;
+pub mod upload;
@@ -0,0 +1,70 @@
+use crate::{
+    config::moderation::ModerationMode,
+    utils::error::{format_error, AppError, AppResult, ErrorCode},
+    ServiceState,
+};
+use axum::http::StatusCode;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hyper::body::Bytes;
+use serde_json::json;
+use tracing::warn;
+
+/// Screens a user message as `MODERATION_MODE` asks. In log mode the
+/// categories a flagged message was flagged for are returned to be stored
+/// with it; in block mode it is refused with a `422` carrying the scores.
+pub async fn screen(
+    state: &ServiceState,
+    text: &str,
+    images: &[Bytes],
+) -> AppResult<Option<Vec<String>>> {
+    let mode = state.config.moderation.mode;
+    if mode == ModerationMode::Off {
+        return Ok(None);
+    }
+    let images = images.iter().map(|bytes| data_url(bytes)).collect();
+    let result = match state
+        .provider
+        .moderate(&state.config.moderation.model, text, images)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) if mode == ModerationMode::Log => {
+            warn!("Moderation failed, sending the message unscreened: {}", e);
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(format_error(
+                "The message could not be screened",
+                e,
+                StatusCode::BAD_GATEWAY,
+            ))
+        }
+    };
+    if !result.flagged {
+        return Ok(None);
+    }
+    if mode == ModerationMode::Block {
+        warn!("Refused a message flagged for {:?}.", result.categories);
+        return Err(AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::ContentFlagged,
+            format!(
+                "The message was flagged by moderation: {}",
+                result.categories.join(", ")
+            ),
+        )
+        .with_details(json!({
+            "categories": result.categories,
+            "category_scores": result.category_scores,
+        })));
+    }
+    warn!("Sending a message flagged for {:?}.", result.categories);
+    Ok(Some(result.categories))
+}
+
+fn data_url(bytes: &[u8]) -> String {
+    let mime = image::guess_format(bytes)
+        .map(|format| format.to_mime_type())
+        .unwrap_or("image/jpeg");
+    format!("data:{};base64,{}", mime, BASE64_STANDARD.encode(bytes))
+}
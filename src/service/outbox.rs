@@ -0,0 +1,269 @@
+use crate::{
+    entity::outbox,
+    repositories::outbox as outbox_repository,
+    utils::signature::{sign, SIGNATURE_HEADER},
+    ServiceState,
+};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use sea_orm::{DatabaseTransaction, TransactionTrait};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub const MESSAGE_CREATED: &str = "message.created";
+pub const ACTION_ITEMS_EXTRACTED: &str = "action_items.extracted";
+pub const ALERT: &str = "alert";
+pub const BOT_REPLY: &str = "bot.reply";
+
+// Domain events published to the event bus for analytics.
+pub const MESSAGE_SENT: &str = "message_sent";
+pub const GENERATION_COMPLETED: &str = "generation_completed";
+pub const IMAGE_GENERATED: &str = "image_generated";
+pub const CREDITS_CHARGED: &str = "credits_charged";
+
+// Destinations starting with this go to the event bus subject that follows instead of a webhook.
+const BUS_DESTINATION_PREFIX: &str = "bus:";
+
+// Receivers may see an event more than once and should deduplicate on this id.
+pub const EVENT_ID_HEADER: &str = "X-Event-Id";
+pub const EVENT_TOPIC_HEADER: &str = "X-Event-Topic";
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY_SECS: u64 = 3600;
+
+// One client for every delivery, so connections to a receiver are reused.
+static WEBHOOK_CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_else(|e| {
+            error!(
+                "Failed to build the webhook client, using the defaults: {}",
+                e
+            );
+            Client::new()
+        })
+});
+
+pub fn spawn_outbox_dispatcher(state: Arc<ServiceState>) {
+    let interval_secs = state.config.outbox.dispatch_interval_secs;
+    if interval_secs == 0 {
+        info!("Outbox dispatcher is disabled.");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = dispatch_due(&state).await {
+                error!("Outbox dispatch failed: {}", e);
+            }
+        }
+    });
+}
+
+pub async fn dispatch_due(state: &Arc<ServiceState>) -> Result<u64, String> {
+    let config = &state.config.outbox;
+    let mut delivered = 0;
+    loop {
+        let transaction = state
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Starting a database transaction failed: {}", e))?;
+
+        let due = outbox_repository::find_due(
+            &transaction,
+            Utc::now(),
+            config.max_attempts,
+            config.batch_size,
+        )
+        .await?;
+        if due.is_empty() {
+            let _ = transaction.rollback().await;
+            break;
+        }
+        let batch = due.len() as u64;
+        for event in due {
+            if attempt(state, &transaction, event).await? {
+                delivered += 1;
+            }
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Committing the database transaction failed: {}", e))?;
+        if batch < config.batch_size {
+            break;
+        }
+    }
+
+    if delivered > 0 {
+        info!("Outbox dispatcher delivered {} events.", delivered);
+    }
+    Ok(delivered)
+}
+
+/// Tries to deliver an event right after the transaction that created it has
+/// committed. Failures are left to the dispatcher to retry.
+pub async fn deliver_now(state: &Arc<ServiceState>, event_id: Uuid) -> bool {
+    let result = async {
+        let transaction = state
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Starting a database transaction failed: {}", e))?;
+        // Already delivered or being delivered by the dispatcher.
+        let Some(event) = outbox_repository::find_pending_by_id(&transaction, event_id).await?
+        else {
+            let _ = transaction.rollback().await;
+            return Ok(false);
+        };
+        let delivered = attempt(state, &transaction, event).await?;
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Committing the database transaction failed: {}", e))?;
+        Ok::<bool, String>(delivered)
+    }
+    .await;
+    result.unwrap_or_else(|e| {
+        error!("Failed to deliver outbox event '{}': {}", event_id, e);
+        false
+    })
+}
+
+/// Stores an event outside of any other transaction and tries to deliver it
+/// immediately.
+pub async fn publish(
+    state: &Arc<ServiceState>,
+    topic: &str,
+    destination: String,
+    payload: Value,
+) -> bool {
+    let event = async {
+        let transaction = state
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Starting a database transaction failed: {}", e))?;
+        let event = outbox_repository::enqueue(&transaction, topic, destination, payload).await?;
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Committing the database transaction failed: {}", e))?;
+        Ok::<outbox::Model, String>(event)
+    }
+    .await;
+    match event {
+        Ok(event) => deliver_now(state, event.id).await,
+        Err(e) => {
+            error!("Failed to store the '{}' outbox event: {}", topic, e);
+            false
+        }
+    }
+}
+
+/// Queues domain events in the caller's transaction, so they are published
+/// if and only if the change they describe commits. Returns the ids to hand
+/// to `deliver_now`, or nothing when no event bus is configured.
+pub async fn enqueue_domain_events(
+    state: &ServiceState,
+    tx: &DatabaseTransaction,
+    events: Vec<(&str, Value)>,
+) -> Result<Vec<Uuid>, String> {
+    if state.event_bus.is_none() {
+        return Ok(vec![]);
+    }
+    let mut ids = vec![];
+    for (event, data) in events {
+        let destination = format!(
+            "{}{}",
+            BUS_DESTINATION_PREFIX,
+            state.config.events.subject(event)
+        );
+        let payload = json!({
+            "type": event,
+            "occurred_at": Utc::now(),
+            "data": data,
+        });
+        let stored = outbox_repository::enqueue(tx, event, destination, payload).await?;
+        ids.push(stored.id);
+    }
+    Ok(ids)
+}
+
+// Sends an operator alert to the configured webhook. The payload uses a `text` field so chat webhooks accept it as is.
+pub async fn alert(state: &Arc<ServiceState>, text: String) {
+    warn!(target: "alert", "{}", text);
+    let Some(webhook_url) = state.config.budget.alert_webhook.clone() else {
+        return;
+    };
+    publish(state, ALERT, webhook_url, json!({ "text": text })).await;
+}
+
+async fn attempt(
+    state: &Arc<ServiceState>,
+    tx: &DatabaseTransaction,
+    event: outbox::Model,
+) -> Result<bool, String> {
+    match deliver(state, &event).await {
+        Ok(()) => {
+            outbox_repository::mark_delivered(tx, event).await?;
+            Ok(true)
+        }
+        Err(e) => {
+            error!(
+                "Delivering the '{}' outbox event '{}' failed (attempt {}): {}",
+                event.topic,
+                event.id,
+                event.attempts + 1,
+                e
+            );
+            let next_attempt_at = retry_at(event.attempts + 1, state.config.outbox.retry_base_secs);
+            outbox_repository::mark_failed(tx, event, e, next_attempt_at).await?;
+            Ok(false)
+        }
+    }
+}
+
+fn retry_at(attempts: i32, base_secs: u64) -> DateTime<Utc> {
+    let exponent = (attempts.max(1) - 1).min(16) as u32;
+    let delay = base_secs
+        .saturating_mul(2u64.pow(exponent))
+        .min(MAX_RETRY_DELAY_SECS);
+    Utc::now() + chrono::Duration::seconds(delay as i64)
+}
+
+async fn deliver(state: &ServiceState, event: &outbox::Model) -> Result<(), String> {
+    let Some(subject) = event.destination.strip_prefix(BUS_DESTINATION_PREFIX) else {
+        return post(event, state.config.outbox.webhook_secret_key.as_deref()).await;
+    };
+    let Some(bus) = state.event_bus.as_ref() else {
+        return Err("No event bus is configured".to_string());
+    };
+    bus.publish(subject, &event.id.to_string(), &event.payload)
+        .await
+}
+
+async fn post(event: &outbox::Model, secret_key: Option<&str>) -> Result<(), String> {
+    let secret_key = secret_key.ok_or_else(|| "No webhook secret is configured".to_string())?;
+    let body = event.payload.to_string();
+    let signature = sign(body.as_bytes(), secret_key)?;
+    WEBHOOK_CLIENT
+        .post(&event.destination)
+        .header(SIGNATURE_HEADER, signature)
+        .header(EVENT_ID_HEADER, event.id.to_string())
+        .header(EVENT_TOPIC_HEADER, event.topic.as_str())
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Sending the webhook request failed: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("Webhook rejected the payload: {}", e))?;
+    Ok(())
+}
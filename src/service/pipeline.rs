@@ -0,0 +1,389 @@
+use crate::{
+    client::{
+        provider::{ChatParams, ToolTurn},
+        registry::ProviderRegistry,
+    },
+    config::ServiceConfig,
+    entity::{
+        collection as collection_entity,
+        conversation::{
+            self as conversation_entity, Citation, MessageType, ReplyMode, ToolCall, Waveform,
+        },
+    },
+    repositories::{bot, collection, conversation, draft, instruction, usage},
+    utils::{
+        history_cache::HistoryCache,
+        openai::TokenUsage,
+        session::{send_session_data, SessionCache},
+    },
+};
+use futures::{stream::BoxStream, StreamExt};
+use hyper::body::Bytes;
+use rs_openai::chat::Role;
+use sea_orm::DatabaseTransaction;
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub type PromptMessage = (String, Role, Vec<String>, Option<ToolTurn>);
+pub type ChunkStream = BoxStream<'static, Result<Bytes, String>>;
+
+/// The stages of a chat turn that touch the database or another service.
+/// `handle_user_message` only talks to them through these traits, so each one
+/// can be swapped for a stub.
+#[derive(Clone)]
+pub struct ChatPipeline {
+    pub history: Arc<dyn HistoryLoader>,
+    pub caller: Arc<dyn ProviderCaller>,
+    pub persister: Arc<dyn TurnPersister>,
+    pub biller: Arc<dyn Biller>,
+}
+
+impl ChatPipeline {
+    pub fn build_from_config(
+        config: &ServiceConfig,
+        registry: Arc<ProviderRegistry>,
+        sessions: Arc<SessionCache>,
+    ) -> Self {
+        ChatPipeline {
+            history: Arc::new(DatabaseHistory {
+                cache: HistoryCache::new(&config.history_cache),
+            }),
+            caller: Arc::new(RegistryCaller {
+                registry: registry.clone(),
+            }),
+            persister: Arc::new(DatabasePersister),
+            biller: Arc::new(CreditBiller {
+                registry,
+                sessions,
+                auth_service: config.server.auth_service.clone(),
+                auth_secret_key: config.server.auth_secret_key.clone(),
+            }),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait HistoryLoader: Send + Sync {
+    async fn conversation(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+    ) -> Result<Option<conversation_entity::Model>, String>;
+
+    async fn custom_instructions(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+    ) -> Result<Option<String>, String>;
+
+    /// A collection the turn retrieves context from; access is checked by the caller.
+    async fn collection(
+        &self,
+        tx: &DatabaseTransaction,
+        collection_id: Uuid,
+    ) -> Result<Option<collection_entity::Model>, String>;
+}
+
+#[async_trait::async_trait]
+pub trait ProviderCaller: Send + Sync {
+    /// Starts a streamed completion and returns the raw chunks of the reply.
+    /// `region` is the user's data region, if they have one.
+    async fn stream_chat(
+        &self,
+        model_name: String,
+        region: Option<String>,
+        messages: Vec<PromptMessage>,
+        params: ChatParams,
+    ) -> Result<ChunkStream, String>;
+}
+
+/// Everything saved for a finished turn besides the owner and conversation.
+pub struct Turn {
+    pub user_message_type: MessageType,
+    pub user_message: String,
+    pub transcription: Option<String>,
+    pub images: Vec<String>,
+    pub reply_mode: ReplyMode,
+    pub answer: String,
+    pub citations: Vec<Citation>,
+    pub reply_audio: Option<String>,
+    pub user_waveform: Option<Waveform>,
+    pub reply_waveform: Option<Waveform>,
+    pub title: Option<String>,
+    pub model: String,
+    pub truncated: bool,
+    pub message_id: i64,
+    pub reply_to: Option<usize>,
+    /// Set when the user message is a tool result.
+    pub tool_call_id: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+    pub moderation_flags: Option<Vec<String>>,
+    /// Size of the media files first written for this turn.
+    pub media_bytes: i64,
+}
+
+#[async_trait::async_trait]
+pub trait TurnPersister: Send + Sync {
+    async fn save_turn(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        turn: Turn,
+    ) -> Result<(), String>;
+
+    /// Remembers the language the conversation turned out to be in.
+    async fn save_language(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        language: Option<String>,
+    ) -> Result<(), String>;
+
+    /// Remembers the voice profile picked for the conversation.
+    async fn save_voice_profile(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        voice_profile: Option<String>,
+    ) -> Result<(), String>;
+}
+
+#[async_trait::async_trait]
+pub trait Biller: Send + Sync {
+    fn credits_for_usage(&self, model: &str, usage: &TokenUsage) -> i64;
+
+    async fn charge(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        model: String,
+        credits: i64,
+        usage: &TokenUsage,
+    ) -> Result<(), String>;
+
+    /// Takes the credits of a turn answered by a bot out of the bot's pool.
+    async fn charge_bot(
+        &self,
+        tx: &DatabaseTransaction,
+        bot_id: Uuid,
+        credits: i64,
+    ) -> Result<(), String>;
+
+    /// Tells the auth service about the new balance once the charge has committed.
+    async fn report_balance(&self, user_id: i64, credits_remaining: i64) -> Result<(), String>;
+}
+
+pub struct DatabaseHistory {
+    cache: HistoryCache,
+}
+
+#[async_trait::async_trait]
+impl HistoryLoader for DatabaseHistory {
+    async fn conversation(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+    ) -> Result<Option<conversation_entity::Model>, String> {
+        if !self.cache.is_enabled() {
+            return conversation::find_by_user_id_and_conversation_id(tx, user_id, conversation_id)
+                .await;
+        }
+        // Read before the row itself, so a write in between only costs a miss later.
+        let Some(row_version) =
+            conversation::find_row_version(tx, user_id, conversation_id).await?
+        else {
+            return Ok(None);
+        };
+        if let Some(model) = self.cache.get(user_id, conversation_id, &row_version) {
+            return Ok(Some(model));
+        }
+        let model =
+            conversation::find_by_user_id_and_conversation_id(tx, user_id, conversation_id).await?;
+        if let Some(model) = &model {
+            self.cache.insert(row_version, model.clone());
+        }
+        Ok(model)
+    }
+
+    async fn custom_instructions(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+    ) -> Result<Option<String>, String> {
+        Ok(instruction::find_by_user_id(tx, user_id)
+            .await?
+            .and_then(|model| model.system_prompt()))
+    }
+
+    async fn collection(
+        &self,
+        tx: &DatabaseTransaction,
+        collection_id: Uuid,
+    ) -> Result<Option<collection_entity::Model>, String> {
+        collection::find_by_id(tx, collection_id).await
+    }
+}
+
+pub struct RegistryCaller {
+    registry: Arc<ProviderRegistry>,
+}
+
+#[async_trait::async_trait]
+impl ProviderCaller for RegistryCaller {
+    async fn stream_chat(
+        &self,
+        model_name: String,
+        region: Option<String>,
+        messages: Vec<PromptMessage>,
+        params: ChatParams,
+    ) -> Result<ChunkStream, String> {
+        let provider = self
+            .registry
+            .chat_provider(&model_name, region.as_deref())
+            .map_err(|e| e.message)?;
+        let response = provider
+            .send_chat_completion(model_name, messages, &params)
+            .await?;
+        Ok(response
+            .bytes_stream()
+            .map(|chunk| chunk.map_err(|e| e.to_string()))
+            .boxed())
+    }
+}
+
+pub struct DatabasePersister;
+
+#[async_trait::async_trait]
+impl TurnPersister for DatabasePersister {
+    async fn save_turn(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        turn: Turn,
+    ) -> Result<(), String> {
+        conversation::add_message(
+            tx,
+            user_id,
+            conversation_id,
+            turn.user_message_type,
+            turn.user_message,
+            turn.transcription,
+            turn.images,
+            turn.reply_mode,
+            turn.answer,
+            turn.citations,
+            turn.reply_audio,
+            turn.user_waveform,
+            turn.reply_waveform,
+            turn.title,
+            turn.model,
+            turn.truncated,
+            turn.message_id,
+            vec![],
+            turn.reply_to,
+            turn.tool_call_id,
+            turn.tool_calls,
+            turn.moderation_flags,
+        )
+        .await
+        .map_err(|e| format!("Failed to save message in database: {}", e))?;
+        if turn.media_bytes > 0 {
+            conversation::add_media_bytes(tx, conversation_id, turn.media_bytes)
+                .await
+                .map_err(|e| format!("Failed to record the conversation media size: {}", e))?;
+        }
+        // The draft was the message that has just been sent.
+        draft::delete(tx, user_id, conversation_id)
+            .await
+            .map_err(|e| format!("Failed to clear the conversation draft: {}", e))?;
+        Ok(())
+    }
+
+    async fn save_language(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        language: Option<String>,
+    ) -> Result<(), String> {
+        conversation::set_language(tx, user_id, conversation_id, language)
+            .await
+            .map(|_| ())
+    }
+
+    async fn save_voice_profile(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        voice_profile: Option<String>,
+    ) -> Result<(), String> {
+        conversation::set_voice_profile(tx, user_id, conversation_id, voice_profile)
+            .await
+            .map(|_| ())
+    }
+}
+
+pub struct CreditBiller {
+    registry: Arc<ProviderRegistry>,
+    sessions: Arc<SessionCache>,
+    auth_service: String,
+    auth_secret_key: String,
+}
+
+#[async_trait::async_trait]
+impl Biller for CreditBiller {
+    fn credits_for_usage(&self, model: &str, usage: &TokenUsage) -> i64 {
+        self.registry.credits_for_usage(model, usage)
+    }
+
+    async fn charge(
+        &self,
+        tx: &DatabaseTransaction,
+        user_id: i64,
+        conversation_id: Uuid,
+        model: String,
+        credits: i64,
+        usage: &TokenUsage,
+    ) -> Result<(), String> {
+        usage::record(tx, user_id, model, credits, usage)
+            .await
+            .map_err(|e| format!("Failed to record credit usage: {}", e))?;
+        conversation::add_credits_spent(tx, conversation_id, credits)
+            .await
+            .map_err(|e| format!("Failed to record conversation credit usage: {}", e))
+    }
+
+    async fn charge_bot(
+        &self,
+        tx: &DatabaseTransaction,
+        bot_id: Uuid,
+        credits: i64,
+    ) -> Result<(), String> {
+        bot::spend_credits(tx, bot_id, credits)
+            .await
+            .map_err(|e| format!("Failed to charge the bot: {}", e))
+    }
+
+    async fn report_balance(&self, user_id: i64, credits_remaining: i64) -> Result<(), String> {
+        send_session_data(
+            json!({
+                "credits_remaining" : credits_remaining,
+                "user_id" : user_id
+            }),
+            self.auth_service.as_str(),
+            self.auth_secret_key.clone(),
+        )
+        .await?;
+        self.sessions.invalidate_user(user_id);
+        Ok(())
+    }
+}
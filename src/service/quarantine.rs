@@ -0,0 +1,142 @@
+use crate::{
+    dto::{request::MessageReplacement, response::CorruptedMessage},
+    entity::conversation::Message,
+};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+static CORRUPTED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+pub struct RepairOutcome {
+    pub conversation: Vec<Value>,
+    pub repaired: usize,
+    pub dropped_turns: usize,
+    pub corrupted: Vec<CorruptedMessage>,
+}
+
+/// Splits the stored messages of a conversation into the readable ones and
+/// the ones that fail to deserialize, so a single bad entry no longer hides
+/// the rest of the history.
+pub fn load_messages(
+    conversation_id: Uuid,
+    values: Vec<Value>,
+) -> (Vec<Message>, Vec<CorruptedMessage>) {
+    let mut messages = vec![];
+    let mut corrupted = vec![];
+    for (index, value) in values.into_iter().enumerate() {
+        match Message::from_stored(value.clone()) {
+            Ok(message) => messages.push(message),
+            Err(e) => {
+                error!(
+                    "Message {} of conversation '{}' could not be read: {}",
+                    index, conversation_id, e
+                );
+                corrupted.push(CorruptedMessage {
+                    index,
+                    error: e.to_string(),
+                    raw: value,
+                });
+            }
+        }
+    }
+    if !corrupted.is_empty() {
+        let count = corrupted.len() as u64;
+        let total = CORRUPTED_MESSAGES.fetch_add(count, Ordering::Relaxed) + count;
+        warn!(
+            target: "metrics",
+            "corrupted_messages_total={} conversation='{}' quarantined={}",
+            total, conversation_id, count
+        );
+    }
+    (messages, corrupted)
+}
+
+/// Applies admin-provided replacements, optionally drops every turn that is
+/// still unreadable, and rewrites the readable messages at the current
+/// schema version with ids matching their new positions.
+pub fn repair(
+    conversation_id: Uuid,
+    mut values: Vec<Value>,
+    replacements: Vec<MessageReplacement>,
+    drop_corrupted: bool,
+) -> Result<RepairOutcome, String> {
+    let repaired = replacements.len();
+    for replacement in replacements {
+        let Some(slot) = values.get_mut(replacement.index) else {
+            return Err(format!(
+                "Replacement index {} is out of range",
+                replacement.index
+            ));
+        };
+        let message = Message::from_stored(replacement.message).map_err(|e| {
+            format!(
+                "Replacement for message {} is invalid: {}",
+                replacement.index, e
+            )
+        })?;
+        *slot = serde_json::to_value(&message)
+            .map_err(|e| format!("Error to converting JSON Value from Message: {}", e))?;
+    }
+
+    let broken_turns: HashSet<usize> = values
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| Message::from_stored((*value).clone()).is_err())
+        .map(|(index, _)| index / 2)
+        .collect();
+    let dropped_turns = if drop_corrupted {
+        values = values
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !broken_turns.contains(&(index / 2)))
+            .map(|(_, value)| value)
+            .collect();
+        broken_turns.len()
+    } else {
+        0
+    };
+
+    let mut conversation = Vec::with_capacity(values.len());
+    let mut corrupted = vec![];
+    for (index, value) in values.into_iter().enumerate() {
+        match Message::from_stored(value.clone()) {
+            Ok(mut message) => {
+                // Both messages of a turn share the id of the user message.
+                message.id = index / 2 * 2 + 1;
+                conversation.push(
+                    serde_json::to_value(&message).map_err(|e| {
+                        format!("Error to converting JSON Value from Message: {}", e)
+                    })?,
+                );
+            }
+            Err(e) => {
+                corrupted.push(CorruptedMessage {
+                    index,
+                    error: e.to_string(),
+                    raw: value.clone(),
+                });
+                conversation.push(value);
+            }
+        }
+    }
+
+    warn!(
+        target: "audit",
+        "Repaired conversation '{}': {} replaced, {} turns dropped, {} still corrupted.",
+        conversation_id,
+        repaired,
+        dropped_turns,
+        corrupted.len()
+    );
+    Ok(RepairOutcome {
+        conversation,
+        repaired,
+        dropped_turns,
+        corrupted,
+    })
+}
@@ -0,0 +1,192 @@
+use crate::config::queue::QueueConfig;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::sync::watch;
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    running_by_user: HashMap<i64, usize>,
+    // Waiting tickets in arrival order.
+    waiting: Vec<(u64, i64)>,
+    next_ticket: u64,
+}
+
+impl QueueState {
+    // Admission order: the next slot always goes to the waiting user with the fewest requests
+    // already running or scheduled ahead, so one busy user cannot starve the others.
+    fn schedule(&self) -> Vec<u64> {
+        let mut load = self.running_by_user.clone();
+        let mut pending = self.waiting.clone();
+        let mut order = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            let (index, _) = pending
+                .iter()
+                .enumerate()
+                .min_by_key(|(index, (_, user_id))| {
+                    (load.get(user_id).copied().unwrap_or_default(), *index)
+                })
+                .unwrap();
+            let (ticket, user_id) = pending.remove(index);
+            *load.entry(user_id).or_default() += 1;
+            order.push(ticket);
+        }
+        order
+    }
+
+    fn start(&mut self, user_id: i64) {
+        self.running += 1;
+        *self.running_by_user.entry(user_id).or_default() += 1;
+    }
+
+    fn finish(&mut self, user_id: i64) {
+        self.running = self.running.saturating_sub(1);
+        if let Some(count) = self.running_by_user.get_mut(&user_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.running_by_user.remove(&user_id);
+            }
+        }
+    }
+}
+
+// Bounds concurrent upstream chat requests, queueing the rest fairly across users.
+pub struct ChatQueue {
+    max_concurrent: usize,
+    max_waiting: usize,
+    state: Mutex<QueueState>,
+    changed: watch::Sender<u64>,
+}
+
+pub enum Admission {
+    Ready(QueueSlot),
+    Queued(QueueTicket),
+}
+
+pub enum QueueEvent {
+    Position(usize),
+    Admitted(QueueSlot),
+}
+
+// Held while a request talks to the provider; dropping it frees the slot.
+pub struct QueueSlot {
+    queue: Option<Arc<ChatQueue>>,
+    user_id: i64,
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.lock().finish(self.user_id);
+            queue.notify();
+        }
+    }
+}
+
+pub struct QueueTicket {
+    queue: Arc<ChatQueue>,
+    id: u64,
+    user_id: i64,
+    admitted: bool,
+    last_position: Option<usize>,
+    changes: watch::Receiver<u64>,
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        if !self.admitted {
+            self.queue.lock().waiting.retain(|(id, _)| *id != self.id);
+            self.queue.notify();
+        }
+    }
+}
+
+impl QueueTicket {
+    /// Waits until the request is admitted or its 1-based position in the queue changes.
+    pub async fn next(&mut self) -> QueueEvent {
+        loop {
+            {
+                let mut state = self.queue.lock();
+                let position = state
+                    .schedule()
+                    .iter()
+                    .position(|id| *id == self.id)
+                    .unwrap_or_default();
+                if position == 0 && state.running < self.queue.max_concurrent {
+                    state.waiting.retain(|(id, _)| *id != self.id);
+                    state.start(self.user_id);
+                    drop(state);
+                    self.admitted = true;
+                    self.queue.notify();
+                    return QueueEvent::Admitted(QueueSlot {
+                        queue: Some(self.queue.clone()),
+                        user_id: self.user_id,
+                    });
+                }
+                if self.last_position != Some(position) {
+                    self.last_position = Some(position);
+                    return QueueEvent::Position(position + 1);
+                }
+            }
+            let _ = self.changes.changed().await;
+        }
+    }
+}
+
+impl ChatQueue {
+    pub fn new(config: &QueueConfig) -> Self {
+        ChatQueue {
+            max_concurrent: config.max_concurrent,
+            max_waiting: config.max_waiting,
+            state: Mutex::new(QueueState::default()),
+            changed: watch::channel(0).0,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notify(&self) {
+        self.changed.send_modify(|version| *version += 1);
+    }
+
+    pub fn admit(self: &Arc<Self>, user_id: i64) -> Result<Admission, String> {
+        if self.max_concurrent == 0 {
+            return Ok(Admission::Ready(QueueSlot {
+                queue: None,
+                user_id,
+            }));
+        }
+        let mut state = self.lock();
+        if state.waiting.is_empty() && state.running < self.max_concurrent {
+            state.start(user_id);
+            return Ok(Admission::Ready(QueueSlot {
+                queue: Some(self.clone()),
+                user_id,
+            }));
+        }
+        if state.waiting.len() >= self.max_waiting {
+            return Err(format!(
+                "{} requests are already waiting for the assistant",
+                state.waiting.len()
+            ));
+        }
+        let id = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push((id, user_id));
+        drop(state);
+        let changes = self.changed.subscribe();
+        self.notify();
+        Ok(Admission::Queued(QueueTicket {
+            queue: self.clone(),
+            id,
+            user_id,
+            admitted: false,
+            last_position: None,
+            changes,
+        }))
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    config::redaction::PiiKind,
+    service::pipeline::PromptMessage,
+    utils::redaction::{mask_spans, redact_patterns},
+    ServiceState,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+const NER_TIMEOUT: Duration = Duration::from_secs(10);
+const PERSON_LABELS: &[&str] = &["PERSON", "PER"];
+
+#[derive(Debug, Deserialize)]
+struct NerEntity {
+    start: usize,
+    end: usize,
+    label: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NerResponse {
+    entities: Vec<Vec<NerEntity>>,
+}
+
+/// Masks personal data of the given kinds in every message, in place, and
+/// returns the number of replacements. Only the copy sent upstream is changed;
+/// the stored conversation keeps the original text.
+pub async fn redact_messages(
+    state: &Arc<ServiceState>,
+    kinds: &BTreeSet<PiiKind>,
+    messages: &mut [PromptMessage],
+) -> Result<usize, String> {
+    if kinds.is_empty() || messages.is_empty() {
+        return Ok(0);
+    }
+    let mut count = 0;
+    if kinds.contains(&PiiKind::Name) {
+        let texts: Vec<&str> = messages
+            .iter()
+            .map(|(text, _, _, _)| text.as_str())
+            .collect();
+        let entities = find_names(state, &texts).await?;
+        for ((text, _, _, _), spans) in messages.iter_mut().zip(entities) {
+            let (masked, masked_count) = mask_spans(text, &spans, PiiKind::Name.placeholder());
+            *text = masked;
+            count += masked_count;
+        }
+    }
+    for (text, _, _, _) in messages.iter_mut() {
+        let (masked, masked_count) = redact_patterns(text, kinds);
+        *text = masked;
+        count += masked_count;
+    }
+    Ok(count)
+}
+
+// The NER service takes `{"texts": [...]}` and answers with one list of
+// `{start, end, label}` byte ranges per text.
+async fn find_names(
+    state: &Arc<ServiceState>,
+    texts: &[&str],
+) -> Result<Vec<Vec<(usize, usize)>>, String> {
+    let ner_url = state
+        .config
+        .redaction
+        .ner_url
+        .as_deref()
+        .ok_or_else(|| "No NER service is configured".to_string())?;
+    let response = reqwest::Client::new()
+        .post(ner_url)
+        .timeout(NER_TIMEOUT)
+        .json(&json!({ "texts": texts }))
+        .send()
+        .await
+        .map_err(|e| format!("Calling the NER service failed: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("The NER service rejected the request: {}", e))?
+        .json::<NerResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse the NER response: {}", e))?;
+    if response.entities.len() != texts.len() {
+        return Err(format!(
+            "The NER service returned {} results for {} texts",
+            response.entities.len(),
+            texts.len()
+        ));
+    }
+    Ok(response
+        .entities
+        .into_iter()
+        .map(|entities| {
+            entities
+                .into_iter()
+                .filter(|entity| PERSON_LABELS.contains(&entity.label.to_uppercase().as_str()))
+                .map(|entity| (entity.start, entity.end))
+                .collect()
+        })
+        .collect())
+}
@@ -0,0 +1,195 @@
+use crate::{
+    config::retention::{RetentionAction, VOICE_TTL_DIR_PREFIX},
+    repositories::{conversation, draft},
+    utils::file::delete_files,
+    ServiceState,
+};
+use chrono::Utc;
+use sea_orm::TransactionTrait;
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub fn spawn_retention_sweeper(state: Arc<ServiceState>) {
+    let interval_secs = state.config.retention.sweep_interval_secs;
+    if interval_secs == 0 {
+        info!("Conversation retention sweeper is disabled.");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep_expired_conversations(&state).await {
+                error!("Conversation retention sweep failed: {}", e);
+            }
+            if let Err(e) = purge_deleted_conversations(&state).await {
+                error!("Purging deleted conversations failed: {}", e);
+            }
+            let removed = tokio::task::spawn_blocking(|| {
+                sweep_expired_voice_files(Path::new("./public/voice"))
+            })
+            .await
+            .unwrap_or_default();
+            if removed > 0 {
+                info!("Removed {} expired voice uploads.", removed);
+            }
+        }
+    });
+}
+
+pub async fn sweep_expired_conversations(state: &Arc<ServiceState>) -> Result<u64, String> {
+    let config = &state.config.retention;
+    let mut total = 0;
+    loop {
+        let transaction = state
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Starting a database transaction failed: {}", e))?;
+
+        let expired = conversation::find_expired(&transaction, config.sweep_batch_size).await?;
+        if expired.is_empty() {
+            let _ = transaction.rollback().await;
+            break;
+        }
+        let ids: Vec<Uuid> = expired.iter().map(|model| model.id).collect();
+        let affected = match config.action {
+            RetentionAction::Archive => conversation::archive_by_ids(&transaction, ids).await?,
+            RetentionAction::Delete => {
+                draft::delete_by_conversation_ids(&transaction, ids.clone()).await?;
+                conversation::delete_by_ids(&transaction, ids).await?
+            }
+        };
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Committing the database transaction failed: {}", e))?;
+
+        if config.action == RetentionAction::Delete {
+            let media_files: Vec<String> = expired
+                .iter()
+                .flat_map(|model| model.media_files())
+                .collect();
+            delete_files(&media_files);
+        }
+        total += affected;
+        if (expired.len() as u64) < config.sweep_batch_size {
+            break;
+        }
+    }
+
+    if total > 0 {
+        let action = match config.action {
+            RetentionAction::Archive => "archived",
+            RetentionAction::Delete => "deleted",
+        };
+        info!(
+            "Retention sweep {} {} expired conversations.",
+            action, total
+        );
+    }
+    Ok(total)
+}
+
+/// Permanently removes conversations deleted more than `purge_after_days` ago,
+/// together with their drafts and media files.
+pub async fn purge_deleted_conversations(state: &Arc<ServiceState>) -> Result<u64, String> {
+    let config = &state.config.retention;
+    let before = Utc::now() - chrono::Duration::days(config.purge_after_days as i64);
+    let mut total = 0;
+    loop {
+        let transaction = state
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Starting a database transaction failed: {}", e))?;
+
+        let deleted =
+            conversation::find_purgeable(&transaction, before, config.sweep_batch_size).await?;
+        if deleted.is_empty() {
+            let _ = transaction.rollback().await;
+            break;
+        }
+        let ids: Vec<Uuid> = deleted.iter().map(|model| model.id).collect();
+        draft::delete_by_conversation_ids(&transaction, ids.clone()).await?;
+        let affected = conversation::delete_by_ids(&transaction, ids).await?;
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Committing the database transaction failed: {}", e))?;
+
+        let media_files: Vec<String> = deleted
+            .iter()
+            .flat_map(|model| model.media_files())
+            .collect();
+        delete_files(&media_files);
+        total += affected;
+        if (deleted.len() as u64) < config.sweep_batch_size {
+            break;
+        }
+    }
+
+    if total > 0 {
+        info!("Purged {} deleted conversations.", total);
+    }
+    Ok(total)
+}
+
+/// Deletes voice uploads kept under a `ttl-<days>d` directory once they are
+/// older than that many days, wherever below `dir` the directory sits.
+pub fn sweep_expired_voice_files(dir: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let ttl_days = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(VOICE_TTL_DIR_PREFIX))
+            .and_then(|days| days.strip_suffix('d'))
+            .and_then(|days| days.parse::<u64>().ok());
+        match ttl_days {
+            Some(days) => removed += remove_older_than(&path, Duration::from_secs(days * 86400)),
+            None => removed += sweep_expired_voice_files(&path),
+        }
+    }
+    removed
+}
+
+fn remove_older_than(dir: &Path, max_age: Duration) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if !expired {
+            continue;
+        }
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!(
+                "Failed to delete expired voice upload '{}': {}",
+                entry.path().display(),
+                e
+            ),
+        }
+    }
+    removed
+}
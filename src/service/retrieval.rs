@@ -0,0 +1,189 @@
+use crate::{
+    config::{
+        chat::InjectionPolicy,
+        rag::{FusionStrategy, RagConfig},
+    },
+    entity::{collection as collection_entity, conversation::Citation},
+    repositories::collection,
+    utils::{chunker::chunk_text, injection::detect_injection, request_id::current_request_id},
+    ServiceState,
+};
+use sea_orm::DatabaseTransaction;
+use std::{collections::HashMap, sync::Arc};
+use tracing::warn;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct RetrievedChunk {
+    pub document_id: Uuid,
+    pub chunk_index: i32,
+    pub content: String,
+    pub score: f32,
+}
+
+pub fn context_prompt(chunks: &[RetrievedChunk]) -> Option<String> {
+    if chunks.is_empty() {
+        return None;
+    }
+    let sources = chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| format!("[{}] {}", index + 1, chunk.content.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Some(format!(
+        "Answer using the sources below when they are relevant and cite them by their number, e.g. [1]. \
+If the sources do not contain the answer, say so.\n\n{}",
+        sources
+    ))
+}
+
+pub fn screen_injections(
+    chunks: Vec<RetrievedChunk>,
+    policy: InjectionPolicy,
+    user_id: i64,
+    conversation_id: Uuid,
+) -> Vec<RetrievedChunk> {
+    if policy == InjectionPolicy::Allow {
+        return chunks;
+    }
+    chunks
+        .into_iter()
+        .filter(|chunk| {
+            let Some(pattern) = detect_injection(&chunk.content) else {
+                return true;
+            };
+            let blocked = policy == InjectionPolicy::Block;
+            warn!(
+                target: "audit",
+                request_id = %current_request_id().unwrap_or_default(),
+                user_id,
+                %conversation_id,
+                document_id = %chunk.document_id,
+                chunk_index = chunk.chunk_index,
+                pattern,
+                blocked,
+                "Prompt injection detected in retrieved context"
+            );
+            !blocked
+        })
+        .collect()
+}
+
+pub fn citations(chunks: &[RetrievedChunk]) -> Vec<Citation> {
+    chunks
+        .iter()
+        .map(|chunk| Citation {
+            document_id: chunk.document_id,
+            chunk_index: chunk.chunk_index,
+            score: chunk.score,
+        })
+        .collect()
+}
+
+/// Splits a document into chunks and embeds them. Embedding is a slow upstream
+/// call, so callers run it outside any transaction and save the chunks afterwards.
+pub async fn embed_document(
+    state: &Arc<ServiceState>,
+    embedding_model: &str,
+    content: &str,
+) -> Result<Vec<(String, Vec<f32>)>, String> {
+    let chunks = chunk_text(
+        content,
+        state.config.rag.chunk_size,
+        state.config.rag.chunk_overlap,
+    );
+    let embeddings = state
+        .provider
+        .embed(embedding_model, chunks.clone())
+        .await?;
+    Ok(chunks.into_iter().zip(embeddings).collect())
+}
+
+pub async fn retrieve(
+    state: &Arc<ServiceState>,
+    tx: &DatabaseTransaction,
+    collections: &[collection_entity::Model],
+    query: &str,
+) -> Result<Vec<RetrievedChunk>, String> {
+    let config = &state.config.rag;
+    let top_k = config.top_k;
+    let candidates = match config.fusion {
+        FusionStrategy::Vector => top_k,
+        _ => top_k * config.candidate_multiplier,
+    };
+    let mut by_model: HashMap<&str, Vec<Uuid>> = HashMap::new();
+    for model in collections {
+        by_model
+            .entry(model.embedding_model.as_str())
+            .or_default()
+            .push(model.id);
+    }
+
+    let mut vector_chunks = vec![];
+    for (embedding_model, collection_ids) in by_model {
+        let embedding = state
+            .provider
+            .embed(embedding_model, vec![query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| "No embedding returned for the query".to_string())?;
+        vector_chunks
+            .extend(collection::search_chunks(tx, &collection_ids, &embedding, candidates).await?);
+    }
+    vector_chunks.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut chunks = match config.fusion {
+        FusionStrategy::Vector => vector_chunks,
+        strategy => {
+            let collection_ids: Vec<Uuid> = collections.iter().map(|model| model.id).collect();
+            let keyword_chunks = collection::search_chunks_by_text(
+                tx,
+                &collection_ids,
+                query,
+                &config.text_search_config,
+                candidates,
+            )
+            .await?;
+            fuse(strategy, config, vector_chunks, keyword_chunks)
+        }
+    };
+    chunks.truncate(top_k as usize);
+    Ok(chunks)
+}
+
+fn fuse(
+    strategy: FusionStrategy,
+    config: &RagConfig,
+    vector_chunks: Vec<RetrievedChunk>,
+    keyword_chunks: Vec<RetrievedChunk>,
+) -> Vec<RetrievedChunk> {
+    let max_keyword_score = keyword_chunks
+        .iter()
+        .map(|chunk| chunk.score)
+        .fold(0.0, f32::max);
+    let mut fused: HashMap<(Uuid, i32), RetrievedChunk> = HashMap::new();
+    for (is_keyword, ranked) in [(false, vector_chunks), (true, keyword_chunks)] {
+        for (rank, chunk) in ranked.into_iter().enumerate() {
+            let score = match strategy {
+                FusionStrategy::Weighted if is_keyword => {
+                    let normalized = if max_keyword_score > 0.0 {
+                        chunk.score / max_keyword_score
+                    } else {
+                        0.0
+                    };
+                    normalized * config.keyword_weight
+                }
+                FusionStrategy::Weighted => chunk.score * (1.0 - config.keyword_weight),
+                _ => 1.0 / (config.rrf_k + rank as f32 + 1.0),
+            };
+            fused
+                .entry((chunk.document_id, chunk.chunk_index))
+                .and_modify(|existing| existing.score += score)
+                .or_insert(RetrievedChunk { score, ..chunk });
+        }
+    }
+    let mut chunks: Vec<RetrievedChunk> = fused.into_values().collect();
+    chunks.sort_by(|a, b| b.score.total_cmp(&a.score));
+    chunks
+}
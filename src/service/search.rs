@@ -0,0 +1,58 @@
+use crate::{
+    dto::response::{SearchMatch, SearchResult},
+    entity::conversation::{self, Message, MessageType},
+};
+
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+pub fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// Cuts a window of text around the first case-insensitive occurrence of the query.
+fn snippet(text: &str, query: &str) -> Option<String> {
+    let lowered = text.to_lowercase();
+    let position = lowered.find(&query.to_lowercase())?;
+    let start = lowered[..position].chars().count();
+    let chars: Vec<char> = text.chars().collect();
+    let from = start.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let to = (start + query.chars().count() + SNIPPET_CONTEXT_CHARS).min(chars.len());
+    let mut snippet: String = chars[from.min(to)..to].iter().collect();
+    if from > 0 {
+        snippet.insert(0, '…');
+    }
+    if to < chars.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+pub fn search_conversation(model: conversation::Model, query: &str) -> SearchResult {
+    let matches = model
+        .conversation
+        .iter()
+        .enumerate()
+        .filter_map(|(message_index, value)| {
+            let message = Message::from_stored(value.clone()).ok()?;
+            let text = match message.msgtype {
+                MessageType::Voice => message.transcription.unwrap_or_default(),
+                _ => message.content,
+            };
+            Some(SearchMatch {
+                message_index,
+                role: message.role,
+                snippet: snippet(&text, query)?,
+            })
+        })
+        .collect();
+    SearchResult {
+        conversation_id: model.id,
+        title_match: model.title.to_lowercase().contains(&query.to_lowercase()),
+        title: model.title,
+        updated_at: model.updated_at,
+        matches,
+    }
+}
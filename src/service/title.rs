@@ -0,0 +1,112 @@
+use crate::{
+    config::redaction::PiiKind,
+    repositories::conversation,
+    service::{extraction::extract_structured, redaction},
+    ServiceState,
+};
+use once_cell::sync::Lazy;
+use rs_openai::chat::Role;
+use sea_orm::TransactionTrait;
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+// Only the start of each side is needed to name the conversation.
+const EXCHANGE_CHARS: usize = 2000;
+const TITLE_MAX_CHARS: usize = 80;
+
+const TITLE_INSTRUCTION: &str = "Read the first exchange of a conversation and write a short \
+title of at most six words that names its topic. Write it in the user's language, without \
+quotes or a trailing period.";
+
+static TITLE_SCHEMA: Lazy<Value> = Lazy::new(|| {
+    json!({
+        "type": "object",
+        "properties": {
+            "title": { "type": "string" },
+        },
+        "required": ["title"],
+        "additionalProperties": false,
+    })
+});
+
+/// Names a conversation after its first exchange with the title model, unless
+/// the user has renamed it by the time the reply arrives. Failures keep the
+/// heuristic title and are only logged.
+pub async fn refresh_title(
+    state: Arc<ServiceState>,
+    user_id: i64,
+    conversation_id: Uuid,
+    question: String,
+    answer: String,
+    redaction_kinds: BTreeSet<PiiKind>,
+) {
+    let Some(model) = state.config.chat.title_model.clone() else {
+        return;
+    };
+    let mut exchange = vec![(
+        format!(
+            "User: {}\n\nAssistant: {}",
+            question.chars().take(EXCHANGE_CHARS).collect::<String>(),
+            answer.chars().take(EXCHANGE_CHARS).collect::<String>()
+        ),
+        Role::User,
+        vec![],
+        None,
+    )];
+    let title = async {
+        redaction::redact_messages(&state, &redaction_kinds, &mut exchange).await?;
+        let (transcript, _, _, _) = exchange.remove(0);
+        let data = extract_structured(
+            &state,
+            &model,
+            TITLE_INSTRUCTION,
+            vec![json!({ "type": "text", "text": transcript })],
+            "title",
+            &TITLE_SCHEMA,
+        )
+        .await?;
+        let title = data["title"]
+            .as_str()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .trim_end_matches('.')
+            .chars()
+            .take(TITLE_MAX_CHARS)
+            .collect::<String>()
+            .trim()
+            .to_string();
+        if title.is_empty() {
+            return Err("The title model returned an empty title".to_string());
+        }
+
+        let transaction = state
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Starting a database transaction failed: {}", e))?;
+        let updated =
+            conversation::set_generated_title(&transaction, user_id, conversation_id, title)
+                .await?;
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Committing the database transaction failed: {}", e))?;
+        Ok::<bool, String>(updated)
+    }
+    .await;
+
+    match title {
+        Ok(true) => info!("Generated a title for conversation '{}'.", conversation_id),
+        Ok(false) => info!(
+            "Conversation '{}' was renamed before its title was generated.",
+            conversation_id
+        ),
+        Err(e) => warn!(
+            "Failed to generate a title for conversation '{}': {}",
+            conversation_id, e
+        ),
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    client::provider::ToolTurn,
+    config::{
+        chat::InjectionPolicy,
+        tools::{ToolLimits, ToolsConfig},
+    },
+    entity::conversation::ToolCall,
+    service::pipeline::PromptMessage,
+    utils::{injection::detect_injection, request_id::current_request_id},
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Sent to the provider in place of a tool result the injection policy blocked.
+pub const WITHHELD_RESULT: &str =
+    "[The tool result was withheld because it looks like a prompt injection.]";
+
+/// Counts the tool calls of one turn, which runs from a user message through
+/// every call the model makes and every result the client sends back, and
+/// drops calls past a tool's per-turn limit.
+pub struct ToolBudget {
+    config: ToolsConfig,
+    calls: HashMap<String, u32>,
+}
+
+impl ToolBudget {
+    pub fn new(config: &ToolsConfig) -> Self {
+        ToolBudget {
+            config: config.clone(),
+            calls: HashMap::new(),
+        }
+    }
+
+    /// Counts the calls already made in the turn a tool result continues.
+    pub fn record_history(&mut self, history: &[PromptMessage]) {
+        for (_, _, _, tool_turn) in history.iter().rev() {
+            match tool_turn {
+                Some(ToolTurn::Calls(calls)) => {
+                    for call in calls {
+                        *self.calls.entry(call.name.clone()).or_default() += 1;
+                    }
+                }
+                Some(ToolTurn::Result(_)) => {}
+                None => break,
+            }
+        }
+    }
+
+    /// Keeps the calls the budget still allows and stamps them with the time
+    /// they were issued, so a late result can be refused.
+    pub fn admit(&mut self, calls: Vec<ToolCall>, now: DateTime<Utc>) -> Vec<ToolCall> {
+        calls
+            .into_iter()
+            .filter_map(|mut call| {
+                let limits = self.config.limits_for(&call.name);
+                let made = self.calls.entry(call.name.clone()).or_default();
+                if *made >= limits.max_calls_per_turn {
+                    warn!(
+                        "Dropped a call to tool '{}', which exceeded its budget of {} calls per turn.",
+                        call.name, limits.max_calls_per_turn
+                    );
+                    return None;
+                }
+                *made += 1;
+                call.issued_at = Some(now);
+                Some(call)
+            })
+            .collect()
+    }
+}
+
+/// Whether the result of `call` arrives later than the tool's timeout allows.
+pub fn timed_out(call: &ToolCall, limits: ToolLimits, now: DateTime<Utc>) -> bool {
+    call.issued_at.is_some_and(|issued_at| {
+        now - issued_at > chrono::Duration::seconds(limits.timeout_secs as i64)
+    })
+}
+
+pub fn cap_output(tool_name: &str, output: String, max_chars: usize) -> String {
+    let Some((cut, _)) = output.char_indices().nth(max_chars) else {
+        return output;
+    };
+    warn!(
+        "Truncated the output of tool '{}' to {} characters.",
+        tool_name, max_chars
+    );
+    format!("{}\n[output truncated]", &output[..cut])
+}
+
+/// Checks the arguments or the result of a tool call for prompt injection and
+/// leaves an audit record on every detection. Returns false when the policy
+/// blocks the text.
+pub fn screen_injection(
+    text: &str,
+    policy: InjectionPolicy,
+    user_id: i64,
+    conversation_id: Uuid,
+    call: &ToolCall,
+    source: &'static str,
+) -> bool {
+    if policy == InjectionPolicy::Allow {
+        return true;
+    }
+    let Some(pattern) = detect_injection(text) else {
+        return true;
+    };
+    let blocked = policy == InjectionPolicy::Block;
+    warn!(
+        target: "audit",
+        request_id = %current_request_id().unwrap_or_default(),
+        user_id,
+        %conversation_id,
+        tool = %call.name,
+        tool_call_id = %call.id,
+        source,
+        pattern,
+        blocked,
+        "Prompt injection detected in a tool call"
+    );
+    !blocked
+}
@@ -0,0 +1,194 @@
+use crate::{
+    client::provider::TranscriptSegment,
+    utils::{
+        audio::split_wav,
+        error::{format_error, AppResult},
+        retry::upstream_status,
+        streaming::StreamWriter,
+    },
+    ServiceState,
+};
+use axum::http::StatusCode;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info};
+
+// Whisper only looks at the last couple of hundred tokens of the prompt.
+const CONTEXT_CHARS: usize = 400;
+
+/// Transcribes the recording piece by piece, sending a `progress` event with
+/// the partial transcript after every piece and a `done` event at the end.
+/// Recordings that cannot be split go through as a single piece.
+pub async fn stream_transcription(
+    state: Arc<ServiceState>,
+    writer: StreamWriter,
+    user_id: i64,
+    filename: String,
+    data: Vec<u8>,
+    prompt: Option<String>,
+    language: Option<String>,
+) {
+    let pieces = match split_wav(&data, state.config.transcription.chunk_secs, 0) {
+        Some(pieces) if !pieces.is_empty() => pieces.into_iter().map(|(_, piece)| piece).collect(),
+        _ => vec![data],
+    };
+    let total = pieces.len();
+    info!(
+        "Transcribing the voice of user '{}' in {} pieces.",
+        user_id, total
+    );
+    if writer
+        .event("progress", json!({ "percent": 0, "total": total }))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut transcript = String::new();
+    for (index, piece) in pieces.into_iter().enumerate() {
+        if writer.is_closed() {
+            info!("Client of user '{}' left the transcription.", user_id);
+            return;
+        }
+        // The tail of what came before keeps the pieces consistent with each other.
+        let context = tail(&transcript, CONTEXT_CHARS);
+        let piece_prompt = match (prompt.as_deref(), context.is_empty()) {
+            (Some(prompt), false) => Some(format!("{}\n{}", prompt, context)),
+            (Some(prompt), true) => Some(prompt.to_string()),
+            (None, false) => Some(context.to_string()),
+            (None, true) => None,
+        };
+        let piece_filename = if total == 1 {
+            filename.clone()
+        } else {
+            format!("{}-{}.wav", filename, index)
+        };
+        let text = match state
+            .provider
+            .speech_to_text(piece, piece_filename, piece_prompt, language.clone())
+            .await
+        {
+            Ok(text) => text.trim().to_string(),
+            Err(e) => {
+                error!("{}", e);
+                writer.error(e).await;
+                return;
+            }
+        };
+        if !text.is_empty() {
+            if !transcript.is_empty() {
+                transcript.push(' ');
+            }
+            transcript.push_str(&text);
+        }
+        let percent = (index + 1) * 100 / total;
+        let progress = json!({
+            "percent": percent,
+            "segment": index,
+            "total": total,
+            "text": text,
+        });
+        if writer.event("progress", progress).await.is_err() {
+            return;
+        }
+    }
+    let _ = writer.event("done", json!({ "text": transcript })).await;
+}
+
+/// Transcribes a recording, first splitting it into overlapping pieces when
+/// it is over the provider's upload limit. The pieces are transcribed
+/// concurrently and their segments merged by timestamp.
+pub async fn transcribe(
+    state: &Arc<ServiceState>,
+    filename: String,
+    data: Vec<u8>,
+    prompt: Option<String>,
+    language: Option<String>,
+) -> AppResult<String> {
+    let config = &state.config.transcription;
+    if data.len() <= config.max_upload_bytes {
+        let text = state
+            .provider
+            .speech_to_text(data, filename, prompt, language)
+            .await
+            .map_err(|e| {
+                error!("{}", e);
+                (upstream_status(&e), e)
+            })?;
+        return Ok(text);
+    }
+    let Some(pieces) = split_wav(&data, config.chunk_secs, config.overlap_secs) else {
+        return Err(format_error(
+            "The recording is too large and only WAV recordings can be split. Limit in bytes",
+            config.max_upload_bytes,
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    };
+    info!(
+        "Splitting a recording of {} bytes into {} pieces for transcription.",
+        data.len(),
+        pieces.len()
+    );
+    let starts: Vec<f64> = pieces.iter().map(|(start, _)| *start).collect();
+    let transcribed: Vec<Vec<TranscriptSegment>> = stream::iter(pieces.into_iter().enumerate())
+        .map(|(index, (_, piece))| {
+            let piece_filename = format!("{}-{}.wav", filename, index);
+            let prompt = prompt.clone();
+            let language = language.clone();
+            async move {
+                state
+                    .provider
+                    .speech_to_segments(piece, piece_filename, prompt, language)
+                    .await
+            }
+        })
+        .buffered(config.max_concurrency)
+        .try_collect()
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            (upstream_status(&e), e)
+        })?;
+    Ok(merge_segments(
+        &starts,
+        transcribed,
+        config.overlap_secs as f64,
+    ))
+}
+
+// Neighbouring pieces share `overlap_secs` of audio. Each keeps the segments
+// whose middle falls before the middle of its overlap with the next piece.
+fn merge_segments(
+    starts: &[f64],
+    pieces: Vec<Vec<TranscriptSegment>>,
+    overlap_secs: f64,
+) -> String {
+    let half_overlap = overlap_secs / 2.0;
+    let mut texts = vec![];
+    for (index, segments) in pieces.into_iter().enumerate() {
+        let from = match index {
+            0 => f64::NEG_INFINITY,
+            _ => starts[index] + half_overlap,
+        };
+        let until = starts
+            .get(index + 1)
+            .map_or(f64::INFINITY, |next| next + half_overlap);
+        for segment in segments {
+            let middle = starts[index] + (segment.start + segment.end) / 2.0;
+            let text = segment.text.trim();
+            if middle >= from && middle < until && !text.is_empty() {
+                texts.push(text.to_string());
+            }
+        }
+    }
+    texts.join(" ")
+}
+
+fn tail(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().rev().nth(max_chars.saturating_sub(1)) {
+        Some((start, _)) => &text[start..],
+        None => text,
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{
+    controllers::chat::handle_transaction,
+    repositories::attachment,
+    utils::error::{format_error, AppResult},
+    ServiceState,
+};
+use axum::http::StatusCode;
+use std::{io::SeekFrom, sync::Arc};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Writes a chunk at `offset`, dropping whatever a failed append left past it.
+pub async fn write_chunk(path: &str, offset: i64, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(path)
+        .await?;
+    file.set_len(offset as u64).await?;
+    file.seek(SeekFrom::Start(offset as u64)).await?;
+    file.write_all(data).await?;
+    file.flush().await
+}
+
+/// Cuts the upload file back to the size saved in the database.
+pub async fn truncate_chunks(path: &str, size: i64) -> std::io::Result<()> {
+    let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    file.set_len(size as u64).await
+}
+
+pub async fn load_attachment(
+    state: &Arc<ServiceState>,
+    user_id: i64,
+    attachment_id: Uuid,
+) -> AppResult<(String, Vec<u8>)> {
+    let model = handle_transaction(&state.db, |transaction| {
+        Box::pin(async move {
+            attachment::find_by_user_id_and_attachment_id(transaction, user_id, attachment_id)
+                .await
+                .map_err(|e| {
+                    format_error(
+                        "Database query failed while fetching the attachment",
+                        e,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })
+        })
+    })
+    .await?;
+    let Some(model) = model.filter(|model| model.is_complete()) else {
+        return Err(format_error(
+            "No completed attachment found for the user",
+            attachment_id,
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let data = tokio::fs::read(state.config.upload.path_for(attachment_id))
+        .await
+        .map_err(|e| {
+            format_error(
+                "Failed to read the attachment file",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    Ok((model.filename, data))
+}
@@ -0,0 +1,83 @@
+use crate::{
+    entity::bot,
+    repositories::bot as bot_repository,
+    utils::error::{format_error, AppError, ErrorCode},
+    ServiceState,
+};
+use axum::{extract::FromRequestParts, http::request::Parts, http::StatusCode};
+use sea_orm::TransactionTrait;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+const API_KEY_PREFIX: &str = "wbk_";
+
+/// A new random bot key. Only its hash is stored, so it is shown once.
+pub fn generate_api_key() -> String {
+    format!(
+        "{}{}{}",
+        API_KEY_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+pub fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.trim().as_bytes()))
+}
+
+/// The integration bot authenticated by the `X-Api-Key` header.
+pub struct BotAuth(pub bot::Model);
+
+#[async_trait::async_trait]
+impl FromRequestParts<Arc<ServiceState>> for BotAuth {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServiceState>,
+    ) -> Result<Self, Self::Rejection> {
+        let api_key = parts
+            .headers
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| v.starts_with(API_KEY_PREFIX))
+            .ok_or_else(|| {
+                error!("Missing or malformed bot API key");
+                AppError::new(
+                    StatusCode::UNAUTHORIZED,
+                    ErrorCode::Unauthorized,
+                    "Missing or malformed API key",
+                )
+            })?;
+
+        let transaction = state.db.begin().await.map_err(|e| {
+            format_error(
+                "Could not start a database transaction due to an error",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        let bot_model = bot_repository::find_by_api_key_hash(&transaction, &hash_api_key(api_key))
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to look up the API key",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+        let _ = transaction.rollback().await;
+
+        bot_model.map(BotAuth).ok_or_else(|| {
+            error!("Unknown bot API key");
+            AppError::new(
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                "Invalid API key",
+            )
+        })
+    }
+}
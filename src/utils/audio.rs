@@ -0,0 +1,200 @@
+use crate::utils::file::wav_header_len;
+use axum::http::{header, HeaderMap};
+use hyper::body::Bytes;
+use mp3lame_encoder::{Bitrate, Builder, Encoder, FlushNoGap, MonoPcm, Quality};
+use serde::Deserialize;
+use std::io::Cursor;
+
+/// Encoding of the voice reply sent to the client. The TTS providers always
+/// produce Linear16 WAV; anything else is transcoded while streaming.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Wav,
+    Mp3,
+}
+
+impl AudioFormat {
+    /// Picks the format from the `Accept` header. WAV stays the default so
+    /// existing clients keep receiving what they always did.
+    pub fn from_accept(headers: &HeaderMap) -> AudioFormat {
+        let accepts_mp3 = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|range| range.split(';').next())
+            .any(|media_type| {
+                matches!(
+                    media_type.trim().to_ascii_lowercase().as_str(),
+                    "audio/mpeg" | "audio/mp3"
+                )
+            });
+        if accepts_mp3 {
+            AudioFormat::Mp3
+        } else {
+            AudioFormat::Wav
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "audio/wav",
+            AudioFormat::Mp3 => "audio/mpeg",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+/// Re-encodes a Linear16 WAV stream chunk by chunk. Chunks may split the WAV
+/// header or a sample; the leftover bytes are kept for the next call.
+pub struct Transcoder {
+    encoder: Option<Encoder>,
+    pending: Vec<u8>,
+    header_checked: bool,
+}
+
+impl Transcoder {
+    pub fn new(format: AudioFormat, sample_rate: u32) -> Result<Transcoder, String> {
+        let encoder = match format {
+            AudioFormat::Wav => None,
+            AudioFormat::Mp3 => Some(mp3_encoder(sample_rate)?),
+        };
+        Ok(Transcoder {
+            encoder,
+            pending: vec![],
+            header_checked: false,
+        })
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Bytes, String> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Ok(Bytes::copy_from_slice(data));
+        };
+        self.pending.extend_from_slice(data);
+        if !self.header_checked {
+            // Wait until the whole header has arrived before stripping it.
+            if self.pending.starts_with(b"RIFF") {
+                let header_len = wav_header_len(&self.pending);
+                if header_len == 0 || header_len > self.pending.len() {
+                    return Ok(Bytes::new());
+                }
+                self.pending.drain(..header_len);
+            } else if self.pending.len() < 4 && b"RIFF".starts_with(&self.pending) {
+                return Ok(Bytes::new());
+            }
+            self.header_checked = true;
+        }
+
+        let whole = self.pending.len() / 2 * 2;
+        let samples: Vec<i16> = self.pending[..whole]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        self.pending.drain(..whole);
+        encode(encoder, &samples).map(Bytes::from)
+    }
+
+    pub fn flush(&mut self) -> Result<Bytes, String> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Ok(Bytes::new());
+        };
+        let mut out = Vec::with_capacity(7200);
+        let encoded_size = encoder
+            .flush::<FlushNoGap>(out.spare_capacity_mut())
+            .map_err(|e| e.to_string())?;
+        unsafe {
+            out.set_len(encoded_size);
+        }
+        Ok(Bytes::from(out))
+    }
+}
+
+fn mp3_encoder(sample_rate: u32) -> Result<Encoder, String> {
+    let mut builder = Builder::new().ok_or("Failed to create the LAME builder")?;
+    builder.set_num_channels(1).map_err(|e| e.to_string())?;
+    builder
+        .set_sample_rate(sample_rate)
+        .map_err(|e| e.to_string())?;
+    builder
+        .set_brate(Bitrate::Kbps64)
+        .map_err(|e| e.to_string())?;
+    builder
+        .set_quality(Quality::Good)
+        .map_err(|e| e.to_string())?;
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn encode(encoder: &mut Encoder, samples: &[i16]) -> Result<Vec<u8>, String> {
+    if samples.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut out = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(samples.len()));
+    let encoded_size = encoder
+        .encode(MonoPcm(samples), out.spare_capacity_mut())
+        .map_err(|e| e.to_string())?;
+    unsafe {
+        out.set_len(encoded_size);
+    }
+    Ok(out)
+}
+
+/// Encodes a complete Linear16 recording to MP3.
+pub fn encode_mp3(samples: &[i16], sample_rate: u32) -> Result<Vec<u8>, String> {
+    let mut encoder = mp3_encoder(sample_rate)?;
+    let mut out = encode(&mut encoder, samples)?;
+    out.reserve(7200);
+    let encoded_size = encoder
+        .flush::<FlushNoGap>(out.spare_capacity_mut())
+        .map_err(|e| e.to_string())?;
+    unsafe {
+        out.set_len(out.len() + encoded_size);
+    }
+    Ok(out)
+}
+
+/// Splits a 16-bit PCM WAV recording into WAV pieces of at most `chunk_secs`
+/// seconds each, every piece starting `overlap_secs` before the previous one
+/// ends. Each piece comes with its start in seconds. Returns `None` for
+/// anything else, which cannot be cut without decoding it.
+pub fn split_wav(data: &[u8], chunk_secs: u32, overlap_secs: u32) -> Option<Vec<(f64, Vec<u8>)>> {
+    let reader = hound::WavReader::new(Cursor::new(data)).ok()?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return None;
+    }
+    let samples: Vec<i16> = reader
+        .into_samples::<i16>()
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let per_sec = (spec.sample_rate as usize * spec.channels.max(1) as usize).max(1);
+    let chunk_len = per_sec.saturating_mul(chunk_secs.max(1) as usize);
+    let step = chunk_len
+        - per_sec
+            .saturating_mul(overlap_secs as usize)
+            .min(chunk_len - 1);
+    let mut pieces = vec![];
+    let mut start = 0;
+    while start < samples.len() {
+        let end = (start + chunk_len).min(samples.len());
+        let mut out = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut out, spec).ok()?;
+        for sample in &samples[start..end] {
+            writer.write_sample(*sample).ok()?;
+        }
+        writer.finalize().ok()?;
+        pieces.push((start as f64 / per_sec as f64, out.into_inner()));
+        if end == samples.len() {
+            break;
+        }
+        start += step;
+    }
+    Some(pieces)
+}
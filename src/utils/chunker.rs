@@ -0,0 +1,56 @@
+pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.len() + paragraph.len() + 2 > chunk_size {
+            let tail = overlap_tail(&current, overlap);
+            chunks.push(std::mem::replace(&mut current, tail));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+        while current.len() > chunk_size {
+            let cut = split_point(&current, chunk_size);
+            let rest = current.split_off(cut);
+            let tail = overlap_tail(&current, overlap);
+            chunks.push(std::mem::replace(&mut current, tail + rest.trim_start()));
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn split_point(text: &str, limit: usize) -> usize {
+    let mut limit = limit.min(text.len());
+    while !text.is_char_boundary(limit) {
+        limit -= 1;
+    }
+    match text[..limit].rfind(char::is_whitespace) {
+        Some(pos) if pos > 0 => pos,
+        _ => limit,
+    }
+}
+
+fn overlap_tail(text: &str, overlap: usize) -> String {
+    if overlap == 0 || text.len() <= overlap {
+        return String::new();
+    }
+    let mut start = text.len() - overlap;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let tail = &text[start..];
+    let tail = match tail.find(char::is_whitespace) {
+        Some(pos) => &tail[pos..],
+        None => tail,
+    };
+    let tail = tail.trim_start();
+    if tail.is_empty() {
+        String::new()
+    } else {
+        format!("{} ", tail)
+    }
+}
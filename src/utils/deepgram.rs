@@ -1,3 +1,4 @@
+use crate::utils::request_id::{current_request_id, WithRequestId, REQUEST_ID_HEADER};
 use deepgram::{
     speak::options::{Container, Encoding, Model, Options},
     Deepgram,
@@ -5,8 +6,36 @@ use deepgram::{
 use futures::Stream;
 use hyper::body::Bytes;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async, tungstenite::client::IntoClientRequest, MaybeTlsStream, WebSocketStream,
+};
+use url::Url;
+
+const LIVE_URL: &str = "wss://api.deepgram.com/v1/listen";
+
+pub type LiveSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct LiveOptions {
+    pub language: Option<String>,
+    pub encoding: Option<String>,
+    pub sample_rate: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveTranscript {
+    pub text: String,
+    pub is_final: bool,
+    pub speech_final: bool,
+    pub start: f64,
+    pub duration: f64,
+}
 pub async fn text_to_speech(
     api_token: &str,
+    voice: &str,
+    sample_rate: u32,
     text: &str,
     is_started: bool,
 ) -> Result<impl Stream<Item = Bytes>, String> {
@@ -15,9 +44,8 @@ pub async fn text_to_speech(
         return Err(format!("Failed to create deepgram client"));
     }
     let dg_client = dg_client.unwrap();
-    let sample_rate = 16000;
     let options = Options::builder()
-        .model(Model::AuraAsteriaEn)
+        .model(Model::CustomId(voice.to_string()))
         .encoding(Encoding::Linear16)
         .sample_rate(sample_rate)
         .container(if is_started == false {
@@ -30,11 +58,21 @@ pub async fn text_to_speech(
         .text_to_speech()
         .speak_to_stream(text, &options)
         .await;
-    if audio_stream.is_err() {
-        return Err(format!("Failed to create deepgram response stream"));
-    }
-    Ok(audio_stream.unwrap())
+    audio_stream.map_err(|e| format!("Failed to create deepgram response stream: {}", e))
+}
+// Lists the projects of the key, which fails for a revoked or mistyped key.
+pub async fn probe(api_token: &str) -> Result<(), String> {
+    reqwest::Client::new()
+        .get("https://api.deepgram.com/v1/projects")
+        .header(AUTHORIZATION, format!("Token {}", api_token))
+        .send()
+        .await
+        .map_err(|e| format!("Error in sending deepgram request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("Deepgram rejected the probe: {}", e))?;
+    Ok(())
 }
+
 pub async fn speech_to_text(
     api_token: &str,
     language: &str,
@@ -57,6 +95,7 @@ pub async fn speech_to_text(
     let response = client
         .post(url)
         .headers(headers)
+        .with_request_id()
         .body(audio_data)
         .send()
         .await
@@ -82,3 +121,66 @@ pub async fn speech_to_text(
         "Error in retrieving transcript field data in response"
     ));
 }
+
+/// Opens a live transcription session. Audio is sent as binary frames and
+/// results come back as JSON text frames, see `parse_live_message`.
+pub async fn connect_live(api_token: &str, options: &LiveOptions) -> Result<LiveSocket, String> {
+    let mut url = Url::parse(LIVE_URL).map_err(|e| format!("Invalid Deepgram URL: {}", e))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query
+            .append_pair("model", "nova-2")
+            .append_pair("interim_results", "true")
+            .append_pair("smart_format", "true");
+        if let Some(language) = options.language.as_deref() {
+            query.append_pair("language", language);
+        }
+        // Containerized audio (WebM, Ogg) is detected by Deepgram; raw audio must be described.
+        if let Some(encoding) = options.encoding.as_deref() {
+            query
+                .append_pair("encoding", encoding)
+                .append_pair("channels", "1");
+        }
+        if let Some(sample_rate) = options.sample_rate {
+            query.append_pair("sample_rate", &sample_rate.to_string());
+        }
+    }
+
+    let mut request = url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| format!("Invalid Deepgram request: {}", e))?;
+    request.headers_mut().insert(
+        "Authorization",
+        format!("Token {}", api_token)
+            .parse()
+            .map_err(|e| format!("Invalid Header Value: {}", e))?,
+    );
+    if let Some(value) = current_request_id().and_then(|id| id.parse().ok()) {
+        request.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let (socket, _) = connect_async(request)
+        .await
+        .map_err(|e| format!("Failed to connect to Deepgram live transcription: {}", e))?;
+    Ok(socket)
+}
+
+pub fn parse_live_message(text: &str) -> Option<LiveTranscript> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    if value.get("type").and_then(|t| t.as_str()) != Some("Results") {
+        return None;
+    }
+    let transcript = value
+        .get("channel")
+        .and_then(|channel| channel.get("alternatives"))
+        .and_then(|alternatives| alternatives.get(0))
+        .and_then(|alternative| alternative.get("transcript"))
+        .and_then(|transcript| transcript.as_str())?;
+    Some(LiveTranscript {
+        text: transcript.to_string(),
+        is_final: value["is_final"].as_bool().unwrap_or_default(),
+        speech_final: value["speech_final"].as_bool().unwrap_or_default(),
+        start: value["start"].as_f64().unwrap_or_default(),
+        duration: value["duration"].as_f64().unwrap_or_default(),
+    })
+}
@@ -0,0 +1,78 @@
+use futures::{Stream, StreamExt};
+use hyper::body::Bytes;
+use serde_json::json;
+use tracing::error;
+
+pub const SAMPLE_RATE: u32 = 16000;
+
+pub fn streaming_wav_header(sample_rate: u32) -> Bytes {
+    let byte_rate = sample_rate * 2;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    Bytes::from(header)
+}
+
+pub async fn probe(api_key: &str) -> Result<(), String> {
+    reqwest::Client::new()
+        .get("https://api.elevenlabs.io/v1/user")
+        .header("xi-api-key", api_key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send ElevenLabs request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("ElevenLabs rejected the probe: {}", e))?;
+    Ok(())
+}
+
+pub async fn text_to_speech(
+    api_key: &str,
+    model: &str,
+    voice_id: &str,
+    text: &str,
+    is_started: bool,
+) -> Result<impl Stream<Item = Bytes>, String> {
+    let request_url = format!(
+        "https://api.elevenlabs.io/v1/text-to-speech/{}/stream?output_format=pcm_{}",
+        voice_id, SAMPLE_RATE
+    );
+    let response = reqwest::Client::new()
+        .post(request_url)
+        .header("xi-api-key", api_key)
+        .json(&json!({
+            "text": text,
+            "model_id": model,
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send ElevenLabs request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("ElevenLabs speech synthesis failed: {}", e))?;
+
+    let header = if is_started {
+        None
+    } else {
+        Some(streaming_wav_header(SAMPLE_RATE))
+    };
+    let audio_stream = response.bytes_stream().filter_map(|chunk| async move {
+        match chunk {
+            Ok(data) => Some(data),
+            Err(e) => {
+                error!("ElevenLabs audio stream error: {}", e);
+                None
+            }
+        }
+    });
+    Ok(futures::stream::iter(header).chain(audio_stream))
+}
@@ -0,0 +1,154 @@
+use crate::{config::encryption::EncryptionConfig, entity::conversation};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use base64::prelude::*;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use tracing::{error, info};
+
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+// Only the text a user typed or said is encrypted; ids, roles and media names stay queryable.
+const ENCRYPTED_FIELDS: [&str; 2] = ["content", "transcription"];
+// Lists the encrypted fields of a stored message, empty while encryption is
+// off. The prefix alone cannot tell, since a user can type it.
+const ENCRYPTED_MARKER: &str = "encrypted_fields";
+
+static MESSAGE_CIPHER: OnceCell<Aes256Gcm> = OnceCell::new();
+
+/// Sets up message encryption for the process. Without a key, messages are
+/// written in plain text, but values encrypted earlier still fail loudly on read.
+pub fn install(config: &EncryptionConfig) -> Result<(), String> {
+    let Some(key) = config.message_key.as_deref() else {
+        return Ok(());
+    };
+    let key = BASE64_STANDARD
+        .decode(key.trim())
+        .map_err(|_| "The message encryption key is not valid base64".to_string())?;
+    let cipher = Aes256Gcm::new_from_slice(&key)
+        .map_err(|_| "The message encryption key has an invalid length".to_string())?;
+    MESSAGE_CIPHER
+        .set(cipher)
+        .map_err(|_| "Message encryption is already set up".to_string())?;
+    info!("Message encryption at rest is enabled.");
+    Ok(())
+}
+
+/// Encrypts the text fields of stored messages. Fields already marked as
+/// encrypted are left alone, so this is safe to call on a mix of old and new
+/// messages.
+pub fn encrypt_messages(values: Vec<Value>) -> Result<Vec<Value>, String> {
+    let cipher = MESSAGE_CIPHER.get();
+    values
+        .into_iter()
+        .map(|mut value| {
+            let mut encrypted = marked_fields(value.get(ENCRYPTED_MARKER)).unwrap_or_default();
+            if let Some(cipher) = cipher {
+                for field in ENCRYPTED_FIELDS {
+                    if encrypted.iter().any(|marked| marked == field) {
+                        continue;
+                    }
+                    if let Some(Value::String(text)) = value.get_mut(field) {
+                        *text = encrypt(cipher, text)?;
+                        encrypted.push(field.to_string());
+                    }
+                }
+            }
+            if let Some(object) = value.as_object_mut() {
+                object.insert(ENCRYPTED_MARKER.to_string(), json!(encrypted));
+            }
+            Ok(value)
+        })
+        .collect()
+}
+
+fn marked_fields(marker: Option<&Value>) -> Option<Vec<String>> {
+    marker.and_then(Value::as_array).map(|fields| {
+        fields
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+/// Decrypts the text fields of stored messages. Plain-text fields written
+/// before encryption was enabled pass through. A field that cannot be
+/// decrypted becomes `null` rather than reaching clients as ciphertext; an
+/// unreadable `content` gets the message quarantined.
+pub fn decrypt_messages(values: Vec<Value>) -> Vec<Value> {
+    values
+        .into_iter()
+        .map(|mut value| {
+            let marker = value
+                .as_object_mut()
+                .and_then(|object| object.remove(ENCRYPTED_MARKER));
+            let marked = marked_fields(marker.as_ref());
+            for field in ENCRYPTED_FIELDS {
+                let Some(slot) = value.get_mut(field) else {
+                    continue;
+                };
+                let Some(text) = slot.as_str() else {
+                    continue;
+                };
+                let encoded = match &marked {
+                    Some(marked) if marked.iter().any(|marked| marked == field) => {
+                        text.strip_prefix(ENCRYPTED_PREFIX).unwrap_or(text)
+                    }
+                    Some(_) => continue,
+                    // Messages saved before the marker existed only have the prefix to go by.
+                    None => match text.strip_prefix(ENCRYPTED_PREFIX) {
+                        Some(encoded) => encoded,
+                        None => continue,
+                    },
+                };
+                *slot = match decrypt(encoded) {
+                    Ok(text) => Value::String(text),
+                    Err(e) => {
+                        error!("Failed to decrypt the '{}' of a message: {}", field, e);
+                        Value::Null
+                    }
+                };
+            }
+            value
+        })
+        .collect()
+}
+
+pub fn decrypt_model(mut model: conversation::Model) -> conversation::Model {
+    model.conversation = decrypt_messages(model.conversation);
+    model
+}
+
+fn encrypt(cipher: &Aes256Gcm, text: &str) -> Result<String, String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, text.as_bytes())
+        .map_err(|e| format!("Failed to encrypt a message: {}", e))?;
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_PREFIX,
+        BASE64_STANDARD.encode(data)
+    ))
+}
+
+fn decrypt(encoded: &str) -> Result<String, String> {
+    let Some(cipher) = MESSAGE_CIPHER.get() else {
+        return Err("no message encryption key is configured".to_string());
+    };
+    let data = BASE64_STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("invalid base64: {}", e))?;
+    if data.len() < NONCE_LEN {
+        return Err("the value is too short".to_string());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| format!("authentication failed: {}", e))?;
+    String::from_utf8(plaintext).map_err(|e| format!("invalid UTF-8: {}", e))
+}
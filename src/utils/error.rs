@@ -1,11 +1,136 @@
-use axum::http::StatusCode;
+use crate::utils::request_id::current_request_id;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
 use tracing::error;
-pub fn format_error(
-    message: &str,
-    error: impl std::fmt::Display,
-    status: StatusCode,
-) -> (StatusCode, String) {
+
+pub type AppResult<T> = Result<T, AppError>;
+
+tokio::task_local! {
+    /// Id of the request currently being handled, set by `assign_request_id`.
+    pub static REQUEST_ID: String;
+}
+
+/// Machine-readable error codes clients can branch on. Codes are stable;
+/// messages are not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    Locked,
+    RateLimited,
+    UpstreamUnavailable,
+    InternalError,
+    InsufficientCredits,
+    CreditLimitReached,
+    BudgetExhausted,
+    UnknownModel,
+    InvalidMessageId,
+    ConversationNotFound,
+    ConversationLocked,
+    CollectionNotFound,
+    DocumentNotFound,
+    QueueFull,
+    RegionUnavailable,
+    ContentFlagged,
+}
+
+impl ErrorCode {
+    pub fn for_status(status: StatusCode) -> ErrorCode {
+        match status {
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::PAYMENT_REQUIRED => ErrorCode::CreditLimitReached,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::LOCKED => ErrorCode::Locked,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => ErrorCode::UpstreamUnavailable,
+            status if status.is_client_error() => ErrorCode::BadRequest,
+            _ => ErrorCode::InternalError,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppError {
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<Value>,
+    pub request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
+        AppError {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+            request_id: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn body(&self) -> Value {
+        json!({
+            "code": self.code,
+            "message": self.message,
+            "details": self.details,
+            "request_id": self.request_id,
+        })
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<(StatusCode, String)> for AppError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        AppError::new(status, ErrorCode::for_status(status), message)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(mut self) -> Response {
+        if self.request_id.is_none() {
+            self.request_id = current_request_id();
+        }
+        let mut response = (self.status, Json(self.body())).into_response();
+        // Kept on the response so outer middleware can rewrite the body.
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+pub fn format_error(message: &str, error: impl std::fmt::Display, status: StatusCode) -> AppError {
     let error_message = format!("{}: {}", message, error);
     error!("Error occurred: {}", error_message);
-    (status, error_message)
+    AppError::new(status, ErrorCode::for_status(status), error_message)
 }
@@ -1,43 +1,82 @@
-use mp3lame_encoder::{Builder, FlushNoGap, MonoPcm};
+use crate::utils::audio::encode_mp3;
+use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::prelude::*;
+use tracing::error;
+
+const CONTENT_HASH_LEN: usize = 32;
+
+/// Builds a media path whose name is derived from the file content, so a
+/// published URL always refers to the same bytes and can be cached forever.
+pub fn content_filename(dir: &str, scope: &str, data: &[u8], extension: Option<&str>) -> String {
+    let hash = hex::encode(Sha256::digest(data));
+    let hash = &hash[..CONTENT_HASH_LEN];
+    match extension {
+        Some(extension) => format!("{}/{}-{}.{}", dir, scope, hash, extension),
+        None => format!("{}/{}-{}", dir, scope, hash),
+    }
+}
+
+/// Returns the content hash embedded by `content_filename`, if the name has one.
+pub fn content_hash(filename: &str) -> Option<&str> {
+    let name = filename.rsplit('/').next()?;
+    let stem = name.split('.').next()?;
+    let hash = stem.rsplit('-').next()?;
+    (hash.len() == CONTENT_HASH_LEN && hash.bytes().all(|b| b.is_ascii_hexdigit())).then_some(hash)
+}
+fn create_file(filename: &str) -> std::io::Result<File> {
+    let path = format!("./public/{}", filename);
+    // Regional media lives in subdirectories that may not exist yet.
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    File::create(path)
+}
+
 pub fn save_file(filename: &str, filedata: Vec<u8>) -> std::io::Result<()> {
-    let mut file = File::create(format!("./public/{}", filename))?;
+    let mut file = create_file(filename)?;
     file.write_all(&filedata)?;
     Ok(())
 }
 
-pub fn save_audio_file(filename: &str, filedata: Vec<i16>) -> Result<(), String> {
-    let mut mp3_encoder = Builder::new().expect("Create LAME builder");
-    mp3_encoder.set_num_channels(2).expect("set channels");
-    mp3_encoder
-        .set_sample_rate(44_100)
-        .map_err(|e| e.to_string())?;
-    mp3_encoder
-        .set_brate(mp3lame_encoder::Bitrate::Kbps192)
-        .map_err(|e| e.to_string())?;
-    mp3_encoder
-        .set_quality(mp3lame_encoder::Quality::Best)
-        .map_err(|e| e.to_string())?;
-    let mut mp3_encoder = mp3_encoder.build().expect("To initialize LAME encoder");
-    let input = MonoPcm(filedata.as_slice());
-
-    let mut mp3_out_buffer = Vec::new();
-    mp3_out_buffer.reserve(mp3lame_encoder::max_required_buffer_size(filedata.len()));
-    let encoded_size = mp3_encoder
-        .encode(input, mp3_out_buffer.spare_capacity_mut())
-        .map_err(|e| e.to_string())?;
-    unsafe {
-        mp3_out_buffer.set_len(mp3_out_buffer.len().wrapping_add(encoded_size));
+pub fn file_exists(filename: &str) -> bool {
+    std::path::Path::new(&format!("./public/{}", filename)).exists()
+}
+
+pub fn delete_files(filenames: &[String]) -> usize {
+    let mut deleted = 0;
+    for filename in filenames {
+        if filename.contains("..") {
+            continue;
+        }
+        match std::fs::remove_file(format!("./public/{}", filename)) {
+            Ok(()) => deleted += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => error!("Failed to delete media file '{}': {}", filename, e),
+        }
     }
+    deleted
+}
 
-    let encoded_size = mp3_encoder
-        .flush::<FlushNoGap>(mp3_out_buffer.spare_capacity_mut())
-        .map_err(|e| e.to_string())?;
-    unsafe {
-        mp3_out_buffer.set_len(mp3_out_buffer.len().wrapping_add(encoded_size));
+pub fn wav_header_len(audio: &[u8]) -> usize {
+    if !audio.starts_with(b"RIFF") {
+        return 0;
     }
-    let mut file = File::create(format!("./public/{}", filename)).map_err(|e| e.to_string())?;
-    file.write_all(&mp3_out_buffer).map_err(|e| e.to_string())?;
-    return Ok(());
+    audio
+        .windows(4)
+        .position(|window| window == b"data")
+        .map(|pos| (pos + 8).min(audio.len()))
+        .unwrap_or(0)
+}
+
+/// Encodes the samples as MP3 and returns the size of the written file.
+pub fn save_audio_file(
+    filename: &str,
+    filedata: Vec<i16>,
+    sample_rate: u32,
+) -> Result<usize, String> {
+    let mp3 = encode_mp3(&filedata, sample_rate)?;
+    let mut file = create_file(filename).map_err(|e| e.to_string())?;
+    file.write_all(&mp3).map_err(|e| e.to_string())?;
+    Ok(mp3.len())
 }
@@ -0,0 +1,78 @@
+use crate::{config::history_cache::HistoryCacheConfig, entity::conversation, utils::metrics};
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+use uuid::Uuid;
+
+struct CachedConversation {
+    // Postgres `xmin` of the row the model was read from.
+    row_version: String,
+    model: conversation::Model,
+    last_used: Instant,
+}
+
+/// Recently used conversations, decrypted and deserialized, keyed by id, so a
+/// chat turn does not read the whole message history from the database again.
+/// An entry only counts while its row version matches the stored row, so any
+/// write to the conversation, by this instance or another, drops it.
+pub struct HistoryCache {
+    capacity: usize,
+    entries: Mutex<HashMap<Uuid, CachedConversation>>,
+}
+
+impl HistoryCache {
+    pub fn new(config: &HistoryCacheConfig) -> Self {
+        HistoryCache {
+            capacity: config.capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn get(
+        &self,
+        user_id: i64,
+        conversation_id: Uuid,
+        row_version: &str,
+    ) -> Option<conversation::Model> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = match entries.get_mut(&conversation_id) {
+            Some(entry) if entry.row_version == row_version && entry.model.user_id == user_id => {
+                entry.last_used = Instant::now();
+                Some(entry.model.clone())
+            }
+            Some(_) => {
+                entries.remove(&conversation_id);
+                None
+            }
+            None => None,
+        };
+        metrics::history_cache_lookup(cached.is_some());
+        cached
+    }
+
+    pub fn insert(&self, row_version: String, model: conversation::Model) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity && !entries.contains_key(&model.id) {
+            let least_recent = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| *id);
+            if let Some(least_recent) = least_recent {
+                entries.remove(&least_recent);
+            }
+        }
+        entries.insert(
+            model.id,
+            CachedConversation {
+                row_version,
+                model,
+                last_used: Instant::now(),
+            },
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::utils::{
+    error::{AppError, ErrorCode},
+    language::normalize_language,
+};
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+pub const ERROR_CODE_HEADER: &str = "x-error-code";
+
+const SUPPORTED_LANGUAGES: &[&str] = &["en", "es", "fr", "de", "pt"];
+
+// Only codes that name a specific failure are translated. Errors carrying a
+// generic status code keep their original message, which is more useful than
+// a translated "the request is invalid".
+static TRANSLATIONS: Lazy<HashMap<(&'static str, ErrorCode), &'static str>> = Lazy::new(|| {
+    let entries: &[(ErrorCode, [&str; 5])] = &[
+        (
+            ErrorCode::InsufficientCredits,
+            [
+                "You don't have enough credits for this.",
+                "No tienes créditos suficientes para esto.",
+                "Vous n'avez pas assez de crédits pour cela.",
+                "Dafür reicht dein Guthaben nicht aus.",
+                "Você não tem créditos suficientes para isso.",
+            ],
+        ),
+        (
+            ErrorCode::CreditLimitReached,
+            [
+                "The credit limit for this has been reached.",
+                "Se alcanzó el límite de créditos para esto.",
+                "La limite de crédits a été atteinte.",
+                "Das Guthabenlimit hierfür ist erreicht.",
+                "O limite de créditos para isso foi atingido.",
+            ],
+        ),
+        (
+            ErrorCode::BudgetExhausted,
+            [
+                "This is unavailable for the rest of the day. Please try again tomorrow.",
+                "Esto no está disponible por el resto del día. Inténtalo de nuevo mañana.",
+                "Ce service est indisponible pour le reste de la journée. Réessayez demain.",
+                "Dies ist für den Rest des Tages nicht verfügbar. Bitte versuche es morgen erneut.",
+                "Isso está indisponível pelo resto do dia. Tente novamente amanhã.",
+            ],
+        ),
+        (
+            ErrorCode::UnknownModel,
+            [
+                "The selected model is not available.",
+                "El modelo seleccionado no está disponible.",
+                "Le modèle sélectionné n'est pas disponible.",
+                "Das ausgewählte Modell ist nicht verfügbar.",
+                "O modelo selecionado não está disponível.",
+            ],
+        ),
+        (
+            ErrorCode::InvalidMessageId,
+            [
+                "The message could not be found in this conversation.",
+                "No se encontró el mensaje en esta conversación.",
+                "Le message est introuvable dans cette conversation.",
+                "Die Nachricht wurde in dieser Unterhaltung nicht gefunden.",
+                "A mensagem não foi encontrada nesta conversa.",
+            ],
+        ),
+        (
+            ErrorCode::ConversationNotFound,
+            [
+                "We couldn't find this conversation.",
+                "No encontramos esta conversación.",
+                "Nous n'avons pas trouvé cette conversation.",
+                "Diese Unterhaltung wurde nicht gefunden.",
+                "Não encontramos esta conversa.",
+            ],
+        ),
+        (
+            ErrorCode::ConversationLocked,
+            [
+                "This conversation is locked.",
+                "Esta conversación está bloqueada.",
+                "Cette conversation est verrouillée.",
+                "Diese Unterhaltung ist gesperrt.",
+                "Esta conversa está bloqueada.",
+            ],
+        ),
+        (
+            ErrorCode::CollectionNotFound,
+            [
+                "We couldn't find this collection.",
+                "No encontramos esta colección.",
+                "Nous n'avons pas trouvé cette collection.",
+                "Diese Sammlung wurde nicht gefunden.",
+                "Não encontramos esta coleção.",
+            ],
+        ),
+        (
+            ErrorCode::DocumentNotFound,
+            [
+                "We couldn't find this document.",
+                "No encontramos este documento.",
+                "Nous n'avons pas trouvé ce document.",
+                "Dieses Dokument wurde nicht gefunden.",
+                "Não encontramos este documento.",
+            ],
+        ),
+        (
+            ErrorCode::QueueFull,
+            [
+                "The assistant is busy. Please try again shortly.",
+                "El asistente está ocupado. Inténtalo de nuevo en breve.",
+                "L'assistant est occupé. Réessayez dans un instant.",
+                "Der Assistent ist ausgelastet. Bitte versuche es gleich erneut.",
+                "O assistente está ocupado. Tente novamente em instantes.",
+            ],
+        ),
+        (
+            ErrorCode::RegionUnavailable,
+            [
+                "This model is not available in your data region.",
+                "Este modelo no está disponible en tu región de datos.",
+                "Ce modèle n'est pas disponible dans votre région de données.",
+                "Dieses Modell ist in deiner Datenregion nicht verfügbar.",
+                "Este modelo não está disponível na sua região de dados.",
+            ],
+        ),
+        (
+            ErrorCode::ContentFlagged,
+            [
+                "Your message was flagged by our content policy.",
+                "Tu mensaje fue marcado por nuestra política de contenido.",
+                "Votre message a été signalé par notre politique de contenu.",
+                "Deine Nachricht wurde von unserer Inhaltsrichtlinie markiert.",
+                "Sua mensagem foi sinalizada pela nossa política de conteúdo.",
+            ],
+        ),
+    ];
+    let mut translations = HashMap::new();
+    for (code, messages) in entries {
+        for (language, message) in SUPPORTED_LANGUAGES.iter().zip(messages) {
+            translations.insert((*language, *code), *message);
+        }
+    }
+    translations
+});
+
+pub fn code_for_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::PAYMENT_REQUIRED => "credit_limit_reached",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::LOCKED => "locked",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            "upstream_unavailable"
+        }
+        status if status.is_client_error() => "bad_request",
+        _ => "internal_error",
+    }
+}
+
+pub fn translate(code: ErrorCode, language: &str) -> Option<&'static str> {
+    TRANSLATIONS
+        .get(&(language, code))
+        .or_else(|| TRANSLATIONS.get(&(DEFAULT_LANGUAGE, code)))
+        .copied()
+}
+
+/// Picks the supported language with the highest `q` weight from an
+/// `Accept-Language` header value.
+pub fn negotiate(accept_language: &str) -> Option<&'static str> {
+    let mut best: Option<(&'static str, f32)> = None;
+    for entry in accept_language.split(',') {
+        let mut parts = entry.split(';');
+        let Some(code) = parts.next().and_then(normalize_language) else {
+            continue;
+        };
+        let weight = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let Some(language) = SUPPORTED_LANGUAGES.iter().find(|l| **l == code) else {
+            continue;
+        };
+        if weight > 0.0 && best.map_or(true, |(_, w)| weight > w) {
+            best = Some((*language, weight));
+        }
+    }
+    best.map(|(language, _)| language)
+}
+
+/// Tags every error response with its error code and, for clients asking for a
+/// supported non-default language, replaces the message of errors with a
+/// specific code by the translated one. Other errors are left as they are.
+pub async fn localize_errors(req: Request, next: Next) -> Response {
+    let language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(negotiate);
+    let response = next.run(req).await;
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return response;
+    }
+
+    let code = response
+        .headers()
+        .get(ERROR_CODE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| code_for_status(status).to_string());
+    let (mut parts, body) = response.into_parts();
+    if let Ok(value) = HeaderValue::from_str(&code) {
+        parts.headers.insert(ERROR_CODE_HEADER, value);
+    }
+
+    let Some(language) = language.filter(|l| *l != DEFAULT_LANGUAGE) else {
+        return Response::from_parts(parts, body);
+    };
+    let Some(mut error) = parts.extensions.remove::<AppError>() else {
+        return Response::from_parts(parts, body);
+    };
+    let Some(message) = translate(error.code, language) else {
+        parts.extensions.insert(error);
+        return Response::from_parts(parts, body);
+    };
+    error.message = message.to_string();
+    let mut response = error.into_response();
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().append(name, value.clone());
+        }
+    }
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LANGUAGE, HeaderValue::from_static(language));
+    response
+}
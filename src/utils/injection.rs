@@ -0,0 +1,37 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static INJECTION_PATTERNS: Lazy<Vec<(&'static str, Regex)>> = Lazy::new(|| {
+    [
+        (
+            "override",
+            r"(?i)\b(ignore|disregard|forget|override)\b.{0,40}\b(previous|prior|above|earlier|all|system)\b.{0,20}\b(instructions?|prompts?|rules|messages?)\b",
+        ),
+        (
+            "role_reassignment",
+            r"(?i)\byou are (now|no longer)\b|\bact as (an? )?(unrestricted|jailbroken|developer mode)\b",
+        ),
+        (
+            "prompt_exfiltration",
+            r"(?i)\b(reveal|print|repeat|show|output)\b.{0,30}\b(system prompt|hidden instructions?|initial instructions?)\b",
+        ),
+        (
+            "fake_turn",
+            r"(?im)^\s*(system|assistant)\s*:|<\|?(im_start|im_end|system)\|?>",
+        ),
+        (
+            "tool_directive",
+            r"(?i)\b(call|invoke|execute|run)\b.{0,30}\b(tool|function|command)\b.{0,40}\b(without|do not|don't)\b.{0,20}\b(asking|telling|confirm)",
+        ),
+    ]
+    .into_iter()
+    .map(|(name, pattern)| (name, Regex::new(pattern).unwrap()))
+    .collect()
+});
+
+pub fn detect_injection(text: &str) -> Option<&'static str> {
+    INJECTION_PATTERNS
+        .iter()
+        .find(|(_, pattern)| pattern.is_match(text))
+        .map(|(name, _)| *name)
+}
@@ -1,4 +1,8 @@
-use crate::{dto::response::SessionData, ServiceState};
+use crate::{
+    dto::response::SessionData,
+    utils::{error::AppError, request_id::WithRequestId},
+    ServiceState,
+};
 use axum::{
     extract::FromRequestParts,
     http::{request::Parts, StatusCode},
@@ -25,6 +29,8 @@ pub struct UserClaims {
     pub sid: Uuid,
     pub session_data: Option<SessionData>,
     pub token: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
 impl UserClaims {
@@ -35,11 +41,47 @@ impl UserClaims {
             &DECODE_HEADER,
         )
     }
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+    /// Reads the session from the auth service again instead of the cache.
+    /// Callers that outlive a single request, like the chat socket, call this
+    /// before each message so they never charge against an old balance.
+    pub async fn refresh_session(&mut self, state: &ServiceState) -> Result<(), AppError> {
+        let Some(token) = self.token.clone() else {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "Invalid authorization header".to_string(),
+            )
+                .into());
+        };
+        let valid = self
+            .check_session(state.config.server.auth_service.as_str(), &token)
+            .await
+            .map_err(|e| {
+                let error_message = format!("Failed to check session: {}", e);
+                error!("{}", error_message);
+                AppError::from((StatusCode::INTERNAL_SERVER_ERROR, error_message))
+            })?;
+        if !valid {
+            state.sessions.invalidate_user(self.uid);
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "The session is no longer valid".to_string(),
+            )
+                .into());
+        }
+        if let Some(session_data) = self.session_data.clone() {
+            state.sessions.insert(self.sid, self.uid, session_data);
+        }
+        Ok(())
+    }
     async fn check_session(&mut self, auth_uri: &str, token: &str) -> Result<bool, String> {
         let client = reqwest::Client::new();
         self.token = Some(token.to_string());
         match client
             .get(&format!("{}/session", auth_uri))
+            .with_request_id()
             .bearer_auth(token)
             .send()
             .await
@@ -66,7 +108,7 @@ impl UserClaims {
 
 #[async_trait::async_trait]
 impl FromRequestParts<Arc<ServiceState>> for UserClaims {
-    type Rejection = (StatusCode, String);
+    type Rejection = AppError;
 
     async fn from_request_parts(
         parts: &mut Parts,
@@ -94,6 +136,12 @@ impl FromRequestParts<Arc<ServiceState>> for UserClaims {
                 })?
                 .claims;
 
+        if let Some(session_data) = state.sessions.get(user_claims.sid, user_claims.uid) {
+            user_claims.token = Some(bearer.token().to_string());
+            user_claims.session_data = Some(session_data);
+            return Ok(user_claims);
+        }
+
         if user_claims
             .check_session(state.config.server.auth_service.as_str(), bearer.token())
             .await
@@ -108,8 +156,14 @@ impl FromRequestParts<Arc<ServiceState>> for UserClaims {
             return Err((
                 StatusCode::UNAUTHORIZED,
                 "Invalid authorization header".to_string(),
-            ));
+            )
+                .into());
         };
+        if let Some(session_data) = user_claims.session_data.clone() {
+            state
+                .sessions
+                .insert(user_claims.sid, user_claims.uid, session_data);
+        }
 
         Ok(user_claims)
     }
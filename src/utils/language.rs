@@ -0,0 +1,53 @@
+use isolang::Language;
+use std::collections::HashMap;
+
+const MIN_DETECTION_CHARS: usize = 20;
+
+pub fn normalize_language(code: &str) -> Option<String> {
+    let code = code.trim().to_lowercase();
+    let code = code.split(['-', '_']).next().unwrap_or_default();
+    let language = match code.len() {
+        2 => Language::from_639_1(code),
+        3 => Language::from_639_3(code),
+        _ => None,
+    }?;
+    language.to_639_1().map(|c| c.to_string())
+}
+
+pub fn language_name(code: &str) -> Option<&'static str> {
+    Language::from_639_1(code).map(|language| language.to_name())
+}
+
+pub fn detect_language(text: &str) -> Option<String> {
+    if text.trim().chars().count() < MIN_DETECTION_CHARS {
+        return None;
+    }
+    let info = whatlang::detect(text).filter(|info| info.is_reliable())?;
+    Language::from_639_3(info.lang().code())
+        .and_then(|language| language.to_639_1())
+        .map(|c| c.to_string())
+}
+
+/// Detects the language most of `texts` is written in, weighting every text
+/// by its length so a few short greetings do not outvote the conversation.
+pub fn detect_dominant_language<'a>(texts: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for text in texts {
+        if let Some(code) = detect_language(text) {
+            *totals.entry(code).or_default() += text.chars().count();
+        }
+    }
+    totals
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(code, _)| code)
+}
+
+pub fn language_instruction(code: &str) -> Option<String> {
+    language_name(code).map(|name| {
+        format!(
+            "Always respond in {} unless the user explicitly asks for another language.",
+            name
+        )
+    })
+}
@@ -0,0 +1,69 @@
+use crate::utils::file::wav_header_len;
+
+const BLOCK_SAMPLES: usize = 1600;
+const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+const PEAK_CEILING: f32 = 0.98;
+
+/// Gated loudness of 16-bit PCM in LUFS, measured on 100 ms blocks at 16 kHz
+/// without K-weighting.
+pub fn loudness(samples: &[i16]) -> Option<f32> {
+    let gated: Vec<f32> = samples
+        .chunks(BLOCK_SAMPLES)
+        .map(|block| {
+            block
+                .iter()
+                .map(|&s| {
+                    let s = s as f32 / i16::MAX as f32;
+                    s * s
+                })
+                .sum::<f32>()
+                / block.len() as f32
+        })
+        .filter(|&power| power > 0.0 && to_lufs(power) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    if gated.is_empty() {
+        return None;
+    }
+    Some(to_lufs(gated.iter().sum::<f32>() / gated.len() as f32))
+}
+
+fn to_lufs(power: f32) -> f32 {
+    -0.691 + 10.0 * power.log10()
+}
+
+pub fn normalize(samples: &mut [i16], target_lufs: f32, max_gain_db: f32) {
+    let Some(current) = loudness(samples) else {
+        return;
+    };
+    let gain_db = (target_lufs - current).clamp(-max_gain_db, max_gain_db);
+    let mut gain = 10f32.powf(gain_db / 20.0);
+    let peak = samples
+        .iter()
+        .map(|&s| (s as f32 / i16::MAX as f32).abs())
+        .fold(0.0, f32::max);
+    if peak * gain > PEAK_CEILING {
+        gain = PEAK_CEILING / peak;
+    }
+    for sample in samples.iter_mut() {
+        *sample = (*sample as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// Normalizes a synthesized linear16 segment, leaving a leading WAV header intact.
+pub fn normalize_pcm_bytes(audio: &[u8], target_lufs: f32, max_gain_db: f32) -> Vec<u8> {
+    let header_len = wav_header_len(audio);
+    let (header, pcm) = audio.split_at(header_len);
+    let mut samples: Vec<i16> = pcm
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    normalize(&mut samples, target_lufs, max_gain_db);
+
+    let mut output = Vec::with_capacity(audio.len());
+    output.extend_from_slice(header);
+    output.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+    if pcm.len() % 2 == 1 {
+        output.push(pcm[pcm.len() - 1]);
+    }
+    output
+}
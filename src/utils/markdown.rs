@@ -0,0 +1,359 @@
+use crate::config::{
+    chat::MarkdownMode,
+    tts::{ArtifactMode, TtsConfig},
+};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+static MARKDOWN_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[([^\]]*)\]\((https?://[^)\s]+)\)").unwrap());
+pub static BARE_URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[^\s)>\]]+").unwrap());
+static INLINE_IMAGE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"!\[([^\]]*)\]\(([^)\s]*)[^)]*\)").unwrap());
+static INLINE_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[([^\]]+)\]\(([^)\s]*)[^)]*\)").unwrap());
+static HEADING: Lazy<Regex> = Lazy::new(|| Regex::new(r"^#{1,6}(\s+|$)").unwrap());
+static BLOCKQUOTE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(>\s?)+").unwrap());
+static BULLET: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[-*+]\s+").unwrap());
+static RULE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\*{3,}|-{3,}|_{3,})$").unwrap());
+static TABLE_SEPARATOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\|?[\s:|-]+\|?$").unwrap());
+
+const INLINE_MARKERS: [char; 4] = ['*', '_', '`', '~'];
+const LINE_START_MARKERS: &str = "#>-*+_=0123456789. ";
+
+fn url_host(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            u.host_str()
+                .map(|h| h.trim_start_matches("www.").to_string())
+        })
+        .unwrap_or_else(|| "a website".to_string())
+}
+
+pub struct SpeechFilter {
+    code_blocks: ArtifactMode,
+    tables: ArtifactMode,
+    urls: ArtifactMode,
+    pending: String,
+    in_code_block: bool,
+    code_language: String,
+    in_table: bool,
+}
+
+impl SpeechFilter {
+    pub fn from_config(config: &TtsConfig) -> Self {
+        SpeechFilter {
+            code_blocks: config.code_blocks,
+            tables: config.tables,
+            urls: config.urls,
+            pending: String::new(),
+            in_code_block: false,
+            code_language: String::new(),
+            in_table: false,
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let mut output = String::new();
+        while let Some(pos) = self.pending.find('\n') {
+            let rest = self.pending.split_off(pos + 1);
+            let line = std::mem::replace(&mut self.pending, rest);
+            output.push_str(&self.process_line(&line));
+        }
+
+        let partial = self.pending.trim_start();
+        let may_be_artifact = self.in_code_block
+            || partial.is_empty()
+            || partial.starts_with('`')
+            || partial.starts_with('|');
+        if !may_be_artifact {
+            if let Some(pos) = self.pending.rfind(char::is_whitespace) {
+                let rest = self.pending.split_off(pos);
+                let words = std::mem::replace(&mut self.pending, rest);
+                self.in_table = false;
+                output.push_str(&self.replace_urls(&words));
+            }
+        }
+        output
+    }
+
+    pub fn flush(&mut self) -> String {
+        let line = std::mem::take(&mut self.pending);
+        let mut output = self.process_line(&line);
+        if self.in_code_block {
+            self.in_code_block = false;
+            output.push_str(&self.code_block_summary());
+        }
+        self.in_table = false;
+        output
+    }
+
+    fn process_line(&mut self, line: &str) -> String {
+        let trimmed = line.trim();
+        if self.code_blocks != ArtifactMode::Keep {
+            if let Some(language) = trimmed.strip_prefix("```") {
+                if self.in_code_block {
+                    self.in_code_block = false;
+                    return self.code_block_summary();
+                }
+                self.in_code_block = true;
+                self.code_language = language.trim().to_string();
+                return String::new();
+            }
+            if self.in_code_block {
+                return String::new();
+            }
+        }
+
+        if self.tables != ArtifactMode::Keep && trimmed.starts_with('|') {
+            if self.in_table {
+                return String::new();
+            }
+            self.in_table = true;
+            return match self.tables {
+                ArtifactMode::Summarize => "There is a table in the text version.\n".to_string(),
+                _ => String::new(),
+            };
+        }
+        self.in_table = false;
+        self.replace_urls(line)
+    }
+
+    fn code_block_summary(&self) -> String {
+        match self.code_blocks {
+            ArtifactMode::Summarize if self.code_language.is_empty() => {
+                "There is a code example in the text version.\n".to_string()
+            }
+            ArtifactMode::Summarize => format!(
+                "There is a {} code example in the text version.\n",
+                self.code_language
+            ),
+            _ => String::new(),
+        }
+    }
+
+    fn replace_urls(&self, text: &str) -> String {
+        let mode = self.urls;
+        if mode == ArtifactMode::Keep {
+            return text.to_string();
+        }
+        let text = MARKDOWN_LINK.replace_all(text, |caps: &Captures| match mode {
+            ArtifactMode::Summarize if caps[1].trim().is_empty() => {
+                format!("a link to {}", url_host(&caps[2]))
+            }
+            _ => caps[1].to_string(),
+        });
+        BARE_URL
+            .replace_all(&text, |caps: &Captures| match mode {
+                ArtifactMode::Summarize => format!("a link to {}", url_host(&caps[0])),
+                _ => String::new(),
+            })
+            .into_owned()
+    }
+}
+
+pub struct MarkdownSanitizer {
+    mode: MarkdownMode,
+    pending: String,
+    line_started: bool,
+    in_code_block: bool,
+    prev_char: Option<char>,
+}
+
+impl MarkdownSanitizer {
+    pub fn new(mode: MarkdownMode) -> Self {
+        MarkdownSanitizer {
+            mode,
+            pending: String::new(),
+            line_started: false,
+            in_code_block: false,
+            prev_char: None,
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> String {
+        if self.mode == MarkdownMode::Keep {
+            return text.to_string();
+        }
+        self.pending.push_str(text);
+        let mut output = String::new();
+        while let Some(pos) = self.pending.find('\n') {
+            let rest = self.pending.split_off(pos + 1);
+            let line = std::mem::replace(&mut self.pending, rest);
+            output.push_str(&self.process(&line, true));
+        }
+
+        let safe = self.safe_prefix_len();
+        if safe > 0 {
+            let rest = self.pending.split_off(safe);
+            let head = std::mem::replace(&mut self.pending, rest);
+            output.push_str(&self.process(&head, false));
+        }
+        output
+    }
+
+    pub fn flush(&mut self) -> String {
+        if self.mode == MarkdownMode::Keep {
+            return String::new();
+        }
+        let line = std::mem::take(&mut self.pending);
+        let output = self.process(&line, true);
+        self.in_code_block = false;
+        output
+    }
+
+    fn safe_prefix_len(&self) -> usize {
+        let pending = self.pending.as_str();
+        if !self.line_started {
+            let trimmed = pending.trim_start();
+            if trimmed.is_empty()
+                || trimmed.starts_with('|')
+                || trimmed.starts_with('`')
+                || trimmed.starts_with('~')
+            {
+                return 0;
+            }
+            if !self.in_code_block && trimmed.chars().all(|c| LINE_START_MARKERS.contains(c)) {
+                return 0;
+            }
+        }
+        if self.in_code_block {
+            return pending.len();
+        }
+
+        let trailing: usize = pending
+            .chars()
+            .rev()
+            .take_while(|c| INLINE_MARKERS.contains(c) || *c == '!')
+            .map(char::len_utf8)
+            .sum();
+        let mut cut = pending.len() - trailing;
+        if let Some(open) = pending[..cut].rfind('[') {
+            if !pending[open..].contains(')') {
+                cut = if pending[..open].ends_with('!') {
+                    open - 1
+                } else {
+                    open
+                };
+            }
+        }
+        cut
+    }
+
+    fn process(&mut self, text: &str, line_complete: bool) -> String {
+        let mut output = String::new();
+        let mut body = text;
+        if !self.line_started {
+            let trimmed = text.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                self.in_code_block = !self.in_code_block;
+                return String::new();
+            }
+            if !self.in_code_block {
+                let indent_len = text.len() - text.trim_start().len();
+                output.push_str(&text[..indent_len]);
+                body = &text[indent_len..];
+                if RULE.is_match(trimmed) {
+                    return String::new();
+                }
+                if trimmed.starts_with('|') {
+                    return self.table_row(trimmed);
+                }
+                body = match HEADING.find(body) {
+                    Some(m) => &body[m.end()..],
+                    None => body,
+                };
+                body = match BLOCKQUOTE.find(body) {
+                    Some(m) => &body[m.end()..],
+                    None => body,
+                };
+                if let Some(m) = BULLET.find(body) {
+                    if self.mode == MarkdownMode::Normalize {
+                        output.push_str("• ");
+                    }
+                    body = &body[m.end()..];
+                }
+            }
+        }
+
+        if self.in_code_block {
+            output.push_str(body);
+        } else {
+            output.push_str(&self.inline(body));
+        }
+        self.line_started = !line_complete;
+        if line_complete {
+            self.prev_char = None;
+        }
+        output
+    }
+
+    fn table_row(&mut self, row: &str) -> String {
+        self.line_started = false;
+        if TABLE_SEPARATOR.is_match(row) {
+            return String::new();
+        }
+        let cells: Vec<String> = row
+            .trim_matches('|')
+            .split('|')
+            .map(|cell| self.inline(cell.trim()))
+            .collect();
+        let separator = match self.mode {
+            MarkdownMode::Normalize => " | ",
+            _ => ", ",
+        };
+        format!("{}\n", cells.join(separator))
+    }
+
+    fn inline(&mut self, text: &str) -> String {
+        let text = INLINE_IMAGE.replace_all(text, "$1");
+        let text = match self.mode {
+            MarkdownMode::Normalize => INLINE_LINK.replace_all(&text, "$1 ($2)"),
+            _ => INLINE_LINK.replace_all(&text, "$1"),
+        };
+
+        let chars: Vec<char> = text.chars().collect();
+        let mut output = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if !INLINE_MARKERS.contains(&c) {
+                output.push(c);
+                i += 1;
+                continue;
+            }
+            let run_start = i;
+            while i < chars.len() && chars[i] == c {
+                i += 1;
+            }
+            let run_len = i - run_start;
+            let prev = if run_start == 0 {
+                self.prev_char
+            } else {
+                Some(chars[run_start - 1])
+            };
+            let next = chars.get(i).copied();
+            let opening = next.is_some_and(|n| !n.is_whitespace());
+            let closing = prev.is_some_and(|p| !p.is_whitespace());
+            let remove = match c {
+                '`' => true,
+                '~' => run_len >= 2,
+                '_' if prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric) =>
+                {
+                    false
+                }
+                _ => opening || closing,
+            };
+            if !remove {
+                output.extend(std::iter::repeat_n(c, run_len));
+            }
+        }
+        if let Some(&last) = chars.last() {
+            self.prev_char = Some(last);
+        }
+        output
+    }
+}
@@ -0,0 +1,72 @@
+use crate::utils::file::content_hash;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::{path::Path, time::UNIX_EPOCH};
+
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+// Files saved before names were content-addressed may still be overwritten.
+const MUTABLE_CACHE_CONTROL: &str = "public, max-age=300, must-revalidate";
+
+/// Adds `Cache-Control` and `ETag` headers to media served from `dir` and
+/// answers matching `If-None-Match` requests with `304 Not Modified`.
+pub async fn cache_media(State(dir): State<&'static str>, req: Request, next: Next) -> Response {
+    let filename = req.uri().path().trim_start_matches('/').to_string();
+    if filename.contains("..") {
+        return next.run(req).await;
+    }
+    let (etag, cache_control) = match content_hash(&filename) {
+        Some(hash) => (Some(format!("\"{}\"", hash)), IMMUTABLE_CACHE_CONTROL),
+        None => (modified_etag(dir, &filename), MUTABLE_CACHE_CONTROL),
+    };
+
+    if let Some(etag) = etag.as_deref() {
+        let matches = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.split(',')
+                    .any(|tag| tag.trim() == etag || tag.trim() == "*")
+            });
+        // A deleted or purged file must not keep looking valid, so it falls
+        // through to the file service and its 404.
+        if matches && Path::new(&format!("{}/{}", dir, filename)).is_file() {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_MODIFIED;
+            insert_headers(&mut response, Some(etag), cache_control);
+            return response;
+        }
+    }
+
+    let mut response = next.run(req).await;
+    if response.status().is_success() {
+        insert_headers(&mut response, etag.as_deref(), cache_control);
+    }
+    response
+}
+
+fn modified_etag(dir: &str, filename: &str) -> Option<String> {
+    let metadata = std::fs::metadata(format!("{}/{}", dir, filename)).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "W/\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_secs()
+    ))
+}
+
+fn insert_headers(response: &mut Response, etag: Option<&str>, cache_control: &'static str) {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+}
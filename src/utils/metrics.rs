@@ -0,0 +1,195 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::time::Instant;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+// Streaming replies are measured until their headers are sent, not until the stream ends.
+static HTTP_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "Time to produce the response headers, per route",
+        ),
+        &["method", "route", "status"],
+    ))
+});
+
+static FIRST_TOKEN_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "chat_first_token_seconds",
+            "Time from receiving a message to the first streamed token",
+        )
+        .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0]),
+        &["model"],
+    ))
+});
+
+static TOKENS_STREAMED: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "chat_tokens_streamed_total",
+        "Content deltas streamed from chat completions",
+    ))
+});
+
+static TOKENS_USED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "chat_tokens_total",
+            "Prompt and completion tokens per model",
+        ),
+        &["model", "kind"],
+    ))
+});
+
+static TTS_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "tts_request_seconds",
+            "Time for a speech provider to start streaming a segment",
+        ),
+        &["provider"],
+    ))
+});
+
+static CREDITS_CONSUMED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("credits_consumed_total", "Credits charged to users"),
+        &["model"],
+    ))
+});
+
+static DB_TRANSACTION_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "db_transaction_failures_total",
+            "Database transactions that failed to begin, commit or roll back",
+        ),
+        &["stage"],
+    ))
+});
+
+static SESSION_CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "session_cache_lookups_total",
+            "Session data lookups answered from the cache or the auth service",
+        ),
+        &["result"],
+    ))
+});
+
+static HISTORY_CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "history_cache_lookups_total",
+            "Conversation history lookups answered from the cache or the database",
+        ),
+        &["result"],
+    ))
+});
+
+static PROVIDER_UP: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new(
+            "provider_up",
+            "Whether the last health probe of an upstream provider succeeded",
+        ),
+        &["provider"],
+    ))
+});
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: prometheus::Result<T>) -> T {
+    let metric = metric.expect("metric definitions are valid");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
+pub fn observe_first_token(model: &str, seconds: f64) {
+    FIRST_TOKEN_DURATION
+        .with_label_values(&[model])
+        .observe(seconds);
+}
+
+pub fn add_streamed_tokens(count: usize) {
+    TOKENS_STREAMED.inc_by(count as u64);
+}
+
+pub fn add_token_usage(model: &str, prompt_tokens: u64, completion_tokens: u64) {
+    TOKENS_USED
+        .with_label_values(&[model, "prompt"])
+        .inc_by(prompt_tokens);
+    TOKENS_USED
+        .with_label_values(&[model, "completion"])
+        .inc_by(completion_tokens);
+}
+
+pub fn observe_tts(provider: &str, seconds: f64) {
+    TTS_DURATION.with_label_values(&[provider]).observe(seconds);
+}
+
+pub fn add_credits(model: &str, credits: i64) {
+    if credits > 0 {
+        CREDITS_CONSUMED
+            .with_label_values(&[model])
+            .inc_by(credits as u64);
+    }
+}
+
+/// `stage` is one of `begin`, `commit` or `rollback`.
+pub fn transaction_failed(stage: &str) {
+    DB_TRANSACTION_FAILURES.with_label_values(&[stage]).inc();
+}
+
+pub fn session_cache_lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    SESSION_CACHE_LOOKUPS.with_label_values(&[result]).inc();
+}
+
+pub fn history_cache_lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    HISTORY_CACHE_LOOKUPS.with_label_values(&[result]).inc();
+}
+
+pub fn set_provider_up(provider: &str, up: bool) {
+    PROVIDER_UP.with_label_values(&[provider]).set(up as i64);
+}
+
+/// Records the latency of every request against its route template, so
+/// `/api/chat/conversation/:conversation_id` stays a single series.
+pub async fn track_requests(req: Request, next: Next) -> Response {
+    let started_at = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let response = next.run(req).await;
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .observe(started_at.elapsed().as_secs_f64());
+    response
+}
+
+/// Renders every metric in the Prometheus text format.
+pub fn gather() -> Result<Vec<u8>, String> {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .map_err(|e| e.to_string())?;
+    Ok(buffer)
+}
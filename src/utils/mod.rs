@@ -1,6 +1,34 @@
+pub mod api_key;
+pub mod audio;
+pub mod chunker;
 pub mod deepgram;
+pub mod elevenlabs;
+pub mod encryption;
 pub mod error;
 pub mod file;
+pub mod history_cache;
+pub mod i18n;
+pub mod injection;
 pub mod jwt;
+pub mod language;
+pub mod loudness;
+pub mod markdown;
+pub mod media_cache;
+pub mod metrics;
 pub mod openai;
+pub mod postprocess;
+pub mod proxy;
+pub mod rate_limit;
+pub mod redaction;
+pub mod request_id;
+pub mod retry;
+pub mod schema;
+pub mod segmenter;
 pub mod session;
+pub mod signature;
+pub mod speech;
+pub mod streaming;
+pub mod title;
+pub mod unix_socket;
+pub mod waveform;
+pub mod widget;
@@ -1,20 +1,44 @@
+use crate::{
+    client::provider::{
+        ChatParams, ImageModel, ImageOptions, ImageQuality, ImageStyle, ModerationResult, ToolTurn,
+        TranscriptSegment,
+    },
+    entity::conversation::ToolCall,
+    utils::{metrics, request_id::WithRequestId},
+};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use hyper::body::Bytes;
 use image::{ImageFormat, ImageReader};
-use reqwest::{Client, Response};
+use reqwest::{
+    multipart::{Form, Part},
+    Client, Response,
+};
 use rs_openai::{
     audio::{AudioModel, CreateTranscriptionRequestBuilder, ResponseFormat},
     chat::Role,
     shared::types::FileMeta,
     OpenAI,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::io::Cursor;
+use std::{collections::BTreeMap, io::Cursor};
 
+#[derive(Debug, Deserialize)]
+pub struct ToolCallFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+/// A fragment of a streamed tool call; see `merge_tool_calls`.
+#[derive(Debug, Deserialize)]
+pub struct ToolCallDelta {
+    index: usize,
+    id: Option<String>,
+    function: Option<ToolCallFunctionDelta>,
+}
 #[derive(Debug, Deserialize)]
 pub struct ChatChunkDelta {
     content: Option<String>,
+    tool_calls: Option<Vec<ToolCallDelta>>,
 }
 #[derive(Debug, Deserialize)]
 pub struct ChatChunkChoice {
@@ -22,6 +46,11 @@ pub struct ChatChunkChoice {
     index: usize,
     finish_reason: Option<String>,
 }
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
 #[derive(Debug, Deserialize)]
 pub struct ChatCompletionChunk {
     id: String,
@@ -29,23 +58,89 @@ pub struct ChatCompletionChunk {
     created: usize,
     model: String,
     choices: Vec<ChatChunkChoice>,
+    usage: Option<TokenUsage>,
 }
 #[derive(Deserialize)]
 struct ImageGenerationResponse {
     pub created: u32,
     pub data: Vec<serde_json::Value>,
 }
-pub async fn send_chat_completion(
-    openai_key: String,
-    model_name: String,
-    conversations: Vec<(String, Role, Vec<String>)>,
-) -> Result<Response, String> {
-    let request_body = json!({
+#[derive(Deserialize)]
+struct ChatCompletionMessage {
+    content: Option<String>,
+}
+#[derive(Deserialize)]
+struct ChatCompletionChoice {
+    message: ChatCompletionMessage,
+}
+#[derive(Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
+}
+#[derive(Deserialize)]
+struct VerboseTranscription {
+    segments: Vec<TranscriptSegment>,
+}
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+#[derive(Deserialize)]
+struct ModerationVerdict {
+    flagged: bool,
+    categories: BTreeMap<String, bool>,
+    category_scores: BTreeMap<String, f64>,
+}
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationVerdict>,
+}
+
+const IMAGE_PROMPT_INSTRUCTION: &str = "Rewrite the user's request as a single detailed prompt \
+for an image generation model. Describe the subject, setting, composition, lighting, colors and \
+style in vivid, concrete terms. Keep everything the user asked for, do not add text overlays \
+unless requested, and reply with the prompt only.";
+pub fn chat_completion_body(
+    model_name: &str,
+    conversations: &[(String, Role, Vec<String>, Option<ToolTurn>)],
+    params: &ChatParams,
+) -> serde_json::Value {
+    let mut body = json!({
         "model": model_name,
         "stream": true,
+        "stream_options": { "include_usage": true },
         "messages": conversations
         .iter()
-        .map(|&(ref message, ref role, ref images)| {
+        .map(|&(ref message, ref role, ref images, ref tool_turn)| {
+            match tool_turn {
+                Some(ToolTurn::Calls(calls)) => {
+                    return json!({
+                        "role": role,
+                        "content": if message.is_empty() { json!(null) } else { json!(message) },
+                        "tool_calls": calls
+                            .iter()
+                            .map(|call| json!({
+                                "id": call.id,
+                                "type": "function",
+                                "function": { "name": call.name, "arguments": call.arguments }
+                            }))
+                            .collect::<Vec<_>>(),
+                    });
+                }
+                Some(ToolTurn::Result(tool_call_id)) => {
+                    return json!({
+                        "role": "tool",
+                        "tool_call_id": tool_call_id,
+                        "content": message,
+                    });
+                }
+                None => {}
+            }
 
             let content = if images.is_empty() {
                 json!([{
@@ -92,18 +187,94 @@ pub async fn send_chat_completion(
             })
         }).collect::<Vec<_>>(),
     });
-    let client = Client::new();
+    if let Some(temperature) = params.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = params.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        body["max_tokens"] = json!(max_tokens);
+    }
+    // One call per turn, since every tool result takes up a turn of its own.
+    if let Some(tools) = &params.tools {
+        body["tools"] = tools.clone();
+        body["parallel_tool_calls"] = json!(false);
+    }
+    body
+}
+
+/// Checks that `tools` is a list of OpenAI function definitions.
+pub fn validate_tools(tools: &serde_json::Value) -> Result<(), String> {
+    let Some(definitions) = tools.as_array() else {
+        return Err("tools must be a JSON array".to_string());
+    };
+    for (index, definition) in definitions.iter().enumerate() {
+        if definition["type"] != "function" {
+            return Err(format!("Tool {} is not of type 'function'", index));
+        }
+        let has_name = definition["function"]["name"]
+            .as_str()
+            .is_some_and(|name| !name.trim().is_empty());
+        if !has_name {
+            return Err(format!("Tool {} has no function name", index));
+        }
+    }
+    Ok(())
+}
+
+/// Folds streamed tool call fragments into the calls they belong to. The id
+/// and name come with the first fragment of a call, the arguments in pieces.
+pub fn merge_tool_calls(calls: &mut Vec<ToolCall>, deltas: Vec<ToolCallDelta>) {
+    for delta in deltas {
+        while calls.len() <= delta.index {
+            calls.push(ToolCall {
+                id: String::new(),
+                name: String::new(),
+                arguments: String::new(),
+                issued_at: None,
+            });
+        }
+        let call = &mut calls[delta.index];
+        if let Some(id) = delta.id {
+            call.id = id;
+        }
+        if let Some(function) = delta.function {
+            if let Some(name) = function.name {
+                call.name.push_str(&name);
+            }
+            if let Some(arguments) = function.arguments {
+                call.arguments.push_str(&arguments);
+            }
+        }
+    }
+}
+
+pub async fn send_chat_completion(
+    client: &Client,
+    openai_key: &str,
+    model_name: String,
+    conversations: Vec<(String, Role, Vec<String>, Option<ToolTurn>)>,
+    params: &ChatParams,
+) -> Result<Response, String> {
+    let request_body = chat_completion_body(&model_name, &conversations, params);
     let request_url = "https://api.openai.com/v1/chat/completions";
     Ok(client
         .post(request_url)
-        .bearer_auth(openai_key.clone())
+        .with_request_id()
+        .bearer_auth(openai_key)
         .json(&request_body)
         .send()
         .await
         .map_err(|e| format!("OpenAI response failed: {}", e))?)
 }
-pub fn chunk_to_content_list(chunk: Bytes) -> Result<Vec<String>, String> {
+// Returns the text and tool call deltas in the chunk and, for the final chunk, the token usage of the reply.
+pub fn chunk_to_content_list(
+    chunk: Bytes,
+) -> Result<(Vec<String>, Vec<ToolCallDelta>, Option<TokenUsage>), String> {
     let mut content_list = vec![];
+    let mut tool_calls = vec![];
+    let mut usage = None;
     let chunk_str = match std::str::from_utf8(&chunk) {
         Ok(v) => v,
         Err(e) => {
@@ -124,81 +295,367 @@ pub fn chunk_to_content_list(chunk: Bytes) -> Result<Vec<String>, String> {
                     continue;
                 }
                 let d = d.unwrap();
+                cached_str = String::from("");
+                if d.usage.is_some() {
+                    usage = d.usage;
+                }
 
-                let c = d.choices.get(0);
+                let c = d.choices.into_iter().next();
                 if c.is_none() {
                     continue;
                 }
                 let c = c.unwrap();
-                cached_str = String::from("");
-                if let Some(content) = &c.delta.content {
-                    content_list.push(content.clone());
+                if let Some(content) = c.delta.content {
+                    content_list.push(content);
+                }
+                if let Some(deltas) = c.delta.tool_calls {
+                    tool_calls.extend(deltas);
                 }
             }
             None => {}
         }
     }
-    Ok(vec![])
+    metrics::add_streamed_tokens(content_list.len());
+    Ok((content_list, tool_calls, usage))
 }
-pub async fn speech_to_text(
+fn transcription_form(
+    audio_data: Vec<u8>,
+    filename: String,
+    prompt: Option<String>,
+    language: Option<String>,
+    response_format: &'static str,
+) -> Result<Form, String> {
+    let file_part = Part::bytes(audio_data)
+        .file_name(filename)
+        .mime_str("application/octet-stream")
+        .map_err(|e| format!("OpenAI transcription request build failed: {}", e))?;
+    let mut form = Form::new()
+        .part("file", file_part)
+        .text("model", "whisper-1")
+        .text("response_format", response_format);
+    if let Some(prompt) = prompt.filter(|p| !p.trim().is_empty()) {
+        form = form.text("prompt", prompt);
+    }
+    if let Some(language) = language {
+        form = form.text("language", language);
+    }
+    Ok(form)
+}
+
+async fn send_transcription(
+    client: &Client,
     api_key: &str,
+    form: Form,
+) -> Result<Response, String> {
+    let request_url = "https://api.openai.com/v1/audio/transcriptions";
+    client
+        .post(request_url)
+        .with_request_id()
+        .bearer_auth(api_key)
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| format!("OpenAI transcription sending request failed: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI transcription failed: {}", e))
+}
+
+pub async fn speech_to_text(
+    client: &OpenAI,
     audio_data: Vec<u8>,
     filename: String,
+    prompt: Option<String>,
+    language: Option<String>,
 ) -> Result<String, String> {
-    let client = OpenAI::new(&OpenAI {
-        api_key: api_key.into(),
-        org_id: None,
-    });
-    let req = CreateTranscriptionRequestBuilder::default()
-        .file(FileMeta {
-            buffer: audio_data.to_vec(),
-            filename: filename,
-        })
-        .model(AudioModel::Whisper1)
-        .response_format(ResponseFormat::Text)
+    let mut req = CreateTranscriptionRequestBuilder::default();
+    req.file(FileMeta {
+        buffer: audio_data,
+        filename,
+    })
+    .model(AudioModel::Whisper1)
+    .response_format(ResponseFormat::Text);
+    if let Some(prompt) = prompt.filter(|p| !p.trim().is_empty()) {
+        req.prompt(prompt);
+    }
+    if let Some(language) = language {
+        req.language(language);
+    }
+    let req = req
         .build()
         .map_err(|e| format!("OpenAI transcription request build failed: {}", e))?;
 
-    let res = client
+    client
         .audio()
         .create_transcription_with_text_response(&req)
         .await
-        .map_err(|e| format!("OpenAI transcription sending request failed: {}", e))?;
-    Ok(res)
+        .map_err(|e| format!("OpenAI transcription sending request failed: {}", e))
 }
 
-pub async fn text_to_image(api_key: &str, prompt: &str) -> Result<String, String> {
-    let request_body = json!({
-        "model":"dall-e-3",
-        "prompt":prompt,
-        "size":"1024x1024",
-        "quality":"standard",
-        "n":1,
+pub async fn speech_to_segments(
+    client: &Client,
+    api_key: &str,
+    audio_data: Vec<u8>,
+    filename: String,
+    prompt: Option<String>,
+    language: Option<String>,
+) -> Result<Vec<TranscriptSegment>, String> {
+    let form = transcription_form(audio_data, filename, prompt, language, "verbose_json")?;
+    send_transcription(client, api_key, form)
+        .await?
+        .json::<VerboseTranscription>()
+        .await
+        .map(|transcription| transcription.segments)
+        .map_err(|e| format!("Failed to read OpenAI transcription response: {}", e))
+}
+
+pub async fn text_to_image(
+    client: &Client,
+    api_key: &str,
+    prompt: &str,
+    options: &ImageOptions,
+) -> Result<Vec<String>, String> {
+    let mut request_body = json!({
+        "model": options.model.name(),
+        "prompt": prompt,
+        "size": options.size(),
+        "n": options.count,
     });
-    let client = Client::new();
+    // dall-e-2 rejects the quality and style parameters.
+    if options.model == ImageModel::DallE3 {
+        request_body["quality"] = json!(match options.quality {
+            ImageQuality::Standard => "standard",
+            ImageQuality::Hd => "hd",
+        });
+        if let Some(style) = options.style {
+            request_body["style"] = json!(match style {
+                ImageStyle::Vivid => "vivid",
+                ImageStyle::Natural => "natural",
+            });
+        }
+    }
     let request_url = "https://api.openai.com/v1/images/generations";
 
     let response = client
         .post(request_url)
+        .with_request_id()
         .bearer_auth(api_key)
         .json(&request_body)
         .send()
         .await
         .map_err(|e| format!("Failed to send OpenAI rquest: {}", e.to_string()))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI image generation failed: {}", e))?
         .json::<ImageGenerationResponse>()
         .await
         .map_err(|e| format!("Failed to parse OpenAI response as json: {}", e))?;
-    if response.data.len() != 1 {
-        return Err(format!("Failed to generate one image for the text"));
+    if response.data.len() != options.count as usize {
+        return Err(format!(
+            "Expected {} generated images but got {}",
+            options.count,
+            response.data.len()
+        ));
     }
-    let url = response.data[0].get("url");
-    if url.is_none() {
-        return Err(format!("Failed to get the url for the generated image"));
+    response
+        .data
+        .iter()
+        .map(|image| {
+            image
+                .get("url")
+                .and_then(|url| url.as_str())
+                .map(str::to_string)
+                .ok_or_else(|| "Failed to get the url for the generated image".to_string())
+        })
+        .collect()
+}
+
+pub async fn enhance_image_prompt(
+    client: &Client,
+    api_key: &str,
+    model_name: &str,
+    prompt: &str,
+) -> Result<String, String> {
+    let request_body = json!({
+        "model": model_name,
+        "messages": [
+            { "role": "system", "content": IMAGE_PROMPT_INSTRUCTION },
+            { "role": "user", "content": prompt },
+        ],
+    });
+    let request_url = "https://api.openai.com/v1/chat/completions";
+
+    let response = client
+        .post(request_url)
+        .with_request_id()
+        .bearer_auth(api_key)
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send OpenAI request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI prompt enhancement failed: {}", e))?
+        .json::<ChatCompletionResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse OpenAI response as json: {}", e))?;
+
+    response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+        .ok_or_else(|| "OpenAI returned an empty enhanced prompt".to_string())
+}
+
+// Text and images go in as one input, so a flag on either flags the message.
+pub async fn moderate(
+    client: &Client,
+    api_key: &str,
+    model_name: &str,
+    text: &str,
+    images: Vec<String>,
+) -> Result<ModerationResult, String> {
+    let mut input = vec![json!({ "type": "text", "text": text })];
+    input.extend(
+        images
+            .into_iter()
+            .map(|url| json!({ "type": "image_url", "image_url": { "url": url } })),
+    );
+    let request_body = json!({ "model": model_name, "input": input });
+    let request_url = "https://api.openai.com/v1/moderations";
+
+    let response = client
+        .post(request_url)
+        .with_request_id()
+        .bearer_auth(api_key)
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send OpenAI request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI moderation failed: {}", e))?
+        .json::<ModerationResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse OpenAI response as json: {}", e))?;
+
+    let verdict = response
+        .results
+        .into_iter()
+        .next()
+        .ok_or_else(|| "OpenAI returned no moderation result".to_string())?;
+    Ok(ModerationResult {
+        flagged: verdict.flagged,
+        categories: verdict
+            .categories
+            .into_iter()
+            .filter(|(_, flagged)| *flagged)
+            .map(|(category, _)| category)
+            .collect(),
+        category_scores: verdict.category_scores,
+    })
+}
+
+// The cheapest authenticated call: lists the models the key can use.
+pub async fn list_models(client: &Client, api_key: &str) -> Result<(), String> {
+    let request_url = "https://api.openai.com/v1/models";
+    client
+        .get(request_url)
+        .with_request_id()
+        .bearer_auth(api_key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send OpenAI request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI model listing failed: {}", e))?;
+    Ok(())
+}
+
+pub async fn create_embeddings(
+    client: &Client,
+    api_key: &str,
+    model_name: &str,
+    inputs: Vec<String>,
+) -> Result<Vec<Vec<f32>>, String> {
+    if inputs.is_empty() {
+        return Ok(vec![]);
     }
-    let url = url.unwrap().as_str();
-    if url.is_none() {
-        return Err(format!("Failed to parse the url as string"));
+    let input_count = inputs.len();
+    let request_body = json!({
+        "model": model_name,
+        "input": inputs,
+    });
+    let request_url = "https://api.openai.com/v1/embeddings";
+
+    let mut response = client
+        .post(request_url)
+        .with_request_id()
+        .bearer_auth(api_key)
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send OpenAI request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI embedding failed: {}", e))?
+        .json::<EmbeddingResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse OpenAI response as json: {}", e))?;
+
+    if response.data.len() != input_count {
+        return Err(format!(
+            "OpenAI returned {} embeddings for {} inputs",
+            response.data.len(),
+            input_count
+        ));
     }
-    let url = url.unwrap();
-    Ok(url.to_string())
+    response.data.sort_by_key(|data| data.index);
+    Ok(response
+        .data
+        .into_iter()
+        .map(|data| data.embedding)
+        .collect())
+}
+
+pub async fn structured_completion(
+    client: &Client,
+    api_key: &str,
+    model_name: &str,
+    messages: Vec<serde_json::Value>,
+    schema_name: &str,
+    schema: &serde_json::Value,
+) -> Result<String, String> {
+    let request_body = json!({
+        "model": model_name,
+        "messages": messages,
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": {
+                "name": schema_name,
+                "schema": schema,
+                "strict": true,
+            },
+        },
+    });
+    let request_url = "https://api.openai.com/v1/chat/completions";
+
+    let response = client
+        .post(request_url)
+        .with_request_id()
+        .bearer_auth(api_key)
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send OpenAI request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("OpenAI structured completion failed: {}", e))?
+        .json::<ChatCompletionResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse OpenAI response as json: {}", e))?;
+
+    response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .filter(|content| !content.trim().is_empty())
+        .ok_or_else(|| "OpenAI returned an empty structured completion".to_string())
 }
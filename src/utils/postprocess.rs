@@ -0,0 +1,194 @@
+use crate::{
+    config::{
+        chat::MarkdownMode,
+        postprocess::{PostProcessConfig, PostProcessStage},
+    },
+    utils::markdown::{MarkdownSanitizer, BARE_URL},
+};
+use regex::{Captures, Regex};
+
+/// A streaming transformation of reply text. `push` may hold back a partial
+/// tail it cannot decide on yet; `flush` releases it once the reply is complete.
+pub trait PostProcessor: Send {
+    fn push(&mut self, text: &str) -> String;
+    fn flush(&mut self) -> String;
+}
+
+impl PostProcessor for MarkdownSanitizer {
+    fn push(&mut self, text: &str) -> String {
+        MarkdownSanitizer::push(self, text)
+    }
+
+    fn flush(&mut self) -> String {
+        MarkdownSanitizer::flush(self)
+    }
+}
+
+/// The configured stages for one reply, each feeding the next.
+pub struct PostProcessChain {
+    stages: Vec<Box<dyn PostProcessor>>,
+}
+
+impl PostProcessChain {
+    pub fn from_config(config: &PostProcessConfig, markdown_mode: MarkdownMode) -> Self {
+        let stages = config
+            .stages
+            .iter()
+            .filter_map(|stage| -> Option<Box<dyn PostProcessor>> {
+                match stage {
+                    PostProcessStage::Markdown => {
+                        Some(Box::new(MarkdownSanitizer::new(markdown_mode)))
+                    }
+                    PostProcessStage::Profanity => {
+                        ProfanityFilter::new(&config.profanity_words).map(|f| Box::new(f) as _)
+                    }
+                    PostProcessStage::Links => config
+                        .link_prefix
+                        .clone()
+                        .map(|prefix| Box::new(LinkRewriter::new(prefix)) as _),
+                    PostProcessStage::Watermark => config
+                        .watermark
+                        .clone()
+                        .map(|text| Box::new(Watermark { text }) as _),
+                }
+            })
+            .collect();
+        PostProcessChain { stages }
+    }
+
+    pub fn push(&mut self, text: &str) -> String {
+        let mut output = text.to_string();
+        for stage in self.stages.iter_mut() {
+            if output.is_empty() {
+                break;
+            }
+            output = stage.push(&output);
+        }
+        output
+    }
+
+    pub fn flush(&mut self) -> String {
+        let mut output = String::new();
+        for stage in self.stages.iter_mut() {
+            let mut flushed = stage.push(&output);
+            flushed.push_str(&stage.flush());
+            output = flushed;
+        }
+        output
+    }
+}
+
+// Holds back the trailing partial word, so a stage only ever sees whole words.
+#[derive(Default)]
+struct WordBuffer {
+    pending: String,
+}
+
+impl WordBuffer {
+    fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        match self.pending.rfind(char::is_whitespace) {
+            Some(pos) => {
+                let cut = pos + self.pending[pos..].chars().next().map_or(1, char::len_utf8);
+                let rest = self.pending.split_off(cut);
+                std::mem::replace(&mut self.pending, rest)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn flush(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+pub struct ProfanityFilter {
+    pattern: Regex,
+    buffer: WordBuffer,
+}
+
+impl ProfanityFilter {
+    pub fn new(words: &[String]) -> Option<Self> {
+        if words.is_empty() {
+            return None;
+        }
+        let alternatives: Vec<String> = words.iter().map(|w| regex::escape(w)).collect();
+        let pattern = Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).ok()?;
+        Some(ProfanityFilter {
+            pattern,
+            buffer: WordBuffer::default(),
+        })
+    }
+
+    fn mask(&self, text: &str) -> String {
+        self.pattern
+            .replace_all(text, |caps: &Captures| "*".repeat(caps[0].chars().count()))
+            .into_owned()
+    }
+}
+
+impl PostProcessor for ProfanityFilter {
+    fn push(&mut self, text: &str) -> String {
+        let words = self.buffer.push(text);
+        self.mask(&words)
+    }
+
+    fn flush(&mut self) -> String {
+        let words = self.buffer.flush();
+        self.mask(&words)
+    }
+}
+
+pub struct LinkRewriter {
+    prefix: String,
+    buffer: WordBuffer,
+}
+
+impl LinkRewriter {
+    pub fn new(prefix: String) -> Self {
+        LinkRewriter {
+            prefix,
+            buffer: WordBuffer::default(),
+        }
+    }
+
+    fn rewrite(&self, text: &str) -> String {
+        BARE_URL
+            .replace_all(text, |caps: &Captures| {
+                let url = &caps[0];
+                if url.starts_with(&self.prefix) {
+                    return url.to_string();
+                }
+                let encoded: String =
+                    url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+                format!("{}{}", self.prefix, encoded)
+            })
+            .into_owned()
+    }
+}
+
+impl PostProcessor for LinkRewriter {
+    fn push(&mut self, text: &str) -> String {
+        let words = self.buffer.push(text);
+        self.rewrite(&words)
+    }
+
+    fn flush(&mut self) -> String {
+        let words = self.buffer.flush();
+        self.rewrite(&words)
+    }
+}
+
+pub struct Watermark {
+    text: String,
+}
+
+impl PostProcessor for Watermark {
+    fn push(&mut self, text: &str) -> String {
+        text.to_string()
+    }
+
+    fn flush(&mut self) -> String {
+        self.text.clone()
+    }
+}
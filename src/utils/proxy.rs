@@ -0,0 +1,22 @@
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+
+use crate::config::proxy::ProxyConfig;
+
+/// Routes the client through the per-client proxy if one is set, otherwise the
+/// global one. `none` turns proxying off for that client.
+pub fn with_proxy(
+    builder: ClientBuilder,
+    config: &ProxyConfig,
+    override_url: Option<&str>,
+) -> Result<ClientBuilder, String> {
+    match override_url.or(config.url.as_deref()) {
+        None => Ok(builder),
+        Some("none") => Ok(builder.no_proxy()),
+        Some(url) => {
+            let proxy = Proxy::all(url)
+                .map_err(|e| format!("Invalid proxy URL: {}", e))?
+                .no_proxy(config.no_proxy.as_deref().and_then(NoProxy::from_string));
+            Ok(builder.proxy(proxy))
+        }
+    }
+}
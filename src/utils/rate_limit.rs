@@ -0,0 +1,128 @@
+use crate::{
+    utils::{
+        error::{AppError, ErrorCode},
+        jwt::UserClaims,
+    },
+    ServiceState,
+};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::headers::{authorization::Bearer, Authorization, HeaderMapExt};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+// Routes that call paid upstream providers and share the per-user request budget.
+const LIMITED_ROUTES: &[(Method, &str)] = &[
+    (Method::POST, "/api/chat/conversation/:conversation_id"),
+    (Method::PATCH, "/api/chat/conversation/:conversation_id"),
+    (
+        Method::POST,
+        "/api/chat/conversation/:conversation_id/regenerate",
+    ),
+    (
+        Method::GET,
+        "/api/chat/conversation/:conversation_id/suggestions",
+    ),
+    (
+        Method::POST,
+        "/api/chat/conversation/:conversation_id/action-items",
+    ),
+    (Method::POST, "/api/chat/extract"),
+    (Method::POST, "/api/chat/image"),
+    (Method::POST, "/api/chat/voice"),
+];
+
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: Duration,
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<i64, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    pub fn check(&self, user_id: i64, limit: u32, window: Duration) -> RateLimitStatus {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        let (started, count) = windows.entry(user_id).or_insert((now, 0));
+        let allowed = *count < limit;
+        if allowed {
+            *count += 1;
+        }
+        RateLimitStatus {
+            allowed,
+            limit,
+            remaining: limit.saturating_sub(*count),
+            reset: window.saturating_sub(now.duration_since(*started)),
+        }
+    }
+}
+
+fn insert_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    let reset = status.reset.as_secs().max(1);
+    headers.insert("x-ratelimit-limit", HeaderValue::from(status.limit));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(status.remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(reset));
+    if !status.allowed {
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(reset));
+    }
+}
+
+pub async fn rate_limit(
+    State(state): State<Arc<ServiceState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.rate_limit;
+    let is_limited = req.extensions().get::<MatchedPath>().is_some_and(|path| {
+        LIMITED_ROUTES
+            .iter()
+            .any(|(method, route)| method == req.method() && *route == path.as_str())
+    });
+    if !config.is_enabled() || !is_limited {
+        return next.run(req).await;
+    }
+    // Unauthenticated requests are rejected by the handlers, so they are not counted here.
+    let Some(user_id) = req
+        .headers()
+        .typed_get::<Authorization<Bearer>>()
+        .and_then(|bearer| {
+            UserClaims::decode(bearer.token(), &state.config.jwt.access_token_secret).ok()
+        })
+        .map(|token| token.claims.uid)
+    else {
+        return next.run(req).await;
+    };
+
+    let status = state.rate_limiter.check(
+        user_id,
+        config.requests,
+        Duration::from_secs(config.window_secs),
+    );
+    let mut response = if status.allowed {
+        next.run(req).await
+    } else {
+        error!("User '{}' exceeded the rate limit.", user_id);
+        AppError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Rate limit exceeded. Please slow down.",
+        )
+        .into_response()
+    };
+    insert_headers(response.headers_mut(), &status);
+    response
+}
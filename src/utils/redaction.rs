@@ -0,0 +1,96 @@
+use crate::config::redaction::PiiKind;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::BTreeSet;
+
+static EMAIL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(\.[a-z0-9-]+)*\.[a-z]{2,}\b").unwrap()
+});
+static CARD: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap());
+// Needs a separator or a leading `+` so dates and plain amounts are left alone.
+static PHONE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?:\+\d{1,3}[\s.-]?|\b)(?:\(\d{2,5}\)\s?|\d{2,5}[\s.-])\d{3,4}[\s.-]?\d{3,4}\b|\+\d{8,15}\b",
+    )
+    .unwrap()
+});
+
+/// Masks e-mail addresses, card numbers and phone numbers among `kinds`.
+/// Returns the masked text and the number of matches replaced.
+pub fn redact_patterns(text: &str, kinds: &BTreeSet<PiiKind>) -> (String, usize) {
+    let mut text = text.to_string();
+    let mut count = 0;
+    // Cards go before phones, whose pattern also matches long digit groups.
+    if kinds.contains(&PiiKind::Card) {
+        text = CARD
+            .replace_all(&text, |caps: &Captures| {
+                if passes_luhn(&caps[0]) {
+                    count += 1;
+                    PiiKind::Card.placeholder().to_string()
+                } else {
+                    caps[0].to_string()
+                }
+            })
+            .into_owned();
+    }
+    for (kind, pattern) in [(PiiKind::Email, &*EMAIL), (PiiKind::Phone, &*PHONE)] {
+        if kinds.contains(&kind) {
+            count += pattern.find_iter(&text).count();
+            text = pattern.replace_all(&text, kind.placeholder()).into_owned();
+        }
+    }
+    (text, count)
+}
+
+/// Replaces the given byte ranges, which must not overlap, with `placeholder`.
+/// Ranges that do not fall on character boundaries are skipped.
+pub fn mask_spans(text: &str, spans: &[(usize, usize)], placeholder: &str) -> (String, usize) {
+    let mut spans: Vec<(usize, usize)> = spans
+        .iter()
+        .copied()
+        .filter(|(start, end)| {
+            start < end
+                && *end <= text.len()
+                && text.is_char_boundary(*start)
+                && text.is_char_boundary(*end)
+        })
+        .collect();
+    spans.sort_unstable();
+    spans.dedup();
+    let mut masked = String::with_capacity(text.len());
+    let mut position = 0;
+    let mut count = 0;
+    for (start, end) in spans {
+        if start < position {
+            continue;
+        }
+        masked.push_str(&text[position..start]);
+        masked.push_str(placeholder);
+        position = end;
+        count += 1;
+    }
+    masked.push_str(&text[position..]);
+    (masked, count)
+}
+
+fn passes_luhn(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, &digit)| {
+            if index % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
@@ -0,0 +1,100 @@
+use crate::utils::error::{AppError, REQUEST_ID};
+use axum::{
+    body::to_bytes,
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use reqwest::RequestBuilder;
+use std::future::Future;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// Tags every request with an id (reusing the caller's `x-request-id` when
+/// present), echoes it back in the response headers and makes sure every
+/// error leaves the service as a structured `AppError` body carrying it.
+pub async fn assign_request_id(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    Span::current().record("request_id", request_id.as_str());
+
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), async move {
+            let response = next.run(req).await;
+            into_structured_error(response).await
+        })
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Id of the request currently being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Spawns `future` with the current request id and span carried over, so
+/// work that outlives the handler still logs and forwards the same id.
+pub fn spawn_in_request<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let future = future.in_current_span();
+    match current_request_id() {
+        Some(request_id) => tokio::spawn(REQUEST_ID.scope(request_id, future)),
+        None => tokio::spawn(future),
+    }
+}
+
+/// Forwards the current request id to an upstream call.
+pub trait WithRequestId {
+    fn with_request_id(self) -> Self;
+}
+
+impl WithRequestId for RequestBuilder {
+    fn with_request_id(self) -> Self {
+        match current_request_id() {
+            Some(request_id) => self.header(REQUEST_ID_HEADER, request_id),
+            None => self,
+        }
+    }
+}
+
+// Errors raised outside our handlers (extractor rejections, unknown routes)
+// still come back as plain text; wrap them so clients see one error shape.
+async fn into_structured_error(response: Response) -> Response {
+    let status = response.status();
+    if (!status.is_client_error() && !status.is_server_error())
+        || response.extensions().get::<AppError>().is_some()
+    {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let message = match to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => String::new(),
+    };
+    let mut structured = AppError::from((status, message)).into_response();
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            structured.headers_mut().append(name, value.clone());
+        }
+    }
+    structured
+}
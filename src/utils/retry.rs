@@ -0,0 +1,176 @@
+use crate::config::retry::RetryConfig;
+use axum::http::StatusCode;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+const CIRCUIT_OPEN: &str = "is unavailable, not calling it for now";
+
+// Status lines and transport failures that are worth another attempt.
+const TRANSIENT_MARKERS: [&str; 8] = [
+    "429 Too Many Requests",
+    "500 Internal Server Error",
+    "502 Bad Gateway",
+    "503 Service Unavailable",
+    "504 Gateway Timeout",
+    "timed out",
+    "error sending request",
+    "connection",
+];
+
+pub fn is_transient(error: &str) -> bool {
+    TRANSIENT_MARKERS
+        .iter()
+        .any(|marker| error.contains(marker))
+}
+
+/// `503` for a call the circuit breaker refused, `500` for anything else.
+pub fn upstream_status(error: &str) -> StatusCode {
+    if error.contains(CIRCUIT_OPEN) {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+// Once open, the breaker lets a call through after `open_for`; its failure opens it again.
+struct CircuitBreaker {
+    threshold: u32,
+    open_for: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn allows(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .open_until
+            .map_or(true, |until| until <= Instant::now())
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = BreakerState::default();
+    }
+
+    // Returns true when this failure opened the circuit.
+    fn record_failure(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.failures = state.failures.saturating_add(1);
+        if state.failures < self.threshold {
+            return false;
+        }
+        state.open_until = Some(Instant::now() + self.open_for);
+        true
+    }
+}
+
+/// Retries transient upstream failures with jittered exponential backoff and
+/// keeps one circuit breaker per upstream.
+pub struct Resilience {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    breaker_failures: u32,
+    breaker_open: Duration,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl Resilience {
+    pub fn new(config: &RetryConfig) -> Self {
+        Resilience {
+            max_attempts: config.max_attempts,
+            base_delay: Duration::from_millis(config.base_delay_ms),
+            max_delay: Duration::from_millis(config.max_delay_ms),
+            breaker_failures: config.breaker_failures,
+            breaker_open: Duration::from_secs(config.breaker_open_secs),
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn call<T, F, Fut>(&self, upstream: &str, operation: F) -> Result<T, String>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let breaker = self.breaker(upstream);
+        let mut attempt = 1;
+        loop {
+            if !breaker.allows() {
+                return Err(format!("{} {}", upstream, CIRCUIT_OPEN));
+            }
+            let error = match operation().await {
+                Ok(value) => {
+                    breaker.record_success();
+                    return Ok(value);
+                }
+                // Any other failure still means the upstream answered.
+                Err(e) if !is_transient(&e) => {
+                    breaker.record_success();
+                    return Err(e);
+                }
+                Err(e) => e,
+            };
+            if breaker.record_failure() {
+                warn!(
+                    "Opening the circuit for {} for {} seconds after: {}",
+                    upstream,
+                    self.breaker_open.as_secs(),
+                    error
+                );
+                return Err(error);
+            }
+            if attempt >= self.max_attempts {
+                return Err(error);
+            }
+            let delay = self.backoff(attempt);
+            warn!(
+                "Retrying {} in {} ms (attempt {} of {}) after: {}",
+                upstream,
+                delay.as_millis(),
+                attempt + 1,
+                self.max_attempts,
+                error
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn breaker(&self, upstream: &str) -> Arc<CircuitBreaker> {
+        self.breakers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(upstream.to_string())
+            .or_insert_with(|| {
+                Arc::new(CircuitBreaker {
+                    threshold: self.breaker_failures,
+                    open_for: self.breaker_open,
+                    state: Mutex::new(BreakerState::default()),
+                })
+            })
+            .clone()
+    }
+
+    // Half the capped exponential delay plus a random share of the other half.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = (attempt - 1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_delay);
+        let half = delay / 2;
+        let jitter = (Uuid::new_v4().as_u128() % 1000) as u32;
+        half + (delay - half) * jitter / 1000
+    }
+}
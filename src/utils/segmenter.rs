@@ -0,0 +1,172 @@
+use crate::config::tts::TtsConfig;
+
+const SPACED_TERMINATORS: [char; 6] = ['.', '!', '?', '…', '؟', '।'];
+const UNSPACED_TERMINATORS: [char; 4] = ['。', '！', '？', '；'];
+const CODE_FENCE: &str = "```";
+
+fn default_abbreviations(language: &str) -> &'static [&'static str] {
+    match language {
+        "en" => &[
+            "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "approx",
+            "no", "fig", "inc", "ltd",
+        ],
+        "de" => &[
+            "z.b", "bzw", "usw", "ca", "dr", "nr", "vgl", "u.a", "d.h", "prof",
+        ],
+        "fr" => &["m", "mme", "mlle", "dr", "etc", "p.ex", "cf", "env"],
+        "es" => &["sr", "sra", "srta", "dr", "etc", "p.ej", "ud", "uds"],
+        _ => &[],
+    }
+}
+
+fn is_dense_script(language: &str) -> bool {
+    matches!(language, "zh" | "ja" | "ko")
+}
+
+pub struct SentenceSegmenter {
+    buffer: String,
+    min_chars: usize,
+    max_chars: usize,
+    abbreviations: Vec<String>,
+    in_code_block: bool,
+}
+
+impl SentenceSegmenter {
+    pub fn for_language(config: &TtsConfig, language: &str) -> Self {
+        Self::new(
+            config.segment_min_chars,
+            config.segment_max_chars,
+            language,
+            &config.abbreviations,
+        )
+    }
+
+    pub fn new(
+        min_chars: usize,
+        max_chars: usize,
+        language: &str,
+        extra_abbreviations: &[String],
+    ) -> Self {
+        let (min_chars, max_chars) = if is_dense_script(language) {
+            (min_chars / 3, max_chars / 3)
+        } else {
+            (min_chars, max_chars)
+        };
+        let mut abbreviations: Vec<String> = default_abbreviations(language)
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        abbreviations.extend(extra_abbreviations.iter().cloned());
+        SentenceSegmenter {
+            buffer: String::new(),
+            min_chars,
+            max_chars: max_chars.max(1),
+            abbreviations,
+            in_code_block: false,
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> Vec<String> {
+        self.buffer.push_str(text);
+        let mut segments = vec![];
+        while let Some(cut) = self.find_cut() {
+            let rest = self.buffer.split_off(cut);
+            let segment = std::mem::replace(&mut self.buffer, rest);
+            let segment = segment.trim();
+            if !segment.is_empty() {
+                segments.push(segment.to_string());
+            }
+        }
+        segments
+    }
+
+    pub fn flush(&mut self) -> Option<String> {
+        let segment = std::mem::take(&mut self.buffer);
+        self.in_code_block = false;
+        let segment = segment.trim();
+        if segment.is_empty() {
+            None
+        } else {
+            Some(segment.to_string())
+        }
+    }
+
+    fn find_cut(&mut self) -> Option<usize> {
+        let chars: Vec<(usize, char)> = self.buffer.char_indices().collect();
+        let mut in_code_block = self.in_code_block;
+        // Offset of the last whitespace, with whether it was inside a code block.
+        let mut last_whitespace: Option<(usize, bool)> = None;
+        let mut i = 0;
+        while i < chars.len() {
+            let (offset, c) = chars[i];
+            let end = offset + c.len_utf8();
+
+            if self.buffer[offset..].starts_with(CODE_FENCE) {
+                in_code_block = !in_code_block;
+                i += CODE_FENCE.len();
+                if !in_code_block && i >= self.min_chars {
+                    let cut = chars.get(i).map(|&(o, _)| o).unwrap_or(self.buffer.len());
+                    self.in_code_block = false;
+                    return Some(cut);
+                }
+                continue;
+            }
+
+            if c.is_whitespace() {
+                last_whitespace = Some((offset, in_code_block));
+            }
+
+            if i + 1 >= self.max_chars {
+                // A fence may lie between the whitespace and here, so the state
+                // carried over must be the one at the cut.
+                let (cut, cut_in_code_block) = last_whitespace
+                    .filter(|&(w, _)| w > 0)
+                    .unwrap_or((end, in_code_block));
+                self.in_code_block = cut_in_code_block;
+                return Some(cut);
+            }
+
+            if !in_code_block && i + 1 >= self.min_chars && self.is_boundary(&chars, i) {
+                self.in_code_block = false;
+                return Some(end);
+            }
+            i += 1;
+        }
+        None
+    }
+
+    fn is_boundary(&self, chars: &[(usize, char)], i: usize) -> bool {
+        let c = chars[i].1;
+        if c == '\n' || UNSPACED_TERMINATORS.contains(&c) {
+            return true;
+        }
+        if !SPACED_TERMINATORS.contains(&c) {
+            return false;
+        }
+        match chars.get(i + 1) {
+            Some(&(_, next)) if next.is_whitespace() => {}
+            _ => return false,
+        }
+        if c == '.' && self.is_abbreviation(chars, i) {
+            return false;
+        }
+        true
+    }
+
+    fn is_abbreviation(&self, chars: &[(usize, char)], dot: usize) -> bool {
+        let start = chars[..dot]
+            .iter()
+            .rposition(|&(_, c)| c.is_whitespace() || c == '(')
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        let word: String = chars[start..dot]
+            .iter()
+            .map(|&(_, c)| c)
+            .collect::<String>()
+            .to_lowercase();
+        if word.chars().count() == 1 && word.chars().all(char::is_alphabetic) {
+            return true;
+        }
+        self.abbreviations.contains(&word)
+    }
+}
@@ -1,9 +1,115 @@
-use base64::prelude::*;
-use hmac::{Hmac, Mac};
+use crate::{
+    config::session::SessionConfig,
+    dto::response::SessionData,
+    utils::{
+        metrics,
+        request_id::WithRequestId,
+        signature::{sign, SIGNATURE_HEADER},
+    },
+};
 use reqwest::Client;
-use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
 
-type HmacSha256 = Hmac<Sha256>;
+struct CachedSession {
+    user_id: i64,
+    session_data: SessionData,
+    cached_at: Instant,
+}
+
+/// Session data fetched from the auth service, keyed by session id, so that
+/// authenticated requests do not each make a round trip to `/session`.
+pub struct SessionCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<Uuid, CachedSession>>,
+}
+
+impl SessionCache {
+    pub fn new(config: &SessionConfig) -> Self {
+        SessionCache {
+            ttl: Duration::from_secs(config.cache_ttl_secs),
+            capacity: if config.is_cache_enabled() {
+                config.cache_capacity
+            } else {
+                0
+            },
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, session_id: Uuid, user_id: i64) -> Option<SessionData> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = match entries.get(&session_id) {
+            Some(entry) if entry.cached_at.elapsed() < self.ttl && entry.user_id == user_id => {
+                Some(entry.session_data.clone())
+            }
+            Some(_) => {
+                entries.remove(&session_id);
+                None
+            }
+            None => None,
+        };
+        metrics::session_cache_lookup(cached.is_some());
+        cached
+    }
+
+    pub fn insert(&self, session_id: Uuid, user_id: i64, session_data: SessionData) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity && !entries.contains_key(&session_id) {
+            entries.retain(|_, entry| entry.cached_at.elapsed() < self.ttl);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.cached_at)
+                    .map(|(id, _)| *id);
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            session_id,
+            CachedSession {
+                user_id,
+                session_data,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops every cached session of the user, so the next request reads the
+    /// balance and preferences from the auth service again.
+    pub fn invalidate_user(&self, user_id: i64) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let before = entries.len();
+        entries.retain(|_, entry| entry.user_id != user_id);
+        before - entries.len()
+    }
+
+    /// Drops one cached session, e.g. after the user revoked it, so the next
+    /// request with its token is checked against the auth service.
+    pub fn invalidate_session(&self, session_id: Uuid, user_id: i64) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(&session_id) {
+            Some(entry) if entry.user_id == user_id => {
+                entries.remove(&session_id);
+                1
+            }
+            _ => 0,
+        }
+    }
+}
 
 pub async fn send_session_data(
     session_data: serde_json::Value,
@@ -13,16 +119,12 @@ pub async fn send_session_data(
     let client = Client::new();
 
     let body = session_data.to_string();
-    let mut mac = HmacSha256::new_from_slice(secret_key.as_bytes()).map_err(|e| {
-        let error_message = format!("Failed to make new hmac slice : {}", e);
-        error_message
-    })?;
-    mac.update(body.as_bytes());
-    let signature = mac.finalize().into_bytes();
+    let signature = sign(body.as_bytes(), &secret_key)?;
 
     let response = client
         .post(format!("{}/session", auth_uri))
-        .header("X-Signature", BASE64_STANDARD.encode(&signature)) // Include signature in headers
+        .with_request_id()
+        .header(SIGNATURE_HEADER, signature) // Include signature in headers
         .header("Content-Type", "application/json")
         .body(body)
         .send()
@@ -0,0 +1,153 @@
+use crate::{utils::error::AppError, ServiceState};
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+};
+use base64::prelude::*;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+pub const NONCE_HEADER: &str = "X-Signature-Nonce";
+
+// Signed requests older than this are rejected, so seen nonces only need to be kept this long.
+const MAX_SIGNATURE_AGE_SECS: i64 = 300;
+const MAX_NONCE_LEN: usize = 128;
+
+static SEEN_NONCES: Lazy<Mutex<HashMap<String, i64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn sign(body: &[u8], secret_key: &str) -> Result<String, String> {
+    let mut mac = HmacSha256::new_from_slice(secret_key.as_bytes())
+        .map_err(|e| format!("Failed to make new hmac slice : {}", e))?;
+    mac.update(body);
+    Ok(BASE64_STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+pub fn verify(body: &[u8], signature: &str, secret_key: &str) -> bool {
+    let Ok(signature) = BASE64_STANDARD.decode(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret_key.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// With `INTERNAL_SIGNED_TIMESTAMPS`, the auth service signs the timestamp and
+// nonce together with the body, so neither can be swapped.
+fn signed_payload(timestamp: i64, nonce: &str, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{}.{}.", timestamp, nonce).into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+// Returns false when the nonce was already used within the accepted window.
+fn remember_nonce(nonce: &str, timestamp: i64, now: i64) -> bool {
+    let mut seen = SEEN_NONCES.lock().unwrap_or_else(|e| e.into_inner());
+    seen.retain(|_, seen_at| now - *seen_at <= MAX_SIGNATURE_AGE_SECS);
+    seen.insert(nonce.to_string(), timestamp).is_none()
+}
+
+pub struct SignedJson<T>(pub T);
+
+#[async_trait::async_trait]
+impl<T> FromRequest<Arc<ServiceState>> for SignedJson<T>
+where
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request(
+        req: Request,
+        state: &Arc<ServiceState>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+                .ok_or_else(|| {
+                    error!("Missing {} header on internal request", name);
+                    AppError::from((StatusCode::UNAUTHORIZED, format!("Missing {} header", name)))
+                })
+        };
+        let signature = header(SIGNATURE_HEADER)?;
+        let replay_check = if state.config.server.signed_timestamps {
+            let nonce = header(NONCE_HEADER)?;
+            let timestamp = header(TIMESTAMP_HEADER)?.parse::<i64>().map_err(|_| {
+                error!("Invalid signature timestamp on internal request");
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid signature timestamp".to_string(),
+                )
+            })?;
+            let now = Utc::now().timestamp();
+            if (now - timestamp).abs() > MAX_SIGNATURE_AGE_SECS {
+                error!("Stale signature on internal request");
+                return Err((StatusCode::UNAUTHORIZED, "Stale signature".to_string()).into());
+            }
+            if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+                error!("Invalid signature nonce on internal request");
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid signature nonce".to_string(),
+                )
+                    .into());
+            }
+            Some((timestamp, nonce, now))
+        } else {
+            None
+        };
+
+        let body = Bytes::from_request(req, state).await.map_err(|e| {
+            error!("Failed to read internal request body: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read request body: {}", e),
+            )
+        })?;
+
+        let secret_key = &state.config.server.auth_secret_key;
+        let verified = match &replay_check {
+            Some((timestamp, nonce, _)) => verify(
+                &signed_payload(*timestamp, nonce, &body),
+                &signature,
+                secret_key,
+            ),
+            None => verify(&body, &signature, secret_key),
+        };
+        if !verified {
+            error!("Invalid signature on internal request");
+            return Err((StatusCode::UNAUTHORIZED, "Invalid signature".to_string()).into());
+        }
+        // Checked only after the signature, so unsigned requests cannot fill the nonce cache.
+        if let Some((timestamp, nonce, now)) = replay_check {
+            if !remember_nonce(&nonce, timestamp, now) {
+                error!("Replayed internal request with nonce '{}'", nonce);
+                return Err((StatusCode::UNAUTHORIZED, "Replayed request".to_string()).into());
+            }
+        }
+
+        let value = serde_json::from_slice::<T>(&body).map_err(|e| {
+            error!("Failed to parse internal request body: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed to parse request body: {}", e),
+            )
+        })?;
+        Ok(SignedJson(value))
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    config::{
+        deepgram::DeepgramConfig,
+        tts::{TtsConfig, DEFAULT_SAMPLE_RATE},
+    },
+    utils::{deepgram, elevenlabs},
+};
+use futures::{stream::BoxStream, StreamExt};
+use hyper::body::Bytes;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpeechVoice {
+    Deepgram { model: String, sample_rate: u32 },
+    Custom(String),
+}
+
+impl SpeechVoice {
+    // A registered custom voice wins over a voice profile, which wins over the language default.
+    pub fn resolve(
+        config: &TtsConfig,
+        language: Option<&str>,
+        custom_voice: Option<&str>,
+        voice_profile: Option<&str>,
+    ) -> Self {
+        match custom_voice {
+            Some(voice_id) if config.allows_custom_voice(voice_id) => {
+                SpeechVoice::Custom(voice_id.to_string())
+            }
+            _ => match voice_profile.and_then(|name| config.voice_profile(name)) {
+                Some(profile) => SpeechVoice::Deepgram {
+                    model: profile.model.clone(),
+                    sample_rate: profile.sample_rate,
+                },
+                None => SpeechVoice::Deepgram {
+                    model: config.voice_for(language).to_string(),
+                    sample_rate: DEFAULT_SAMPLE_RATE,
+                },
+            },
+        }
+    }
+
+    pub fn provider(&self) -> &'static str {
+        match self {
+            SpeechVoice::Deepgram { .. } => "deepgram",
+            SpeechVoice::Custom(_) => "elevenlabs",
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        match self {
+            SpeechVoice::Deepgram { sample_rate, .. } => *sample_rate,
+            SpeechVoice::Custom(_) => elevenlabs::SAMPLE_RATE,
+        }
+    }
+}
+
+pub async fn synthesize(
+    tts: &TtsConfig,
+    deepgram_config: &DeepgramConfig,
+    voice: &SpeechVoice,
+    text: &str,
+    is_started: bool,
+) -> Result<BoxStream<'static, Bytes>, String> {
+    match voice {
+        SpeechVoice::Deepgram { model, sample_rate } => Ok(deepgram::text_to_speech(
+            &deepgram_config.deepgram_key,
+            model,
+            *sample_rate,
+            text,
+            is_started,
+        )
+        .await?
+        .boxed()),
+        SpeechVoice::Custom(voice_id) => {
+            let Some(api_key) = tts.elevenlabs_key.as_deref() else {
+                return Err("ElevenLabs API key is not configured".to_string());
+            };
+            Ok(elevenlabs::text_to_speech(
+                api_key,
+                &tts.elevenlabs_model,
+                voice_id,
+                text,
+                is_started,
+            )
+            .await?
+            .boxed())
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::utils::error::{format_error, AppResult};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use http_body_util::StreamBody;
+use hyper::body::{Bytes, Frame};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// How a streamed reply is framed on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// Server-sent events, one named event per frame.
+    Sse,
+    /// Newline-delimited JSON objects tagged with a `type`, for replies
+    /// mixing several kinds of payload.
+    Multiplexed,
+    /// The payload bytes as is, served with the given content type.
+    Raw(&'static str),
+}
+
+impl StreamFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Sse => "text/event-stream",
+            StreamFormat::Multiplexed => "application/x-ndjson",
+            StreamFormat::Raw(content_type) => content_type,
+        }
+    }
+}
+
+/// Sending half of a streamed response. Cloning it is cheap.
+#[derive(Debug, Clone)]
+pub struct StreamWriter {
+    tx: mpsc::Sender<Result<Frame<Bytes>, String>>,
+    format: StreamFormat,
+}
+
+/// Receiving half of a streamed response, turned into the HTTP response.
+pub struct StreamResponse {
+    rx: mpsc::Receiver<Result<Frame<Bytes>, String>>,
+    format: StreamFormat,
+    headers: Vec<(&'static str, String)>,
+}
+
+pub fn channel(format: StreamFormat, buffer: usize) -> (StreamWriter, StreamResponse) {
+    let (tx, rx) = mpsc::channel(buffer);
+    (
+        StreamWriter { tx, format },
+        StreamResponse {
+            rx,
+            format,
+            headers: vec![],
+        },
+    )
+}
+
+impl StreamWriter {
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Resolves once the client has gone away.
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+
+    /// Sends bytes without any framing.
+    pub async fn send(&self, data: Bytes) -> Result<(), String> {
+        self.tx
+            .send(Ok(Frame::data(data)))
+            .await
+            .map_err(|_| "Failed to send response stream data to buffer".to_string())
+    }
+
+    /// Sends a named event. Multiplexed frames merge the name into an object
+    /// payload as `type`, or wrap any other payload as `data`. Raw streams
+    /// only carry the payload itself, so events are dropped.
+    pub async fn event(&self, event: &str, data: Value) -> Result<(), String> {
+        match self.format {
+            StreamFormat::Sse => self.send(sse_event(event, data)).await,
+            StreamFormat::Multiplexed => {
+                let line = match data {
+                    Value::Object(mut object) => {
+                        object.insert("type".to_string(), json!(event));
+                        Value::Object(object)
+                    }
+                    data => json!({ "type": event, "data": data }),
+                };
+                self.send(json_line(line)).await
+            }
+            StreamFormat::Raw(_) => Ok(()),
+        }
+    }
+
+    /// Reports a failure. Only SSE can carry it in-band; other formats abort
+    /// the body so the client sees a broken response instead of a short one.
+    pub async fn error(&self, message: String) {
+        if self.format == StreamFormat::Sse {
+            let _ = self
+                .send(sse_event("error", json!({ "message": message })))
+                .await;
+        } else {
+            let _ = self.tx.send(Err(message)).await;
+        }
+    }
+}
+
+impl StreamResponse {
+    pub fn header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn into_response(self) -> AppResult<Response> {
+        let mut response = Response::builder()
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .header("Content-Type", self.format.content_type());
+        for (name, value) in self.headers {
+            response = response.header(name, value);
+        }
+        response
+            .body(StreamBody::new(ReceiverStream::new(self.rx)))
+            .map(IntoResponse::into_response)
+            .map_err(|e| {
+                format_error(
+                    "Failed to build the streaming response",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })
+    }
+}
+
+fn json_line(value: Value) -> Bytes {
+    let mut line = value.to_string();
+    line.push('\n');
+    Bytes::from(line)
+}
+
+fn sse_event(event: &str, data: Value) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
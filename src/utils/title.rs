@@ -0,0 +1,36 @@
+// Scripts written without spaces between words, where the title is cut by characters instead.
+const UNSPACED_LANGUAGES: &[&str] = &["ja", "zh", "th", "lo", "km", "my"];
+
+pub fn generate_title(
+    text: &str,
+    language: Option<&str>,
+    max_words: usize,
+    max_chars: usize,
+) -> Option<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let title = if language.is_some_and(|code| UNSPACED_LANGUAGES.contains(&code)) {
+        words.join(" ")
+    } else {
+        words
+            .into_iter()
+            .take(max_words)
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+
+    let title = if title.chars().count() > max_chars {
+        let truncated: String = title.chars().take(max_chars).collect();
+        match truncated.rfind(' ') {
+            Some(index) if index > 0 => truncated[..index].to_string(),
+            _ => truncated,
+        }
+    } else {
+        title
+    };
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
@@ -0,0 +1,36 @@
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use std::path::Path;
+use tokio::net::UnixListener;
+use tracing::{error, info};
+
+// axum::serve only accepts TCP listeners, so unix socket connections are driven through hyper directly.
+pub async fn serve_unix_socket(path: String, router: Router) -> Result<(), String> {
+    if Path::new(&path).exists() {
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove the stale socket '{}': {}", path, e))?;
+    }
+    let listener = UnixListener::bind(&path)
+        .map_err(|e| format!("Failed to bind the unix socket '{}': {}", path, e))?;
+    info!("🚀 The server is listening on: unix:{}", path);
+
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Failed to accept a unix socket connection: {}", e))?;
+        let service = TowerToHyperService::new(router.clone());
+        tokio::spawn(async move {
+            if let Err(e) = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                error!("Unix socket connection failed: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,146 @@
+use crate::entity::conversation::Waveform;
+use std::{fs::File, io::Cursor, path::Path};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as DecodeError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+const PEAK_COUNT: usize = 64;
+
+pub fn from_pcm(samples: &[i16], sample_rate: u32) -> Option<Waveform> {
+    if samples.is_empty() || sample_rate == 0 {
+        return None;
+    }
+    let bucket = samples.len().div_ceil(PEAK_COUNT);
+    let peaks = samples
+        .chunks(bucket)
+        .map(|chunk| {
+            let peak = chunk.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+            (peak as u32 * 255 / i16::MAX as u32).min(255) as u8
+        })
+        .collect();
+    Some(Waveform {
+        duration_ms: samples.len() as u64 * 1000 / sample_rate as u64,
+        peaks,
+    })
+}
+
+pub fn from_wav_bytes(data: &[u8]) -> Option<Waveform> {
+    let reader = hound::WavReader::new(Cursor::new(data)).ok()?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return None;
+    }
+    let channels = spec.channels.max(1) as usize;
+    let samples: Vec<i16> = reader
+        .into_samples::<i16>()
+        .filter_map(Result::ok)
+        .step_by(channels)
+        .collect();
+    from_pcm(&samples, spec.sample_rate)
+}
+
+/// Peaks of short windows, so a long recording never has to be held in memory.
+struct PeakWindows {
+    window: u64,
+    frames: u64,
+    current: u16,
+    peaks: Vec<u16>,
+}
+
+impl PeakWindows {
+    fn new(sample_rate: u32) -> Self {
+        PeakWindows {
+            window: (sample_rate as u64 / 100).max(1),
+            frames: 0,
+            current: 0,
+            peaks: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, sample: i16) {
+        self.current = self.current.max(sample.unsigned_abs());
+        self.frames += 1;
+        if self.frames % self.window == 0 {
+            self.peaks.push(self.current);
+            self.current = 0;
+        }
+    }
+
+    fn finish(mut self, sample_rate: u32) -> Option<Waveform> {
+        if self.frames == 0 {
+            return None;
+        }
+        if self.frames % self.window != 0 {
+            self.peaks.push(self.current);
+        }
+        let bucket = self.peaks.len().div_ceil(PEAK_COUNT);
+        let peaks = self
+            .peaks
+            .chunks(bucket)
+            .map(|chunk| {
+                let peak = chunk.iter().copied().max().unwrap_or(0);
+                (peak as u32 * 255 / i16::MAX as u32).min(255) as u8
+            })
+            .collect();
+        Some(Waveform {
+            duration_ms: self.frames * 1000 / sample_rate as u64,
+            peaks,
+        })
+    }
+}
+
+/// Decodes an audio file of any supported format packet by packet, reading the
+/// first channel. `extension` helps pick the container when probing is ambiguous.
+pub fn from_audio_file(path: &Path, extension: Option<&str>) -> Option<Waveform> {
+    let file = File::open(path).ok()?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?
+        .format;
+    let track = format.default_track()?;
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate.filter(|rate| *rate > 0)?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+    let mut windows = PeakWindows::new(sample_rate);
+    let mut buffer: Option<SampleBuffer<i16>> = None;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet only loses its own samples.
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(_) => break,
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let needed = decoded.capacity() * channels;
+        if buffer
+            .as_ref()
+            .is_some_and(|buffer| buffer.capacity() < needed)
+        {
+            buffer = None;
+        }
+        let buffer =
+            buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            windows.push(frame[0]);
+        }
+    }
+    windows.finish(sample_rate)
+}
@@ -0,0 +1,120 @@
+use crate::{
+    entity::bot,
+    repositories::bot as bot_repository,
+    utils::error::{format_error, AppError, ErrorCode},
+    ServiceState,
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    RequestPartsExt,
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use chrono::Utc;
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use sea_orm::TransactionTrait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+// Keeps widget tokens and user access tokens, signed with the same secret, apart.
+const WIDGET_AUDIENCE: &str = "widget";
+
+/// Lets an embedded chat post into one conversation on behalf of a bot
+/// until the token expires.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WidgetClaims {
+    pub iat: i64,
+    pub exp: i64,
+    pub aud: String,
+    pub bot_id: Uuid,
+    pub conversation_id: Uuid,
+}
+
+impl WidgetClaims {
+    pub fn new(bot_id: Uuid, conversation_id: Uuid, ttl_secs: u64) -> Self {
+        let now = Utc::now().timestamp();
+        WidgetClaims {
+            iat: now,
+            exp: now + ttl_secs as i64,
+            aud: WIDGET_AUDIENCE.to_string(),
+            bot_id,
+            conversation_id,
+        }
+    }
+
+    pub fn encode(&self, key: &str) -> Result<String, jsonwebtoken::errors::Error> {
+        jsonwebtoken::encode(
+            &Header::default(),
+            self,
+            &EncodingKey::from_secret(key.as_ref()),
+        )
+    }
+
+    pub fn decode(token: &str, key: &str) -> Result<Self, jsonwebtoken::errors::Error> {
+        let mut validation = Validation::default();
+        validation.set_audience(&[WIDGET_AUDIENCE]);
+        jsonwebtoken::decode::<WidgetClaims>(
+            token,
+            &DecodingKey::from_secret(key.as_ref()),
+            &validation,
+        )
+        .map(|data| data.claims)
+    }
+}
+
+/// The bot and conversation a widget token in the `Authorization` header is
+/// scoped to. Deleting the bot revokes its outstanding tokens.
+pub struct WidgetAuth {
+    pub bot: bot::Model,
+    pub conversation_id: Uuid,
+}
+
+#[async_trait::async_trait]
+impl FromRequestParts<Arc<ServiceState>> for WidgetAuth {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServiceState>,
+    ) -> Result<Self, Self::Rejection> {
+        let unauthorized = |message: &str| {
+            error!("{}", message);
+            AppError::new(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, message)
+        };
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| unauthorized("Missing widget token"))?;
+        let claims = WidgetClaims::decode(bearer.token(), &state.config.jwt.access_token_secret)
+            .map_err(|_| unauthorized("Invalid or expired widget token"))?;
+
+        let transaction = state.db.begin().await.map_err(|e| {
+            format_error(
+                "Could not start a database transaction due to an error",
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        let bot_model = bot_repository::find_by_id(&transaction, claims.bot_id)
+            .await
+            .map_err(|e| {
+                format_error(
+                    "Failed to look up the widget bot",
+                    e,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+        let _ = transaction.rollback().await;
+
+        let bot_model = bot_model.ok_or_else(|| unauthorized("The widget bot no longer exists"))?;
+        Ok(WidgetAuth {
+            bot: bot_model,
+            conversation_id: claims.conversation_id,
+        })
+    }
+}